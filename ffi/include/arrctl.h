@@ -0,0 +1,85 @@
+/*
+ * C API for arrctl, link with -larrctl_ffi.
+ *
+ * Calls that can fail return 0 on success and otherwise the exit code the
+ * arrctl CLI uses for the same error (2 unsupported CPU, 3 permission
+ * denied, 4 register locked by firmware, 5 invalid arguments, 6 msr kernel
+ * module not loaded, 1 anything else). arrctl_last_error() has the message.
+ *
+ * Setting limits needs root, like the CLI, and goes through the same
+ * journal so `arrctl recover` can undo a write that was cut short.
+ *
+ * Keep in sync with src/lib.rs, tests/header.rs checks the two agree.
+ */
+#ifndef ARRCTL_H
+#define ARRCTL_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Arrctl Arrctl;
+
+typedef struct ArrctlLimits {
+	/* In steps of 125, the registers hold eighths of a W or A */
+	uint32_t tdp_mw;
+	uint32_t tdc_ma;
+	bool tdp_override;
+	bool tdc_override;
+	bool turbo;
+	uint32_t tjmax_celsius;
+} ArrctlLimits;
+
+typedef struct ArrctlCpuSample {
+	uint16_t cpu;
+	float effective_mhz;
+	/* Fraction of the interval spent awake */
+	float busy;
+	float volts;
+	/* -1 without a valid reading */
+	int32_t celsius;
+	bool throttling;
+} ArrctlCpuSample;
+
+typedef struct ArrctlMonitor ArrctlMonitor;
+
+/* NULL on failure, see arrctl_last_error() */
+Arrctl *arrctl_open(void);
+
+/* Reads registers from an `arrctl dump` file, writes only stay in memory */
+Arrctl *arrctl_open_dump(const char *path);
+
+void arrctl_close(Arrctl *arrctl);
+
+int arrctl_get_limits(const Arrctl *arrctl, ArrctlLimits *out);
+
+/* Sets the limit and its override bit on every package, rounded to 125 */
+int arrctl_set_tdp_mw(const Arrctl *arrctl, uint32_t milliwatts);
+int arrctl_set_tdc_ma(const Arrctl *arrctl, uint32_t milliamps);
+
+int arrctl_set_turbo(const Arrctl *arrctl, bool enabled);
+
+/* Samples are averages since the previous call, or since monitor_new */
+ArrctlMonitor *arrctl_monitor_new(const Arrctl *arrctl);
+
+/*
+ * Fills up to len CPUs, count gets how many there are in total and
+ * package_watts the estimated package draw.
+ */
+int arrctl_monitor_sample(ArrctlMonitor *monitor, const Arrctl *arrctl, ArrctlCpuSample *cpus, size_t len,
+			  size_t *count, float *package_watts);
+
+void arrctl_monitor_close(ArrctlMonitor *monitor);
+
+/* Valid until the next failing call on the same thread */
+const char *arrctl_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,322 @@
+// C API over the library, see include/arrctl.h for how to use it.
+//
+// Every call that can fail returns 0 or the exit code the CLI would have
+// used for the same error, with the message for arrctl_last_error(). The
+// same calls are there for Rust too, python/ builds on them.
+
+use anyhow::{bail, Context, Result};
+use arrctl::error::{self, Error, ErrorKind};
+use arrctl::journal::{self, Journal};
+use arrctl::msr::{MockMsr, MsrAccess, MsrDevice, RegSpec};
+use arrctl::profile::MAX_LIMIT;
+use arrctl::regs::*;
+use arrctl::monitor::Sampler;
+use arrctl::{apply, cpu, state};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::fs;
+use std::path::Path;
+use std::ptr;
+
+pub struct Arrctl {
+    msr: Box<dyn MsrAccess>,
+    // None for dumps, their writes only live in memory anyway
+    journal: Option<Journal>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArrctlLimits {
+    pub tdp_mw: u32,
+    pub tdc_ma: u32,
+    pub tdp_override: bool,
+    pub tdc_override: bool,
+    pub turbo: bool,
+    pub tjmax_celsius: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ArrctlCpuSample {
+    pub cpu: u16,
+    pub effective_mhz: f32,
+    // Fraction of the interval spent awake
+    pub busy: f32,
+    pub volts: f32,
+    // -1 without a valid reading
+    pub celsius: i32,
+    pub throttling: bool,
+}
+
+pub struct ArrctlMonitor {
+    sampler: Sampler,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(err: &anyhow::Error) -> c_int {
+    // Interior NULs can't make it through a C string
+    let message = format!("{:#}", err).replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).unwrap_or_default());
+    error::kind_of(err).exit_code().into()
+}
+
+fn check(res: Result<()>) -> c_int {
+    match res {
+        Ok(()) => 0,
+        Err(e) => set_error(&e),
+    }
+}
+
+fn open(res: Result<Arrctl>) -> *mut Arrctl {
+    match res {
+        Ok(arrctl) => Box::into_raw(Box::new(arrctl)),
+        Err(e) => {
+            set_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+fn handle<'a>(handle: *const Arrctl) -> Result<&'a Arrctl> {
+    match unsafe { handle.as_ref() } {
+        Some(handle) => Ok(handle),
+        None => bail!(Error::new(ErrorKind::Validation, "NULL handle")),
+    }
+}
+
+// Units of 1/8 W or A, rounded to the nearest
+fn eighths(milli: u32) -> Result<u64> {
+    let eighths = (u64::from(milli) * 8 + 500) / 1000;
+    if eighths == 0 || eighths > MAX_LIMIT * 8 {
+        bail!(Error::new(ErrorKind::Validation, format!("Limit must be between 125 and {} thousandths", MAX_LIMIT * 1000)));
+    }
+    Ok(eighths)
+}
+
+fn layout(msr: &dyn MsrAccess) -> Vec<cpu::CpuInfo> {
+    cpu::layout_from(Path::new("/sys/devices/system/cpu"), msr.cpus())
+}
+
+impl Arrctl {
+    fn with(msr: Box<dyn MsrAccess>, journal: Option<Journal>) -> Result<Self> {
+        // Fail here rather than on the first real call
+        msr_platform_info(&*msr)?;
+        Ok(Arrctl { msr, journal })
+    }
+
+    // The CPU's own registers, sets are journaled like the CLI's
+    pub fn open() -> Result<Self> {
+        Arrctl::with(Box::new(MsrDevice::new()), Some(Journal::new(journal::DEFAULT_PATH)))
+    }
+
+    pub fn open_dump(path: &Path) -> Result<Self> {
+        let dump = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Arrctl::with(Box::new(MockMsr::from_dump(&dump)?), None)
+    }
+
+    pub fn msr(&self) -> &dyn MsrAccess {
+        &*self.msr
+    }
+
+    pub fn limits(&self) -> Result<ArrctlLimits> {
+        let msr = &*self.msr;
+        let turbo_limits = msr_turbo_limits(msr)?;
+        Ok(ArrctlLimits {
+            tdp_mw: (turbo_limits.tdp() * 1000 / 8) as u32,
+            tdc_ma: (turbo_limits.tdc() * 1000 / 8) as u32,
+            tdp_override: turbo_limits.tdp_override(),
+            tdc_override: turbo_limits.tdc_override(),
+            turbo: !ia32_misc_enable(msr)?.turbo_disable(),
+            tjmax_celsius: msr_temperature_target(msr)?.get() as u32,
+        })
+    }
+
+    fn write(&self, writes: &[(RegSpec, u64)]) -> Result<()> {
+        match &self.journal {
+            Some(journal) => {
+                // Same as the CLI, a missing baseline only matters for undoing later
+                if let Ok(id) = state::boot_id() {
+                    let _ = state::ensure_baseline(Path::new(state::DEFAULT_PATH), &*self.msr, id);
+                }
+                journal.apply(&*self.msr, writes)
+            }
+            None => writes.iter().try_for_each(|&(spec, val)| self.msr.write(spec.reg, spec.cpu, val)),
+        }
+    }
+
+    // Always on every package, a library caller has no --all-cores to forget
+    fn set_limit(&self, milli: u32, set: fn(&mut MsrTurboLimits, u64)) -> Result<()> {
+        let msr = &*self.msr;
+        let value = eighths(milli)?;
+        if !msr_platform_info(msr)?.programmable_tdc_tdp() {
+            bail!(Error::new(ErrorKind::RegisterLocked, "CPU doesn't support setting TDP and TDC").register(MSR_TURBO_LIMITS, 0));
+        }
+        let mut writes = Vec::new();
+        for cpu in apply::targets(&layout(msr), true, MSR_TURBO_LIMITS) {
+            let mut turbo_limits = MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, cpu)?);
+            set(&mut turbo_limits, value);
+            writes.push((RegSpec { reg: MSR_TURBO_LIMITS, cpu }, turbo_limits.0));
+        }
+        self.write(&writes)
+    }
+
+    // Rounded to the 1/8 W the register holds
+    pub fn set_tdp_mw(&self, milliwatts: u32) -> Result<()> {
+        self.set_limit(milliwatts, |limits, v| {
+            limits.set_tdp(v);
+            limits.set_tdp_override(true);
+        })
+    }
+
+    pub fn set_tdc_ma(&self, milliamps: u32) -> Result<()> {
+        self.set_limit(milliamps, |limits, v| {
+            limits.set_tdc(v);
+            limits.set_tdc_override(true);
+        })
+    }
+
+    pub fn set_turbo(&self, enabled: bool) -> Result<()> {
+        let msr = &*self.msr;
+        let writes = apply::targets(&layout(msr), true, IA32_MISC_ENABLE)
+            .into_iter()
+            .map(|cpu| {
+                let mut misc = Ia32MiscEnable(msr.read(IA32_MISC_ENABLE, cpu)?);
+                misc.set_turbo_disable(!enabled);
+                Ok((RegSpec { reg: IA32_MISC_ENABLE, cpu }, misc.0))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.write(&writes)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn arrctl_open() -> *mut Arrctl {
+    open(Arrctl::open())
+}
+
+/// # Safety
+/// `path` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_open_dump(path: *const c_char) -> *mut Arrctl {
+    open((|| {
+        if path.is_null() {
+            bail!(Error::new(ErrorKind::Validation, "NULL path"));
+        }
+        let path = unsafe { CStr::from_ptr(path) }.to_string_lossy().into_owned();
+        Arrctl::open_dump(Path::new(&path))
+    })())
+}
+
+/// # Safety
+/// `arrctl` must come from arrctl_open and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_close(arrctl: *mut Arrctl) {
+    if !arrctl.is_null() {
+        drop(unsafe { Box::from_raw(arrctl) });
+    }
+}
+
+/// # Safety
+/// `arrctl` must come from arrctl_open, `out` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_get_limits(arrctl: *const Arrctl, out: *mut ArrctlLimits) -> c_int {
+    check((|| {
+        let limits = handle(arrctl)?.limits()?;
+        match unsafe { out.as_mut() } {
+            Some(out) => *out = limits,
+            None => bail!(Error::new(ErrorKind::Validation, "NULL limits")),
+        }
+        Ok(())
+    })())
+}
+
+/// # Safety
+/// `arrctl` must come from arrctl_open.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_set_tdp_mw(arrctl: *const Arrctl, milliwatts: u32) -> c_int {
+    check(handle(arrctl).and_then(|arrctl| arrctl.set_tdp_mw(milliwatts)))
+}
+
+/// # Safety
+/// `arrctl` must come from arrctl_open.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_set_tdc_ma(arrctl: *const Arrctl, milliamps: u32) -> c_int {
+    check(handle(arrctl).and_then(|arrctl| arrctl.set_tdc_ma(milliamps)))
+}
+
+/// # Safety
+/// `arrctl` must come from arrctl_open.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_set_turbo(arrctl: *const Arrctl, enabled: bool) -> c_int {
+    check(handle(arrctl).and_then(|arrctl| arrctl.set_turbo(enabled)))
+}
+
+/// # Safety
+/// `arrctl` must come from arrctl_open.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_monitor_new(arrctl: *const Arrctl) -> *mut ArrctlMonitor {
+    match handle(arrctl).and_then(|arrctl| Sampler::new(&*arrctl.msr)) {
+        Ok(sampler) => Box::into_raw(Box::new(ArrctlMonitor { sampler })),
+        Err(e) => {
+            set_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `monitor` must come from arrctl_monitor_new on the same `arrctl`, `cpus`
+/// must have room for `len` samples, `count` and `package_watts` must be
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_monitor_sample(
+    monitor: *mut ArrctlMonitor,
+    arrctl: *const Arrctl,
+    cpus: *mut ArrctlCpuSample,
+    len: usize,
+    count: *mut usize,
+    package_watts: *mut f32,
+) -> c_int {
+    check((|| {
+        let (Some(monitor), Some(count), Some(package_watts)) = (unsafe { monitor.as_mut() }, unsafe { count.as_mut() }, unsafe { package_watts.as_mut() }) else {
+            bail!(Error::new(ErrorKind::Validation, "NULL monitor or output"));
+        };
+        let sample = monitor.sampler.sample(&*handle(arrctl)?.msr)?;
+        if !sample.cpus.is_empty() && cpus.is_null() {
+            bail!(Error::new(ErrorKind::Validation, "NULL cpus"));
+        }
+        for (i, cpu) in sample.cpus.iter().take(len).enumerate() {
+            let out = ArrctlCpuSample {
+                cpu: cpu.cpu,
+                effective_mhz: cpu.activity.effective_mhz,
+                busy: cpu.activity.active,
+                volts: cpu.activity.volts,
+                celsius: cpu.celsius.map_or(-1, |c| c as i32),
+                throttling: cpu.throttling,
+            };
+            unsafe { cpus.add(i).write(out) };
+        }
+        // All of them, so a caller with too small a buffer can tell
+        *count = sample.cpus.len();
+        *package_watts = sample.package_watts;
+        Ok(())
+    })())
+}
+
+/// # Safety
+/// `monitor` must come from arrctl_monitor_new and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn arrctl_monitor_close(monitor: *mut ArrctlMonitor) {
+    if !monitor.is_null() {
+        drop(unsafe { Box::from_raw(monitor) });
+    }
+}
+
+// Valid until the next failing call on the same thread
+#[no_mangle]
+pub extern "C" fn arrctl_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
@@ -0,0 +1,79 @@
+use arrctl_ffi::*;
+use std::ffi::{CStr, CString};
+use std::fs;
+
+fn source(path: &str) -> String {
+    fs::read_to_string(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), path)).unwrap()
+}
+
+// Everything exported is declared and nothing declared is missing
+#[test]
+fn header_matches_exports() {
+    let lib = source("src/lib.rs");
+    let header = source("include/arrctl.h");
+    let exported: Vec<&str> = lib
+        .lines()
+        .filter_map(|l| l.split("extern \"C\" fn ").nth(1))
+        .map(|l| l.split('(').next().unwrap())
+        .collect();
+    let declared: Vec<&str> = header
+        .lines()
+        .filter(|l| !l.trim_start().starts_with(['/', '*']))
+        .filter_map(|l| l.split_once('(').map(|(start, _)| start))
+        .filter_map(|start| start.rsplit([' ', '*']).next())
+        .filter(|name| name.starts_with("arrctl_"))
+        .collect();
+    assert!(!exported.is_empty());
+    assert_eq!(exported, declared);
+}
+
+fn open_fixture() -> *mut Arrctl {
+    let path = CString::new(format!("{}/../tests/fixtures/i5-520m.dump", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let arrctl = unsafe { arrctl_open_dump(path.as_ptr()) };
+    assert!(!arrctl.is_null());
+    arrctl
+}
+
+#[test]
+fn limits_round_trip() {
+    let arrctl = open_fixture();
+    let mut limits = ArrctlLimits::default();
+    assert_eq!(unsafe { arrctl_get_limits(arrctl, &mut limits) }, 0);
+    assert_eq!(limits, ArrctlLimits { tdp_mw: 35000, tdc_ma: 48000, tdp_override: false, tdc_override: false, turbo: true, tjmax_celsius: 105 });
+
+    assert_eq!(unsafe { arrctl_set_tdp_mw(arrctl, 17_600) }, 0);
+    assert_eq!(unsafe { arrctl_set_turbo(arrctl, false) }, 0);
+    assert_eq!(unsafe { arrctl_get_limits(arrctl, &mut limits) }, 0);
+    assert_eq!((limits.tdp_mw, limits.tdp_override, limits.turbo), (17_625, true, false));
+
+    // Same exit code the CLI would use
+    assert_eq!(unsafe { arrctl_set_tdc_ma(arrctl, 0) }, 5);
+    let message = unsafe { CStr::from_ptr(arrctl_last_error()) }.to_str().unwrap();
+    assert!(message.starts_with("Limit must be between"), "{}", message);
+    unsafe { arrctl_close(arrctl) };
+}
+
+#[test]
+fn bad_dump_fails_open() {
+    let path = CString::new("/nonexistent").unwrap();
+    assert!(unsafe { arrctl_open_dump(path.as_ptr()) }.is_null());
+    assert!(!unsafe { CStr::from_ptr(arrctl_last_error()) }.to_bytes().is_empty());
+}
+
+#[test]
+fn monitor_reports_every_cpu() {
+    let arrctl = open_fixture();
+    let monitor = unsafe { arrctl_monitor_new(arrctl) };
+    assert!(!monitor.is_null());
+    let mut cpus = [ArrctlCpuSample::default(); 2];
+    let (mut count, mut watts) = (0, 0.0);
+    assert_eq!(unsafe { arrctl_monitor_sample(monitor, arrctl, cpus.as_mut_ptr(), cpus.len(), &mut count, &mut watts) }, 0);
+    // Only room for two, but it says there are four
+    assert_eq!(count, 4);
+    assert_eq!((cpus[0].cpu, cpus[1].cpu), (0, 1));
+    assert!(cpus.iter().all(|c| c.celsius > 0 && !c.throttling));
+    unsafe {
+        arrctl_monitor_close(monitor);
+        arrctl_close(arrctl);
+    }
+}
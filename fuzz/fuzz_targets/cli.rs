@@ -0,0 +1,9 @@
+#![no_main]
+
+use arrctl::cli::Cli;
+use clap::Parser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|args: Vec<String>| {
+    let _ = Cli::try_parse_from(std::iter::once("arrctl".to_string()).chain(args));
+});
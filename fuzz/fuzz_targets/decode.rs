@@ -0,0 +1,54 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use arrctl::monitor::Counters;
+use arrctl::msr::{MockMsr, MsrAccess};
+use arrctl::output::{self, Format, Locale};
+use arrctl::regs::*;
+use arrctl::{power, sku, status};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    regs: Vec<[u64; 16]>,
+    counters: [[u64; 3]; 2],
+    sku: u8,
+}
+
+fuzz_target!(|input: Input| {
+    let msr = MockMsr::from_dump("").unwrap();
+    for (cpu, values) in input.regs.iter().take(8).enumerate() {
+        for (&val, reg) in values.iter().zip(dumped()) {
+            msr.write(reg.address, cpu as u16, val).unwrap();
+        }
+    }
+
+    let stock = sku::SKUS.get(input.sku as usize % sku::SKUS.len());
+    for format in [Format::Human, Format::Json, Format::Csv, Format::Prometheus] {
+        let mut out = output::sink(format, Locale::parse("de_DE.UTF-8"), true, Vec::new());
+        let _ = status::tdp(&mut *out, &msr, stock);
+        let _ = status::tdc(&mut *out, &msr, stock);
+        let _ = status::tjmax(&mut *out, &msr);
+        let _ = status::turbo_ratios(&mut *out, &msr, stock);
+        let _ = status::voltage(&mut *out, &msr);
+        let _ = out.finish();
+    }
+
+    let [old, now] = input.counters.map(|[tsc, aperf, mperf]| Counters { tsc, aperf, mperf });
+    let mut samples = Vec::new();
+    for cpu in msr.cpus() {
+        let (Ok(plat_info), Ok(perf), Ok(therm), Ok(tjmax)) = (
+            msr_platform_info(&msr),
+            ia32_perf_status(&msr, cpu),
+            ia32_therm_status(&msr, cpu),
+            msr_temperature_target(&msr),
+        ) else {
+            continue;
+        };
+        let _ = therm.celsius(tjmax.get());
+        let base_mhz = plat_info.max_non_turbo_ratio() as f32 * arrctl::cpu::BCLK_MHZ;
+        samples.push(now.sample_since(&old, base_mhz, perf.volts()));
+    }
+    let coeffs = stock.map(|sku| &sku.power).unwrap_or(&power::DEFAULT_COEFFICIENTS);
+    let _ = format!("{:.1}", power::estimate_package_watts(coeffs, &samples, 2));
+});
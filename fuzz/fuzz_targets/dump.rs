@@ -0,0 +1,11 @@
+#![no_main]
+
+use arrctl::msr::{self, MockMsr};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(mock) = MockMsr::from_dump(data) {
+        let mut out = Vec::new();
+        let _ = msr::write_dump(&mut out, &mock, mock.brand.as_deref().unwrap_or(""), mock.microcode);
+    }
+});
@@ -0,0 +1,306 @@
+# English, also what every other language falls back to.
+#
+# Help is "about" for arrctl itself, "about-" and the subcommand path for
+# the commands, "arg-" and the path and argument for their arguments, and
+# plain "arg-" and the argument for the ones without a command or shared
+# by all of them.
+
+## Help
+
+about = Reads and sets the turbo power limits of Intel Arrandale CPUs
+about-help = Print this message or the help of the given subcommand(s)
+arg-help = Print help
+
+arg-get_tdp = Print the turbo TDP limit
+arg-get_tdc = Print the turbo TDC limit
+arg-get_tjmax = Print the temperature throttling starts at
+arg-get_turbo_ratios = Print the turbo ratios and what they mean in MHz
+arg-get_voltage = Print the core voltage of every CPU
+arg-get_thermal = Print core temperatures and thermal events, and the package's where there is a sensor for it
+cpu-place = CPU{ $cpu } (core { $core }, APIC { $apic })
+cpu-place-package = CPU{ $cpu } (package { $package } core { $core }, APIC { $apic })
+thermal-core = CPU{ $cpu } core temperature
+thermal-package = Package temperature
+thermal-throttling = throttling
+thermal-log = throttled since boot
+prochot-log = PROCHOT since boot
+thermal-no-package = This CPU has no package sensor, the hottest core is the closest reading there is
+thermal-package-drives = The package reading is the one the thermal control circuit acts on
+arg-set_tdp = Set the turbo TDP limit, in watts like 25, 25W or 25000mW
+arg-set_tdc = Set the turbo TDC limit, in amps like 30, 30A or 30000mA
+arg-set_clock_modulation = Skip clock cycles, a duty cycle like 50% or in eighths, off turns it off
+arg-set_ratio = Cap every turbo bin at the ratio nearest this frequency, like 2.66GHz or 2660MHz
+arg-all_cores = Apply sets on every core or package instead of just CPU0
+arg-monitor = Print frequency, load, voltage and temperature until stopped
+arg-interval = Time between monitor samples, like 500 (milliseconds), 500ms or 2s
+arg-temp_source = Where monitor takes temperatures from, the other one is still read to cross-check
+arg-thermal_zones = Also print the ACPI thermal zones and the trip points that go off before TJmax
+arg-govern = Run the [governor] from the profiles file while monitoring
+arg-format = How records are printed
+arg-json = Shorthand for --format json, also makes errors JSON
+arg-locale = Decimal separator for human output, "C" forces a period
+arg-color = When to color values that are out of the ordinary
+arg-influx_url = Push records to this Influx write endpoint instead of printing them
+arg-schema = Print the JSON Schema of the command's --json output instead of running it
+arg-allow_vm = Go ahead inside a virtual machine, with a warning
+arg-msr_retries = Tries again after a failed MSR access, 0 gives up on the first one
+arg-msr_retry_delay = How long to wait before each retry
+arg-no_sandbox = Skip the seccomp filter of read-only and daemon runs, for debugging
+arg-read_only = Refuse every MSR write, for deploying only the monitoring parts
+arg-force = Write limits the guard rails refuse, like a TDC under idle draw, or over a set that never finished, never through the daemon
+arg-direct = Write the registers even while the daemon runs, instead of asking it to
+arg-sudo = Re-run through sudo or pkexec when a write needs root
+arg-overrides = Set a key of the profiles file like profiles.quiet.tdp=18, over the file and ARRCTL_SET
+
+about-id = Identify the CPU, without needing root
+about-selftest = Check that every register arrctl uses can be read
+about-features = What this CPU supports, for frontends, --json gives the full document
+about-dump = Print every register arrctl knows, for bug reports and compare
+about-recover = Put back the registers of a set that was interrupted
+about-advise = Watch the CPU under load and suggest limits
+arg-advise-duration = Seconds to watch for
+about-doctor = Look for the usual reasons it runs slow and explain them
+about-bench = Load every CPU and report which limit binds
+arg-bench-load = spin, avx, memory or custom:<command>
+arg-bench-duration = Seconds to load for
+about-soak = Acceptance test: load for hours, fail if it runs hot or slow
+arg-soak-hours = How long to run, fractions are fine
+arg-soak-max_temp = Highest temperature allowed in celsius, hottest core
+arg-soak-min_sustained_mhz = Lowest average frequency allowed over any minute
+arg-soak-load = spin, avx, memory or custom:<command>
+about-budget = Split the package power between the CPU and the graphics
+arg-budget-cpu = CPU share of the package, as the turbo TDP
+arg-budget-igp_cap = Highest graphics frequency step to allow
+arg-budget-save = Also store the split as this profile
+about-cores = Take logical CPUs offline or bring them back
+about-cores-set = Keep this many logical CPUs online
+arg-cores-set-count = A number of CPUs or "all"
+about-config = Work with the profiles file
+about-config-validate = Check a profiles file without applying anything
+arg-config-validate-file = The file to check, the installed one by default
+about-profile = The profiles in the profiles file
+about-profile-show = A profile as written, or with --resolved as applied
+arg-profile-show-name = The profile
+arg-profile-show-resolved = With everything it extends folded in
+arg-profile-show-config = The profiles file
+about-audit = Work with the audit log of register writes
+about-audit-verify = Check the hash chain of every write, and that nothing was cut off
+arg-audit-verify-file = The log to check, the installed one by default
+about-compare = Field by field differences between two `arrctl dump` files
+arg-compare-a = The first dump
+arg-compare-b = The dump to compare it with
+arg-compare-all = Also list the fields that match
+about-run = Run the commands in a file one after the other, in one process
+arg-run-script = The file, one get, set, sleep or if per line
+about-converge = Bring the settings to a desired state, writing only what differs
+arg-converge-config = The desired state, one profile's worth of keys
+arg-converge-check = Only report what would change
+about-set = Change one setting, through the daemon while it runs so it sticks
+arg-set-setting = What to set
+arg-set-value = The value to set it to, max-freq takes a frequency like 2.4GHz
+arg-set-duration = Put it back after this long, like 90, 60s or 20m, needs the daemon
+about-try = Apply one setting that goes back on its own unless confirmed in time
+arg-try-revert_after = How long until it goes back, like 90, 60s or 5m
+about-try-set = Apply the setting and start the countdown
+arg-try-set-setting = What to set
+arg-try-set-value = The value to set it to
+about-try-confirm = Keep the setting being tried
+about-try-expire = Wait for the countdown and revert
+about-init = Ask a few questions and write a starter profiles file
+arg-init-config = Where to write the profiles
+arg-init-goal = Skip the question, needed without a terminal
+arg-init-ac_tdp = TDP on AC, like 25W
+arg-init-battery_tdp = TDP on battery, like 15W
+arg-init-systemd = Also write arrctl.service here, like /etc/systemd/system
+arg-init-overwrite = Replace the profiles file and unit if they're there
+about-tune = Lower the TDP under load until the clock gives, and recommend the last one that held
+arg-tune-min_mhz = Lowest sustained clock that still counts, like 2400 or 2.4GHz
+arg-tune-load = spin, avx, memory or custom:<command>
+arg-tune-from = TDP to start from, the one that's set by default
+arg-tune-floor = Lowest TDP to try
+arg-tune-step = How much lower each step goes
+arg-tune-step_duration = How long each TDP runs, the first half isn't counted
+arg-tune-save = Also store the result as this profile
+about-measure = Run a command and add up what the CPU and the system drew for it
+arg-measure-interval = How often to sample, like 250, 250ms or 1s
+arg-measure-command = The command and its arguments, after --
+about-replay = Play back a `--monitor --format csv` recording
+arg-replay-file = The recording
+arg-replay-interval = What the recording was made with, in milliseconds
+arg-replay-speed = How much faster than recorded to play it
+about-policy = Dry runs of a [policy] script, written in arrctl's own small expression language
+about-policy-test = What the script picks over a `--monitor --format csv` recording
+arg-policy-test-script = The policy script
+arg-policy-test-recording = The recording to run it against
+arg-policy-test-battery = As if the recording was made on battery
+about-policy-backtest = What the script would have set over a recorded session, and where that broke its limits
+arg-policy-backtest-script = The policy script
+arg-policy-backtest-recording = The recording to run it against
+arg-policy-backtest-interval = What the recording was made with, in milliseconds
+arg-policy-backtest-min_interval = Seconds to wait after a write before the next, like min_interval_secs under [policy]
+arg-policy-backtest-max_temp = Highest temperature allowed in celsius, hottest core
+arg-policy-backtest-battery = As if the recording was made on battery
+about-events = Follow what a running daemon sees, --json gives one JSON object per line
+arg-events-socket = The daemon's socket
+about-history = Look back at what a running daemon saw
+about-history-show = What the daemon saw, downsampled the further back it goes
+arg-history-show-since = How far back, like 90s, 30m or 8h
+arg-history-show-socket = The daemon's socket
+about-daemon = Keep watch in the background and serve status over a socket
+arg-daemon-interval = Milliseconds between samples
+arg-daemon-socket = Where to listen for status requests
+arg-daemon-hook = Command to run on power source changes and emergencies
+arg-daemon-listen = Serve a status page at / and the events over a websocket at /ws, like 127.0.0.1:9110
+arg-daemon-config = The profiles file
+arg-daemon-apply = Profile to apply at start and again after every resume, ARRCTL_PROFILE when not given
+arg-daemon-apply_on_battery = Profile to apply instead while running on battery
+arg-daemon-user = Runs as this user once the MSR files are open, root stays root
+arg-daemon-history = How many hours of samples to keep for `arrctl history`
+arg-daemon-enforce_every = Check the set registers this often and write back what changed, like 30s
+arg-daemon-enforce_jitter = Spread the checks up to this much either side
+
+## Status
+
+tdp-max = Maximum turbo TDP
+tdp-stock = Stock TDP of { $sku }
+tdp-override = Turbo TDP override status
+tdc-max = Maximum turbo TDC
+tdc-stock = Stock TDC of { $sku }
+tdc-override = Turbo TDC override status
+tjmax = TJmax
+unavailable = { $section }: unavailable ({ $reason })
+section-tdp = TDP
+section-tdc = TDC
+section-tjmax = TJmax
+section-turbo-ratios = Turbo ratios
+section-voltage = Voltage
+section-thermal = Thermal status
+turbo-ratio-one = Max turbo ratio for one core
+turbo-ratio-two = Max turbo ratio for two cores
+turbo-ratio-three = Max turbo ratio for three cores
+turbo-ratio-four = Max turbo ratio for four cores
+stock-ratios = Stock ratios of { $sku }
+unit-base = base
+unit-for-one-core = for one core
+unit-for-two-cores = for two cores
+turbo-active-one = With 1 active core
+turbo-active-many = With { $count } active cores
+turbo-fused = fused
+turbo-disabled-bins = Turbo is disabled, so every bin runs at most at the base ratio
+turbo-physical-cores = Active cores are physical cores, two busy threads on one core still get the one core bin
+voltage = CPU{ $cpu } voltage
+
+## Monitor
+
+unit-busy = % busy
+unit-estimated-watts = estimated W
+unit-estimated-joules = estimated J
+unit-battery-share = % of it, estimated
+package = Package
+summary = { $samples } sample(s) over { $seconds } s
+summary-peak = peak
+summary-max = max
+summary-throttled = throttled
+turbo-disabled = Turbo is disabled
+estimated-power = Package power is estimated from frequency and voltage, Arrandale can't measure it.
+estimated-power-rough = Treat it as a rough guide only, it can be off by several watts.
+temp-divergence = CPU{ $cpu } reads { $msr } celsius from the MSR but { $hwmon } from coretemp, one of the two is off
+zone-trip = { $zone } { $type } { $trip } trip
+zone-at = at
+zone-before-tjmax = before TJmax
+zone-next-trip = next trip
+zone-to-trip = in
+governor = Governor
+governor-at = at
+governor-target = target
+governor-adjustments = adjustment(s)
+governor-per-hour = in the last hour
+enforcement-title = Enforcement
+enforcement-checks = Checks
+enforcement-rewrites = Written back
+enforcement-reverted = Reverted by firmware
+enforcement-last = Last revert
+unit-registers = register(s)
+unit-times = time(s)
+unit-seconds-ago = s ago
+init-title = Setup
+init-config = Profiles
+init-goal = Goal
+init-ac = On AC
+init-battery = On battery
+init-unit = Unit
+init-enable = Start it with `systemctl daemon-reload && systemctl enable --now arrctl`
+init-ask-goal = Main goal, battery, quiet or performance
+init-ask-ac = TDP on AC
+init-ask-battery = TDP on battery
+init-ask-systemd = Write a systemd unit to { $dir }? y or n
+init-bad-goal = That's battery, quiet or performance
+init-bad-yes = That's y or n
+tune-title = Tuned
+tune-tdp = TDP
+tune-mhz = sustained
+tune-package = package
+tune-max-temp = max
+tune-recommended = Lowest TDP that held
+tune-holding = at
+tune-load = under load
+tune-interrupted = Stopped early, the recommendation only covers the steps that finished
+tune-floor = It held all the way down to { $watts } W, lower --floor to go further
+tune-saved = Saved as profile { $profile } in { $path }
+turbo-residency-time = busy
+unit-busy-time = % of busy time
+unit-of-the-time = % of the time
+policy-sample = Sample { $sample }
+policy-celsius = hottest core
+policy-clock-modulation = clock modulation
+policy-turbo = turbo
+policy-summary = Policy
+policy-samples = samples
+policy-changes = changes
+policy-skipped = without a temperature
+backtest-after = After { $seconds } s
+backtest-summary = Backtest
+backtest-seconds = recorded
+backtest-writes = writes
+backtest-max-celsius = hottest
+backtest-violations = violations
+backtest-violation-seconds = in violation
+profile-title = Profile { $name }
+profile-extends = extends
+profile-layers = from
+profile-turbo = turbo
+profile-clock-modulation = clock modulation
+profile-core-clock-modulation = clock modulation per core
+profile-igp-cap = iGPU cap
+profile-turbo-ratios = turbo ratios
+profile-pinned-ratio = pinned ratio
+profile-fan-level = fan level
+turbo-duration-title = Turbo
+turbo-duration-spells = Cut short by a limit
+turbo-duration-median = typically after
+turbo-duration-longest = at most
+turbo-duration-ongoing = Still in turbo after
+audit-entries = entries
+audit-head = last hash
+history-ago = { $ago } ago
+history-mean-watts = Mean
+history-max-watts = Peak
+history-mean-mhz = Mean clock
+history-max-celsius = Hottest core
+history-throttled = Throttled
+battery-draw = System draw
+battery-cpu-share = CPU
+battery-left = Battery left
+battery-left-after-cut = With { $watts } W less
+measure-seconds = Ran for
+measure-mean-mhz = Mean clock
+measure-cpu-energy = CPU energy
+measure-cpu-power = CPU power
+measure-system-energy = System energy
+measure-system-power = System power
+measure-battery-drop = Battery used
+max-freq-title = Maximum frequency
+max-freq-mhz = Held to
+max-freq-ratio = Ratio
+max-freq-bclk = Measured BCLK
+max-freq-mechanism = Through
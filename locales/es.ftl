@@ -0,0 +1,316 @@
+# Spanish. Anything missing here shows up in English.
+
+heading-usage = Uso:
+heading-commands = Comandos
+heading-arguments = Argumentos
+heading-options = Opciones
+
+exit-codes =
+    Códigos de salida:
+      0  éxito
+      1  otro error
+      2  CPU no compatible
+      3  permiso denegado
+      4  registro bloqueado por el firmware
+      5  argumentos no válidos
+      6  módulo msr del kernel no cargado
+
+## Ayuda
+
+about = Lee y ajusta los límites de potencia turbo de las CPU Intel Arrandale
+about-help = Muestra este mensaje o la ayuda del subcomando indicado
+arg-help = Muestra la ayuda
+
+arg-get_tdp = Muestra el límite de TDP turbo
+arg-get_tdc = Muestra el límite de TDC turbo
+arg-get_tjmax = Muestra la temperatura a la que empieza a reducir la frecuencia
+arg-get_turbo_ratios = Muestra los multiplicadores turbo y su equivalencia en MHz
+arg-get_voltage = Muestra el voltaje de núcleo de cada CPU
+arg-get_thermal = Muestra la temperatura y los eventos térmicos de cada núcleo, y los del paquete donde tenga sensor
+cpu-place = CPU{ $cpu } (núcleo { $core }, APIC { $apic })
+cpu-place-package = CPU{ $cpu } (paquete { $package } núcleo { $core }, APIC { $apic })
+thermal-core = Temperatura de núcleo de CPU{ $cpu }
+thermal-package = Temperatura del paquete
+thermal-throttling = limitando
+thermal-log = limitada desde el arranque
+prochot-log = PROCHOT desde el arranque
+thermal-no-package = Esta CPU no tiene sensor de paquete, el núcleo más caliente es lo más parecido
+thermal-package-drives = La lectura del paquete es la que usa el circuito de control térmico
+arg-set_tdp = Ajusta el límite de TDP turbo, en vatios como 25, 25W o 25000mW
+arg-set_tdc = Ajusta el límite de TDC turbo, en amperios como 30, 30A o 30000mA
+arg-set_clock_modulation = Salta ciclos de reloj, un ciclo de trabajo como 50% o en octavos, off lo desactiva
+arg-set_ratio = Limita cada escalón turbo al multiplicador más cercano a esta frecuencia, como 2.66GHz o 2660MHz
+arg-all_cores = Aplica los ajustes en todos los núcleos o paquetes y no solo en CPU0
+arg-monitor = Muestra frecuencia, carga, voltaje y temperatura hasta que se detenga
+arg-interval = Tiempo entre muestras del monitor, como 500 (milisegundos), 500ms o 2s
+arg-temp_source = De dónde toma el monitor las temperaturas, la otra fuente se sigue leyendo para comparar
+arg-thermal_zones = Muestra también las zonas térmicas ACPI y los puntos de disparo que saltan antes de TJmax
+arg-govern = Ejecuta el [governor] del archivo de perfiles mientras monitoriza
+arg-format = Cómo se muestran los registros
+arg-json = Atajo de --format json, también da los errores en JSON
+arg-locale = Separador decimal de la salida legible, "C" fuerza el punto
+arg-color = Cuándo colorear los valores fuera de lo normal
+arg-influx_url = Envía los registros a este endpoint de escritura de Influx en vez de mostrarlos
+arg-schema = Muestra el JSON Schema de la salida --json del comando en vez de ejecutarlo
+arg-allow_vm = Continúa dentro de una máquina virtual, con un aviso
+arg-msr_retries = Reintenta tras un acceso a MSR fallido, 0 se rinde al primero
+arg-msr_retry_delay = Cuánto esperar antes de cada reintento
+arg-no_sandbox = Omite el filtro seccomp de los modos de solo lectura y demonio, para depurar
+arg-read_only = Rechaza toda escritura de MSR, para desplegar solo la parte de monitorización
+arg-force = Escribe límites que las salvaguardas rechazan, como un TDC por debajo del consumo en reposo, o sobre un cambio que nunca terminó, nunca a través del demonio
+arg-direct = Escribe los registros aunque el demonio esté en marcha, en vez de pedírselo
+arg-sudo = Vuelve a ejecutarse con sudo o pkexec cuando una escritura necesita root
+arg-overrides = Fija una clave del archivo de perfiles como profiles.quiet.tdp=18, por encima del archivo y de ARRCTL_SET
+
+about-id = Identifica la CPU, sin necesidad de root
+about-selftest = Comprueba que se pueden leer todos los registros que usa arrctl
+about-features = Lo que admite esta CPU, para interfaces gráficas, --json da el documento completo
+about-dump = Muestra todos los registros que conoce arrctl, para informes de errores y compare
+about-recover = Restaura los registros de un ajuste que se interrumpió
+about-advise = Observa la CPU bajo carga y sugiere límites
+arg-advise-duration = Segundos de observación
+about-doctor = Busca las causas habituales de lentitud y las explica
+about-bench = Carga todas las CPU e indica qué límite actúa
+arg-bench-load = spin, avx, memory o custom:<comando>
+arg-bench-duration = Segundos de carga
+about-soak = Prueba de aceptación: carga durante horas, falla si se calienta o va lenta
+arg-soak-hours = Cuánto tiempo ejecutar, se admiten fracciones
+arg-soak-max_temp = Temperatura máxima permitida en grados Celsius, del núcleo más caliente
+arg-soak-min_sustained_mhz = Frecuencia media mínima permitida en cualquier minuto
+arg-soak-load = spin, avx, memory o custom:<comando>
+about-budget = Reparte la potencia del paquete entre la CPU y la gráfica
+arg-budget-cpu = Parte de la CPU en el paquete, como TDP turbo
+arg-budget-igp_cap = Paso de frecuencia más alto permitido para la gráfica
+arg-budget-save = Guarda también el reparto como este perfil
+about-cores = Desactiva CPU lógicas o las vuelve a activar
+about-cores-set = Mantiene activas esta cantidad de CPU lógicas
+arg-cores-set-count = Un número de CPU o "all"
+about-config = Trabaja con el archivo de perfiles
+about-config-validate = Comprueba un archivo de perfiles sin aplicar nada
+arg-config-validate-file = El archivo a comprobar, por defecto el instalado
+about-profile = Los perfiles del archivo de perfiles
+about-profile-show = Un perfil tal como está escrito, o con --resolved tal como se aplica
+arg-profile-show-name = El perfil
+arg-profile-show-resolved = Con todo lo que extiende incorporado
+arg-profile-show-config = El archivo de perfiles
+about-audit = Trabaja con el registro de auditoría de escrituras
+about-audit-verify = Comprueba la cadena de hashes de cada escritura y que no falte nada al final
+arg-audit-verify-file = El registro a comprobar, por defecto el instalado
+about-compare = Diferencias campo a campo entre dos archivos de `arrctl dump`
+arg-compare-a = El primer volcado
+arg-compare-b = El volcado con el que compararlo
+arg-compare-all = Lista también los campos que coinciden
+about-run = Ejecuta los comandos de un archivo uno tras otro, en un solo proceso
+arg-run-script = El archivo, un get, set, sleep o if por línea
+about-converge = Lleva los ajustes a un estado deseado, escribiendo solo lo que difiere
+arg-converge-config = El estado deseado, con las claves de un perfil
+arg-converge-check = Solo informa de lo que cambiaría
+about-set = Cambia un ajuste, a través del demonio si está en marcha para que se mantenga
+arg-set-setting = Qué ajustar
+arg-set-value = El valor a poner, max-freq toma una frecuencia como 2.4GHz
+arg-set-duration = Lo devuelve tras este tiempo, como 90, 60s o 20m, necesita el demonio
+about-try = Aplica un ajuste que se deshace solo si no se confirma a tiempo
+arg-try-revert_after = Cuánto tiempo hasta deshacerlo, como 90, 60s o 5m
+about-try-set = Aplica el ajuste e inicia la cuenta atrás
+arg-try-set-setting = Qué ajustar
+arg-try-set-value = El valor a poner
+about-try-confirm = Conserva el ajuste que se está probando
+about-try-expire = Espera a la cuenta atrás y lo deshace
+about-init = Hace unas preguntas y escribe un archivo de perfiles inicial
+arg-init-config = Dónde escribir los perfiles
+arg-init-goal = Saltar la pregunta, necesario sin terminal
+arg-init-ac_tdp = TDP con corriente, como 25W
+arg-init-battery_tdp = TDP con batería, como 15W
+arg-init-systemd = Escribir también arrctl.service aquí, como /etc/systemd/system
+arg-init-overwrite = Reemplazar el archivo de perfiles y la unidad si existen
+about-tune = Baja el TDP bajo carga hasta que cae la frecuencia y recomienda el último que aguantó
+arg-tune-min_mhz = Frecuencia sostenida mínima que aún cuenta, como 2400 o 2.4GHz
+arg-tune-load = spin, avx, memory o custom:<comando>
+arg-tune-from = TDP desde el que empezar, el fijado por defecto
+arg-tune-floor = TDP más bajo a probar
+arg-tune-step = Cuánto baja cada paso
+arg-tune-step_duration = Cuánto dura cada TDP, la primera mitad no cuenta
+arg-tune-save = Guardar también el resultado como este perfil
+about-measure = Ejecuta un comando y suma lo que consumieron la CPU y el sistema
+arg-measure-interval = Cada cuánto muestrear, como 250, 250ms o 1s
+arg-measure-command = El comando y sus argumentos, tras --
+about-replay = Reproduce una grabación de `--monitor --format csv`
+arg-replay-file = La grabación
+arg-replay-interval = Con qué intervalo se grabó, en milisegundos
+arg-replay-speed = Cuántas veces más rápido que la grabación reproducirla
+about-policy = Pruebas en seco de un script de [policy], escrito en el pequeño lenguaje de expresiones propio de arrctl
+about-policy-test = Lo que elige el script a lo largo de una grabación de `--monitor --format csv`
+arg-policy-test-script = El script de la política
+arg-policy-test-recording = La grabación con la que probarlo
+arg-policy-test-battery = Como si la grabación se hubiera hecho con batería
+about-policy-backtest = Lo que el script habría fijado durante una sesión grabada, y dónde se superaron sus límites
+arg-policy-backtest-script = El script de política
+arg-policy-backtest-recording = La grabación con la que probarlo
+arg-policy-backtest-interval = Con qué intervalo se grabó, en milisegundos
+arg-policy-backtest-min_interval = Segundos de espera tras una escritura antes de la siguiente, como min_interval_secs en [policy]
+arg-policy-backtest-max_temp = Temperatura máxima permitida en grados Celsius, del núcleo más caliente
+arg-policy-backtest-battery = Como si la grabación se hubiera hecho con batería
+about-events = Sigue lo que ve un daemon en ejecución, --json da un objeto JSON por línea
+arg-events-socket = El socket del daemon
+about-history = Revisa lo que vio un daemon en marcha
+about-history-show = Lo que vio el daemon, con menos detalle cuanto más atrás
+arg-history-show-since = Hasta cuándo, como 90s, 30m u 8h
+arg-history-show-socket = El socket del daemon
+about-daemon = Vigila en segundo plano y sirve el estado por un socket
+arg-daemon-interval = Milisegundos entre muestras
+arg-daemon-socket = Dónde escuchar las peticiones de estado
+arg-daemon-hook = Comando a ejecutar al cambiar la fuente de alimentación y en emergencias
+arg-daemon-listen = Sirve una página de estado en / y los eventos por websocket en /ws, como 127.0.0.1:9110
+arg-daemon-config = El archivo de perfiles
+arg-daemon-apply = Perfil a aplicar al iniciar y de nuevo tras cada reanudación, ARRCTL_PROFILE si no se indica
+arg-daemon-apply_on_battery = Perfil a aplicar en su lugar con batería
+arg-daemon-user = Se ejecuta como este usuario una vez abiertos los ficheros MSR, root se queda como root
+arg-daemon-history = Cuántas horas de muestras guardar para `arrctl history`
+arg-daemon-enforce_every = Comprobar los registros fijados con esta frecuencia y reescribir lo que cambió, como 30s
+arg-daemon-enforce_jitter = Repartir las comprobaciones hasta esto a cada lado
+
+## Estado
+
+tdp-max = TDP turbo máximo
+tdp-stock = TDP de fábrica del { $sku }
+tdp-override = Estado del ajuste manual de TDP turbo
+tdc-max = TDC turbo máximo
+tdc-stock = TDC de fábrica del { $sku }
+tdc-override = Estado del ajuste manual de TDC turbo
+tjmax = TJmax
+unavailable = { $section }: no disponible ({ $reason })
+section-tdp = TDP
+section-tdc = TDC
+section-tjmax = TJmax
+section-turbo-ratios = Ratios de turbo
+section-voltage = Voltaje
+section-thermal = Estado térmico
+turbo-ratio-one = Multiplicador turbo máximo con un núcleo
+turbo-ratio-two = Multiplicador turbo máximo con dos núcleos
+turbo-ratio-three = Multiplicador turbo máximo con tres núcleos
+turbo-ratio-four = Multiplicador turbo máximo con cuatro núcleos
+stock-ratios = Multiplicadores de fábrica del { $sku }
+unit-base = base
+unit-for-one-core = con un núcleo
+unit-for-two-cores = con dos núcleos
+turbo-active-one = Con 1 núcleo activo
+turbo-active-many = Con { $count } núcleos activos
+turbo-fused = de fábrica
+turbo-disabled-bins = El turbo está desactivado, así que ningún paso pasa del multiplicador base
+turbo-physical-cores = Los núcleos activos son físicos, dos hilos ocupados en un mismo núcleo siguen usando el paso de un núcleo
+voltage = Voltaje de CPU{ $cpu }
+
+## Monitor
+
+unit-busy = % ocupada
+unit-battery-share = % de él, estimado
+unit-estimated-watts = W estimados
+unit-estimated-joules = J estimados
+package = Paquete
+summary = { $samples } muestra(s) en { $seconds } s
+summary-peak = pico
+summary-max = máx.
+summary-throttled = limitada
+turbo-disabled = El turbo está desactivado
+estimated-power = La potencia del paquete se estima a partir de la frecuencia y el voltaje, Arrandale no puede medirla.
+estimated-power-rough = Tómala solo como una guía aproximada, puede desviarse varios vatios.
+temp-divergence = CPU{ $cpu } marca { $msr } grados en el MSR pero { $hwmon } en coretemp, una de las dos está mal
+zone-trip = Disparo { $trip } de { $zone } { $type }
+zone-at = a
+zone-before-tjmax = antes de TJmax
+zone-next-trip = siguiente disparo
+zone-to-trip = a
+governor = Regulador
+governor-at = a
+governor-target = objetivo
+governor-adjustments = ajuste(s)
+governor-per-hour = en la última hora
+enforcement-title = Imposición
+enforcement-checks = Comprobaciones
+enforcement-rewrites = Reescritos
+enforcement-reverted = Revertidos por el firmware
+enforcement-last = Última reversión
+unit-registers = registro(s)
+unit-times = vez/veces
+unit-seconds-ago = s atrás
+init-title = Configuración
+init-config = Perfiles
+init-goal = Objetivo
+init-ac = Con corriente
+init-battery = Con batería
+init-unit = Unidad
+init-enable = Arráncala con `systemctl daemon-reload && systemctl enable --now arrctl`
+init-ask-goal = Objetivo principal, battery, quiet o performance
+init-ask-ac = TDP con corriente
+init-ask-battery = TDP con batería
+init-ask-systemd = ¿Escribir una unidad de systemd en { $dir }? y o n
+init-bad-goal = Tiene que ser battery, quiet o performance
+init-bad-yes = Tiene que ser y o n
+tune-title = Ajustado
+tune-tdp = TDP
+tune-mhz = sostenido
+tune-package = paquete
+tune-max-temp = máx.
+tune-recommended = TDP más bajo que aguantó
+tune-holding = a
+tune-load = con carga
+tune-interrupted = Parado antes de tiempo, la recomendación solo cubre los pasos terminados
+tune-floor = Aguantó hasta { $watts } W, baja --floor para seguir
+tune-saved = Guardado como perfil { $profile } en { $path }
+turbo-residency-time = ocupado
+unit-busy-time = % del tiempo ocupado
+unit-of-the-time = % del tiempo
+policy-sample = Muestra { $sample }
+policy-celsius = núcleo más caliente
+policy-clock-modulation = modulación de reloj
+policy-turbo = turbo
+policy-summary = Política
+policy-samples = muestras
+policy-changes = cambios
+policy-skipped = sin temperatura
+backtest-after = Tras { $seconds } s
+backtest-summary = Backtest
+backtest-seconds = grabado
+backtest-writes = escrituras
+backtest-max-celsius = máxima
+backtest-violations = infracciones
+backtest-violation-seconds = en infracción
+profile-title = Perfil { $name }
+profile-extends = extiende
+profile-layers = de
+profile-turbo = turbo
+profile-clock-modulation = modulación de reloj
+profile-core-clock-modulation = modulación de reloj por núcleo
+profile-igp-cap = límite de la iGPU
+profile-turbo-ratios = multiplicadores turbo
+profile-pinned-ratio = multiplicador fijo
+profile-fan-level = nivel del ventilador
+turbo-duration-title = Turbo
+turbo-duration-spells = Cortado por un límite
+turbo-duration-median = normalmente tras
+turbo-duration-longest = como mucho
+turbo-duration-ongoing = Aún en turbo tras
+audit-entries = entradas
+audit-head = último hash
+history-ago = hace { $ago }
+history-mean-watts = Media
+history-max-watts = Pico
+history-mean-mhz = Reloj medio
+history-max-celsius = Núcleo más caliente
+history-throttled = Limitado
+battery-draw = Consumo del sistema
+battery-cpu-share = CPU
+battery-left = Batería restante
+battery-left-after-cut = Con { $watts } W menos
+measure-seconds = Duró
+measure-mean-mhz = Reloj medio
+measure-cpu-energy = Energía de la CPU
+measure-cpu-power = Potencia de la CPU
+measure-system-energy = Energía del sistema
+measure-system-power = Potencia del sistema
+measure-battery-drop = Batería gastada
+max-freq-title = Frecuencia máxima
+max-freq-mhz = Limitada a
+max-freq-ratio = Multiplicador
+max-freq-bclk = BCLK medido
+max-freq-mechanism = Mediante
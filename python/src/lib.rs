@@ -0,0 +1,235 @@
+// The arrctl Python module:
+//
+//   import arrctl
+//
+//   with arrctl.Cpu() as cpu:
+//       print(cpu.limits())
+//       cpu.set_tdp(18)
+//       for sample in cpu.monitor().samples(interval=1.0):
+//           print(sample.package_watts)
+//
+// The same calls as the C API in ffi/, without going through C. Errors
+// raise ArrctlError, whose code is the exit code the CLI would have used.
+
+use arrctl::error::{self, Error, ErrorKind};
+use arrctl::monitor::{self, Sampler};
+use arrctl_ffi::Arrctl;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+create_exception!(arrctl, ArrctlError, PyException);
+
+fn raise(err: anyhow::Error) -> PyErr {
+    Python::with_gil(|py| {
+        let exc = ArrctlError::new_err(format!("{:#}", err));
+        // An attribute, like OSError's errno
+        let _ = exc.value_bound(py).setattr("code", error::kind_of(&err).exit_code());
+        exc
+    })
+}
+
+// Watts or amps in the thousandths the library takes, negative ones end up
+// as 0 and get refused there
+fn milli(value: f64) -> u32 {
+    (value * 1000.0).round() as u32
+}
+
+#[pyclass(frozen, get_all, module = "arrctl")]
+#[derive(Clone)]
+struct Limits {
+    tdp_watts: f64,
+    tdc_amps: f64,
+    tdp_override: bool,
+    tdc_override: bool,
+    turbo: bool,
+    tjmax_celsius: u32,
+}
+
+#[pymethods]
+impl Limits {
+    fn __repr__(&self) -> String {
+        format!(
+            "Limits(tdp_watts={}, tdc_amps={}, tdp_override={}, tdc_override={}, turbo={}, tjmax_celsius={})",
+            self.tdp_watts,
+            self.tdc_amps,
+            bool_repr(self.tdp_override),
+            bool_repr(self.tdc_override),
+            bool_repr(self.turbo),
+            self.tjmax_celsius
+        )
+    }
+}
+
+fn bool_repr(value: bool) -> &'static str {
+    if value { "True" } else { "False" }
+}
+
+#[pyclass(frozen, get_all, module = "arrctl")]
+#[derive(Clone)]
+struct CpuSample {
+    cpu: u16,
+    effective_mhz: f32,
+    // Fraction of the interval spent awake
+    busy: f32,
+    volts: f32,
+    // None without a valid reading
+    celsius: Option<u64>,
+    throttling: bool,
+}
+
+#[pyclass(frozen, get_all, module = "arrctl")]
+#[derive(Clone)]
+struct Sample {
+    cpus: Vec<CpuSample>,
+    // Estimated, Arrandale can't measure it
+    package_watts: f32,
+}
+
+impl From<&monitor::Sample> for Sample {
+    fn from(sample: &monitor::Sample) -> Self {
+        let cpus = sample
+            .cpus
+            .iter()
+            .map(|cpu| CpuSample {
+                cpu: cpu.cpu,
+                effective_mhz: cpu.activity.effective_mhz,
+                busy: cpu.activity.active,
+                volts: cpu.activity.volts,
+                celsius: cpu.celsius,
+                throttling: cpu.throttling,
+            })
+            .collect();
+        Sample { cpus, package_watts: sample.package_watts }
+    }
+}
+
+// The CPU's MSRs, or a recorded `arrctl dump` when dump is given. Writes to
+// a dump only stay in memory.
+#[pyclass(unsendable, module = "arrctl")]
+struct Cpu {
+    // None once closed
+    arrctl: Option<Arrctl>,
+}
+
+impl Cpu {
+    fn handle(&self) -> PyResult<&Arrctl> {
+        self.arrctl.as_ref().ok_or_else(|| raise(Error::new(ErrorKind::Validation, "The Cpu was closed").into()))
+    }
+}
+
+#[pymethods]
+impl Cpu {
+    #[new]
+    #[pyo3(signature = (dump = None))]
+    fn new(dump: Option<PathBuf>) -> PyResult<Self> {
+        let arrctl = match dump {
+            Some(path) => Arrctl::open_dump(&path),
+            None => Arrctl::open(),
+        };
+        Ok(Cpu { arrctl: Some(arrctl.map_err(raise)?) })
+    }
+
+    fn close(&mut self) {
+        self.arrctl = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_exc))]
+    fn __exit__(&mut self, _exc: &Bound<'_, PyTuple>) {
+        self.close();
+    }
+
+    fn limits(&self) -> PyResult<Limits> {
+        let limits = self.handle()?.limits().map_err(raise)?;
+        Ok(Limits {
+            tdp_watts: limits.tdp_mw as f64 / 1000.0,
+            tdc_amps: limits.tdc_ma as f64 / 1000.0,
+            tdp_override: limits.tdp_override,
+            tdc_override: limits.tdc_override,
+            turbo: limits.turbo,
+            tjmax_celsius: limits.tjmax_celsius,
+        })
+    }
+
+    // Both round to the 1/8 steps the register holds
+    fn set_tdp(&self, watts: f64) -> PyResult<()> {
+        self.handle()?.set_tdp_mw(milli(watts)).map_err(raise)
+    }
+
+    fn set_tdc(&self, amps: f64) -> PyResult<()> {
+        self.handle()?.set_tdc_ma(milli(amps)).map_err(raise)
+    }
+
+    fn set_turbo(&self, enabled: bool) -> PyResult<()> {
+        self.handle()?.set_turbo(enabled).map_err(raise)
+    }
+
+    fn monitor(slf: &Bound<'_, Self>) -> PyResult<Monitor> {
+        let sampler = Sampler::new(slf.borrow().handle()?.msr()).map_err(raise)?;
+        Ok(Monitor { cpu: slf.clone().unbind(), sampler })
+    }
+}
+
+// Activity averaged between calls to sample(), starting when it was made
+#[pyclass(unsendable, module = "arrctl")]
+struct Monitor {
+    cpu: Py<Cpu>,
+    sampler: Sampler,
+}
+
+#[pymethods]
+impl Monitor {
+    fn sample(&mut self, py: Python<'_>) -> PyResult<Sample> {
+        let cpu = self.cpu.borrow(py);
+        let sample = self.sampler.sample(cpu.handle()?.msr()).map_err(raise)?;
+        Ok(Sample::from(&sample))
+    }
+
+    // One every interval seconds, for as long as it's iterated
+    #[pyo3(signature = (interval = 1.0))]
+    fn samples(slf: Py<Self>, interval: f64) -> PyResult<Samples> {
+        let interval = Duration::try_from_secs_f64(interval).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Samples { monitor: slf, interval })
+    }
+}
+
+#[pyclass(unsendable, module = "arrctl")]
+struct Samples {
+    monitor: Py<Monitor>,
+    interval: Duration,
+}
+
+#[pymethods]
+impl Samples {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Sample> {
+        let interval = self.interval;
+        // Other Python threads run meanwhile, and Ctrl-C still stops it
+        py.allow_threads(|| thread::sleep(interval));
+        py.check_signals()?;
+        self.monitor.borrow_mut(py).sample(py)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "arrctl")]
+fn arrctl_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Cpu>()?;
+    m.add_class::<Limits>()?;
+    m.add_class::<Monitor>()?;
+    m.add_class::<Sample>()?;
+    m.add_class::<CpuSample>()?;
+    m.add("ArrctlError", m.py().get_type_bound::<ArrctlError>())?;
+    Ok(())
+}
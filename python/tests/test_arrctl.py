@@ -0,0 +1,35 @@
+# Run after `maturin develop` in python/, from the repository root:
+#   python3 -m unittest discover python/tests
+import os
+import unittest
+
+import arrctl
+
+FIXTURE = os.path.join(os.path.dirname(__file__), "..", "..", "tests", "fixtures", "i5-520m.dump")
+
+
+class CpuTest(unittest.TestCase):
+    def test_limits_and_sets(self):
+        with arrctl.Cpu(dump=FIXTURE) as cpu:
+            limits = cpu.limits()
+            self.assertEqual((limits.tdp_watts, limits.tdc_amps, limits.tjmax_celsius), (35, 48, 105))
+            self.assertTrue(limits.turbo)
+
+            cpu.set_tdp(17.6)
+            cpu.set_turbo(False)
+            limits = cpu.limits()
+            self.assertEqual((limits.tdp_watts, limits.tdp_override, limits.turbo), (17.625, True, False))
+
+            with self.assertRaises(arrctl.ArrctlError) as err:
+                cpu.set_tdc(0)
+            self.assertEqual(err.exception.code, 5)
+
+    def test_monitor(self):
+        with arrctl.Cpu(dump=FIXTURE) as cpu:
+            sample = cpu.monitor().sample()
+            self.assertEqual([c.cpu for c in sample.cpus], [0, 1, 2, 3])
+            self.assertEqual(sorted({c.celsius for c in sample.cpus}), [47, 50])
+
+
+if __name__ == "__main__":
+    unittest.main()
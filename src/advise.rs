@@ -0,0 +1,307 @@
+use crate::cpu;
+use crate::monitor::{ExternalThrottle, Sample, Sampler};
+use crate::msr::MsrAccess;
+use crate::output::{OutputSink, Record, Value};
+use crate::progress::Progress;
+use crate::quantity::Ratio;
+use crate::regs::*;
+use crate::signals;
+use crate::sku::{self, Sku};
+use crate::turbo::Residency;
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+// What the advisor bases its suggestions on, gathered by observe()
+#[derive(Clone, Debug, Default)]
+pub struct Observation {
+    pub seconds: f32,
+    pub tdp_watts: f32,
+    pub tdc_amps: f32,
+    pub tjmax: u64,
+    // Averages over the window of the busiest CPU
+    pub sustained_mhz: f32,
+    pub busy: f32,
+    pub max_turbo_mhz: f32,
+    pub avg_package_watts: f32,
+    pub max_package_watts: f32,
+    pub max_celsius: Option<u64>,
+    // Thermal or PROCHOT log bits were set on any core
+    pub throttled: bool,
+    // Stuck at the lowest ratio with no limit of its own to blame
+    pub externally_throttled: bool,
+    pub peak: PeakDraw,
+    // Per logical CPU, in the order the sampler has them
+    pub cpus: Vec<CpuObservation>,
+    pub turbo: Residency,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CpuObservation {
+    pub cpu: u16,
+    // Average effective frequency while busy
+    pub sustained_mhz: f32,
+    pub max_celsius: Option<u64>,
+    // Seconds into the window it first throttled
+    pub throttled_at: Option<f32>,
+}
+
+// Highest core plane current seen, for the TDC suggestion
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PeakDraw {
+    pub amps: f32,
+    pub amps_at_tjmax: f32,
+    pub volts: f32,
+}
+
+impl PeakDraw {
+    pub fn update(&mut self, sample: &Sample) {
+        if sample.core_amps > self.amps {
+            self.amps = sample.core_amps;
+            self.volts = sample.max_volts();
+        }
+        self.amps_at_tjmax = self.amps_at_tjmax.max(sample.core_amps_at_tjmax);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Advice {
+    pub text: String,
+    pub suggested_tdp: Option<u64>,
+    pub suggested_tdc: Option<u64>,
+    pub gain_mhz: Option<u64>,
+}
+
+impl Advice {
+    fn note(text: impl Into<String>) -> Self {
+        Advice { text: text.into(), suggested_tdp: None, suggested_tdc: None, gain_mhz: None }
+    }
+}
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+// Below this the busiest CPU was mostly idle and limits don't matter
+const IDLE_BUSY: f32 = 0.5;
+
+pub fn observe(msr: &dyn MsrAccess, duration: Duration) -> Result<Observation> {
+    let limits = msr_turbo_limits(msr)?;
+    let ratios = msr_turbo_ratios(msr)?;
+    let mut obs = Observation {
+        tdp_watts: limits.tdp_watts().0 as f32,
+        tdc_amps: limits.tdc_amps().0 as f32,
+        tjmax: msr_temperature_target(msr)?.get(),
+        max_turbo_mhz: Ratio(ratios.one_core()).max(msr_platform_info(msr)?.base()).mhz(),
+        turbo: Residency::for_cpu(msr)?,
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let mut progress = Progress::new("Watching", duration.as_millis() as u64);
+    let mut sampler = Sampler::new(msr)?;
+    let mut external = ExternalThrottle::new(msr)?;
+    let mut samples = 0;
+    while start.elapsed() < duration {
+        // Cut short by a signal, advise on what was seen so far
+        if !signals::sleep(SAMPLE_INTERVAL) {
+            break;
+        }
+        progress.set(start.elapsed().as_millis() as u64);
+        let sample = sampler.sample(msr)?;
+        samples += 1;
+
+        let busiest = sample.cpus.iter().map(|c| c.activity.effective_mhz * c.activity.active).fold(0.0, f32::max);
+        obs.sustained_mhz += busiest;
+        obs.busy += sample.cpus.iter().map(|c| c.activity.active).fold(0.0, f32::max);
+        obs.avg_package_watts += sample.package_watts;
+        obs.max_package_watts = obs.max_package_watts.max(sample.package_watts);
+        obs.peak.update(&sample);
+        obs.turbo.update(&sample, SAMPLE_INTERVAL.as_secs_f64());
+        obs.externally_throttled |= external.update(&sample, start.elapsed());
+        for temp in sample.cpus.iter().filter_map(|c| c.celsius) {
+            obs.max_celsius = Some(obs.max_celsius.map_or(temp, |max| max.max(temp)));
+        }
+        for c in &sample.cpus {
+            let index = match obs.cpus.iter().position(|o| o.cpu == c.cpu) {
+                Some(index) => index,
+                None => {
+                    obs.cpus.push(CpuObservation { cpu: c.cpu, ..Default::default() });
+                    obs.cpus.len() - 1
+                }
+            };
+            let cpu = &mut obs.cpus[index];
+            cpu.sustained_mhz += c.activity.effective_mhz * c.activity.active;
+            cpu.max_celsius = cpu.max_celsius.max(c.celsius);
+            if c.throttling && cpu.throttled_at.is_none() {
+                cpu.throttled_at = Some(start.elapsed().as_secs_f32());
+            }
+        }
+    }
+    let samples = samples.max(1) as f32;
+    for cpu in &mut obs.cpus {
+        cpu.sustained_mhz /= samples;
+    }
+    obs.sustained_mhz /= samples;
+    obs.busy /= samples;
+    obs.avg_package_watts /= samples;
+    obs.seconds = start.elapsed().as_secs_f32();
+
+    for core in msr.cpus() {
+        let therm = ia32_therm_status(msr, core)?;
+        obs.throttled |= therm.thermal_log() || therm.prochot_log();
+    }
+    Ok(obs)
+}
+
+fn round_to(value: f32, step: f32) -> u64 {
+    ((value / step).round() * step) as u64
+}
+
+fn tdp_advice(obs: &Observation, stock: Option<&Sku>) -> Vec<Advice> {
+    let mut advice = Vec::new();
+    let headroom = obs.max_celsius.map(|temp| obs.tjmax.saturating_sub(temp));
+
+    if obs.busy < IDLE_BUSY {
+        advice.push(Advice::note(format!(
+            "CPU was only {:.0}% busy, run advise while the machine is under load to see where it is limited",
+            obs.busy * 100.0
+        )));
+        return advice;
+    }
+
+    if obs.throttled || headroom.is_some_and(|h| h < 5) {
+        let mut text = format!(
+            "Thermal limited: reached {} celsius against a TJmax of {}, raising TDP won't gain anything until cooling improves",
+            obs.max_celsius.map_or("?".to_string(), |t| t.to_string()),
+            obs.tjmax
+        );
+        let mut suggested_tdp = None;
+        if let Some(sku) = stock.filter(|sku| obs.tdp_watts > sku.tdp as f32) {
+            text += &format!("; consider going back to the stock {} W TDP", sku.tdp);
+            suggested_tdp = Some(sku.tdp as u64);
+        }
+        advice.push(Advice { suggested_tdp, ..Advice::note(text) });
+        return advice;
+    }
+
+    let turbo_left = obs.max_turbo_mhz - obs.sustained_mhz;
+    if turbo_left < cpu::BCLK_MHZ {
+        advice.push(Advice::note("Already sustaining the top turbo bin, raising limits has nothing left to gain"));
+        return advice;
+    }
+
+    if obs.max_package_watts >= obs.tdp_watts * 0.9 {
+        // More thermal headroom, bigger step
+        let step = match headroom {
+            Some(h) if h >= 25 => 5.0,
+            Some(h) if h >= 15 => 3.0,
+            _ => 2.0,
+        };
+        let mut target = obs.tdp_watts + step;
+        if let Some(sku) = stock {
+            // Stay inside what set would warn about
+            let ceiling = (sku.tdp as f32 * (1.0 + sku::SPEC_MARGIN_ABOVE)).floor();
+            target = target.min(ceiling);
+        }
+        if target > obs.tdp_watts {
+            // With voltage rising alongside frequency, power goes roughly
+            // with the square of frequency near the operating point
+            let gain = obs.sustained_mhz * (target - obs.tdp_watts) / (2.0 * obs.tdp_watts);
+            let gain = round_to(gain.min(turbo_left), 50.0);
+            advice.push(Advice {
+                text: format!(
+                    "TDP limited before thermal limit; raising TDP to {} W likely gains ~{} MHz sustained",
+                    target as u64, gain
+                ),
+                suggested_tdp: Some(target as u64),
+                suggested_tdc: None,
+                gain_mhz: Some(gain),
+            });
+        } else {
+            advice.push(Advice::note(format!(
+                "TDP limited, but {} W is already as far above stock as is reasonable",
+                obs.tdp_watts
+            )));
+        }
+        return advice;
+    }
+
+    advice.push(Advice::note(format!(
+        "Neither TDP ({:.1} of {} W estimated) nor temperature is limiting, turbo is held back by TDC or the workload",
+        obs.max_package_watts, obs.tdp_watts
+    )));
+    advice
+}
+
+// Needed current is the hot figure, since leakage only grows as the die
+// heats up under a sustained load, plus some margin for VID steps
+const TDC_MARGIN: f32 = 1.1;
+
+pub fn tdc_advice(peak: &PeakDraw, tdc_amps: f32, stock: Option<&Sku>) -> Option<Advice> {
+    if peak.amps <= 0.0 {
+        return None;
+    }
+    let needed = (peak.amps_at_tjmax * TDC_MARGIN).ceil() as u64;
+    let mut text = format!(
+        "Peak core draw was ~{:.1} A at {:.3} V, ~{:.1} A with the die at TJmax; a TDC of {} A covers it",
+        peak.amps, peak.volts, peak.amps_at_tjmax, needed
+    );
+    if let Some(sku) = stock.filter(|sku| needed > sku.tdc as u64) {
+        text += &format!(", which is above the rated {} A of {}, so keep the stock value", sku.tdc, sku.name);
+        return Some(Advice { suggested_tdc: Some(sku.tdc as u64), ..Advice::note(text) });
+    }
+    if needed as f32 > tdc_amps {
+        text += &format!(" (currently {} A, which limits turbo)", tdc_amps);
+    } else {
+        text += &format!(" (currently {} A)", tdc_amps);
+    }
+    Some(Advice { suggested_tdc: Some(needed), ..Advice::note(text) })
+}
+
+pub fn advise(obs: &Observation, stock: Option<&Sku>) -> Vec<Advice> {
+    let mut advice = tdp_advice(obs, stock);
+    if obs.busy >= IDLE_BUSY {
+        advice.extend(tdc_advice(&obs.peak, obs.tdc_amps, stock));
+    }
+    advice
+}
+
+pub fn advice_record(advice: Advice) -> Record {
+    let mut record = Record::new("advice").field("text", "Advice", advice.text, "");
+    if let Some(tdp) = advice.suggested_tdp {
+        record = record.hidden("suggested_tdp", tdp);
+    }
+    if let Some(tdc) = advice.suggested_tdc {
+        record = record.hidden("suggested_tdc", tdc);
+    }
+    if let Some(gain) = advice.gain_mhz {
+        record = record.hidden("gain_mhz", gain);
+    }
+    record
+}
+
+pub fn observed_record(obs: &Observation) -> Record {
+    let mut record = Record::new("observed")
+        .title(format!("Observed over {:.0} s", obs.seconds))
+        .field("sustained_mhz", "", Value::Fixed(obs.sustained_mhz as f64, 0), "MHz sustained")
+        .field("busy_percent", "", Value::Fixed(obs.busy as f64 * 100.0, 0), "% busy")
+        .field("max_package_watts", "up to", Value::Fixed(obs.max_package_watts as f64, 1), "estimated W")
+        .field("tdp_watts", "of", Value::Float(obs.tdp_watts as f64), "W TDP");
+    if let Some(temp) = obs.max_celsius {
+        record = record.field("max_celsius", "max", temp, "celsius");
+    }
+    record
+        .field("throttled", "throttled", obs.throttled, "")
+        .hidden("externally_throttled", obs.externally_throttled)
+}
+
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess, duration: Duration) -> Result<()> {
+    let stock = sku::detect();
+    out.note(&format!("Watching for {} seconds, keep the machine under its usual load...", duration.as_secs()))?;
+    signals::install()?;
+    let obs = observe(msr, duration)?;
+    out.record(&observed_record(&obs))?;
+
+    for advice in advise(&obs, stock) {
+        out.record(&advice_record(advice))?;
+    }
+    Ok(())
+}
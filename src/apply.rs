@@ -0,0 +1,269 @@
+use crate::cooling;
+use crate::cores;
+use crate::freq;
+use crate::log;
+use crate::power;
+use crate::cpu::{self, CpuInfo};
+use crate::error::{Error, ErrorKind};
+use crate::igp::{self, Gt};
+use crate::journal::Journal;
+use crate::msr::{MsrAccess, RegSpec};
+use crate::profile::Profile;
+use crate::quantity::{Amps, Watts};
+use crate::regs::{self, *};
+use crate::sku::{self, Sku};
+#[cfg(feature = "thinkpad")]
+use crate::thinkpad;
+use anyhow::{bail, Result};
+use std::path::Path;
+
+pub fn validate(profile: &Profile, plat_info: &MsrPlatformInfo) -> Result<()> {
+    if (profile.tdp.is_some() || profile.tdc.is_some()) && !plat_info.programmable_tdc_tdp() {
+        bail!(Error::new(ErrorKind::RegisterLocked, "CPU doesn't support setting TDP and TDC").register(MSR_TURBO_LIMITS, 0));
+    }
+    if profile.turbo_ratios.is_some() && !plat_info.programmable_turbo_ratio() {
+        bail!(Error::new(ErrorKind::RegisterLocked, "CPU doesn't support setting turbo ratios").register(MSR_TURBO_RATIOS, 0));
+    }
+    if profile.fan_level.is_some() && !cfg!(feature = "thinkpad") {
+        bail!(Error::new(ErrorKind::Validation, "fan_level needs arrctl built with the thinkpad feature"));
+    }
+    if let Some(problem) = profile.problems().into_iter().next() {
+        bail!(Error::new(ErrorKind::Validation, problem.message));
+    }
+    Ok(())
+}
+
+// About what Arrandale runs its lowest ratio at, where VID isn't readable
+const FLOOR_VOLTS: f32 = 0.8;
+
+// A TDC under what the cores draw at the lowest ratio holds them below it,
+// which is slow enough to be unusable until a reboot puts the limit back
+pub fn check_tdc_floor(msr: &dyn MsrAccess, profile: &Profile, stock: Option<&Sku>) -> Result<()> {
+    let Some(tdc) = profile.tdc else {
+        return Ok(());
+    };
+    let mhz = msr_platform_info(msr)?.minimum_ratio().max(1) as f32 * cpu::BCLK_MHZ;
+    // Idle cores sit at the lowest ratio, so the lowest VID reading is about
+    // its voltage
+    let volts = msr
+        .cpus()
+        .iter()
+        .filter_map(|&cpu| msr.read(IA32_PERF_STATUS, cpu).ok())
+        .map(|val| Ia32PerfStatus(val).volts())
+        .filter(|&volts| volts > 0.0)
+        .reduce(f32::min)
+        .unwrap_or(FLOOR_VOLTS);
+    let coeffs = stock.map_or(&power::DEFAULT_COEFFICIENTS, |sku| &sku.power);
+    let floor = power::floor_amps(coeffs, msr.cpus().len(), cpu::threads_per_core(), mhz, volts);
+    if (tdc as f32) < floor {
+        bail!(Error::new(ErrorKind::Validation, format!(
+            "A TDC of {} A is under the {:.0} A the cores draw at their lowest {:.0} MHz and {:.2} V, they couldn't run at all. Pass --force to set it anyway.",
+            tdc, floor.ceil(), mhz, volts
+        )).register(MSR_TURBO_LIMITS, 0));
+    }
+    Ok(())
+}
+
+pub fn warn_outside_spec(profile: &Profile, stock: Option<&Sku>) {
+    let Some(sku) = stock else {
+        return;
+    };
+    if let Some(tdp) = profile.tdp.filter(|&tdp| sku::far_outside_spec(sku.tdp, tdp)) {
+        log!("<4>Warning: {} W is far from the stock TDP of {} W for {}", tdp, sku.tdp, sku.name);
+    }
+    if let Some(tdc) = profile.tdc.filter(|&tdc| sku::far_outside_spec(sku.tdc, tdc)) {
+        log!("<4>Warning: {} A is far from the stock TDC of {} A for {}", tdc, sku.tdc, sku.name);
+    }
+}
+
+// A pinned ratio only holds under the userspace governor, the rest put
+// their own back within a few milliseconds
+pub fn warn_governor(msr: &dyn MsrAccess, profile: &Profile, sysfs: &Path) {
+    if profile.pinned_ratio.is_none() {
+        return;
+    }
+    if let Some((cpu, governor)) = freq::stepping_governors(sysfs, &msr.cpus()).first() {
+        log!(
+            "<4>Warning: CPU{} runs the {} cpufreq governor, which replaces the pinned ratio on its next step. \
+             It only holds with the userspace governor, `cpupower frequency-set -g userspace` switches to it.",
+            cpu, governor
+        );
+    }
+}
+
+// Against what recorded sessions say the chassis keeps up with. It reads
+// every recording, so it's for sets made by hand and not the daemon's.
+pub fn warn_cooling(profile: &Profile, tjmax: u64, history: &Path) {
+    let Some(tdp) = profile.tdp else {
+        return;
+    };
+    match cooling::load(history, tjmax) {
+        Ok(points) => {
+            if let Some(text) = cooling::sustainable_watts(&points, tjmax).and_then(|watts| cooling::warning(tdp, watts)) {
+                eprintln!("Warning: {}", text);
+            }
+        }
+        Err(e) => eprintln!("Warning: couldn't read the cooling history: {:#}", e),
+    }
+}
+
+// Only the Extreme Edition parts go above what's fused, on the rest the
+// programmable ratios only lower the bins. Arrandale has two cores, the
+// three and four core bins can't be above the two core one.
+pub fn check_fused(profile: &Profile, stock: Option<&Sku>) -> Result<()> {
+    let (Some(ratios), Some(sku)) = (&profile.turbo_ratios, stock) else {
+        return Ok(());
+    };
+    for (index, &ratio) in ratios.iter().enumerate() {
+        let fused = sku.turbo_ratios[index.min(1)] as u64;
+        if ratio > fused {
+            bail!(Error::new(ErrorKind::Validation, format!(
+                "A ratio of {} with {} core(s) active is above the fused {} of the {}",
+                ratio, index + 1, fused, sku.name
+            )).register(MSR_TURBO_RATIOS, 0));
+        }
+    }
+    Ok(())
+}
+
+// After writing: the CPU quietly clamps ratios it won't take, so a profile
+// saved on another machine or before a BIOS update shows up here
+pub fn verify_ratios(msr: &dyn MsrAccess, profile: &Profile, cpus: &[u16]) -> Result<()> {
+    let Some(ratios) = &profile.turbo_ratios else {
+        return Ok(());
+    };
+    for &cpu in cpus {
+        let now = MsrTurboRatios(msr.read(MSR_TURBO_RATIOS, cpu)?);
+        if let Some((index, &ratio)) = ratios.iter().enumerate().find(|&(index, &ratio)| now.bin(index) != ratio) {
+            bail!(Error::new(ErrorKind::Failure, format!(
+                "Asked for a ratio of {} with {} core(s) active but the CPU kept {}, it's likely above what this part is fused for",
+                ratio, index + 1, now.bin(index)
+            )).register(MSR_TURBO_RATIOS, cpu));
+        }
+    }
+    Ok(())
+}
+
+// CPUs a register gets written on, one per core or package it is shared by
+// with --all-cores and just CPU0 otherwise
+pub fn targets(layout: &[CpuInfo], all_cores: bool, reg: u32) -> Vec<u16> {
+    match all_cores {
+        true => cpu::representatives(layout, regs::scope(reg)),
+        false => vec![0],
+    }
+}
+
+pub fn register_writes(msr: &dyn MsrAccess, profile: &Profile, layout: &[CpuInfo], all_cores: bool) -> Result<Vec<(RegSpec, u64)>> {
+    let mut writes = Vec::new();
+
+    if profile.tdp.is_some() || profile.tdc.is_some() {
+        for cpu in targets(layout, all_cores, MSR_TURBO_LIMITS) {
+            let mut turbo_limits = MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, cpu)?);
+            if let Some(tdp) = profile.tdp {
+                turbo_limits.set_tdp_watts(Watts::from(tdp))?;
+                turbo_limits.set_tdp_override(true);
+            }
+            if let Some(tdc) = profile.tdc {
+                turbo_limits.set_tdc_amps(Amps::from(tdc))?;
+                turbo_limits.set_tdc_override(true);
+            }
+            writes.push((RegSpec { reg: MSR_TURBO_LIMITS, cpu }, turbo_limits.0));
+        }
+    }
+
+    if let Some(turbo) = profile.turbo {
+        for cpu in targets(layout, all_cores, IA32_MISC_ENABLE) {
+            let mut misc = Ia32MiscEnable(msr.read(IA32_MISC_ENABLE, cpu)?);
+            misc.set_turbo_disable(!turbo);
+            writes.push((RegSpec { reg: IA32_MISC_ENABLE, cpu }, misc.0));
+        }
+    }
+
+    if let Some(ratios) = &profile.turbo_ratios {
+        for cpu in targets(layout, all_cores, MSR_TURBO_RATIOS) {
+            let mut turbo_ratios = MsrTurboRatios(msr.read(MSR_TURBO_RATIOS, cpu)?);
+            for (index, &ratio) in ratios.iter().enumerate() {
+                turbo_ratios.set_bin(index, ratio);
+            }
+            writes.push((RegSpec { reg: MSR_TURBO_RATIOS, cpu }, turbo_ratios.0));
+        }
+    }
+
+    if let Some(duty) = profile.clock_modulation {
+        for cpu in targets(layout, all_cores, IA32_CLOCK_MODULATION) {
+            let mut modulation = Ia32ClockModulation(msr.read(IA32_CLOCK_MODULATION, cpu)?);
+            modulation.set_enable(duty != 0);
+            modulation.set_duty_cycle(duty);
+            writes.push((RegSpec { reg: IA32_CLOCK_MODULATION, cpu }, modulation.0));
+        }
+    }
+
+    // Every thread regardless of --all-cores, one left alone would pull its
+    // core back up
+    if let Some(ratio) = profile.pinned_ratio {
+        for info in layout {
+            let mut perf_ctl = Ia32PerfCtl(msr.read(IA32_PERF_CTL, info.cpu)?);
+            perf_ctl.set_ratio(ratio);
+            writes.push((RegSpec { reg: IA32_PERF_CTL, cpu: info.cpu }, perf_ctl.0));
+        }
+    }
+
+    // Per core regardless of --all-cores, on every thread since the core
+    // runs at the faster of what its threads ask for
+    if let Some(duties) = &profile.core_clock_modulation {
+        let cores = cpu::cores(layout);
+        if duties.len() > cores.len() {
+            bail!(Error::new(ErrorKind::Validation, format!(
+                "core_clock_modulation has {} duty cycles but this CPU has {} core(s)",
+                duties.len(), cores.len()
+            )));
+        }
+        for (&duty, cpus) in duties.iter().zip(&cores) {
+            for &cpu in cpus {
+                let mut modulation = Ia32ClockModulation(msr.read(IA32_CLOCK_MODULATION, cpu)?);
+                modulation.set_enable(duty != 0);
+                modulation.set_duty_cycle(duty);
+                writes.push((RegSpec { reg: IA32_CLOCK_MODULATION, cpu }, modulation.0));
+            }
+        }
+    }
+    Ok(writes)
+}
+
+// Registers go through the journal, the IGP cap and fan level are plain
+// files and come last so a locked register doesn't leave them set alone
+pub fn apply(msr: &dyn MsrAccess, journal: &Journal, profile: &Profile, all_cores: bool) -> Result<()> {
+    apply_with(msr, journal, profile, all_cores, false)
+}
+
+// With force past the guard rails, for --force
+pub fn apply_with(msr: &dyn MsrAccess, journal: &Journal, profile: &Profile, all_cores: bool, force: bool) -> Result<()> {
+    let stock = sku::detect();
+    validate(profile, &msr_platform_info(msr)?)?;
+    check_fused(profile, stock)?;
+    if !force {
+        check_tdc_floor(msr, profile, stock)?;
+    }
+    warn_outside_spec(profile, stock);
+    warn_governor(msr, profile, Path::new(cores::SYSFS));
+
+    // Find the GT before touching anything so a missing driver fails early
+    let gt = profile.igp_cap.map(|_| Gt::find(Path::new(igp::DRM))).transpose()?;
+    #[cfg(feature = "thinkpad")]
+    let fan = profile.fan_level.map(|_| thinkpad::Fan::find(Path::new(thinkpad::FAN))).transpose()?;
+
+    let layout = cpu::layout();
+    let writes = register_writes(msr, profile, &layout, all_cores)?;
+    if !writes.is_empty() {
+        journal.apply(msr, &writes)?;
+    }
+    verify_ratios(msr, profile, &targets(&layout, all_cores, MSR_TURBO_RATIOS))?;
+    if let (Some(gt), Some(cap)) = (gt, profile.igp_cap) {
+        gt.set_cap(cap)?;
+    }
+    #[cfg(feature = "thinkpad")]
+    if let (Some(fan), Some(level)) = (fan, profile.fan_level) {
+        fan.set_level(level)?;
+    }
+    Ok(())
+}
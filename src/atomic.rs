@@ -0,0 +1,76 @@
+use crate::audit;
+use crate::error::{Error, ErrorKind};
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Where a file sits while it's written, next to it so the rename stays on
+// one filesystem. Each write gets its own, the CLI and the daemon or two
+// threads writing the same file would otherwise rename each other's half
+// written one into place.
+pub fn tmp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}.{}.tmp", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+    PathBuf::from(name)
+}
+
+// All or nothing: a power cut leaves either the old file or the new one,
+// never half of it. The fsyncs are what make that hold with ext4's delayed
+// allocation, the rename alone can hit the disk before the data does.
+pub fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let tmp = tmp_path(path);
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&tmp).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    let written = file
+        .write_all(contents)
+        .with_context(|| format!("Failed to write {}", tmp.display()))
+        .and_then(|()| file.sync_all().with_context(|| format!("Failed to flush {}", tmp.display())))
+        .and_then(|()| fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display())));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written?;
+    // The rename itself lives in the directory
+    File::open(dir).and_then(|dir| dir.sync_all()).with_context(|| format!("Failed to flush {}", dir.display()))
+}
+
+const CHECKSUM: &str = "checksum";
+
+// serde_json keeps object keys sorted, so the text is the same every time
+// for the same value
+fn checksum(value: &Value) -> String {
+    audit::sha256(value.to_string().as_bytes())
+}
+
+// A JSON object with the SHA-256 of the rest of it in "checksum"
+pub fn seal(mut value: Value) -> Value {
+    if let Some(object) = value.as_object_mut() {
+        object.remove(CHECKSUM);
+        let sum = checksum(&Value::Object(object.clone()));
+        object.insert(CHECKSUM.into(), sum.into());
+    }
+    value
+}
+
+// Takes the checksum back off after checking it. Files from before there
+// were checksums don't have one and pass.
+pub fn unseal(path: &Path, mut value: Value) -> Result<Value> {
+    let Some(object) = value.as_object_mut() else {
+        return Ok(value);
+    };
+    let Some(expected) = object.remove(CHECKSUM) else {
+        return Ok(value);
+    };
+    if expected.as_str() != Some(checksum(&Value::Object(object.clone())).as_str()) {
+        bail!(Error::new(
+            ErrorKind::Validation,
+            format!("{} doesn't match its checksum, it got corrupted. Remove it to start over.", path.display())
+        ));
+    }
+    Ok(value)
+}
@@ -0,0 +1,239 @@
+use crate::atomic;
+use crate::error::{Error, ErrorKind};
+use crate::l10n;
+use crate::msr::RegSpec;
+use crate::output::Record;
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_PATH: &str = "/var/lib/arrctl/audit.log";
+
+// Every register write, one line each, as
+//
+//   <seq> <unix time> uid=<uid> [claimed_uid=<uid>] cpu=<cpu> reg=<register> old=<value> new=<value> <hash>
+//
+// where the hash is SHA-256 over the previous line's hash, a newline and
+// everything before this one's. Editing or dropping a line breaks every
+// hash after it. Cutting off the end doesn't, which is what the head file
+// next to it is for, it keeps the last seq and hash. Root can still redo
+// both, for that copy the head off the machine now and then, and
+// `chattr +a` the log. Writes go on from the head too, so the log itself
+// isn't read again.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+pub fn head_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".head");
+    PathBuf::from(name)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Caller {
+    // The real uid, or the daemon client's from its socket
+    pub uid: u32,
+    // Who sudo or pkexec say started them. Anyone who can run arrctl as
+    // root can set those, so it's only a claim.
+    pub claimed: Option<u32>,
+}
+
+impl Caller {
+    pub fn new(uid: u32) -> Self {
+        Caller { uid, claimed: None }
+    }
+}
+
+pub fn caller() -> Caller {
+    let uid = unsafe { libc::getuid() };
+    let claimed = ["SUDO_UID", "PKEXEC_UID"].iter().find_map(|var| std::env::var(var).ok()?.parse().ok());
+    Caller { uid, claimed: claimed.filter(|&claimed| claimed != uid) }
+}
+
+fn last_line(text: &str) -> Option<(u64, String)> {
+    let line = text.lines().last()?;
+    Some((line.split(' ').next()?.parse::<u64>().ok()? + 1, line.rsplit(' ').next()?.to_string()))
+}
+
+// What the next entry follows on from, out of the head. The end of the log
+// is only read to check it: a crash between writing the two can only leave
+// the head behind, a log that ends before the head or disagrees with it
+// was cut.
+fn next(file: &mut File, path: &Path) -> Result<(u64, String)> {
+    let len = file.metadata().with_context(|| format!("Failed to read {}", path.display()))?.len();
+    let head = fs::read_to_string(head_path(path)).ok().and_then(|text| {
+        let (entries, hash) = text.trim().split_once(' ')?;
+        Some((entries.parse::<u64>().ok()?, hash.to_string()))
+    });
+    let cut = |head: &(u64, String)| Error::new(ErrorKind::Validation, format!(
+        "{} doesn't reach entry {} of its head, the audit log was tampered with", path.display(), head.0
+    ));
+    if len == 0 {
+        return match head {
+            Some(head) if head.0 > 0 => bail!(cut(&head)),
+            _ => Ok((0, GENESIS.to_string())),
+        };
+    }
+    // Entries are well under this
+    let from = len.saturating_sub(4096);
+    let mut tail = String::new();
+    file.seek(SeekFrom::Start(from)).and_then(|_| file.read_to_string(&mut tail)).with_context(|| format!("Failed to read {}", path.display()))?;
+    match (head, last_line(&tail)) {
+        (Some(head), Some(last)) if head == last => Ok(head),
+        (Some(head), Some(last)) if last.0 > head.0 => Ok(last),
+        (Some(head), _) => bail!(cut(&head)),
+        (None, Some(last)) => Ok(last),
+        (None, None) => bail!(Error::new(ErrorKind::Validation, format!("{} doesn't end in an entry, the audit log was tampered with", path.display()))),
+    }
+}
+
+// Locked, so the daemon and a command line run don't both take the same seq
+pub fn append(path: &Path, caller: Caller, writes: &[(RegSpec, u64, u64)]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| format!("Failed to lock {}", path.display()));
+    }
+    let (mut seq, mut prev) = next(&mut file, path)?;
+    let uid = match caller.claimed {
+        Some(claimed) => format!("uid={} claimed_uid={}", caller.uid, claimed),
+        None => format!("uid={}", caller.uid),
+    };
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let mut lines = String::new();
+    for (spec, old, new) in writes {
+        let body = format!("{} {} {} cpu={} reg={:#x} old={:#018x} new={:#018x}", seq, time, uid, spec.cpu, spec.reg, old, new);
+        prev = sha256(format!("{}\n{}", prev, body).as_bytes());
+        lines += &format!("{} {}\n", body, prev);
+        seq += 1;
+    }
+    file.write_all(lines.as_bytes())?;
+    file.sync_data()?;
+    write_head(path, seq, &prev)
+}
+
+fn write_head(path: &Path, entries: u64, hash: &str) -> Result<()> {
+    atomic::write(&head_path(path), format!("{} {}\n", entries, hash).as_bytes())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Verified {
+    pub entries: u64,
+    pub head: String,
+}
+
+fn broken(path: &Path, line: usize, why: &str) -> Error {
+    Error::new(ErrorKind::Validation, format!("{}:{}: {}, the audit log was tampered with", path.display(), line, why))
+}
+
+pub fn verify(path: &Path) -> Result<Verified> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut prev = GENESIS.to_string();
+    let mut entries = 0;
+    for (i, line) in text.lines().enumerate() {
+        let Some((body, hash)) = line.rsplit_once(' ') else {
+            bail!(broken(path, i + 1, "not an entry"));
+        };
+        if body.split(' ').next() != Some(entries.to_string().as_str()) {
+            bail!(broken(path, i + 1, &format!("expected entry {}", entries)));
+        }
+        if sha256(format!("{}\n{}", prev, body).as_bytes()) != hash {
+            bail!(broken(path, i + 1, "hash doesn't match"));
+        }
+        prev = hash.to_string();
+        entries += 1;
+    }
+
+    let head = head_path(path);
+    match fs::read_to_string(&head) {
+        Ok(text) => {
+            let expected = format!("{} {}", entries, prev);
+            if text.trim() != expected {
+                bail!(Error::new(
+                    ErrorKind::Validation,
+                    format!("{} has {} entries ending in {}, {} says {}, the audit log was truncated", path.display(), entries, prev, head.display(), text.trim())
+                ));
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if entries > 0 {
+                bail!(Error::new(ErrorKind::Validation, format!("{} is missing, the audit log can't be checked for truncation", head.display())));
+            }
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", head.display())),
+    }
+    Ok(Verified { entries, head: prev })
+}
+
+pub fn record(path: &Path, verified: &Verified) -> Record {
+    Record::new("audit")
+        .title(path.display().to_string())
+        .field("entries", l10n::text("audit-entries"), verified.entries, "")
+        .field("head", l10n::text("audit-head"), verified.head.as_str(), "")
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// FIPS 180-4, lowercase hex. The tests hold it to the FIPS 180-2 example
+// vectors.
+pub fn sha256(data: &[u8]) -> String {
+    let mut h: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, v) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(v);
+        }
+    }
+    h.iter().map(|word| format!("{:08x}", word)).collect()
+}
@@ -0,0 +1,136 @@
+use crate::l10n;
+use crate::monitor::Sample;
+use crate::output::{Level, Record, Value};
+use crate::policy::{self, Inputs, Script};
+use crate::profile::Profile;
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+// What a policy would have set over a recorded session, and where the
+// recording broke the limits it would have had in force. The temperatures
+// and draw are what the recording's own limits gave, so this shows when the
+// policy would have stepped in, not how much cooler that would have run.
+
+pub struct Options {
+    // What the recording was made with
+    pub interval: Duration,
+    // Like [policy], picks that come sooner than this after a write wait
+    pub min_interval: Duration,
+    pub ac: bool,
+    // Hotter than this is a violation too
+    pub max_celsius: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Check {
+    Hot,
+    Watts,
+    Amps,
+}
+
+// A stretch of samples breaking the same check
+struct Span {
+    check: Check,
+    from: Duration,
+    to: Duration,
+    worst: f64,
+    limit: f64,
+}
+
+impl Span {
+    fn record(&self) -> Record {
+        let (what, unit) = match self.check {
+            Check::Hot => ("celsius on the hottest core", "celsius"),
+            Check::Watts => ("W drawn", "W TDP"),
+            Check::Amps => ("A drawn", "A TDC"),
+        };
+        let text = format!("Up to {:.1} {}, over the {} {}, for {} s", self.worst, what, self.limit, unit, (self.to - self.from).as_secs());
+        Record::new("violation")
+            .title(l10n::format("backtest-after", &[("seconds", &self.from.as_secs().to_string())]))
+            .hidden("at_seconds", self.from.as_secs())
+            .field("text", "", text, "")
+            .level(Level::Bad)
+    }
+}
+
+fn breaks(check: Check, sample: &Sample, inputs: &Inputs, in_force: &Profile, max_celsius: Option<u64>) -> Option<(f64, f64)> {
+    let (value, limit) = match check {
+        Check::Hot => (inputs.temperature, max_celsius? as f64),
+        Check::Watts => (sample.package_watts as f64, in_force.tdp? as f64),
+        Check::Amps => (sample.core_amps as f64, in_force.tdc? as f64),
+    };
+    (value > limit).then_some((value, limit))
+}
+
+// The trajectory, a record each time the policy would have written, then the
+// violations and a summary
+pub fn run(script: &Script, samples: &[Sample], opts: &Options) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut in_force = Profile::default();
+    let mut last_write: Option<Duration> = None;
+    let mut open: Vec<Span> = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
+    let (mut writes, mut skipped, mut in_violation) = (0u64, 0u64, 0u64);
+    let mut hottest: Option<f64> = None;
+
+    for (index, sample) in samples.iter().enumerate() {
+        let at = opts.interval * index as u32;
+        let Some(inputs) = Inputs::from_sample(sample, opts.ac) else {
+            skipped += 1;
+            continue;
+        };
+        hottest = Some(hottest.map_or(inputs.temperature, |h| h.max(inputs.temperature)));
+
+        let pick = script.evaluate(&inputs).with_context(|| format!("Sample {}", index + 1))?;
+        let waiting = last_write.is_some_and(|t| at - t < opts.min_interval);
+        if pick != in_force && !waiting {
+            in_force = pick;
+            last_write = Some(at);
+            writes += 1;
+            let record = Record::new("backtest_step")
+                .title(l10n::format("backtest-after", &[("seconds", &at.as_secs().to_string())]))
+                .hidden("at_seconds", at.as_secs())
+                .field("celsius", l10n::text("policy-celsius"), Value::Float(inputs.temperature), "celsius");
+            records.push(policy::limit_fields(record, &in_force));
+        }
+
+        let mut any = false;
+        for check in [Check::Hot, Check::Watts, Check::Amps] {
+            let position = open.iter().position(|s| s.check == check);
+            match (breaks(check, sample, &inputs, &in_force, opts.max_celsius), position) {
+                (Some((value, limit)), Some(i)) => {
+                    let span = &mut open[i];
+                    span.to = at + opts.interval;
+                    span.worst = span.worst.max(value);
+                    span.limit = limit;
+                    any = true;
+                }
+                (Some((value, limit)), None) => {
+                    open.push(Span { check, from: at, to: at + opts.interval, worst: value, limit });
+                    any = true;
+                }
+                (None, Some(i)) => spans.push(open.remove(i)),
+                (None, None) => (),
+            }
+        }
+        in_violation += any as u64;
+    }
+    spans.append(&mut open);
+    spans.sort_by_key(|s| s.from);
+
+    records.extend(spans.iter().map(Span::record));
+    let mut summary = Record::new("backtest")
+        .title(l10n::text("backtest-summary"))
+        .field("samples", l10n::text("policy-samples"), samples.len() as u64, "")
+        .field("seconds", l10n::text("backtest-seconds"), (opts.interval * samples.len() as u32).as_secs(), "s")
+        .field("writes", l10n::text("backtest-writes"), writes, "")
+        .field("skipped", l10n::text("policy-skipped"), skipped, "");
+    if let Some(celsius) = hottest {
+        summary = summary.field("max_celsius", l10n::text("backtest-max-celsius"), Value::Float(celsius), "celsius");
+    }
+    records.push(summary
+        .field("violations", l10n::text("backtest-violations"), spans.len() as u64, "")
+        .field("violation_seconds", l10n::text("backtest-violation-seconds"), (opts.interval * in_violation as u32).as_secs(), "s")
+        .level(if spans.is_empty() { Level::Good } else { Level::Bad }));
+    Ok(records)
+}
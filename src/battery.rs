@@ -0,0 +1,88 @@
+use crate::l10n;
+use crate::output::{Record, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CLASS: &str = "/sys/class/power_supply";
+
+// What a TDP cut is worth in runtime, shown alongside the draw
+pub const CUT_WATTS: f64 = 5.0;
+
+pub struct Battery {
+    pub name: String,
+    dir: PathBuf,
+}
+
+// Every battery there is, a ThinkPad with the slice has two
+pub fn batteries(class: &Path) -> Vec<Battery> {
+    let Ok(entries) = fs::read_dir(class) else {
+        return Vec::new();
+    };
+    let mut batteries: Vec<Battery> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| fs::read_to_string(e.path().join("type")).is_ok_and(|t| t.trim() == "Battery"))
+        .filter_map(|e| Some(Battery { name: e.file_name().to_str()?.to_string(), dir: e.path() }))
+        .collect();
+    batteries.sort_by(|a, b| a.name.cmp(&b.name));
+    batteries
+}
+
+// The power_supply files are in micro units
+fn micro(dir: &Path, file: &str) -> Option<f64> {
+    let value: f64 = fs::read_to_string(dir.join(file)).ok()?.trim().parse().ok()?;
+    Some(value.abs() / 1_000_000.0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Discharge {
+    pub watts: f64,
+    // What's left, where the battery says
+    pub watt_hours: Option<f64>,
+}
+
+impl Battery {
+    // Only while it's running the machine. Some report power_now, others
+    // only current_now, and the charge in Ah to go with it.
+    pub fn discharge(&self) -> Option<Discharge> {
+        if fs::read_to_string(self.dir.join("status")).ok()?.trim() != "Discharging" {
+            return None;
+        }
+        let volts = micro(&self.dir, "voltage_now");
+        let watts = micro(&self.dir, "power_now").or_else(|| Some(micro(&self.dir, "current_now")? * volts?))?;
+        let watt_hours = micro(&self.dir, "energy_now").or_else(|| Some(micro(&self.dir, "charge_now")? * volts?));
+        Some(Discharge { watts, watt_hours })
+    }
+}
+
+// All batteries together, nothing when none is discharging
+pub fn discharge(batteries: &[Battery]) -> Option<Discharge> {
+    let draws: Vec<Discharge> = batteries.iter().filter_map(Battery::discharge).collect();
+    let draw = Discharge {
+        watts: draws.iter().map(|d| d.watts).sum(),
+        watt_hours: draws.iter().map(|d| d.watt_hours).sum(),
+    };
+    // Right after unplugging some read 0 for a moment
+    Some(draw).filter(|d| d.watts > 0.0)
+}
+
+// Minutes left at a draw, none when the draw can't last
+fn minutes(watt_hours: f64, watts: f64) -> Option<u64> {
+    (watts > 0.0).then(|| (watt_hours / watts * 60.0).round() as u64)
+}
+
+// The system's draw next to the CPU's estimate, and how long the battery
+// lasts at it and with CUT_WATTS less
+pub fn record(draw: &Discharge, package_watts: f32) -> Record {
+    let mut record = Record::new("battery")
+        .field("system_watts", l10n::text("battery-draw"), Value::Fixed(draw.watts, 1), "W")
+        .field("cpu_percent", l10n::text("battery-cpu-share"), Value::Fixed((package_watts as f64 / draw.watts * 100.0).min(100.0), 0), l10n::text("unit-battery-share"));
+    if let Some(watt_hours) = draw.watt_hours {
+        if let Some(left) = minutes(watt_hours, draw.watts) {
+            record = record.field("minutes_left", l10n::text("battery-left"), left, "min");
+        }
+        if let Some(cut) = minutes(watt_hours, draw.watts - CUT_WATTS) {
+            record = record.field("minutes_left_after_cut", l10n::format("battery-left-after-cut", &[("watts", &CUT_WATTS.to_string())]), cut, "min");
+        }
+    }
+    record
+}
@@ -0,0 +1,260 @@
+use crate::advise::{self, CpuObservation, Observation};
+use crate::cpu;
+use crate::msr::MsrAccess;
+use crate::output::{Level, OutputSink, Record, Value};
+use crate::signals;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::hint::black_box;
+use std::io;
+use std::os::unix::thread::JoinHandleExt;
+use std::process::{Child, Command};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// TDC and TDP bind differently depending on what the cores are busy with:
+// a tight integer loop draws less current per MHz than packed math
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Load {
+    Spin,
+    // Arrandale predates AVX, this is the widest it has, SSE2 packed doubles
+    Avx,
+    Memory,
+    Custom(String),
+}
+
+impl FromStr for Load {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spin" => Ok(Load::Spin),
+            "avx" => Ok(Load::Avx),
+            "memory" => Ok(Load::Memory),
+            s => match s.strip_prefix("custom:") {
+                Some("") => Err("custom: needs a command after it".to_string()),
+                Some(cmd) => Ok(Load::Custom(cmd.to_string())),
+                None => Err(format!("Expected spin, avx, memory or custom:<command>, got {:?}", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Load {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Load::Spin => write!(f, "spin"),
+            Load::Avx => write!(f, "avx"),
+            Load::Memory => write!(f, "memory"),
+            Load::Custom(cmd) => write!(f, "custom:{}", cmd),
+        }
+    }
+}
+
+// Checking the flag every iteration would be most of the work, so every
+// kernel does a chunk between checks
+fn spin(stop: &AtomicBool) {
+    let mut x = 1u64;
+    while !stop.load(Ordering::Relaxed) {
+        for _ in 0..100_000 {
+            x = black_box(x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407));
+        }
+    }
+}
+
+fn packed_math(stop: &AtomicBool) {
+    let mut a = [1.0f64; 1024];
+    let b = [0.999_999f64; 1024];
+    let c = [1e-9f64; 1024];
+    while !stop.load(Ordering::Relaxed) {
+        for _ in 0..100 {
+            for ((a, b), c) in a.iter_mut().zip(&b).zip(&c) {
+                *a = *a * b + c;
+            }
+            black_box(&mut a);
+        }
+    }
+}
+
+// Well past the 3 or 4 MiB of L3 so it goes out to DRAM
+const MEMORY_BYTES: usize = 32 << 20;
+
+fn memory(stop: &AtomicBool) {
+    let mut buf = vec![0u64; MEMORY_BYTES / 8];
+    let mut sum = 0u64;
+    while !stop.load(Ordering::Relaxed) {
+        // One word per cache line
+        for word in buf.iter_mut().step_by(8) {
+            sum = sum.wrapping_add(*word);
+            *word = sum;
+        }
+        black_box(&mut buf);
+    }
+}
+
+pub struct Running {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    child: Option<Child>,
+}
+
+// So each CPU's numbers are its own thread's, not whatever the scheduler
+// moved over from a hotter core
+fn pin(thread: &JoinHandle<()>, cpu: u16) -> Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::CPU_SET(cpu.into(), &mut set) };
+    let err = unsafe { libc::pthread_setaffinity_np(thread.as_pthread_t(), std::mem::size_of::<libc::cpu_set_t>(), &set) };
+    if err != 0 {
+        return Err(io::Error::from_raw_os_error(err)).with_context(|| format!("Failed to pin the load to CPU{}", cpu));
+    }
+    Ok(())
+}
+
+// One thread pinned to each of the CPUs, or the custom command on its own
+pub fn start(load: &Load, cpus: &[u16]) -> Result<Running> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut running = Running { stop: stop.clone(), threads: Vec::new(), child: None };
+    let kernel: fn(&AtomicBool) = match load {
+        Load::Spin => spin,
+        Load::Avx => packed_math,
+        Load::Memory => memory,
+        Load::Custom(cmd) => {
+            let child = Command::new("/bin/sh").arg("-c").arg(cmd).spawn().with_context(|| format!("Failed to run {:?}", cmd))?;
+            running.child = Some(child);
+            return Ok(running);
+        }
+    };
+    for &cpu in cpus {
+        let stop = stop.clone();
+        let thread = thread::spawn(move || kernel(&stop));
+        // Pushed first so a failure still stops it
+        running.threads.push(thread);
+        pin(running.threads.last().unwrap(), cpu)?;
+    }
+    Ok(running)
+}
+
+impl Running {
+    // Whether the custom command gave up before the bench was over
+    pub fn exited_early(&mut self) -> Result<Option<std::process::ExitStatus>> {
+        match &mut self.child {
+            Some(child) => Ok(child.try_wait()?),
+            None => Ok(None),
+        }
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// So an error half way through doesn't leave load running
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// Within this share of a limit counts as running into it
+const NEAR: f32 = 0.95;
+
+// Which limit the load ran into, the closer one if both
+pub fn binding_limit(obs: &Observation) -> Option<&'static str> {
+    let tdp = obs.max_package_watts / obs.tdp_watts;
+    let tdc = obs.peak.amps / obs.tdc_amps;
+    match (tdp >= NEAR, tdc >= NEAR) {
+        (false, false) => None,
+        (true, false) => Some("TDP"),
+        (false, true) => Some("TDC"),
+        (true, true) => Some(if tdc > tdp { "TDC" } else { "TDP" }),
+    }
+}
+
+// Seconds one core has to throttle ahead of another before it's worth
+// pointing out
+const EARLY_SECS: f32 = 10.0;
+
+// The CPUs that throttled well before some other one did, if it did at all.
+// With the same load on every core that's usually the paste or the contact
+// over one corner of the die.
+pub fn early_throttlers(cpus: &[CpuObservation], seconds: f32) -> Vec<u16> {
+    cpus.iter()
+        .filter(|c| {
+            c.throttled_at.is_some_and(|at| cpus.iter().any(|other| other.throttled_at.unwrap_or(seconds) >= at + EARLY_SECS))
+        })
+        .map(|c| c.cpu)
+        .collect()
+}
+
+pub fn cpu_record(obs: &CpuObservation, early: bool) -> Record {
+    let mut record = Record::new("bench_cpu")
+        .title(format!("CPU{}", obs.cpu))
+        .cpu(obs.cpu)
+        .field("sustained_mhz", "", Value::Fixed(obs.sustained_mhz as f64, 0), "MHz sustained");
+    if let Some(temp) = obs.max_celsius {
+        record = record.field("max_celsius", "max", temp, "celsius");
+    }
+    if let Some(at) = obs.throttled_at {
+        record = record
+            .field("throttled_at_seconds", "throttled after", Value::Fixed(at as f64, 0), "s")
+            .level(if early { Level::Warn } else { Level::Plain });
+    }
+    record.hidden("early", early)
+}
+
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess, load: &Load, duration: Duration) -> Result<()> {
+    signals::install()?;
+    let cpus = cpu::online_cpus();
+    let threads = cpus.len();
+    let mut running = start(load, &cpus)?;
+    let obs = advise::observe(msr, duration)?;
+    let early = running.exited_early()?;
+    running.stop();
+
+    if let Some(status) = early {
+        out.note(&format!("The load command exited with {} before the bench was over", status))?;
+    }
+    out.record(&advise::observed_record(&obs))?;
+    let mut record = Record::new("bench")
+        .title(format!("Load {}", load))
+        .field("load", "", load.to_string(), "")
+        .field("peak_amps", "peak", Value::Fixed(obs.peak.amps as f64, 1), "estimated A")
+        .field("tdc_amps", "of", Value::Float(obs.tdc_amps as f64), "A TDC");
+    if !matches!(load, Load::Custom(_)) {
+        record = record.field("threads", "on", threads as u64, "thread(s)");
+    }
+    out.record(&record.field("limit", "limited by", binding_limit(&obs).unwrap_or("neither"), ""))?;
+    for record in obs.turbo.records() {
+        out.record(&record)?;
+    }
+
+    let early = early_throttlers(&obs.cpus, obs.seconds);
+    let places = cpu::Places::current();
+    let mut records: Vec<Record> = obs.cpus.iter().map(|cpu| places.label(cpu_record(cpu, early.contains(&cpu.cpu)))).collect();
+    places.group(&mut records);
+    for record in &records {
+        out.record(record)?;
+    }
+    if !early.is_empty() {
+        let names: Vec<String> = early.iter().map(|cpu| format!("CPU{}", cpu)).collect();
+        out.note(&format!(
+            "{} throttled well before the rest under the same load, often uneven thermal paste or poor heatsink contact",
+            names.join(", ")
+        ))?;
+    }
+    Ok(())
+}
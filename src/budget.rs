@@ -0,0 +1,43 @@
+use crate::apply;
+use crate::igp::{self, Gt, IgpCap};
+use crate::journal::Journal;
+use crate::msr::MsrAccess;
+use crate::output::{OutputSink, Record};
+use crate::profile::{self, Profile};
+use crate::sku;
+use anyhow::Result;
+use std::path::Path;
+
+// CPU and graphics share one package power budget on Arrandale, set both
+// halves of the split at once
+pub fn run(
+    out: &mut dyn OutputSink,
+    msr: &dyn MsrAccess,
+    journal: &Journal,
+    cpu_watts: u64,
+    igp_cap: IgpCap,
+    save: Option<&str>,
+) -> Result<()> {
+    let profile = Profile { tdp: Some(cpu_watts), igp_cap: Some(igp_cap), ..Default::default() };
+    apply::apply(msr, journal, &profile, true)?;
+
+    let gt = Gt::find(Path::new(igp::DRM))?;
+    let mut record = Record::new("budget")
+        .title("Budget")
+        .field("cpu_watts", "CPU", cpu_watts, "W")
+        .field("igp_max_mhz", "graphics up to", gt.max_mhz()?, "MHz");
+    if let Some(sku) = sku::detect() {
+        let left = (sku.tdp as u64).saturating_sub(cpu_watts);
+        record = record
+            .field("igp_watts", "leaving about", left, "W for graphics")
+            .field("package_watts", "of the", sku.tdp as u64, "W package");
+    }
+    out.record(&record)?;
+
+    if let Some(name) = save {
+        let path = Path::new(profile::DEFAULT_PATH);
+        profile::save(path, name, &profile)?;
+        out.note(&format!("Saved as profile {} in {}", name, path.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,629 @@
+use crate::audit;
+use crate::bench::Load;
+use crate::cores::Target;
+use crate::error::EXIT_CODES;
+use crate::hwmon::TempSource;
+use crate::igp::IgpCap;
+use crate::init::{self, Goal};
+use crate::msr::RetryPolicy;
+use crate::profile::Override;
+use crate::output::{ColorMode, Format};
+use crate::{daemon, history, privs, profile, soak, units};
+use crate::l10n::{self, Bundle};
+use clap::{ArgGroup, CommandFactory, FromArgMatches, Parser, Subcommand};
+use crate::trial::Setting;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Reading the limits while setting them would report either the old or the
+// new value depending on the order, so it's one or the other. --all-cores
+// needs something to apply, here or from the subcommand.
+#[derive(Parser)]
+#[command(after_help = EXIT_CODES, subcommand_negates_reqs = true)]
+#[command(group(ArgGroup::new("get_limits").args(["get_tdp", "get_tdc"]).multiple(true).conflicts_with("set_limits")))]
+#[command(group(ArgGroup::new("set_limits").args(["set_tdp", "set_tdc"]).multiple(true)))]
+#[command(group(ArgGroup::new("sets").args(["set_tdp", "set_tdc", "set_ratio", "set_clock_modulation"]).multiple(true)))]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(long)]
+    pub get_tdp: bool,
+
+    #[arg(long)]
+    pub get_tdc: bool,
+
+    #[arg(long)]
+    pub get_tjmax: bool,
+
+    #[arg(long)]
+    pub get_turbo_ratios: bool,
+
+    #[arg(long)]
+    pub get_voltage: bool,
+
+    #[arg(long)]
+    pub get_thermal: bool,
+
+    #[arg(long, value_name = "WATTS", value_parser = units::parse_watts)]
+    pub set_tdp: Option<u64>,
+
+    #[arg(long, value_name = "AMPS", value_parser = units::parse_amps)]
+    pub set_tdc: Option<u64>,
+
+    // Caps every turbo bin at the ratio nearest this frequency, like 2.66GHz
+    #[arg(long, value_name = "FREQ", value_parser = units::parse_mhz)]
+    pub set_ratio: Option<f64>,
+
+    // Duty cycle like 50% or in eighths, off or 0 turns clock modulation off
+    #[arg(long, value_name = "DUTY", value_parser = units::parse_duty_cycle)]
+    pub set_clock_modulation: Option<u64>,
+
+    // Apply sets on every core or package instead of just CPU0
+    #[arg(long, requires = "sets")]
+    pub all_cores: bool,
+
+    #[arg(long)]
+    pub monitor: bool,
+
+    #[arg(long, value_name = "MS", default_value_t = 1000, value_parser = units::parse_millis)]
+    pub interval: u64,
+
+    #[arg(long, value_enum, default_value_t = TempSource::Msr)]
+    pub temp_source: TempSource,
+
+    #[arg(long)]
+    pub thermal_zones: bool,
+
+    // Run the [governor] from the profiles file while monitoring
+    #[arg(long, requires = "monitor")]
+    pub govern: bool,
+
+    #[arg(long, value_enum, global = true, default_value_t = Format::Human)]
+    pub format: Format,
+
+    // Shorthand for --format json, also makes errors JSON
+    #[arg(long, global = true, conflicts_with = "format")]
+    pub json: bool,
+
+    // Decimal separator for human output, "C" forces a period
+    #[arg(long, value_name = "LOCALE", global = true)]
+    pub locale: Option<String>,
+
+    #[arg(long, value_enum, global = true, default_value_t = ColorMode::Auto)]
+    pub color: ColorMode,
+
+    // Push records to this Influx write endpoint instead of printing them
+    #[arg(long, value_name = "URL", global = true)]
+    pub influx_url: Option<String>,
+
+    // Print the JSON Schema of the command's --json output instead of running it
+    #[arg(long, global = true, conflicts_with = "influx_url")]
+    pub schema: bool,
+
+    // Go ahead inside a virtual machine, with a warning
+    #[arg(long, global = true)]
+    pub allow_vm: bool,
+
+    // Refuse every MSR write, for deploying only the monitoring parts
+    #[arg(long, global = true)]
+    pub read_only: bool,
+
+    // Skip the seccomp filter of read-only and daemon runs, for debugging
+    #[arg(long, global = true)]
+    pub no_sandbox: bool,
+
+    // Write the registers even while the daemon runs, instead of asking it to
+    #[arg(long, global = true)]
+    pub direct: bool,
+
+    // Write limits the guard rails refuse, like a TDC under idle draw, or
+    // over a set that never finished. Goes straight to the registers, the
+    // daemon never forces.
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    // Re-run through sudo or pkexec when a write needs root
+    #[arg(long, global = true)]
+    pub sudo: bool,
+
+    // Tries again after a failed MSR access, 0 gives up on the first one
+    #[arg(long, value_name = "N", global = true, default_value_t = 2)]
+    pub msr_retries: u32,
+
+    #[arg(long, value_name = "MS", global = true, default_value_t = 50, value_parser = units::parse_millis)]
+    pub msr_retry_delay: u64,
+
+    // Set a key of the profiles file, over the file and ARRCTL_SET
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    pub overrides: Vec<Override>,
+}
+
+impl Cli {
+    pub fn format(&self) -> Format {
+        if self.json { Format::Json } else { self.format }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy { retries: self.msr_retries, delay: Duration::from_millis(self.msr_retry_delay) }
+    }
+
+    // The flag or the build feature
+    pub fn read_only(&self) -> bool {
+        self.read_only || cfg!(feature = "read-only")
+    }
+
+    // Anything without a command besides the sets
+    pub fn reads(&self) -> bool {
+        self.get_tdp || self.get_tdc || self.get_tjmax || self.get_turbo_ratios || self.get_voltage || self.get_thermal || self.monitor
+    }
+
+    // Whether this run changes anything, for --sudo
+    pub fn writes(&self) -> bool {
+        match &self.command {
+            Some(Command::Recover | Command::Tune { .. } | Command::Budget { .. } | Command::Cores { .. } | Command::Try { .. } | Command::Set { .. } | Command::Run { .. } | Command::Daemon { .. }) => true,
+            Some(Command::Converge { check, .. }) => !check,
+            Some(_) => false,
+            None => self.set_tdp.is_some() || self.set_tdc.is_some() || self.set_clock_modulation.is_some() || self.set_ratio.is_some(),
+        }
+    }
+}
+
+// For errors from before the arguments could be parsed
+pub fn wants_json<I: IntoIterator<Item = String>>(args: I) -> bool {
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        if arg == "--json" || arg == "--format=json" || (arg == "--format" && args.peek().is_some_and(|a| a == "json")) {
+            return true;
+        }
+    }
+    false
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    Id,
+    Selftest,
+    // What this CPU supports, for frontends. --json gives the full document.
+    Features,
+    Dump,
+    Recover,
+    Advise {
+        #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+        duration: u64,
+    },
+    // Looks for the usual reasons it runs slow and explains them
+    Doctor,
+    Bench {
+        #[arg(long, value_name = "LOAD", default_value = "spin")]
+        load: Load,
+
+        #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+        duration: u64,
+    },
+    // Acceptance test: load for hours, fail if it runs hot or slow
+    Soak {
+        #[arg(long, value_name = "HOURS", value_parser = soak::parse_hours)]
+        hours: Duration,
+
+        // In celsius, hottest core
+        #[arg(long, value_name = "CELSIUS")]
+        max_temp: Option<u64>,
+
+        // Lowest average frequency allowed over any minute
+        #[arg(long, value_name = "MHZ", value_parser = units::parse_whole_mhz)]
+        min_sustained_mhz: Option<u64>,
+
+        #[arg(long, value_name = "LOAD", default_value = "spin")]
+        load: Load,
+    },
+    // Lowers the TDP under load until the clock gives, and says where that was
+    Tune {
+        // Lowest sustained clock that still counts as keeping up
+        #[arg(long, value_name = "MHZ", value_parser = units::parse_whole_mhz)]
+        min_mhz: u64,
+
+        #[arg(long, value_name = "LOAD", default_value = "spin")]
+        load: Load,
+
+        // Where to start, the TDP that's set by default
+        #[arg(long, value_name = "WATTS", value_parser = units::parse_watts)]
+        from: Option<u64>,
+
+        // Never below this
+        #[arg(long, value_name = "WATTS", default_value_t = init::MIN_TDP, value_parser = units::parse_watts)]
+        floor: u64,
+
+        #[arg(long, value_name = "WATTS", default_value = "1", value_parser = units::parse_watts)]
+        step: u64,
+
+        // How long each TDP runs, the first half isn't counted
+        #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = parse_duration)]
+        step_duration: Duration,
+
+        // Also store the result as this profile
+        #[arg(long, value_name = "PROFILE")]
+        save: Option<String>,
+    },
+    Budget {
+        // CPU share of the package, as the turbo TDP
+        #[arg(long, value_name = "WATTS", value_parser = units::parse_watts)]
+        cpu: u64,
+
+        #[arg(long, value_enum)]
+        igp_cap: IgpCap,
+
+        // Also store the split as this profile
+        #[arg(long, value_name = "PROFILE")]
+        save: Option<String>,
+    },
+    Cores {
+        #[command(subcommand)]
+        action: CoresAction,
+    },
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    // The profiles in the profiles file
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    Audit {
+        #[command(subcommand)]
+        action: AuditAction,
+    },
+    // Field by field differences between two `arrctl dump` files
+    Compare {
+        a: PathBuf,
+        b: PathBuf,
+
+        // Also list the fields that match
+        #[arg(long)]
+        all: bool,
+    },
+    // Runs the commands in a file one after the other, see script.rs
+    Run {
+        script: PathBuf,
+    },
+    // Brings the settings to a desired state, writing only what differs
+    Converge {
+        #[arg(short = 'c', long, value_name = "FILE")]
+        config: PathBuf,
+
+        // Only report what would change
+        #[arg(long)]
+        check: bool,
+    },
+    // One setting, through the daemon while it runs so it sticks
+    Set {
+        setting: Setting,
+        value: String,
+
+        // Put it back after this long, like 90, 60s or 20m
+        #[arg(long = "for", value_name = "DURATION", value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    // Applies one setting that goes back on its own unless confirmed in time
+    Try {
+        #[arg(long, value_name = "DURATION", default_value = "60s", value_parser = parse_duration)]
+        revert_after: Duration,
+
+        #[command(subcommand)]
+        action: TryAction,
+    },
+    // Asks a few questions and writes a starter profiles file
+    Init {
+        #[arg(long, value_name = "PATH", default_value = profile::DEFAULT_PATH)]
+        config: PathBuf,
+
+        // These skip their questions, --goal is needed without a terminal
+        #[arg(long, value_enum)]
+        goal: Option<Goal>,
+
+        #[arg(long, value_name = "WATTS", value_parser = units::parse_watts)]
+        ac_tdp: Option<u64>,
+
+        #[arg(long, value_name = "WATTS", value_parser = units::parse_watts)]
+        battery_tdp: Option<u64>,
+
+        // Also write arrctl.service here, like /etc/systemd/system
+        #[arg(long, value_name = "DIR")]
+        systemd: Option<PathBuf>,
+
+        // Replace the profiles file and unit if they're there
+        #[arg(long)]
+        overwrite: bool,
+    },
+    // Runs a command and adds up what the CPU and the system drew for it
+    Measure {
+        #[arg(long, value_name = "MS", default_value_t = 250, value_parser = units::parse_millis)]
+        interval: u64,
+
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    // Plays back a `--monitor --format csv` recording
+    Replay {
+        file: PathBuf,
+
+        // What the recording was made with
+        #[arg(long, value_name = "MS", default_value_t = 1000, value_parser = units::parse_millis)]
+        interval: u64,
+
+        #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+        speed: f64,
+    },
+    // Dry runs of a [policy] script
+    Policy {
+        #[command(subcommand)]
+        action: PolicyAction,
+    },
+    // Follows what a running daemon sees, --json gives one JSON object per line
+    Events {
+        #[arg(long, value_name = "PATH", default_value = daemon::SOCKET)]
+        socket: PathBuf,
+    },
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    Daemon {
+        #[arg(long, value_name = "MS", default_value_t = 1000, value_parser = units::parse_millis)]
+        interval: u64,
+
+        #[arg(long, value_name = "PATH", default_value = daemon::SOCKET)]
+        socket: PathBuf,
+
+        #[arg(long, value_name = "COMMAND")]
+        hook: Option<String>,
+
+        // Serve the events over a websocket at /ws, like 127.0.0.1:9110
+        #[arg(long, value_name = "ADDR")]
+        listen: Option<SocketAddr>,
+
+        #[arg(long, value_name = "PATH", default_value = profile::DEFAULT_PATH)]
+        config: PathBuf,
+
+        // Profile to apply at start and again after every resume, ARRCTL_PROFILE without it
+        #[arg(long, value_name = "PROFILE")]
+        apply: Option<String>,
+
+        // Applied instead while running on battery
+        #[arg(long, value_name = "PROFILE")]
+        apply_on_battery: Option<String>,
+
+        // Runs as this user once the MSR files are open, root stays root
+        #[arg(long, value_name = "USER", default_value = privs::DEFAULT_USER)]
+        user: String,
+
+        // How many hours of samples to keep for `arrctl history`
+        #[arg(long, value_name = "HOURS", default_value = history::DEFAULT_HOURS, value_parser = soak::parse_hours)]
+        history: Duration,
+
+        // Check the set registers this often and write back what changed,
+        // for ECs that put the limits back on their own
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        enforce_every: Option<Duration>,
+
+        // Spread the checks up to this much either side
+        #[arg(long, value_name = "DURATION", default_value = "0", value_parser = parse_duration, requires = "enforce_every")]
+        enforce_jitter: Duration,
+    },
+}
+
+impl Command {
+    // As the schema module knows it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Id => "id",
+            Command::Selftest => "selftest",
+            Command::Features => "features",
+            Command::Dump => "dump",
+            Command::Recover => "recover",
+            Command::Advise { .. } => "advise",
+            Command::Doctor => "doctor",
+            Command::Bench { .. } => "bench",
+            Command::Soak { .. } => "soak",
+            Command::Tune { .. } => "tune",
+            Command::Budget { .. } => "budget",
+            Command::Cores { .. } => "cores",
+            Command::Config { .. } => "config",
+            Command::Profile { .. } => "profile",
+            Command::Audit { .. } => "audit",
+            Command::Compare { .. } => "compare",
+            Command::Run { .. } => "run",
+            Command::Converge { .. } => "converge",
+            Command::Set { .. } => "set",
+            Command::Try { .. } => "try",
+            Command::Init { .. } => "init",
+            Command::Measure { .. } => "measure",
+            Command::Replay { .. } => "replay",
+            Command::Policy { .. } => "policy",
+            Command::Events { .. } => "events",
+            Command::History { .. } => "history",
+            Command::Daemon { .. } => "daemon",
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum CoresAction {
+    // Keep this many logical CPUs online, or "all"
+    Set { count: Target },
+}
+
+#[derive(Subcommand)]
+pub enum TryAction {
+    Set { setting: Setting, value: String },
+    Confirm,
+    // Run in the background by set, reverts when the time is up
+    #[command(hide = true)]
+    Expire,
+}
+
+// Seconds, bare or with an s, m or h suffix
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, scale) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 3600),
+        _ => (s, 1),
+    };
+    match number.parse::<u64>().map(|n| n.checked_mul(scale)) {
+        Ok(Some(secs)) => Ok(Duration::from_secs(secs)),
+        Ok(None) => Err(format!("{} is too long a duration", s)),
+        Err(_) => Err(format!("Expected a duration like 90, 60s or 5m, got {:?}", s)),
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    // Check a profiles file without applying anything
+    Validate {
+        #[arg(default_value = profile::DEFAULT_PATH)]
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileAction {
+    // A profile as written, or with --resolved as applied
+    Show {
+        name: String,
+
+        // With everything it extends folded in
+        #[arg(long)]
+        resolved: bool,
+
+        #[arg(long, value_name = "PATH", default_value = profile::DEFAULT_PATH)]
+        config: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PolicyAction {
+    // What the script picks over a `--monitor --format csv` recording
+    Test {
+        script: PathBuf,
+        recording: PathBuf,
+
+        // As if the recording was made on battery
+        #[arg(long)]
+        battery: bool,
+    },
+    // What the script would have set over a recorded session, and where that broke its limits
+    Backtest {
+        script: PathBuf,
+        recording: PathBuf,
+
+        // What the recording was made with, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 1000, value_parser = units::parse_millis)]
+        interval: u64,
+
+        // Like min_interval_secs under [policy]
+        #[arg(long, value_name = "SECONDS", default_value_t = 5)]
+        min_interval: u64,
+
+        // In celsius, hottest core
+        #[arg(long, value_name = "CELSIUS")]
+        max_temp: Option<u64>,
+
+        // As if the recording was made on battery
+        #[arg(long)]
+        battery: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HistoryAction {
+    // What the daemon saw, downsampled the further back it goes
+    Show {
+        #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = parse_duration)]
+        since: Duration,
+
+        #[arg(long, value_name = "PATH", default_value = daemon::SOCKET)]
+        socket: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AuditAction {
+    // Check the hash chain of every write, and that nothing was cut off
+    Verify {
+        #[arg(default_value = audit::DEFAULT_PATH)]
+        file: PathBuf,
+    },
+}
+
+fn heading(bundle: &'static Bundle, id: &'static str) -> &'static str {
+    bundle.get(id).unwrap_or(id)
+}
+
+// Help comes from the message catalog, the derive only knows the arguments.
+// About is "about-" and the subcommand path, arguments "arg-", the path
+// and the argument, or just "arg-" and the argument for shared ones.
+fn localize(cmd: &mut clap::Command, bundle: &'static Bundle, path: &str) {
+    let mut localized = std::mem::take(cmd);
+    let about = if path.is_empty() { "about".to_string() } else { format!("about-{}", path) };
+    if let Some(text) = bundle.get(&about) {
+        localized = localized.about(text.to_string());
+    }
+    if let Some(text) = bundle.get("exit-codes").filter(|_| path.is_empty()) {
+        localized = localized.after_help(text.to_string());
+    }
+    // clap's own headings, left alone in English so they keep its styling
+    if bundle.lang != "en" {
+        localized = localized
+            .help_template(format!("{{about-with-newline}}\n{} {{usage}}\n\n{{all-args}}{{after-help}}", bundle.format("heading-usage", &[])))
+            .subcommand_help_heading(heading(bundle, "heading-commands"));
+    }
+    localized = localized.mut_args(|arg| {
+        let id = arg.get_id().as_str();
+        let text = bundle.get(&format!("arg-{}-{}", path, id)).or_else(|| bundle.get(&format!("arg-{}", id)));
+        let arg = match text {
+            Some(text) => arg.help(text.to_string()),
+            None => arg,
+        };
+        match (bundle.lang, arg.is_positional()) {
+            ("en", _) => arg,
+            (_, true) => arg.help_heading(heading(bundle, "heading-arguments")),
+            (_, false) => arg.help_heading(heading(bundle, "heading-options")),
+        }
+    });
+    for sub in localized.get_subcommands_mut() {
+        // clap's own, under it are copies of the real commands
+        if sub.get_name() == "help" {
+            if let Some(text) = bundle.get("about-help") {
+                *sub = std::mem::take(sub).about(text.to_string());
+            }
+            continue;
+        }
+        let path = match path {
+            "" => sub.get_name().to_string(),
+            path => format!("{}-{}", path, sub.get_name()),
+        };
+        localize(sub, bundle, &path);
+    }
+    *cmd = localized;
+}
+
+pub fn command(bundle: &'static Bundle) -> clap::Command {
+    let mut cmd = Cli::command();
+    // ARRCTL_PROFILE stands in for --apply, clap can't read it without its
+    // env feature
+    if std::env::var(profile::ENV_PROFILE).map_or(true, |name| name.is_empty()) {
+        cmd = cmd.mut_subcommand("daemon", |daemon| daemon.mut_arg("apply_on_battery", |arg| arg.requires("apply")));
+    }
+    // Adds the help flags and copies global arguments down so they get
+    // localized everywhere too
+    cmd.build();
+    localize(&mut cmd, bundle, "");
+    cmd
+}
+
+pub fn try_parse() -> Result<Cli, clap::Error> {
+    Cli::from_arg_matches(&command(l10n::current()).try_get_matches()?)
+}
@@ -0,0 +1,74 @@
+use crate::msr::{MockMsr, MsrAccess};
+use crate::output::{Level, OutputSink, Record};
+use crate::regs::*;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+// Change from one moment to the next on their own, not worth comparing
+const VOLATILE: [u32; 3] = [IA32_PERF_STATUS, IA32_PERF_CTL, IA32_THERM_STATUS];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+impl Entry {
+    pub fn same(&self) -> bool {
+        self.a == self.b
+    }
+}
+
+// Every decoded field of the registers on CPU0, where the package wide
+// settings live, plus what the dump header says
+pub fn compare(a: &MockMsr, b: &MockMsr) -> Vec<Entry> {
+    let hex = |v: Option<u64>| v.map(|v| format!("{:#x}", v));
+    let mut entries = vec![
+        Entry { name: "brand".to_string(), a: a.brand.clone(), b: b.brand.clone() },
+        Entry { name: "microcode".to_string(), a: hex(a.microcode), b: hex(b.microcode) },
+        Entry { name: "cpus".to_string(), a: Some(a.cpus().len().to_string()), b: Some(b.cpus().len().to_string()) },
+    ];
+    for def in dumped().filter(|def| !VOLATILE.contains(&def.address)) {
+        let (va, vb) = (a.read(def.address, 0).ok(), b.read(def.address, 0).ok());
+        for field in def.fields {
+            entries.push(Entry {
+                name: format!("{}.{}", def.name, field.name),
+                a: va.map(|v| field.get(v).to_string()),
+                b: vb.map(|v| field.get(v).to_string()),
+            });
+        }
+    }
+    entries
+}
+
+pub fn load(path: &Path) -> Result<MockMsr> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    MockMsr::from_dump(&text).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+pub fn record(entry: &Entry) -> Record {
+    let text = |v: &Option<String>| v.clone().unwrap_or_else(|| "missing".to_string());
+    Record::new("compare")
+        .title(entry.name.clone())
+        .field("a", "", text(&entry.a), "")
+        .level(if entry.same() { Level::Plain } else { Level::Warn })
+        .field("b", "vs", text(&entry.b), "")
+        .level(if entry.same() { Level::Plain } else { Level::Warn })
+        .hidden("same", entry.same())
+}
+
+// Only the differences unless all is set, machine readable output gets
+// everything so consumers don't have to know the field list
+pub fn run(out: &mut dyn OutputSink, a: &MockMsr, b: &MockMsr, all: bool, machine: bool) -> Result<()> {
+    let entries = compare(a, b);
+    let differing = entries.iter().filter(|e| !e.same()).count();
+    for entry in entries.iter().filter(|e| all || machine || !e.same()) {
+        out.record(&record(entry))?;
+    }
+    out.note(&format!("{} of {} fields differ", differing, entries.len()))?;
+    out.record(&Record::new("compare_summary")
+        .hidden("fields", entries.len() as u64)
+        .hidden("differing", differing as u64))
+}
@@ -0,0 +1,356 @@
+use crate::igp::IgpCap;
+use crate::policy;
+use crate::profile::{Profile, Profiles, Threshold};
+use crate::thinkpad::FanLevel;
+use crate::error::{Error, ErrorKind};
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use toml_edit::{ImDocument, Item, Table, Value};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+// Both 1-based, like every editor
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, column)
+}
+
+struct Checker<'a> {
+    text: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, span: Option<Range<usize>>, message: String) {
+        let (line, column) = position(self.text, span.map_or(0, |s| s.start));
+        self.diagnostics.push(Diagnostic { line, column, message });
+    }
+
+    fn uint(&mut self, key: &str, value: &Value) -> Option<u64> {
+        match value.as_integer() {
+            Some(v) if v >= 0 => Some(v as u64),
+            _ => {
+                self.report(value.span(), format!("{} must be a non-negative integer", key));
+                None
+            }
+        }
+    }
+
+    // Celsius or "tjmax-N"
+    fn threshold(&mut self, key: &str, value: &Value) -> bool {
+        if let Some(text) = value.as_str() {
+            return match text.parse::<Threshold>() {
+                Ok(_) => true,
+                Err(e) => {
+                    self.report(value.span(), format!("{}: {}", key, e));
+                    false
+                }
+            };
+        }
+        self.uint(key, value).is_some()
+    }
+
+    fn emergency(&mut self, table: &Table, span: Option<Range<usize>>, exists: impl Fn(&str) -> bool) {
+        let mut triggers = 0;
+        let mut profile = false;
+        for (key, item) in table.iter() {
+            let key_span = table.key(key).and_then(|k| k.span());
+            let Some(value) = item.as_value() else {
+                self.report(key_span, format!("{} in emergency must be a value, not a table", key));
+                continue;
+            };
+            match key {
+                "profile" => match value.as_str() {
+                    Some(name) if exists(name) => profile = true,
+                    Some(name) => {
+                        profile = true;
+                        self.report(value.span(), format!("Emergency profile {} isn't defined", name));
+                    }
+                    None => self.report(value.span(), "profile must be a profile name".to_string()),
+                },
+                "max_celsius" => {
+                    if self.threshold(key, value) {
+                        triggers += 1;
+                    }
+                }
+                "max_throttle_events" => {
+                    if self.uint(key, value).is_some() {
+                        triggers += 1;
+                    }
+                }
+                "window_secs" => {
+                    if self.uint(key, value) == Some(0) {
+                        self.report(value.span(), "window_secs must be at least 1".to_string());
+                    }
+                }
+                _ => self.report(key_span, format!("Unknown key {} in emergency", key)),
+            }
+        }
+        if !profile {
+            self.report(span.clone(), "emergency needs a profile to apply".to_string());
+        }
+        if triggers == 0 {
+            self.report(span, "emergency needs max_celsius or max_throttle_events".to_string());
+        }
+    }
+
+    fn governor(&mut self, table: &Table, span: Option<Range<usize>>) {
+        let mut values = HashMap::new();
+        for (key, item) in table.iter() {
+            let key_span = table.key(key).and_then(|k| k.span());
+            let Some(value) = item.as_value() else {
+                self.report(key_span, format!("{} in governor must be a value, not a table", key));
+                continue;
+            };
+            match key {
+                "target_celsius" => {
+                    self.threshold(key, value);
+                }
+                "min_tdp" | "max_tdp" | "step" | "hysteresis_celsius" | "min_interval_secs" => {
+                    if let Some(v) = self.uint(key, value) {
+                        values.insert(key, (v, value.span()));
+                    }
+                }
+                "controller" => match value.as_str() {
+                    Some("step" | "pid") => (),
+                    _ => self.report(value.span(), "controller must be \"step\" or \"pid\"".to_string()),
+                },
+                "kp" | "ki" | "kd" => match value.as_float().or(value.as_integer().map(|v| v as f64)) {
+                    Some(gain) if gain >= 0.0 => (),
+                    _ => self.report(value.span(), format!("{} must be a non-negative number", key)),
+                },
+                _ => self.report(key_span, format!("Unknown key {} in governor", key)),
+            }
+        }
+        for key in ["target_celsius", "min_tdp", "max_tdp"] {
+            if !values.contains_key(key) && !table.contains_key(key) {
+                self.report(span.clone(), format!("governor needs {}", key));
+            }
+        }
+        if let (Some((min, _)), Some((max, max_span))) = (values.get("min_tdp"), values.get("max_tdp")) {
+            if min > max {
+                self.report(max_span.clone(), format!("max_tdp {} is under min_tdp {}", max, min));
+            }
+        }
+        if let Some((0, step_span)) = values.get("step") {
+            self.report(step_span.clone(), "step must be at least 1".to_string());
+        }
+    }
+
+    fn policy(&mut self, table: &Table, span: Option<Range<usize>>) {
+        for (key, item) in table.iter() {
+            let key_span = table.key(key).and_then(|k| k.span());
+            let Some(value) = item.as_value() else {
+                self.report(key_span, format!("{} in policy must be a value, not a table", key));
+                continue;
+            };
+            match key {
+                "script" => match value.as_str() {
+                    // A policy that doesn't parse is as bad as a typo here
+                    Some(path) => {
+                        if let Err(e) = policy::load(Path::new(path)) {
+                            self.report(value.span(), format!("{:#}", e));
+                        }
+                    }
+                    None => self.report(value.span(), "script must be a path".to_string()),
+                },
+                "min_interval_secs" => {
+                    self.uint(key, value);
+                }
+                _ => self.report(key_span, format!("Unknown key {} in policy", key)),
+            }
+        }
+        if !table.contains_key("script") {
+            self.report(span, "policy needs script".to_string());
+        }
+    }
+
+    fn thinkpad(&mut self, table: &Table) {
+        for (key, item) in table.iter() {
+            let span = table.key(key).and_then(|k| k.span());
+            match (key, item.as_value()) {
+                ("fan_control", Some(value)) if value.as_bool().is_none() => {
+                    self.report(value.span(), "fan_control must be true or false".to_string())
+                }
+                ("fan_control", Some(_)) => (),
+                ("fan_control", None) => self.report(span, "fan_control must be a value, not a table".to_string()),
+                _ => self.report(span, format!("Unknown key {} in thinkpad", key)),
+            }
+        }
+    }
+
+    // Every extends has to lead somewhere without coming back around
+    fn extends(&mut self, profiles: &Table) {
+        let mut chains = Profiles::default();
+        for (name, item) in profiles.iter() {
+            let extends = item.get("extends").and_then(Item::as_str).map(str::to_string);
+            chains.profiles.insert(name.to_string(), Profile { extends, ..Default::default() });
+        }
+        for (name, item) in profiles.iter() {
+            let Some(value) = item.get("extends").filter(|v| v.is_str()) else {
+                continue;
+            };
+            if let Err(e) = chains.chain(name) {
+                self.report(value.span(), e.to_string());
+            }
+        }
+    }
+
+    fn profile(&mut self, name: &str, table: &Table, fan_control: bool) {
+        let mut profile = Profile::default();
+        let mut spans = Vec::new();
+
+        for (key, item) in table.iter() {
+            let span = table.key(key).and_then(|k| k.span());
+            let Some(value) = item.as_value() else {
+                self.report(span, format!("{} in profile {} must be a value, not a table", key, name));
+                continue;
+            };
+            match key {
+                "tdp" => profile.tdp = self.uint(key, value),
+                "tdc" => profile.tdc = self.uint(key, value),
+                "clock_modulation" => profile.clock_modulation = self.uint(key, value),
+                "turbo" => match value.as_bool() {
+                    Some(v) => profile.turbo = Some(v),
+                    None => self.report(value.span(), "turbo must be true or false".to_string()),
+                },
+                "turbo_ratios" => match value.as_array() {
+                    Some(array) => {
+                        let ratios: Vec<Option<u64>> = array.iter().map(|v| self.uint(key, v)).collect();
+                        profile.turbo_ratios = ratios.into_iter().collect();
+                    }
+                    None => self.report(value.span(), "turbo_ratios must be an array of ratios".to_string()),
+                },
+                "pinned_ratio" => profile.pinned_ratio = self.uint(key, value),
+                "core_clock_modulation" => match value.as_array() {
+                    Some(array) => {
+                        let duties: Vec<Option<u64>> = array.iter().map(|v| self.uint(key, v)).collect();
+                        profile.core_clock_modulation = duties.into_iter().collect();
+                    }
+                    None => self.report(value.span(), "core_clock_modulation must be an array of duty cycles".to_string()),
+                },
+                "igp_cap" => match value.as_str().map(|v| IgpCap::from_str(v, false)) {
+                    Some(Ok(cap)) => profile.igp_cap = Some(cap),
+                    _ => self.report(value.span(), "igp_cap must be one of \"low\", \"medium\", \"high\", \"max\"".to_string()),
+                },
+                "extends" => {
+                    if value.as_str().is_none() {
+                        self.report(value.span(), "extends must be a profile name".to_string());
+                    }
+                }
+                "fan_level" => {
+                    let level = match value {
+                        Value::Integer(n) => n.value().to_string().parse::<FanLevel>(),
+                        Value::String(s) => s.value().parse(),
+                        _ => Err("fan_level must be 0 to 7, \"auto\" or \"full-speed\"".to_string()),
+                    };
+                    match level {
+                        Ok(level) => profile.fan_level = Some(level),
+                        Err(message) => self.report(value.span(), message),
+                    }
+                    if !fan_control {
+                        self.report(span.clone(), format!("Profile {} sets fan_level, which needs fan_control = true under [thinkpad]", name));
+                    } else if !cfg!(feature = "thinkpad") {
+                        self.report(span.clone(), "fan_level needs arrctl built with the thinkpad feature".to_string());
+                    }
+                }
+                _ => {
+                    self.report(span, format!("Unknown key {} in profile {}", key, name));
+                    continue;
+                }
+            }
+            spans.push((key.to_string(), item.span().or(span)));
+        }
+
+        for problem in profile.problems() {
+            let span = spans.iter().find(|(key, _)| key == problem.key).and_then(|(_, span)| span.clone());
+            self.report(span.or(table.span()), format!("Profile {}: {}", name, problem.message));
+        }
+    }
+}
+
+// Checks the whole file and reports every problem found, not just the first
+pub fn validate(text: &str) -> Vec<Diagnostic> {
+    let mut checker = Checker { text, diagnostics: Vec::new() };
+    let doc = match ImDocument::parse(text) {
+        Ok(doc) => doc,
+        Err(e) => {
+            checker.report(e.span(), e.message().trim().to_string());
+            return checker.diagnostics;
+        }
+    };
+
+    let fan_control = doc.get("thinkpad").and_then(|t| t.get("fan_control")).and_then(Item::as_bool) == Some(true);
+    for (key, item) in doc.iter() {
+        let span = doc.key(key).and_then(|k| k.span());
+        match (key, item) {
+            ("profiles", Item::Table(profiles)) => {
+                for (name, item) in profiles.iter() {
+                    match item.as_table() {
+                        Some(table) => checker.profile(name, table, fan_control),
+                        None => {
+                            let span = profiles.key(name).and_then(|k| k.span());
+                            checker.report(span, format!("Profile {} must be a table", name));
+                        }
+                    }
+                }
+                checker.extends(profiles);
+            }
+            ("profiles", _) => checker.report(span, "profiles must be a table".to_string()),
+            ("emergency", Item::Table(table)) => {
+                let profiles = doc.get("profiles").and_then(Item::as_table);
+                checker.emergency(table, span, |name| profiles.is_some_and(|p| p.contains_key(name)));
+            }
+            ("emergency", _) => checker.report(span, "emergency must be a table".to_string()),
+            ("governor", Item::Table(table)) => checker.governor(table, span),
+            ("governor", _) => checker.report(span, "governor must be a table".to_string()),
+            ("policy", Item::Table(table)) => {
+                if doc.contains_key("governor") {
+                    checker.report(span.clone(), "governor and policy both pick the limits, keep one of them".to_string());
+                }
+                checker.policy(table, span);
+            }
+            ("policy", _) => checker.report(span, "policy must be a table".to_string()),
+            ("thinkpad", Item::Table(table)) => checker.thinkpad(table),
+            ("thinkpad", _) => checker.report(span, "thinkpad must be a table".to_string()),
+            _ => checker.report(span, format!("Unknown top level key {}", key)),
+        }
+    }
+    checker.diagnostics
+}
+
+pub fn validate_file(path: &Path) -> Result<Vec<Diagnostic>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(validate(&text))
+}
+
+// For startup: no file is fine, a bad one is an error naming every problem
+pub fn check(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let diagnostics = validate_file(path)?;
+    if !diagnostics.is_empty() {
+        let lines: Vec<String> = diagnostics.iter().map(|d| format!("{}:{}", path.display(), d)).collect();
+        bail!(Error::new(ErrorKind::Validation, format!("Refusing to start with a bad config:\n{}", lines.join("\n"))));
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use crate::apply;
+use crate::cpu;
+use crate::error::{Error, ErrorKind};
+use crate::igp::Gt;
+use crate::journal::Journal;
+use crate::msr::{MsrAccess, RegSpec};
+use crate::output::{OutputSink, Record, Value};
+use crate::profile::Profile;
+use crate::regs::{self, msr_platform_info};
+use crate::sku;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+// The desired state is one profile's worth of keys at the top level,
+//
+//   tdp = 25
+//   turbo = true
+pub fn load(path: &Path) -> Result<Profile> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    match toml::from_str::<Profile>(&text) {
+        // There's no [thinkpad] to opt in with here
+        Ok(profile) if profile.fan_level.is_some() => {
+            bail!(Error::new(ErrorKind::Validation, format!("{}: converge doesn't manage fan_level, put it in a profile", path.display())))
+        }
+        Ok(profile) => Ok(profile),
+        Err(e) => bail!(Error::new(ErrorKind::Validation, format!("Bad desired state in {}: {}", path.display(), e.message()))),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Register { spec: RegSpec, from: u64, to: u64 },
+    IgpCap { from_mhz: u64, to_mhz: u64 },
+}
+
+// Only what actually differs, so a converged machine plans nothing
+pub fn plan(msr: &dyn MsrAccess, profile: &Profile, drm: &Path) -> Result<(Vec<Change>, Option<Gt>)> {
+    let mut changes = Vec::new();
+    for (spec, to) in apply::register_writes(msr, profile, &cpu::layout(), true)? {
+        let from = msr.read(spec.reg, spec.cpu)?;
+        if from != to {
+            changes.push(Change::Register { spec, from, to });
+        }
+    }
+    let mut gt = None;
+    if let Some(cap) = profile.igp_cap {
+        let found = Gt::find(drm)?;
+        let (from_mhz, to_mhz) = (found.max_mhz()?, found.cap_mhz(cap)?);
+        if from_mhz != to_mhz {
+            changes.push(Change::IgpCap { from_mhz, to_mhz });
+        }
+        gt = Some(found);
+    }
+    Ok((changes, gt))
+}
+
+fn record(change: &Change) -> Record {
+    match change {
+        Change::Register { spec, from, to } => Record::new("change")
+            .title(format!("{} on CPU{}", regs::name(spec.reg).unwrap_or("register"), spec.cpu))
+            .cpu(spec.cpu)
+            .hidden("register", Value::Hex(spec.reg.into()))
+            .field("from", "", Value::Hex(*from), "")
+            .field("to", "to", Value::Hex(*to), ""),
+        Change::IgpCap { from_mhz, to_mhz } => Record::new("change")
+            .title("Graphics max frequency")
+            .field("from", "", *from_mhz, "MHz")
+            .field("to", "to", *to_mhz, "MHz"),
+    }
+}
+
+// With check nothing gets written, the output says what would change
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess, journal: &Journal, path: &Path, drm: &Path, check: bool) -> Result<bool> {
+    let profile = load(path)?;
+    apply::validate(&profile, &msr_platform_info(msr)?)?;
+    apply::check_fused(&profile, sku::detect())?;
+    let (changes, gt) = plan(msr, &profile, drm)?;
+
+    for change in &changes {
+        out.record(&record(change))?;
+    }
+    if !check {
+        let writes: Vec<(RegSpec, u64)> = changes
+            .iter()
+            .filter_map(|c| match c {
+                Change::Register { spec, to, .. } => Some((*spec, *to)),
+                Change::IgpCap { .. } => None,
+            })
+            .collect();
+        if !writes.is_empty() {
+            journal.apply(msr, &writes)?;
+        }
+        apply::verify_ratios(msr, &profile, &apply::targets(&cpu::layout(), true, regs::MSR_TURBO_RATIOS))?;
+        if let (Some(gt), Some(cap)) = (gt, profile.igp_cap) {
+            if changes.iter().any(|c| matches!(c, Change::IgpCap { .. })) {
+                gt.set_cap(cap)?;
+            }
+        }
+    }
+
+    let changed = !changes.is_empty();
+    let status = match (changed, check) {
+        (false, _) => "unchanged",
+        (true, true) => "would change",
+        (true, false) => "changed",
+    };
+    out.record(&Record::new("converge")
+        .field("status", "Status", status, "")
+        .hidden("changed", changed)
+        .hidden("check", check))?;
+    Ok(changed)
+}
@@ -0,0 +1,97 @@
+use crate::monitor::Sample;
+use crate::replay;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+// `arrctl --monitor --format csv` recordings saved here are what the
+// chassis gets judged by, any *.csv file counts
+pub const DIR: &str = "/var/lib/arrctl/recordings";
+
+// Samples in a row that have to agree, half a minute at the default interval
+const WINDOW: usize = 30;
+// Celsius the hottest core may wander within a window and still be settled
+const SETTLED_SPREAD: u64 = 2;
+// Where the estimate aims, a little under TJmax so it doesn't sit on the edge
+const MARGIN: f32 = 5.0;
+// A fit over points this close in power says more about noise than cooling
+const MIN_WATTS_SPREAD: f32 = 3.0;
+// How far above the estimate a request can go before it's worth a warning
+const SLACK: f32 = 1.1;
+
+// Power the package held with the temperature steady
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub watts: f32,
+    pub celsius: f32,
+}
+
+// Non-overlapping windows in which the temperature settled without
+// throttling, those are the only ones that say what the cooling keeps up with
+pub fn settled(samples: &[Sample], tjmax: u64) -> Vec<Point> {
+    let mut points = Vec::new();
+    for window in samples.chunks_exact(WINDOW) {
+        let temps: Option<Vec<u64>> = window.iter().map(|s| s.cpus.iter().filter_map(|c| c.celsius).max()).collect();
+        let Some(temps) = temps else {
+            continue;
+        };
+        let (min, max) = (temps.iter().min().copied().unwrap_or(0), temps.iter().max().copied().unwrap_or(0));
+        let throttled = window.iter().any(|s| s.cpus.iter().any(|c| c.throttling));
+        if max - min > SETTLED_SPREAD || max >= tjmax || throttled {
+            continue;
+        }
+        points.push(Point {
+            watts: window.iter().map(|s| s.package_watts).sum::<f32>() / WINDOW as f32,
+            celsius: temps.iter().sum::<u64>() as f32 / WINDOW as f32,
+        });
+    }
+    points
+}
+
+// Temperature rises about linearly with dissipated power, fitting that
+// line over the settled points and following it up to just under TJmax
+// gives the power the chassis can get rid of for good
+pub fn sustainable_watts(points: &[Point], tjmax: u64) -> Option<f32> {
+    let (lowest, highest) = points.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| (lo.min(p.watts), hi.max(p.watts)));
+    if points.len() < 2 || highest - lowest < MIN_WATTS_SPREAD {
+        return None;
+    }
+    let n = points.len() as f32;
+    let mean_watts = points.iter().map(|p| p.watts).sum::<f32>() / n;
+    let mean_celsius = points.iter().map(|p| p.celsius).sum::<f32>() / n;
+    let covariance: f32 = points.iter().map(|p| (p.watts - mean_watts) * (p.celsius - mean_celsius)).sum();
+    let variance: f32 = points.iter().map(|p| (p.watts - mean_watts).powi(2)).sum();
+    // Celsius per watt, with more power not heating it up there's no limit to find
+    let slope = covariance / variance;
+    if slope <= 0.0 {
+        return None;
+    }
+    let intercept = mean_celsius - slope * mean_watts;
+    Some((tjmax as f32 - MARGIN - intercept) / slope)
+}
+
+// Every recording in dir, a missing dir is just no history
+pub fn load(dir: &Path, tjmax: u64) -> Result<Vec<Point>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    let mut points = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "csv") {
+            continue;
+        }
+        let text = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let samples = replay::parse(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        points.extend(settled(&samples, tjmax));
+    }
+    Ok(points)
+}
+
+pub fn warning(tdp: u64, sustainable: f32) -> Option<String> {
+    (tdp as f32 > sustainable * SLACK).then(|| {
+        format!("Chassis history suggests ~{:.0} W sustainable; {} W will thermally throttle", sustainable, tdp)
+    })
+}
@@ -0,0 +1,90 @@
+use crate::cpu::{self, CpuInfo};
+use crate::error::{Error, ErrorKind};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const SYSFS: &str = "/sys/devices/system/cpu";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    All,
+    Count(usize),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Target::All),
+            n => match n.parse() {
+                Ok(0) => Err("At least one CPU has to stay online".to_string()),
+                Ok(n) => Ok(Target::Count(n)),
+                Err(_) => Err(format!("Expected a CPU count or \"all\", got {:?}", n)),
+            },
+        }
+    }
+}
+
+// Which CPUs to keep online: the first thread of every core goes before any
+// SMT sibling, since a second thread buys far less than a second core
+pub fn plan(layout: &[CpuInfo], keep: usize) -> Vec<u16> {
+    let mut ranked: Vec<(usize, u32, u32, u16)> = layout
+        .iter()
+        .map(|info| {
+            let thread = layout
+                .iter()
+                .filter(|other| other.package == info.package && other.core == info.core && other.cpu < info.cpu)
+                .count();
+            (thread, info.package, info.core, info.cpu)
+        })
+        .collect();
+    ranked.sort();
+    let mut kept: Vec<u16> = ranked.into_iter().take(keep).map(|(.., cpu)| cpu).collect();
+    kept.sort();
+    kept
+}
+
+fn present(root: &Path) -> Result<Vec<u16>> {
+    let list = fs::read_to_string(root.join("present")).with_context(|| format!("Failed to read {}/present", root.display()))?;
+    cpu::parse_cpu_list(&list).with_context(|| format!("Bad CPU list in {}/present", root.display()))
+}
+
+fn set_online(root: &Path, cpu: u16, online: bool) -> Result<()> {
+    let path = root.join(format!("cpu{}/online", cpu));
+    // CPU0 usually can't be offlined and has no online file at all
+    if !path.exists() {
+        if online {
+            return Ok(());
+        }
+        bail!(Error::new(ErrorKind::Validation, format!("CPU{} can't be taken offline", cpu)));
+    }
+    fs::write(&path, if online { "1" } else { "0" }).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Returns every present CPU with whether it is online afterwards
+pub fn set(root: &Path, target: Target) -> Result<Vec<(u16, bool)>> {
+    let present = present(root)?;
+    if let Target::Count(n) = target {
+        if n > present.len() {
+            bail!(Error::new(ErrorKind::Validation, format!("Only {} CPUs are present", present.len())));
+        }
+    }
+
+    // Offline CPUs have no topology, so bring everything up before planning
+    for &cpu in &present {
+        set_online(root, cpu, true)?;
+    }
+    let keep = match target {
+        Target::All => present.clone(),
+        Target::Count(n) => plan(&cpu::layout_from(root, present.iter().copied()), n),
+    };
+    for &cpu in present.iter().rev() {
+        if !keep.contains(&cpu) {
+            set_online(root, cpu, false)?;
+        }
+    }
+    Ok(present.into_iter().map(|cpu| (cpu, keep.contains(&cpu))).collect())
+}
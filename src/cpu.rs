@@ -0,0 +1,270 @@
+use crate::msr::MsrAccess;
+use crate::regs::{self, Scope};
+use crate::error::{Error, ErrorKind};
+use crate::l10n;
+use crate::output::Record;
+use anyhow::{bail, Result};
+use raw_cpuid::{CpuId, Hypervisor, TopologyType};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub const BCLK_MHZ: f32 = 133.33;
+
+// From how fast the TSC ticks, which is at the base ratio on these parts.
+// Anything far off nominal is a bad measurement, a dump being replayed has
+// a TSC that never moves, and gets the nominal value instead.
+pub fn measure_bclk(msr: &dyn MsrAccess, base_ratio: u64, over: Duration) -> f64 {
+    let tsc = || regs::read_shared(msr, regs::IA32_TIME_STAMP_COUNTER).ok();
+    let (start, before) = (Instant::now(), tsc());
+    std::thread::sleep(over);
+    let (after, elapsed) = (tsc(), start.elapsed());
+    let bclk = match (before, after) {
+        (Some(before), Some(after)) if base_ratio > 0 => after.wrapping_sub(before) as f64 / elapsed.as_micros().max(1) as f64 / base_ratio as f64,
+        _ => 0.0,
+    };
+    if (100.0..=166.0).contains(&bclk) { bclk } else { BCLK_MHZ as f64 }
+}
+
+pub fn cpu_count() -> u16 {
+    unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) as u16 }
+}
+
+// Kernel CPU list format, like "0-1,3"
+pub fn parse_cpu_list(list: &str) -> Option<Vec<u16>> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<u16>().ok()?..=last.parse().ok()?),
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+// Online logical CPUs. These need not be contiguous, offlining SMT
+// siblings leaves holes.
+pub fn online_cpus() -> Vec<u16> {
+    fs::read_to_string("/sys/devices/system/cpu/online")
+        .ok()
+        .and_then(|list| parse_cpu_list(&list))
+        .unwrap_or_else(|| (0..cpu_count()).collect())
+}
+
+// Revision the kernel loaded, the same on every CPU once it's done
+pub fn microcode() -> Option<u64> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    let line = cpuinfo.lines().find(|l| l.starts_with("microcode"))?;
+    let value = line.split_once(':')?.1.trim();
+    u64::from_str_radix(value.strip_prefix("0x").unwrap_or(value), 16).ok()
+}
+
+// CPUID.1 family 6 model, which picks the register layouts. 0 off Intel.
+pub fn model() -> u32 {
+    CpuId::new().get_feature_info().map_or(0, |info| info.model_id() as u32)
+}
+
+// CPUID.06H:EAX[6], whether IA32_PACKAGE_THERM_STATUS is there to read
+pub fn has_package_thermal() -> bool {
+    CpuId::new().get_thermal_power_info().is_some_and(|info| info.has_ptm())
+}
+
+// CPUID.1:ECX[31] and leaf 0x40000000, the name of whatever is underneath
+pub fn hypervisor() -> Option<String> {
+    let cpuid = CpuId::new();
+    if !cpuid.get_feature_info()?.has_hypervisor() {
+        return None;
+    }
+    let name = match cpuid.get_hypervisor_info().map(|info| info.identify()) {
+        Some(Hypervisor::Xen) => "Xen",
+        Some(Hypervisor::VMware) => "VMware",
+        Some(Hypervisor::HyperV) => "Hyper-V",
+        Some(Hypervisor::KVM) => "KVM",
+        Some(Hypervisor::QEMU) => "QEMU",
+        Some(Hypervisor::Bhyve) => "bhyve",
+        Some(Hypervisor::QNX) => "QNX",
+        Some(Hypervisor::ACRN) => "ACRN",
+        Some(Hypervisor::Unknown(..)) | None => "an unknown hypervisor",
+    };
+    Some(name.to_string())
+}
+
+// Guests get whatever MSRs the hypervisor makes up, reads can be fiction and
+// writes go nowhere. Refused unless allowed, and then only with a warning.
+pub fn check_hypervisor(hypervisor: Option<&str>, allowed: bool) -> Result<Option<String>> {
+    let Some(name) = hypervisor else {
+        return Ok(None);
+    };
+    if !allowed {
+        bail!(Error::new(ErrorKind::UnsupportedCpu, format!(
+            "Running under {}, MSR reads may be emulated and writes won't reach the CPU. Run arrctl on the host, \
+             or pass --allow-vm to go ahead anyway",
+            name
+        )));
+    }
+    Ok(Some(format!("Warning: running under {}, nothing arrctl reads or sets here can be trusted to match the hardware", name)))
+}
+
+pub struct Topology {
+    pub packages: usize,
+    pub cores_per_package: usize,
+    pub threads_per_core: usize,
+}
+
+pub fn topology() -> Topology {
+    let mut threads_per_core = 1;
+    let mut threads_per_package = 1;
+    if let Some(levels) = CpuId::new().get_extended_topology_info() {
+        for level in levels {
+            match level.level_type() {
+                TopologyType::SMT => threads_per_core = level.processors().max(1) as usize,
+                TopologyType::Core => threads_per_package = level.processors().max(1) as usize,
+                _ => (),
+            }
+        }
+    }
+    threads_per_package = threads_per_package.max(threads_per_core);
+
+    Topology {
+        packages: (cpu_count() as usize).div_ceil(threads_per_package).max(1),
+        cores_per_package: threads_per_package / threads_per_core,
+        threads_per_core,
+    }
+}
+
+pub fn threads_per_core() -> usize {
+    topology().threads_per_core
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuInfo {
+    pub cpu: u16,
+    pub core: u32,
+    pub package: u32,
+}
+
+pub fn layout() -> Vec<CpuInfo> {
+    layout_from(Path::new("/sys/devices/system/cpu"), online_cpus())
+}
+
+// A CPU without topology files is treated as its own core, which at worst
+// costs a redundant write
+pub fn layout_from(root: &Path, cpus: impl IntoIterator<Item = u16>) -> Vec<CpuInfo> {
+    let read = |cpu: u16, file: &str| -> Option<u32> {
+        fs::read_to_string(root.join(format!("cpu{}/topology/{}", cpu, file))).ok()?.trim().parse().ok()
+    };
+    cpus.into_iter()
+        .map(|cpu| CpuInfo {
+            cpu,
+            core: read(cpu, "core_id").unwrap_or(u32::MAX - cpu as u32),
+            package: read(cpu, "physical_package_id").unwrap_or(0),
+        })
+        .collect()
+}
+
+// The logical CPUs of every core, in package and core id order. Core ids
+// have gaps, Arrandale's are 0 and 2.
+pub fn cores(layout: &[CpuInfo]) -> Vec<Vec<u16>> {
+    let mut keys: Vec<(u32, u32)> = layout.iter().map(|info| (info.package, info.core)).collect();
+    keys.sort();
+    keys.dedup();
+    keys.iter()
+        .map(|&key| layout.iter().filter(|info| (info.package, info.core) == key).map(|info| info.cpu).collect())
+        .collect()
+}
+
+// The "apicid" of each "processor" in /proc/cpuinfo
+pub fn apic_ids(cpuinfo: &str) -> Vec<(u16, u32)> {
+    let mut ids = Vec::new();
+    let mut processor = None;
+    for line in cpuinfo.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "processor" => processor = value.trim().parse().ok(),
+            "apicid" => ids.extend(processor.zip(value.trim().parse().ok())),
+            _ => (),
+        }
+    }
+    ids
+}
+
+// Where each logical CPU sits, for labelling per CPU rows so two sockets or
+// a pair of SMT siblings can be told apart
+pub struct Places {
+    layout: Vec<CpuInfo>,
+    apic: Vec<(u16, u32)>,
+    packages: usize,
+}
+
+impl Places {
+    pub fn new(layout: Vec<CpuInfo>, apic: Vec<(u16, u32)>) -> Self {
+        let mut packages: Vec<u32> = layout.iter().map(|info| info.package).collect();
+        packages.sort();
+        packages.dedup();
+        Places { layout, apic, packages: packages.len() }
+    }
+
+    pub fn current() -> Self {
+        Places::new(layout(), apic_ids(&fs::read_to_string("/proc/cpuinfo").unwrap_or_default()))
+    }
+
+    pub fn apic(&self, cpu: u16) -> Option<u32> {
+        self.apic.iter().find(|(c, _)| *c == cpu).map(|(_, id)| *id)
+    }
+
+    // Titles the record after the CPU's core and APIC ID, the package too
+    // when there's more than one
+    pub fn label(&self, record: Record) -> Record {
+        let Some(info) = record.cpu.and_then(|cpu| self.layout.iter().find(|info| info.cpu == cpu)) else {
+            return record;
+        };
+        let apic = self.apic(info.cpu);
+        let (cpu, core, package) = (info.cpu.to_string(), info.core.to_string(), info.package.to_string());
+        let apic_text = apic.map_or("?".to_string(), |id| id.to_string());
+        let args = [("cpu", cpu.as_str()), ("core", core.as_str()), ("package", package.as_str()), ("apic", apic_text.as_str())];
+        let id = if self.packages > 1 { "cpu-place-package" } else { "cpu-place" };
+        let mut record = record.hidden("core", u64::from(info.core)).hidden("package", u64::from(info.package));
+        if let Some(apic) = apic {
+            record = record.hidden("apic", u64::from(apic));
+        }
+        record.title = Some(l10n::format(id, &args));
+        record
+    }
+
+    // Each package's CPUs together, Westmere-EP numbers them alternating
+    pub fn group(&self, records: &mut [Record]) {
+        if self.packages > 1 {
+            let package = |record: &Record| record.cpu.and_then(|cpu| self.layout.iter().find(|info| info.cpu == cpu)).map(|info| info.package);
+            records.sort_by_key(|record| package(record).unwrap_or(u32::MAX));
+        }
+    }
+}
+
+// Which online CPU speaks for a package, the lowest numbered one of it, or
+// the lowest numbered one of the others when none of it is online
+pub fn stand_in(layout: &[CpuInfo], package: u32) -> Option<u16> {
+    let lowest = |infos: &mut dyn Iterator<Item = &CpuInfo>| infos.map(|info| info.cpu).min();
+    lowest(&mut layout.iter().filter(|info| info.package == package)).or_else(|| lowest(&mut layout.iter()))
+}
+
+// The lowest numbered CPU of each core or package, or every CPU for thread
+// scope. Writing a shared register once per sibling is at best redundant, and
+// racy for read-modify-write.
+pub fn representatives(layout: &[CpuInfo], scope: Scope) -> Vec<u16> {
+    let mut seen = Vec::new();
+    let mut cpus = Vec::new();
+    for info in layout {
+        let key = match scope {
+            Scope::Thread => (info.package, info.core, info.cpu as u32),
+            Scope::Core => (info.package, info.core, 0),
+            Scope::Package => (info.package, 0, 0),
+        };
+        if !seen.contains(&key) {
+            seen.push(key);
+            cpus.push(info.cpu);
+        }
+    }
+    cpus
+}
@@ -0,0 +1,1420 @@
+use crate::advise::{self, PeakDraw};
+use crate::apply;
+use crate::config;
+use crate::emergency::Guard;
+use crate::enforce::{self, Stats, Strategy};
+use crate::governor::Controller;
+use crate::history::History;
+use crate::error::{Error, ErrorKind};
+use crate::journal::Journal;
+use crate::monitor::{Sample, Sampler};
+use crate::msr::MsrAccess;
+use crate::influx::{self, Url};
+use crate::output::{self, Human, OutputSink, Record};
+use crate::policy::{self, Script};
+use crate::polkit;
+use crate::privs::{self, Account};
+use crate::profile::{self, Profile, Profiles};
+use crate::regs::{ia32_clock_modulation, ia32_misc_enable, msr_temperature_target, msr_turbo_limits};
+use crate::{atomic, cpu, history, log, schema, sku, state, systemd, web};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, io};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::unix::UCred;
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::process::Command;
+use tokio::signal::unix::SignalKind;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinSet;
+use tokio::{signal, time};
+
+pub struct Options {
+    pub interval: Duration,
+    pub socket: PathBuf,
+    pub hook: Option<String>,
+    pub config: PathBuf,
+    pub influx: Option<Url>,
+    pub journal: PathBuf,
+    // Profile kept applied across resumes and config changes
+    pub apply: Option<String>,
+    // Takes over from apply while running on battery
+    pub apply_on_battery: Option<String>,
+    // Only watch and export, the emergency profile and governor stay off
+    pub read_only: bool,
+    // Who to run as once the socket and MSR files are open, None stays root
+    pub user: Option<String>,
+    pub audit: Option<PathBuf>,
+    // Where to serve /ws for browsers, nothing by default
+    pub listen: Option<SocketAddr>,
+    // How far back "history" goes
+    pub history: Duration,
+    // Where it's kept over restarts, None only keeps it in memory
+    pub history_file: Option<PathBuf>,
+    // Check and write back the set registers on a schedule, for ECs that
+    // put them back on their own
+    pub enforce: Option<Strategy>,
+}
+
+impl Options {
+    fn journal(&self) -> Journal {
+        let journal = Journal::new(self.journal.clone());
+        match &self.audit {
+            Some(path) => journal.audited(path.clone()),
+            None => journal,
+        }
+    }
+}
+
+pub type SharedMsr = Arc<dyn MsrAccess + Send + Sync>;
+
+// What sample_loop publishes after every tick
+pub struct State {
+    pub sample: Sample,
+    pub peak: PeakDraw,
+    pub tdc_amps: f32,
+}
+
+type Latest = watch::Receiver<Option<Arc<State>>>;
+type Config = watch::Receiver<Arc<Profiles>>;
+// What clients set by hand with "set", kept over the --apply profile
+type Manual = watch::Receiver<Profile>;
+// The profile kept applied, apply or apply_on_battery by the power source
+type Active = watch::Receiver<Option<String>>;
+
+pub const SOCKET: &str = "/run/arrctl.sock";
+
+// What clients asking for writes or history get to use
+#[derive(Clone)]
+struct Control {
+    msr: SharedMsr,
+    journal: Journal,
+    config: Config,
+    manual: watch::Sender<Profile>,
+    // The --apply profile, what temporary sets go back to
+    apply: Active,
+    expiries: Arc<Mutex<Expiries>>,
+    expiries_path: PathBuf,
+    // Once the daemon isn't root anymore
+    peers: Option<Arc<polkit::PeerLookup>>,
+    history: Arc<Mutex<History>>,
+    // None without --enforce-every
+    enforcement: watch::Receiver<Option<Stats>>,
+}
+
+// The settings "set" takes
+pub const SETTABLE: &[&str] = &["tdp", "tdc", "clock_modulation", "turbo"];
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    PowerSource { ac: bool },
+    Emergency { reason: String, profile: String, applied: bool },
+    Throttle { cpu: u16, active: bool },
+    // The config was reloaded
+    ProfileChange { profiles: Vec<String> },
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::PowerSource { ac: true } => "ac",
+            Event::PowerSource { ac: false } => "battery",
+            Event::Emergency { .. } => "emergency",
+            Event::Throttle { .. } => "throttle",
+            Event::ProfileChange { .. } => "profile-change",
+        }
+    }
+
+    // Hooks predate the event stream and only ever got these
+    fn hooked(&self) -> bool {
+        matches!(self, Event::PowerSource { .. } | Event::Emergency { .. })
+    }
+
+    // One line of `arrctl events`, the "event" key says which kind it is
+    pub fn json(&self) -> serde_json::Value {
+        let mut value = match self {
+            Event::PowerSource { ac } => json!({ "event": "power-source", "ac": ac }),
+            Event::Emergency { reason, profile, applied } => {
+                json!({ "event": "enforcement", "profile": profile, "reason": reason, "applied": applied })
+            }
+            Event::Throttle { cpu, active } => json!({ "event": "throttle", "cpu": cpu, "active": active }),
+            Event::ProfileChange { profiles } => json!({ "event": "profile-change", "profiles": profiles }),
+        };
+        value["time_ms"] = json!((output::now_ns() / 1_000_000) as u64);
+        value["schema_version"] = json!(schema::VERSION);
+        value
+    }
+}
+
+pub fn sample_json(sample: &Sample) -> serde_json::Value {
+    let records: Vec<serde_json::Value> = sample.records().iter().map(output::record_json).collect();
+    json!({ "event": "sample", "records": records, "time_ms": (output::now_ns() / 1_000_000) as u64, "schema_version": schema::VERSION })
+}
+
+pub fn run(msr: SharedMsr, opts: Options) -> Result<()> {
+    config::check(&opts.config)?;
+    for name in opts.apply.iter().chain(&opts.apply_on_battery) {
+        if !profile::load(&opts.config)?.profiles.contains_key(name) {
+            bail!(Error::new(ErrorKind::Validation, format!("No profile {} in {}", name, opts.config.display())));
+        }
+    }
+    if !opts.read_only {
+        opts.journal().ensure_finished(" before starting the daemon")?;
+    }
+    let account = opts.user.as_deref().map(|user| privs::lookup(Path::new(privs::PASSWD), user)).transpose()?;
+    // The web UI's apply finds the browser through other users' /proc
+    let peers = match (opts.listen, account) {
+        (Some(_), Some(account)) if account.uid != 0 => Some(Arc::new(polkit::PeerLookup::spawn()?)),
+        _ => None,
+    };
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(serve(msr, opts, account, peers))
+}
+
+async fn serve(msr: SharedMsr, opts: Options, account: Option<Account>, peers: Option<Arc<polkit::PeerLookup>>) -> Result<()> {
+    let (sample_tx, sample_rx) = watch::channel(None);
+    let (event_tx, event_rx) = broadcast::channel(64);
+    let (config_tx, config_rx) = watch::channel(Arc::new(profile::load(&opts.config)?));
+    let (governor_tx, governor_rx) = watch::channel(None);
+    let (manual_tx, manual_rx) = watch::channel(Profile::default());
+    let (enforcement_tx, enforcement_rx) = watch::channel(None);
+    let (active_tx, active_rx) = watch::channel(active_profile(&opts, on_ac_power()));
+
+    let (listener, activated) = listener(&opts.socket)?;
+    let http = match opts.listen {
+        Some(addr) => Some(TcpListener::bind(addr).await.with_context(|| format!("Failed to listen on {}", addr))?),
+        None => None,
+    };
+    // Hooks run as that user too. The socket is left behind on exit, the
+    // next start removes it, unless it's arrctl.socket's.
+    let expiries_path = expiries_path(&opts.journal);
+    let expiries = Expiries::load(&expiries_path, &state::boot_id().unwrap_or_default())?;
+    if let Some(account) = account {
+        let mut kept = vec![opts.journal.as_path(), expiries_path.as_path()];
+        kept.extend(opts.audit.as_deref());
+        kept.extend(opts.history_file.as_deref());
+        privs::hand_over(&kept, account)?;
+        privs::drop_to(account)?;
+    }
+    let mut tasks = JoinSet::new();
+    tasks.spawn(sample_loop(msr.clone(), opts.interval, sample_tx));
+    tasks.spawn(watch_power_supply(event_tx.clone()));
+    if !opts.read_only {
+        tasks.spawn(guard(msr.clone(), opts.journal(), sample_rx.clone(), config_rx.clone(), event_tx.clone()));
+        tasks.spawn(govern(msr.clone(), opts.journal(), sample_rx.clone(), config_rx.clone(), manual_rx.clone(), governor_tx));
+        tasks.spawn(enact(msr.clone(), opts.journal(), sample_rx.clone(), config_rx.clone(), manual_rx.clone()));
+        if let Some(strategy) = opts.enforce.clone() {
+            let events = event_tx.subscribe();
+            tasks.spawn(keep_enforced(msr.clone(), opts.journal(), strategy, active_rx.clone(), config_rx.clone(), manual_rx.clone(), events, enforcement_tx));
+        }
+        tasks.spawn(follow_power_source(event_tx.subscribe(), opts.apply.clone(), opts.apply_on_battery.clone(), active_tx));
+        tasks.spawn(keep_applied(msr.clone(), opts.journal(), active_rx.clone(), config_rx.clone(), manual_rx));
+    }
+    tasks.spawn(watch_throttle(sample_rx.clone(), event_tx.clone()));
+    tasks.spawn(watch_config(opts.config.clone(), config_tx, event_tx.clone()));
+    let history = Arc::new(Mutex::new(load_history(&opts)));
+    tasks.spawn(keep_history(sample_rx.clone(), history.clone(), opts.history_file.clone()));
+    let control = Control {
+        msr: msr.clone(),
+        journal: opts.journal(),
+        config: config_rx,
+        manual: manual_tx,
+        apply: active_rx,
+        expiries: Arc::new(Mutex::new(expiries)),
+        expiries_path,
+        peers,
+        history: history.clone(),
+        enforcement: enforcement_rx.clone(),
+    };
+    if !opts.read_only {
+        rearm(&control);
+    }
+    if let Some(http) = http {
+        tasks.spawn(serve_http(http, control.clone(), sample_rx.clone(), event_tx.clone()));
+    }
+    tasks.spawn(serve_socket(listener, control, sample_rx.clone(), event_tx));
+    if let Some(every) = systemd::watchdog_interval() {
+        tasks.spawn(keep_watchdog(every, sample_rx.clone()));
+    }
+    if let Some(url) = opts.influx {
+        tasks.spawn(push_influx(url, sample_rx, governor_rx, enforcement_rx));
+    }
+    if let Some(hook) = opts.hook {
+        tasks.spawn(run_hooks(hook, event_rx));
+    }
+    systemd::notify(&format!("READY=1\nSTATUS=Sampling every {} ms", opts.interval.as_millis()));
+
+    // Tasks only ever finish on errors
+    let mut terminate = signal::unix::signal(SignalKind::terminate())?;
+    let result = tokio::select! {
+        Some(res) = tasks.join_next() => match res {
+            Ok(Ok(())) => Err(anyhow!("Daemon task exited unexpectedly")),
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(e.into()),
+        },
+        _ = signal::ctrl_c() => Ok(()),
+        _ = terminate.recv() => Ok(()),
+    };
+
+    systemd::notify("STOPPING=1");
+    tasks.abort_all();
+    if let Some(path) = &opts.history_file {
+        if let Err(e) = history.lock().unwrap().save(path) {
+            log!("Couldn't save the history: {:#}", e);
+        }
+    }
+    if !activated {
+        let _ = fs::remove_file(&opts.socket);
+    }
+    result
+}
+
+// Sends "tdp=25 tdc=40" to a running daemon, None if there isn't one
+pub fn route(socket: &Path, settings: &str, duration: Option<Duration>) -> Result<Option<String>> {
+    let mut stream = match std::os::unix::net::UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to connect to {}", socket.display())),
+    };
+    let request = match duration {
+        Some(duration) => format!("set {} for={}\n", settings, duration.as_secs()),
+        None => format!("set {}\n", settings),
+    };
+    io::Write::write_all(&mut stream, request.as_bytes())?;
+    let mut reply = String::new();
+    io::BufRead::read_line(&mut io::BufReader::new(stream), &mut reply).context("The daemon didn't answer")?;
+    let reply = reply.trim();
+    if reply.starts_with("Set ") {
+        return Ok(Some(reply.to_string()));
+    }
+    let kind = if reply.starts_with("Not authorized") { ErrorKind::PermissionDenied } else { ErrorKind::Failure };
+    bail!(Error::new(kind, format!("The daemon: {}, --direct writes without it", reply)))
+}
+
+// The settings of a profile "set" can take, in the form it takes them
+pub fn settings(profile: &Profile) -> String {
+    let values = [profile.tdp.map(|v| v.to_string()), profile.tdc.map(|v| v.to_string()), profile.clock_modulation.map(|v| v.to_string()), profile.turbo.map(|v| v.to_string())];
+    let settings: Vec<String> = SETTABLE.iter().zip(values).filter_map(|(key, value)| Some(format!("{}={}", key, value?))).collect();
+    settings.join(" ")
+}
+
+// The one arrctl.socket passed for path, with true, or a new one
+fn listener(path: &Path) -> Result<(UnixListener, bool)> {
+    let passed = systemd::listen_fds().into_iter().map(std::os::unix::net::UnixListener::from).find(|listener| {
+        listener.local_addr().is_ok_and(|addr| addr.as_pathname() == Some(path))
+    });
+    if let Some(listener) = passed {
+        listener.set_nonblocking(true)?;
+        return Ok((UnixListener::from_std(listener)?, true));
+    }
+    Ok((bind(path)?, false))
+}
+
+fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        fs::remove_file(path).with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))?;
+    // Anyone can look, writes go through polkit
+    fs::set_permissions(path, fs::Permissions::from_mode(0o666)).with_context(|| format!("Failed to open up {}", path.display()))?;
+    Ok(listener)
+}
+
+fn resample(msr: &dyn MsrAccess) -> Result<Sampler> {
+    msr.refresh();
+    let sampler = Sampler::new(msr)?;
+    log!("Online CPUs changed, now sampling {:?}", sampler.cpus());
+    Ok(sampler)
+}
+
+// Pings the watchdog while samples keep coming, so a hung MSR read gets the
+// daemon restarted. --interval has to stay well under WatchdogSec.
+async fn keep_watchdog(every: Duration, mut samples: Latest) -> Result<()> {
+    let mut ticker = time::interval(every);
+    let mut fresh = true;
+    loop {
+        tokio::select! {
+            res = samples.changed() => {
+                res?;
+                fresh = true;
+                continue;
+            }
+            _ = ticker.tick() => (),
+        }
+        if fresh {
+            systemd::notify("WATCHDOG=1");
+            fresh = false;
+        }
+    }
+}
+
+async fn sample_loop(msr: SharedMsr, interval: Duration, tx: watch::Sender<Option<Arc<State>>>) -> Result<()> {
+    let mut sampler = Sampler::new(&*msr)?;
+    let mut peak = PeakDraw::default();
+    let mut ticker = time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if msr.cpus() != sampler.cpus() {
+            sampler = resample(&*msr)?;
+            continue;
+        }
+        // A batch of MSR reads takes microseconds, not worth a blocking thread
+        let sample = match sampler.sample(&*msr) {
+            Ok(sample) => sample,
+            // A CPU went away between the check and the reads
+            Err(_) if msr.cpus() != sampler.cpus() => {
+                sampler = resample(&*msr)?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        peak.update(&sample);
+        let tdc_amps = msr_turbo_limits(&*msr)?.tdc_amps().0 as f32;
+        tx.send_replace(Some(Arc::new(State { sample, peak, tdc_amps })));
+    }
+}
+
+fn on_ac_power() -> Option<bool> {
+    let mut found = None;
+    for entry in fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+        let path = entry.path();
+        if fs::read_to_string(path.join("type")).ok()?.trim() != "Mains" {
+            continue;
+        }
+        let online = fs::read_to_string(path.join("online")).ok()?.trim() == "1";
+        found = Some(found.unwrap_or(false) || online);
+    }
+    found
+}
+
+// The battery profile while on battery, the --apply one otherwise and
+// when there's no telling
+fn active_profile(opts: &Options, ac: Option<bool>) -> Option<String> {
+    match (&opts.apply_on_battery, ac) {
+        (Some(name), Some(false)) => Some(name.clone()),
+        _ => opts.apply.clone(),
+    }
+}
+
+async fn follow_power_source(mut events: broadcast::Receiver<Event>, apply: Option<String>, battery: Option<String>, tx: watch::Sender<Option<String>>) -> Result<()> {
+    loop {
+        match events.recv().await {
+            Ok(Event::PowerSource { ac }) => {
+                let name = if ac { apply.clone() } else { battery.clone().or(apply.clone()) };
+                tx.send_if_modified(|active| std::mem::replace(active, name.clone()) != name);
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            _ => (),
+        }
+    }
+}
+
+// power_supply attributes don't support poll(), so check periodically
+async fn watch_power_supply(tx: broadcast::Sender<Event>) -> Result<()> {
+    let mut last = on_ac_power();
+    let mut ticker = time::interval(Duration::from_secs(2));
+    loop {
+        ticker.tick().await;
+        let now = on_ac_power();
+        if now != last {
+            if let Some(ac) = now {
+                // No receivers just means no hooks are configured
+                let _ = tx.send(Event::PowerSource { ac });
+            }
+            last = now;
+        }
+    }
+}
+
+// Watches the directory rather than the file, editors save by renaming a
+// new file over the old one and that would drop a watch on the file itself
+fn inotify(dir: &Path) -> Result<AsyncFd<OwnedFd>> {
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to create an inotify instance");
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_DELETE;
+    if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), mask) } < 0 {
+        return Err(io::Error::last_os_error()).with_context(|| format!("Failed to watch {}", dir.display()));
+    }
+    Ok(AsyncFd::new(fd)?)
+}
+
+// Whether any event in the buffer names the file
+fn mentions(events: &[u8], name: &OsStr) -> bool {
+    let header = std::mem::size_of::<libc::inotify_event>();
+    let mut rest = events;
+    while rest.len() >= header {
+        let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(rest.as_ptr().cast()) };
+        let end = (header + event.len as usize).min(rest.len());
+        // The name is NUL padded
+        let event_name = rest[header..end].split(|&b| b == 0).next().unwrap_or_default();
+        if event_name == name.as_bytes() {
+            return true;
+        }
+        rest = &rest[end..];
+    }
+    false
+}
+
+async fn changed(fd: &AsyncFd<OwnedFd>, name: &OsStr) -> Result<()> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let mut guard = fd.readable().await?;
+        let n = match guard.try_io(|fd| {
+            let n = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if n < 0 { Err(io::Error::last_os_error()) } else { Ok(n as usize) }
+        }) {
+            Ok(res) => res?,
+            Err(_would_block) => continue,
+        };
+        if mentions(&buf[..n], name) {
+            return Ok(());
+        }
+    }
+}
+
+// A bad edit keeps the old config running, only a clean one replaces it
+fn reload(path: &Path, tx: &watch::Sender<Arc<Profiles>>, events: &broadcast::Sender<Event>) -> Result<()> {
+    config::check(path)?;
+    let profiles = profile::load(path)?;
+    let _ = events.send(Event::ProfileChange { profiles: profiles.profiles.keys().cloned().collect() });
+    tx.send_replace(Arc::new(profiles));
+    log!("Reloaded {}", path.display());
+    Ok(())
+}
+
+async fn watch_config(path: PathBuf, tx: watch::Sender<Arc<Profiles>>, events: broadcast::Sender<Event>) -> Result<()> {
+    let mut hangup = signal::unix::signal(SignalKind::hangup())?;
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        bail!("{} doesn't name a file", path.display());
+    };
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let watch = match inotify(dir) {
+        Ok(fd) => Some(fd),
+        Err(e) => {
+            log!("Not watching the config, reload it with SIGHUP: {:#}", e);
+            None
+        }
+    };
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => (),
+            res = async { changed(watch.as_ref().unwrap(), name).await }, if watch.is_some() => res?,
+        }
+        systemd::notify("RELOADING=1");
+        if let Err(e) = reload(&path, &tx, &events) {
+            log!("Keeping the old config: {:#}", e);
+        }
+        systemd::notify("READY=1");
+    }
+}
+
+async fn push_influx(url: Url, mut samples: Latest, governor: watch::Receiver<Option<Record>>, enforcement: watch::Receiver<Option<Stats>>) -> Result<()> {
+    let token = std::env::var("INFLUX_TOKEN").ok();
+    loop {
+        samples.changed().await?;
+        let Some(state) = samples.borrow_and_update().clone() else {
+            continue;
+        };
+        let now = output::now_ns();
+        let mut records = state.sample.records();
+        records.extend(governor.borrow().clone());
+        records.extend(enforcement.borrow().as_ref().map(|stats| stats.record((now / 1_000_000) as u64)));
+        let body: String = records.iter().map(|r| output::influx_line(r, now) + "\n").collect();
+        let (url, token) = (url.clone(), token.clone());
+        // A slow Influx holds up only this task, not sampling
+        if let Err(e) = tokio::task::spawn_blocking(move || influx::push(&url, token.as_deref(), &body)).await? {
+            log!("Warning: dropped points: {:#}", e);
+        }
+    }
+}
+
+// Puts the emergency profile on when the guard from the config trips
+async fn guard(msr: SharedMsr, journal: Journal, mut samples: Latest, mut config: Config, events: broadcast::Sender<Event>) -> Result<()> {
+    let tjmax = msr_temperature_target(&*msr)?.tjmax().0;
+    let mut guard = config.borrow().emergency.clone().map(|e| Guard::new(e, tjmax));
+    loop {
+        tokio::select! {
+            res = config.changed() => {
+                res?;
+                guard = config.borrow_and_update().emergency.clone().map(|e| Guard::new(e, tjmax));
+                continue;
+            }
+            res = samples.changed() => res?,
+        }
+        let Some(state) = samples.borrow_and_update().clone() else {
+            continue;
+        };
+        let Some(guard) = guard.as_mut() else {
+            continue;
+        };
+        let Some(reason) = guard.check(&state.sample, Instant::now()) else {
+            continue;
+        };
+
+        let name = &guard.settings.profile;
+        let profile = config.borrow().profiles.get(name).cloned();
+        // The <2> makes it critical in the journal, which reads priorities off stderr
+        let applied = match profile.map(|profile| apply::apply(&*msr, &journal, &profile, true)) {
+            Some(Ok(())) => {
+                log!("<2>Emergency: {}, applied profile {}", reason, name);
+                true
+            }
+            Some(Err(e)) => {
+                log!("<2>Emergency: {}, but applying profile {} failed: {:#}", reason, name, e);
+                false
+            }
+            None => {
+                log!("<2>Emergency: {}, but there's no profile {}", reason, name);
+                false
+            }
+        };
+        let _ = events.send(Event::Emergency { reason, profile: name.clone(), applied });
+    }
+}
+
+// Runs the [governor] controller on every sample and publishes its state
+// for the exporter
+async fn govern(msr: SharedMsr, journal: Journal, mut samples: Latest, mut config: Config, manual: Manual, stats: watch::Sender<Option<Record>>) -> Result<()> {
+    let mut controller: Option<Controller> = None;
+    loop {
+        tokio::select! {
+            res = config.changed() => {
+                res?;
+                // Settings may have changed, so it starts over from the TDP as it is
+                controller = None;
+                continue;
+            }
+            res = samples.changed() => res?,
+        }
+        let Some(state) = samples.borrow_and_update().clone() else {
+            continue;
+        };
+        let Some(settings) = config.borrow().governor.clone() else {
+            continue;
+        };
+        // A TDP set by hand wins until the daemon restarts
+        if manual.borrow().tdp.is_some() {
+            controller = None;
+            continue;
+        }
+        let Some(hottest) = state.sample.cpus.iter().filter_map(|c| c.celsius).max() else {
+            continue;
+        };
+        let controller = match &mut controller {
+            Some(controller) => controller,
+            None => controller.insert(Controller::new(settings, msr_turbo_limits(&*msr)?.tdp_watts().whole(), msr_temperature_target(&*msr)?.tjmax().0)),
+        };
+        let now = Instant::now();
+        if let Some(watts) = controller.update(hottest, now) {
+            let profile = Profile { tdp: Some(watts), ..Default::default() };
+            match apply::apply(&*msr, &journal, &profile, true) {
+                Ok(()) => log!("Governor: {} celsius, TDP to {} W", hottest, watts),
+                Err(e) => log!("<3>Governor couldn't set the TDP to {} W: {:#}", watts, e),
+            }
+        }
+        let _ = stats.send(Some(controller.record(hottest, now)));
+    }
+}
+
+// Loaded again on every config change, a script that stopped parsing turns
+// the policy off rather than the daemon
+fn load_policy(profiles: &Profiles) -> Option<(profile::Policy, Script)> {
+    let settings = profiles.policy.clone()?;
+    match policy::load(&settings.script) {
+        Ok(script) => Some((settings, script)),
+        Err(e) => {
+            log!("<3>Policy off: {:#}", e);
+            None
+        }
+    }
+}
+
+// Runs the [policy] script on every sample. Like the governor it writes
+// only when its pick changes, and a setting made by hand wins.
+async fn enact(msr: SharedMsr, journal: Journal, mut samples: Latest, mut config: Config, manual: Manual) -> Result<()> {
+    let mut current = load_policy(&config.borrow_and_update());
+    let mut last: Option<(Instant, Profile)> = None;
+    loop {
+        tokio::select! {
+            res = config.changed() => {
+                res?;
+                current = load_policy(&config.borrow_and_update());
+                last = None;
+                continue;
+            }
+            res = samples.changed() => res?,
+        }
+        let Some((policy, script)) = &current else {
+            continue;
+        };
+        let Some(state) = samples.borrow_and_update().clone() else {
+            continue;
+        };
+        let Some(inputs) = policy::Inputs::from_sample(&state.sample, on_ac_power().unwrap_or(true)) else {
+            continue;
+        };
+        let mut profile = match script.evaluate(&inputs) {
+            Ok(profile) => profile,
+            Err(e) => {
+                log!("<3>Policy: {:#}", e);
+                continue;
+            }
+        };
+        let manual = manual.borrow().clone();
+        if manual.tdp.is_some() {
+            profile.tdp = None;
+        }
+        if manual.tdc.is_some() {
+            profile.tdc = None;
+        }
+        if manual.clock_modulation.is_some() {
+            profile.clock_modulation = None;
+        }
+        if manual.turbo.is_some() {
+            profile.turbo = None;
+        }
+        let now = Instant::now();
+        match &last {
+            Some((_, applied)) if *applied == profile => continue,
+            Some((at, _)) if now.duration_since(*at) < Duration::from_secs(policy.min_interval_secs) => continue,
+            _ if profile == Profile::default() => continue,
+            _ => (),
+        }
+        match apply::apply(&*msr, &journal, &profile, true) {
+            Ok(()) => log!("Policy: {} celsius, {}", inputs.temperature, settings(&profile)),
+            Err(e) => log!("<3>Policy couldn't apply {}: {:#}", settings(&profile), e),
+        }
+        last = Some((now, profile));
+    }
+}
+
+fn clock(id: libc::clockid_t) -> Result<Duration> {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    if unsafe { libc::clock_gettime(id, &mut ts) } < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to read the clock");
+    }
+    Ok(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+// CLOCK_BOOTTIME keeps going through suspend and CLOCK_MONOTONIC doesn't,
+// the difference is how long it's been asleep since boot
+fn time_asleep() -> Result<Duration> {
+    Ok(clock(libc::CLOCK_BOOTTIME)?.saturating_sub(clock(libc::CLOCK_MONOTONIC)?))
+}
+
+const RESUME_POLL: Duration = Duration::from_secs(5);
+
+// Applies the profile and says why. A failure is logged but doesn't stop
+// the daemon, the next resume or config change gets another try.
+fn reapply(msr: &dyn MsrAccess, journal: &Journal, profiles: &Profiles, name: &str, why: &str) {
+    let result = match profiles.profiles.get(name) {
+        Some(profile) => apply::apply(msr, journal, profile, true),
+        None => Err(anyhow!("there's no profile {} anymore", name)),
+    };
+    match result {
+        Ok(()) => log!("Applied profile {} on {}", name, why),
+        Err(e) => log!("<3>Couldn't apply profile {} on {}: {:#}", name, why, e),
+    }
+}
+
+// The firmware puts the registers back to their power on values on every
+// resume from suspend just like on boot, so the profile has to go back too,
+// and whatever was set by hand on top of it
+async fn keep_applied(msr: SharedMsr, journal: Journal, mut name: Active, mut config: Config, manual: Manual) -> Result<()> {
+    let mut ticker = time::interval(RESUME_POLL);
+    let mut asleep = time_asleep()?;
+    let mut why = "start";
+    loop {
+        let profiles = config.borrow_and_update().clone();
+        if let Some(name) = name.borrow_and_update().clone() {
+            reapply(&*msr, &journal, &profiles, &name, why);
+        }
+        let manual = manual.borrow().clone();
+        if manual != Profile::default() {
+            match apply::apply(&*msr, &journal, &manual, true) {
+                Ok(()) => log!("Applied the manual settings on {}", why),
+                Err(e) => log!("<3>Couldn't apply the manual settings on {}: {:#}", why, e),
+            }
+        }
+        why = loop {
+            tokio::select! {
+                res = config.changed() => {
+                    res?;
+                    break "config change";
+                }
+                res = name.changed() => {
+                    res?;
+                    break "power source change";
+                }
+                _ = ticker.tick() => {
+                    let now = time_asleep()?;
+                    let resumed = now > asleep + Duration::from_secs(1);
+                    asleep = now;
+                    if resumed {
+                        break "resume";
+                    }
+                }
+            }
+        };
+    }
+}
+
+// What the registers should hold: the emergency profile once it's been
+// put on, the --apply profile and manual sets otherwise. The governor owns
+// the TDP while it runs, a policy the limits.
+fn enforced(profiles: &Profiles, emergency: Option<&str>, name: Option<&str>, manual: &Profile) -> Profile {
+    if let Some(profile) = emergency.and_then(|name| profiles.profiles.get(name)) {
+        return profile.clone();
+    }
+    let mut profile = name.and_then(|name| profiles.profiles.get(name)).cloned().unwrap_or_default();
+    overlay(&mut profile, manual);
+    if (profiles.governor.is_some() || profiles.policy.is_some()) && manual.tdp.is_none() {
+        profile.tdp = None;
+    }
+    // And the policy whatever else it could pick
+    if profiles.policy.is_some() {
+        if manual.tdc.is_none() {
+            profile.tdc = None;
+        }
+        if manual.clock_modulation.is_none() {
+            profile.clock_modulation = None;
+        }
+        if manual.turbo.is_none() {
+            profile.turbo = None;
+        }
+    }
+    profile
+}
+
+// Writes back whatever changed in the registers the daemon set, on the
+// strategy's schedule. A config reload ends an emergency here like it does
+// for keep_applied.
+#[allow(clippy::too_many_arguments)]
+async fn keep_enforced(
+    msr: SharedMsr,
+    journal: Journal,
+    strategy: Strategy,
+    name: Active,
+    mut config: Config,
+    manual: Manual,
+    mut events: broadcast::Receiver<Event>,
+    stats: watch::Sender<Option<Stats>>,
+) -> Result<()> {
+    let mut emergency: Option<String> = None;
+    let mut current = Stats::default();
+    stats.send_replace(Some(current.clone()));
+    for round in 0u64.. {
+        let sleep = time::sleep(strategy.wait(output::now_ns() as u64 ^ round));
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                res = config.changed() => {
+                    res?;
+                    emergency = None;
+                }
+                res = events.recv() => match res {
+                    Ok(Event::Emergency { profile, applied: true, .. }) => emergency = Some(profile),
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                    _ => (),
+                },
+            }
+        }
+        let profile = enforced(&config.borrow(), emergency.as_deref(), name.borrow().as_deref(), &manual.borrow());
+        if profile == Profile::default() {
+            continue;
+        }
+        let reverted = current.reverted;
+        let now_ms = (output::now_ns() / 1_000_000) as u64;
+        let result = apply::register_writes(&*msr, &profile, &cpu::layout(), true)
+            .and_then(|writes| enforce::check(&*msr, &journal, &writes, &strategy, &mut current, now_ms));
+        match result {
+            Err(e) => log!("<3>Couldn't enforce the settings: {:#}", e),
+            Ok(()) if current.reverted > reverted => {
+                log!("<4>The firmware put back {} register(s), wrote them again", current.reverted - reverted)
+            }
+            Ok(()) => (),
+        }
+        stats.send_replace(Some(current.clone()));
+    }
+    Ok(())
+}
+
+// A broken file costs the history, not the daemon
+fn load_history(opts: &Options) -> History {
+    let Some(path) = &opts.history_file else {
+        return History::new(opts.history);
+    };
+    History::load(path, opts.history, (output::now_ns() / 1_000_000) as u64).unwrap_or_else(|e| {
+        log!("<4>Starting over with an empty history: {:#}", e);
+        History::new(opts.history)
+    })
+}
+
+async fn keep_history(mut samples: Latest, history: Arc<Mutex<History>>, path: Option<PathBuf>) -> Result<()> {
+    let mut saved = Instant::now();
+    loop {
+        samples.changed().await?;
+        let Some(state) = samples.borrow_and_update().clone() else {
+            continue;
+        };
+        let mut history = history.lock().unwrap();
+        history.push((output::now_ns() / 1_000_000) as u64, &state.sample);
+        if let Some(path) = path.as_deref().filter(|_| saved.elapsed() >= history::SAVE_EVERY) {
+            if let Err(e) = history.save(path) {
+                log!("Couldn't save the history: {:#}", e);
+            }
+            saved = Instant::now();
+        }
+    }
+}
+
+// The points of the last so many seconds, as one line of JSON
+fn history_json(control: &Control, seconds: u64) -> serde_json::Value {
+    let from = ((output::now_ns() / 1_000_000) as u64).saturating_sub(seconds.saturating_mul(1000));
+    json!(control.history.lock().unwrap().since(from))
+}
+
+// Turns the per sample throttling flags into start and stop events
+async fn watch_throttle(mut samples: Latest, events: broadcast::Sender<Event>) -> Result<()> {
+    let mut throttling = HashMap::new();
+    loop {
+        samples.changed().await?;
+        let Some(state) = samples.borrow_and_update().clone() else {
+            continue;
+        };
+        for cpu in &state.sample.cpus {
+            let was = throttling.insert(cpu.cpu, cpu.throttling).unwrap_or(false);
+            if was != cpu.throttling {
+                let _ = events.send(Event::Throttle { cpu: cpu.cpu, active: cpu.throttling });
+            }
+        }
+    }
+}
+
+async fn run_hooks(hook: String, mut rx: broadcast::Receiver<Event>) -> Result<()> {
+    loop {
+        let event = match rx.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                log!("Hooks fell behind, skipped {} event(s)", n);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        if !event.hooked() {
+            continue;
+        }
+
+        // Hooks run in the background so a slow one can't hold up the next event
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(&hook).env("ARRCTL_EVENT", event.name());
+        if let Event::Emergency { reason, .. } = &event {
+            command.env("ARRCTL_REASON", reason);
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to run hook {:?}", hook))?;
+        tokio::spawn(async move {
+            match child.wait().await {
+                Ok(status) if !status.success() => log!("Hook exited with {}", status),
+                Err(e) => log!("Failed to wait for hook: {}", e),
+                _ => (),
+            }
+        });
+    }
+}
+
+async fn serve_socket(listener: UnixListener, control: Control, samples: Latest, events: broadcast::Sender<Event>) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let control = control.clone();
+        let samples = samples.clone();
+        let events = events.subscribe();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, control, samples, events).await {
+                log!("Client error: {:#}", e);
+            }
+        });
+    }
+}
+
+fn render(records: &[Record]) -> Result<String> {
+    let mut text = Vec::new();
+    let mut out = Human::new(&mut text);
+    for record in records {
+        out.record(record)?;
+    }
+    Ok(String::from_utf8(text)?)
+}
+
+// The next line of `arrctl events`, None once the daemon is going away.
+// Samples come straight off the watch channel so a slow reader only ever
+// misses old ones.
+async fn next_event(samples: &mut Latest, events: &mut broadcast::Receiver<Event>) -> Result<Option<serde_json::Value>> {
+    loop {
+        return Ok(Some(tokio::select! {
+            res = samples.changed() => {
+                res?;
+                let Some(state) = samples.borrow_and_update().clone() else {
+                    continue;
+                };
+                sample_json(&state.sample)
+            }
+            res = events.recv() => match res {
+                Ok(event) => event.json(),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    json!({ "event": "lagged", "skipped": n, "time_ms": (output::now_ns() / 1_000_000) as u64, "schema_version": schema::VERSION })
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(None),
+            },
+        }));
+    }
+}
+
+// Newline delimited JSON until the client hangs up
+async fn stream_events(write: &mut OwnedWriteHalf, mut samples: Latest, mut events: broadcast::Receiver<Event>) -> Result<()> {
+    samples.borrow_and_update();
+    while let Some(value) = next_event(&mut samples, &mut events).await? {
+        write.write_all(format!("{}\n", value).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+async fn serve_http(listener: TcpListener, control: Control, samples: Latest, events: broadcast::Sender<Event>) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let control = control.clone();
+        let samples = samples.clone();
+        let events = events.subscribe();
+        tokio::spawn(async move {
+            if let Err(e) = handle_http(stream, peer, control, samples, events).await {
+                log!("HTTP client error: {:#}", e);
+            }
+        });
+    }
+}
+
+fn json_response(value: serde_json::Value) -> Vec<u8> {
+    web::response("200 OK", "application/json", value.to_string().as_bytes())
+}
+
+// What the status page shows above the samples
+fn limits_json(control: &Control) -> Result<serde_json::Value> {
+    let limits = msr_turbo_limits(&*control.msr)?;
+    Ok(json!({ "tdp_watts": limits.tdp_watts().0, "tdc_amps": limits.tdc_amps().0, "profile": *control.apply.borrow() }))
+}
+
+// Only from this machine, where the connection can be traced back to the
+// browser asking and polkit can ask its user
+async fn apply_over_http(control: &Control, request: &web::Request, peer: SocketAddr, local: SocketAddr, name: &str) -> Vec<u8> {
+    if !web::same_origin(request) {
+        return web::response("403 Forbidden", "text/plain", b"Only from the page the daemon serves\n");
+    }
+    if !peer.ip().to_canonical().is_loopback() {
+        return web::response("403 Forbidden", "text/plain", b"Applying profiles only works from this machine\n");
+    }
+    let found = match control.peers.clone() {
+        Some(peers) => tokio::task::spawn_blocking(move || peers.find(peer, local)).await.ok().flatten(),
+        None => polkit::tcp_peer(Path::new("/proc"), peer, local),
+    };
+    let Some((pid, uid)) = found else {
+        return web::response("403 Forbidden", "text/plain", b"Not authorized, couldn't find the browser's process\n");
+    };
+    let reply = apply_for(control, Some(pid), uid, name).await;
+    let status = if reply.starts_with("Applied ") {
+        "200 OK"
+    } else if reply.starts_with("Not authorized") {
+        "403 Forbidden"
+    } else if reply.starts_with("No profile ") {
+        "404 Not Found"
+    } else {
+        "500 Internal Server Error"
+    };
+    web::response(status, "text/plain", reply.as_bytes())
+}
+
+async fn handle_http(mut stream: TcpStream, peer: SocketAddr, control: Control, samples: Latest, events: broadcast::Receiver<Event>) -> Result<()> {
+    let head = web::read_head(&mut stream).await?;
+    let Some(request) = web::parse_request(&head) else {
+        stream.write_all(&web::response("400 Bad Request", "text/plain", b"Bad request\n")).await?;
+        return Ok(());
+    };
+    let reply = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => web::response("200 OK", "text/html; charset=utf-8", web::UI.as_bytes()),
+        ("GET", "/ws") if !web::not_cross_site(&request) => web::response("403 Forbidden", "text/plain", b"Only from the page the daemon serves\n"),
+        ("GET", "/ws") => match web::upgrade(&request).filter(|_| request.wants_websocket()) {
+            Some(upgrade) => {
+                stream.write_all(&upgrade).await?;
+                return stream_websocket(stream, samples, events).await;
+            }
+            None => web::response("400 Bad Request", "text/plain", b"/ws only takes websocket connections\n"),
+        },
+        ("GET", "/limits") => match limits_json(&control) {
+            Ok(value) => json_response(value),
+            Err(e) => web::response("500 Internal Server Error", "text/plain", format!("{:#}\n", e).as_bytes()),
+        },
+        ("GET", path) if path == "/history" || path.starts_with("/history?") => {
+            let since = path.split_once('?').and_then(|(_, query)| query.split('&').find_map(|pair| pair.strip_prefix("since=")?.parse().ok()));
+            json_response(history_json(&control, since.unwrap_or(history::FINE_FOR.as_secs())))
+        }
+        ("GET", "/profiles") => {
+            let names: Vec<String> = control.config.borrow().profiles.keys().cloned().collect();
+            json_response(json!({ "profiles": names }))
+        }
+        ("POST", path) if path.starts_with("/apply/") => match web::percent_decode(&path["/apply/".len()..]) {
+            Some(name) => apply_over_http(&control, &request, peer, stream.local_addr()?, &name).await,
+            None => web::response("400 Bad Request", "text/plain", b"Bad profile name\n"),
+        },
+        ("GET" | "POST", _) => web::response("404 Not Found", "text/plain", b"Not found\n"),
+        _ => web::response("405 Method Not Allowed", "text/plain", b"Only GET and POST\n"),
+    };
+    stream.write_all(&reply).await?;
+    Ok(())
+}
+
+// The same JSON as `arrctl events`, one text message each
+async fn stream_websocket(stream: TcpStream, mut samples: Latest, mut events: broadcast::Receiver<Event>) -> Result<()> {
+    let (mut read, mut write) = stream.into_split();
+    samples.borrow_and_update();
+    loop {
+        tokio::select! {
+            value = next_event(&mut samples, &mut events) => {
+                let frame = match value? {
+                    Some(value) => web::frame(web::TEXT, value.to_string().as_bytes()),
+                    None => web::frame(web::CLOSE, &1001u16.to_be_bytes()),
+                };
+                write.write_all(&frame).await?;
+            }
+            frame = web::read_frame(&mut read) => match frame? {
+                Some((web::PING, payload)) => write.write_all(&web::frame(web::PONG, &payload)).await?,
+                Some((web::CLOSE, payload)) => {
+                    // Echoing the close is how the handshake ends
+                    write.write_all(&web::frame(web::CLOSE, &payload[..payload.len().min(2)])).await?;
+                    return Ok(());
+                }
+                Some(_) => (),
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+// For desktop applets and the status page, which don't run as root
+// themselves
+async fn apply_for(control: &Control, pid: Option<i32>, uid: u32, name: &str) -> String {
+    let Some(profile) = control.config.borrow().profiles.get(name).cloned() else {
+        return format!("No profile {}\n", name);
+    };
+    let Some(pid) = pid else {
+        return "Not authorized, no pid for the client\n".to_string();
+    };
+    match polkit::check(polkit::APPLY_PROFILE, pid, uid, &[("profile", name)]).await {
+        Ok(true) => (),
+        Ok(false) => return "Not authorized\n".to_string(),
+        Err(e) => return format!("{:#}\n", e),
+    }
+    match apply::apply(&*control.msr, &control.journal.clone().for_uid(uid), &profile, true) {
+        Ok(()) => {
+            log!("Applied profile {} for uid {}", name, uid);
+            format!("Applied profile {}\n", name)
+        }
+        Err(e) => format!("Couldn't apply profile {}: {:#}\n", name, e),
+    }
+}
+
+// "tdp=25 tdc=40", through the same parser as the profiles file
+pub fn parse_settings(text: &str) -> Result<Profile> {
+    let mut toml = String::new();
+    for setting in text.split_whitespace() {
+        match setting.split_once('=') {
+            Some((key, value)) if SETTABLE.contains(&key) => toml += &format!("{} = {}\n", key, value),
+            _ => bail!(Error::new(ErrorKind::Validation, format!("Can't set {}, only {} are", setting, SETTABLE.join(", ")))),
+        }
+    }
+    let profile: Profile = toml::from_str(&toml).map_err(|e| Error::new(ErrorKind::Validation, e.message().to_string()))?;
+    if let Some(problem) = profile.problems().into_iter().next() {
+        bail!(Error::new(ErrorKind::Validation, problem.message));
+    }
+    Ok(profile)
+}
+
+// What `arrctl --set-tdp` and friends send while the daemon runs, so the
+// next resume or governor step doesn't undo them. With for=<seconds> it
+// goes back once the time is up.
+async fn set_for(control: &Control, cred: UCred, request: &str) -> String {
+    let (settings, seconds): (Vec<&str>, Vec<&str>) = request.split_whitespace().partition(|word| !word.starts_with("for="));
+    let settings = settings.join(" ");
+    let duration = match seconds.last().map(|word| word["for=".len()..].parse::<u64>()) {
+        Some(Ok(seconds)) => Some(Duration::from_secs(seconds)),
+        Some(Err(_)) => return "for= takes seconds\n".to_string(),
+        None => None,
+    };
+    let profile = match parse_settings(&settings) {
+        Ok(profile) => profile,
+        Err(e) => return format!("{:#}\n", e),
+    };
+    let Some(pid) = cred.pid() else {
+        return "Not authorized, no pid for the client\n".to_string();
+    };
+    match polkit::check(polkit::SET_LIMITS, pid, cred.uid(), &[("settings", &settings)]).await {
+        Ok(true) => (),
+        Ok(false) => return "Not authorized\n".to_string(),
+        Err(e) => return format!("{:#}\n", e),
+    }
+    let journal = control.journal.clone().for_uid(cred.uid());
+    let registers = match saved(&*control.msr, &profile) {
+        Ok(registers) => registers,
+        Err(e) => return format!("Couldn't set {}: {:#}\n", settings, e),
+    };
+    if let Err(e) = apply::apply(&*control.msr, &journal, &profile, true) {
+        return format!("Couldn't set {}: {:#}\n", settings, e);
+    }
+    log!("Set {} for uid {}", settings, cred.uid());
+    match (record_set(control, &profile, registers, duration), duration) {
+        (Some(expiry), Some(duration)) => {
+            tokio::spawn(expire(control.clone(), journal, expiry.id, expiry.deadline));
+            format!("Set {} for {} s\n", settings, duration.as_secs())
+        }
+        _ => format!("Set {}\n", settings),
+    }
+}
+
+fn overlay(manual: &mut Profile, profile: &Profile) {
+    manual.tdp = profile.tdp.or(manual.tdp);
+    manual.tdc = profile.tdc.or(manual.tdc);
+    manual.clock_modulation = profile.clock_modulation.or(manual.clock_modulation);
+    manual.turbo = profile.turbo.or(manual.turbo);
+}
+
+// The settings of a set as the registers have them before it
+fn saved(msr: &dyn MsrAccess, profile: &Profile) -> Result<Profile> {
+    let mut saved = Profile::default();
+    if profile.tdp.is_some() || profile.tdc.is_some() {
+        let limits = msr_turbo_limits(msr)?;
+        saved.tdp = profile.tdp.map(|_| limits.tdp_watts().whole());
+        saved.tdc = profile.tdc.map(|_| limits.tdc_amps().whole());
+    }
+    if profile.turbo.is_some() {
+        saved.turbo = Some(!ia32_misc_enable(msr)?.turbo_disable());
+    }
+    if profile.clock_modulation.is_some() {
+        saved.clock_modulation = Some(ia32_clock_modulation(msr, 0)?.duty().code());
+    }
+    Ok(saved)
+}
+
+// Only the settings the other profile has
+fn only(profile: &Profile, settings: &Profile) -> Profile {
+    Profile {
+        tdp: profile.tdp.filter(|_| settings.tdp.is_some()),
+        tdc: profile.tdc.filter(|_| settings.tdc.is_some()),
+        clock_modulation: profile.clock_modulation.filter(|_| settings.clock_modulation.is_some()),
+        turbo: profile.turbo.filter(|_| settings.turbo.is_some()),
+        ..Profile::default()
+    }
+}
+
+// Leaves out the settings the other profile has
+fn without(profile: &Profile, settings: &Profile) -> Profile {
+    Profile {
+        tdp: profile.tdp.filter(|_| settings.tdp.is_none()),
+        tdc: profile.tdc.filter(|_| settings.tdc.is_none()),
+        clock_modulation: profile.clock_modulation.filter(|_| settings.clock_modulation.is_none()),
+        turbo: profile.turbo.filter(|_| settings.turbo.is_none()),
+        ..Profile::default()
+    }
+}
+
+// A temporary set still to go back. Only the settings no later set has
+// changed are left in it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Expiry {
+    id: u64,
+    // Unix time in milliseconds
+    deadline: u64,
+    set: Profile,
+    // What was set by hand before it, and for the rest what the registers
+    // held
+    manual: Profile,
+    registers: Profile,
+}
+
+// The pending expiries, kept next to the journal so a restart arms them
+// again. The ones from before a reboot are dropped, the registers reset
+// with it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Expiries {
+    boot_id: String,
+    pending: Vec<Expiry>,
+}
+
+fn expiries_path(journal: &Path) -> PathBuf {
+    journal.with_extension("expiries.json")
+}
+
+impl Expiries {
+    fn load(path: &Path, boot_id: &str) -> Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Expiries { boot_id: boot_id.to_string(), pending: Vec::new() }),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let value = serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        let mut expiries: Expiries = serde_json::from_value(atomic::unseal(path, value)?).with_context(|| format!("Bad expiries in {}", path.display()))?;
+        if expiries.boot_id != boot_id {
+            expiries = Expiries { boot_id: boot_id.to_string(), pending: Vec::new() };
+        }
+        Ok(expiries)
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        atomic::write(path, atomic::seal(serde_json::to_value(self)?).to_string().as_bytes())
+    }
+
+    // A later set takes the settings it has over from the pending ones,
+    // with what they go back to when it's temporary too
+    fn supersede(&mut self, set: &Profile, manual: &mut Profile, registers: &mut Profile) {
+        for expiry in &mut self.pending {
+            let taken = only(&expiry.set, set);
+            overlay(manual, &only(&expiry.manual, &taken));
+            overlay(registers, &only(&expiry.registers, &taken));
+            expiry.set = without(&expiry.set, set);
+            expiry.manual = without(&expiry.manual, set);
+            expiry.registers = without(&expiry.registers, set);
+        }
+        self.pending.retain(|expiry| expiry.set != Profile::default());
+    }
+}
+
+fn now_ms() -> u64 {
+    (output::now_ns() / 1_000_000) as u64
+}
+
+// What "set" sends manual and the registers to, and with a duration when
+// they go back
+fn record_set(control: &Control, profile: &Profile, registers: Profile, duration: Option<Duration>) -> Option<Expiry> {
+    let mut manual = only(&control.manual.borrow(), profile);
+    let mut registers = registers;
+    let mut expiries = control.expiries.lock().unwrap();
+    expiries.supersede(profile, &mut manual, &mut registers);
+    control.manual.send_modify(|manual| overlay(manual, profile));
+    let expiry = duration.map(|duration| Expiry {
+        id: expiries.pending.iter().map(|e| e.id + 1).max().unwrap_or(0),
+        deadline: now_ms().saturating_add(duration.as_millis() as u64),
+        set: profile.clone(),
+        manual,
+        registers,
+    });
+    expiries.pending.extend(expiry.clone());
+    if let Err(e) = expiries.save(&control.expiries_path) {
+        log!("<3>Couldn't save the temporary settings, a restart would keep them: {:#}", e);
+    }
+    expiry
+}
+
+// Each setting goes back to what was set by hand before, the active
+// profile's where that has it and to what the registers held otherwise
+async fn expire(control: Control, journal: Journal, id: u64, deadline: u64) {
+    time::sleep(Duration::from_millis(deadline.saturating_sub(now_ms()))).await;
+    let expiry = {
+        let mut expiries = control.expiries.lock().unwrap();
+        let Some(index) = expiries.pending.iter().position(|e| e.id == id) else {
+            return;
+        };
+        let expiry = expiries.pending.remove(index);
+        if let Err(e) = expiries.save(&control.expiries_path) {
+            log!("<3>Couldn't save the temporary settings, a restart would revert them again: {:#}", e);
+        }
+        expiry
+    };
+    control.manual.send_modify(|manual| {
+        *manual = without(manual, &expiry.set);
+        overlay(manual, &expiry.manual);
+    });
+    let profiles = control.config.borrow().clone();
+    let active = control.apply.borrow().clone().and_then(|name| profiles.profiles.get(&name).cloned()).unwrap_or_default();
+    let mut revert = expiry.registers.clone();
+    overlay(&mut revert, &only(&active, &expiry.set));
+    overlay(&mut revert, &expiry.manual);
+    match apply::apply(&*control.msr, &journal, &revert, true) {
+        Ok(()) => log!("Temporary {} ran out, back to {}", settings(&expiry.set), settings(&revert)),
+        Err(e) => log!("<3>Couldn't revert the temporary {}: {:#}", settings(&expiry.set), e),
+    }
+}
+
+// The ones a previous run left, from where they are now
+fn rearm(control: &Control) {
+    for expiry in &control.expiries.lock().unwrap().pending {
+        tokio::spawn(expire(control.clone(), control.journal.clone(), expiry.id, expiry.deadline));
+    }
+}
+
+async fn handle_client(stream: UnixStream, control: Control, samples: Latest, events: broadcast::Receiver<Event>) -> Result<()> {
+    let cred = stream.peer_cred()?;
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim() == "events" {
+            return stream_events(&mut write, samples, events).await;
+        }
+        if let Some(name) = line.trim().strip_prefix("apply ") {
+            let reply = apply_for(&control, cred.pid(), cred.uid(), name.trim()).await;
+            write.write_all(reply.as_bytes()).await?;
+            continue;
+        }
+        if let Some(seconds) = line.trim().strip_prefix("history ") {
+            let reply = match seconds.trim().parse() {
+                Ok(seconds) => format!("{}\n", history_json(&control, seconds)),
+                Err(_) => "history takes seconds\n".to_string(),
+            };
+            write.write_all(reply.as_bytes()).await?;
+            continue;
+        }
+        if let Some(settings) = line.trim().strip_prefix("set ") {
+            let reply = set_for(&control, cred, settings.trim()).await;
+            write.write_all(reply.as_bytes()).await?;
+            continue;
+        }
+        let state = samples.borrow().clone();
+        let reply = match (line.trim(), state) {
+            ("status" | "tdc", None) => "No samples yet\n".to_string(),
+            ("status", Some(state)) => {
+                let mut records = state.sample.records();
+                records.extend(control.enforcement.borrow().as_ref().map(|stats| stats.record((output::now_ns() / 1_000_000) as u64)));
+                render(&records)?
+            }
+            ("tdc", Some(state)) => match advise::tdc_advice(&state.peak, state.tdc_amps, sku::detect()) {
+                Some(advice) => render(&[advise::advice_record(advice)])?,
+                None => "No load seen yet\n".to_string(),
+            },
+            ("profiles", _) => {
+                let names: Vec<String> = control.config.borrow().profiles.keys().cloned().collect();
+                render(&[Record::new("profiles").field("names", "Profiles", names.join(", "), "")])?
+            }
+            (cmd, _) => format!("Unknown command: {}\n", cmd),
+        };
+        write.write_all(reply.as_bytes()).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use crate::advise::{self, Observation};
+use crate::msr::MsrAccess;
+use crate::output::{Level, OutputSink, Record};
+use crate::regs::*;
+use crate::signals;
+use crate::sku::{self, Sku};
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const SAMPLE_TIME: Duration = Duration::from_secs(3);
+
+// A TDP override below this share of stock is worth pointing out
+const LOW_TDP: f32 = 0.6;
+
+// Everything doctor looks at, gathered up front so the reasoning can be
+// tested without hardware
+#[derive(Clone, Debug, Default)]
+pub struct Facts {
+    pub turbo_disabled: bool,
+    pub tdp_watts: f32,
+    pub tdp_override: bool,
+    pub stock_tdp: Option<u32>,
+    // Per CPU, right now and since boot
+    pub prochot: Vec<u16>,
+    pub prochot_log: Vec<u16>,
+    pub thermal: Vec<u16>,
+    pub thermal_log: Vec<u16>,
+    // From the package sensor, false where there isn't one
+    pub package_thermal: bool,
+    pub package_thermal_log: bool,
+    // CPU and duty cycle in eighths
+    pub clock_modulation: Vec<(u16, u64)>,
+    pub cstate_limit: Option<u64>,
+    // intel_idle.max_cstate, when the kernel has intel_idle
+    pub max_cstate: Option<u32>,
+    pub scaling_max_mhz: Option<u32>,
+    pub base_mhz: f32,
+    pub observed: Option<Observation>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    // Stable name for scripts, the text is for people
+    pub cause: &'static str,
+    pub text: String,
+    pub level: Level,
+}
+
+fn cpus(list: &[u16]) -> String {
+    let names: Vec<String> = list.iter().map(|cpu| format!("CPU{}", cpu)).collect();
+    names.join(", ")
+}
+
+// Most likely first
+pub fn diagnose(facts: &Facts) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut find = |cause, level, text: String| findings.push(Finding { cause, text, level });
+
+    if !facts.prochot.is_empty() {
+        find("prochot", Level::Bad, format!(
+            "PROCHOT is asserted on {}: something outside the CPU, usually the embedded controller or a failing \
+             charger, is forcing it to its lowest speed",
+            cpus(&facts.prochot)
+        ));
+    } else if !facts.prochot_log.is_empty() {
+        find("prochot", Level::Warn, format!(
+            "PROCHOT was asserted on {} since boot; if it gets slow under load or on battery, suspect the charger or battery",
+            cpus(&facts.prochot_log)
+        ));
+    }
+    if facts.observed.as_ref().is_some_and(|obs| obs.externally_throttled) {
+        find("external_throttle", Level::Bad, "It sat at the lowest ratio while busy, cool and well under TDP and TDC, \
+             so none of the CPU's own limits explain it. Something outside is throttling it, on these laptops usually \
+             the embedded controller over a failed temperature sensor, battery or charger"
+            .to_string());
+    }
+    if !facts.thermal.is_empty() {
+        find("thermal", Level::Bad, format!(
+            "{} is at its thermal limit right now and throttling; clean the fan and heatsink, or repaste",
+            cpus(&facts.thermal)
+        ));
+    } else if !facts.thermal_log.is_empty() {
+        find("thermal", Level::Warn, format!("{} hit the thermal limit since boot", cpus(&facts.thermal_log)));
+    } else if facts.package_thermal {
+        // The TCC goes by the package, it can throttle with every core short of the limit
+        find("thermal", Level::Bad, "The package is at its thermal limit right now and throttling, though no core \
+             reads that hot; clean the fan and heatsink, or repaste"
+            .to_string());
+    } else if facts.package_thermal_log {
+        find("thermal", Level::Warn, "The package hit the thermal limit since boot".to_string());
+    }
+    if !facts.clock_modulation.is_empty() {
+        let (cpu, duty) = facts.clock_modulation[0];
+        find("clock_modulation", Level::Bad, format!(
+            "Clock modulation is on for {} (CPU{} at a {} duty cycle), it skips clock cycles regardless of frequency. \
+             `arrctl --set-clock-modulation off --all-cores` turns it off",
+            cpus(&facts.clock_modulation.iter().map(|(cpu, _)| *cpu).collect::<Vec<_>>()),
+            cpu,
+            DutyCycle::from_code(duty).map_or_else(|| format!("{}/8", duty), |d| d.to_string())
+        ));
+    }
+    if facts.turbo_disabled {
+        find("turbo_disabled", Level::Warn, format!(
+            "Turbo is disabled in IA32_MISC_ENABLE, so it tops out at the {:.0} MHz base clock",
+            facts.base_mhz
+        ));
+    }
+    let low_tdp = match facts.stock_tdp {
+        Some(stock) => facts.tdp_watts < stock as f32 * LOW_TDP,
+        None => facts.tdp_watts < 15.0,
+    };
+    if facts.tdp_override && low_tdp {
+        let stock = facts.stock_tdp.map_or(String::new(), |tdp| format!(", stock is {} W", tdp));
+        find("low_tdp", Level::Warn, format!("The turbo TDP is overridden down to {} W{}", facts.tdp_watts, stock));
+    }
+    if facts.cstate_limit.and_then(CStateLimit::from_code) == Some(CStateLimit::C1) {
+        find("cstate_limit", Level::Warn, "The BIOS limits the package to C1, idle cores never park and the \
+             higher turbo bins for fewer active cores can't be reached"
+            .to_string());
+    }
+    if let Some(max) = facts.max_cstate.filter(|&max| max <= 1) {
+        find("cstate_limit", Level::Warn, format!(
+            "intel_idle.max_cstate={} on the kernel command line keeps idle cores out of C3/C6, which costs turbo bins",
+            max
+        ));
+    }
+    if let Some(max) = facts.scaling_max_mhz.filter(|&max| (max as f32) < facts.base_mhz * 0.95) {
+        find("cpufreq_cap", Level::Warn, format!(
+            "cpufreq caps CPU0 at {} MHz (scaling_max_freq), below the {:.0} MHz base clock; check the power profile \
+             or TLP settings",
+            max, facts.base_mhz
+        ));
+    }
+    findings
+}
+
+fn read_number(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn gather(msr: &dyn MsrAccess, stock: Option<&Sku>, sysfs: &Path, duration: Duration) -> Result<Facts> {
+    let limits = msr_turbo_limits(msr)?;
+    let mut facts = Facts {
+        turbo_disabled: ia32_misc_enable(msr)?.turbo_disable(),
+        tdp_watts: limits.tdp_watts().0 as f32,
+        tdp_override: limits.tdp_override(),
+        stock_tdp: stock.map(|sku| sku.tdp),
+        cstate_limit: read_shared(msr, MSR_PKG_CST_CONFIG_CONTROL).ok().map(|v| MsrPkgCstConfigControl(v).limit()),
+        max_cstate: read_number(&sysfs.join("module/intel_idle/parameters/max_cstate")),
+        scaling_max_mhz: read_number(&sysfs.join("devices/system/cpu/cpu0/cpufreq/scaling_max_freq")).map(|khz| khz / 1000),
+        base_mhz: msr_platform_info(msr)?.base().mhz(),
+        ..Default::default()
+    };
+    // Reading it tells whether it's there as well as CPUID does
+    if let Ok(therm) = ia32_package_therm_status(msr) {
+        facts.package_thermal = therm.thermal_status();
+        facts.package_thermal_log = therm.thermal_log();
+    }
+    for cpu in msr.cpus() {
+        let therm = ia32_therm_status(msr, cpu)?;
+        let lists = [
+            (therm.prochot(), &mut facts.prochot),
+            (therm.prochot_log(), &mut facts.prochot_log),
+            (therm.thermal_status(), &mut facts.thermal),
+            (therm.thermal_log(), &mut facts.thermal_log),
+        ];
+        for (set, list) in lists {
+            if set {
+                list.push(cpu);
+            }
+        }
+        let modulation = ia32_clock_modulation(msr, cpu)?;
+        if modulation.enable() {
+            facts.clock_modulation.push((cpu, modulation.duty_cycle()));
+        }
+    }
+    if !duration.is_zero() {
+        facts.observed = Some(advise::observe(msr, duration)?);
+    }
+    Ok(facts)
+}
+
+pub fn record(finding: &Finding) -> Record {
+    Record::new("finding")
+        .field("text", "Likely cause", finding.text.as_str(), "")
+        .level(finding.level)
+        .hidden("cause", finding.cause)
+}
+
+// Reads only, never writes
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess) -> Result<()> {
+    signals::install()?;
+    let facts = gather(msr, sku::detect(), Path::new("/sys"), SAMPLE_TIME)?;
+    if let Some(obs) = &facts.observed {
+        out.record(&advise::observed_record(obs))?;
+    }
+    let findings = diagnose(&facts);
+    if findings.is_empty() {
+        out.note("Nothing known to slow it down was found. A mostly idle CPU runs at its lowest speed, \
+                  run doctor again while it's busy, or try `arrctl advise` under load.")?;
+    }
+    for finding in &findings {
+        out.record(&record(finding))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use crate::monitor::Sample;
+use crate::profile::Emergency;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Watches samples for the emergency triggers. Trips once and stays tripped,
+// a config reload makes a new one.
+pub struct Guard {
+    pub settings: Emergency,
+    // max_celsius for this CPU's TJmax
+    max_celsius: Option<u64>,
+    events: VecDeque<Instant>,
+    throttling: bool,
+    tripped: bool,
+}
+
+impl Guard {
+    pub fn new(settings: Emergency, tjmax: u64) -> Self {
+        let max_celsius = settings.max_celsius.map(|t| t.resolve(tjmax));
+        Guard { settings, max_celsius, events: VecDeque::new(), throttling: false, tripped: false }
+    }
+
+    // Why the emergency profile has to go on now, if it does
+    pub fn check(&mut self, sample: &Sample, now: Instant) -> Option<String> {
+        if self.tripped {
+            return None;
+        }
+
+        // A throttle event is any CPU starting to throttle, not every
+        // sample it stays throttled for
+        let throttling = sample.cpus.iter().any(|c| c.throttling);
+        if throttling && !self.throttling {
+            self.events.push_back(now);
+        }
+        self.throttling = throttling;
+        let window = Duration::from_secs(self.settings.window_secs);
+        while self.events.front().is_some_and(|&t| now.duration_since(t) > window) {
+            self.events.pop_front();
+        }
+
+        let hottest = sample.cpus.iter().filter_map(|c| c.celsius).max();
+        let reason = match (self.max_celsius, self.settings.max_throttle_events) {
+            (Some(max), _) if hottest.is_some_and(|t| t > max) => {
+                format!("{} celsius is above the {} celsius ceiling", hottest.unwrap(), max)
+            }
+            (_, Some(max)) if self.events.len() as u64 > max => {
+                format!("{} throttle events in {} s, more than {}", self.events.len(), self.settings.window_secs, max)
+            }
+            _ => return None,
+        };
+        self.tripped = true;
+        Some(reason)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::journal::Journal;
+use crate::l10n;
+use crate::msr::{MsrAccess, RegSpec};
+use crate::output::{Level, Record};
+use crate::state::Saved;
+use anyhow::Result;
+use std::time::Duration;
+
+// Some ECs put the limits back on their own schedule, not just on resume.
+// With this the daemon checks the registers it set every so often and
+// writes back whatever changed. The jitter keeps it from settling into
+// lockstep with an EC that polls at a fixed rate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Strategy {
+    pub every: Duration,
+    pub jitter: Duration,
+    // The boot baseline, a register found back at its firmware value means
+    // the firmware fought back rather than something else writing it
+    pub firmware: Vec<Saved>,
+}
+
+// splitmix64, plenty for spreading out checks
+fn mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Strategy {
+    // Somewhere in every ± jitter, never under a second
+    pub fn wait(&self, seed: u64) -> Duration {
+        let jitter = self.jitter.as_millis() as u64;
+        let every = self.every.as_millis() as u64;
+        let offset = if jitter == 0 { 0 } else { mix(seed) % (2 * jitter + 1) };
+        Duration::from_millis((every + offset).saturating_sub(jitter).max(1000))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub checks: u64,
+    // Registers written back, and how many of those were at their firmware
+    // value again
+    pub rewrites: u64,
+    pub reverted: u64,
+    pub last_reverted_ms: Option<u64>,
+}
+
+impl Stats {
+    pub fn record(&self, now_ms: u64) -> Record {
+        let mut record = Record::new("enforcer")
+            .title(l10n::text("enforcement-title"))
+            .field("checks", l10n::text("enforcement-checks"), self.checks, "")
+            .field("rewrites", l10n::text("enforcement-rewrites"), self.rewrites, l10n::text("unit-registers"))
+            .field("reverted", l10n::text("enforcement-reverted"), self.reverted, l10n::text("unit-times"));
+        if let Some(at) = self.last_reverted_ms {
+            record = record.field("last_reverted_seconds", l10n::text("enforcement-last"), now_ms.saturating_sub(at) / 1000, l10n::text("unit-seconds-ago"));
+        }
+        record.level(if self.reverted > 0 { Level::Warn } else { Level::Plain })
+    }
+}
+
+// Compares what should be in the registers with what is and writes back
+// the differences, counting them into stats
+pub fn check(msr: &dyn MsrAccess, journal: &Journal, desired: &[(RegSpec, u64)], strategy: &Strategy, stats: &mut Stats, now_ms: u64) -> Result<()> {
+    stats.checks += 1;
+    let mut changed = Vec::new();
+    for &(spec, value) in desired {
+        let now = msr.read(spec.reg, spec.cpu)?;
+        if now == value {
+            continue;
+        }
+        let firmware = strategy.firmware.iter().any(|s| s.cpu == spec.cpu && s.register == spec.reg && s.value == now);
+        if firmware {
+            stats.reverted += 1;
+            stats.last_reverted_ms = Some(now_ms);
+        }
+        changed.push((spec, value));
+    }
+    if !changed.is_empty() {
+        journal.apply(msr, &changed)?;
+        stats.rewrites += changed.len() as u64;
+    }
+    Ok(())
+}
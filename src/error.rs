@@ -0,0 +1,104 @@
+use crate::{regs, schema};
+use serde_json::json;
+use std::fmt;
+use std::io;
+
+// Anything not listed here exits with 1
+pub const EXIT_CODES: &str = "Exit codes:
+  0  success
+  1  other failure
+  2  unsupported CPU
+  3  permission denied
+  4  register locked by firmware
+  5  invalid arguments
+  6  msr kernel module not loaded";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Failure,
+    UnsupportedCpu,
+    PermissionDenied,
+    RegisterLocked,
+    Validation,
+    NoMsrDriver,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Failure => 1,
+            ErrorKind::UnsupportedCpu => 2,
+            ErrorKind::PermissionDenied => 3,
+            ErrorKind::RegisterLocked => 4,
+            ErrorKind::Validation => 5,
+            ErrorKind::NoMsrDriver => 6,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub register: Option<u32>,
+    pub cpu: Option<u16>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Error { kind, message: message.into(), register: None, cpu: None }
+    }
+
+    pub fn register(mut self, register: u32, cpu: u16) -> Self {
+        self.register = Some(register);
+        self.cpu = Some(cpu);
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+// The first classified error in the chain wins, so context added on top
+// of a locked register write doesn't hide it
+fn find(err: &anyhow::Error) -> Option<&Error> {
+    err.downcast_ref::<Error>()
+        .or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<Error>()))
+}
+
+pub fn kind_of(err: &anyhow::Error) -> ErrorKind {
+    if let Some(e) = find(err) {
+        return e.kind;
+    }
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            if e.kind() == io::ErrorKind::PermissionDenied {
+                return ErrorKind::PermissionDenied;
+            }
+        }
+    }
+    ErrorKind::Failure
+}
+
+// {"error": {"kind": "RegisterLocked", "register": "MSR_TURBO_LIMITS", ...}}
+pub fn to_json(err: &anyhow::Error) -> serde_json::Value {
+    let mut obj = serde_json::Map::new();
+    obj.insert("kind".into(), json!(format!("{:?}", kind_of(err))));
+    obj.insert("exit_code".into(), json!(kind_of(err).exit_code()));
+    obj.insert("message".into(), json!(format!("{:#}", err)));
+    if let Some(e) = find(err) {
+        if let Some(reg) = e.register {
+            obj.insert("register".into(), json!(regs::name(reg).unwrap_or("unknown")));
+            obj.insert("address".into(), json!(format!("{:#x}", reg)));
+        }
+        if let Some(cpu) = e.cpu {
+            obj.insert("cpu".into(), json!(cpu));
+        }
+    }
+    json!({ "error": obj, "schema_version": schema::VERSION })
+}
@@ -0,0 +1,37 @@
+use crate::profile;
+use anyhow::{Context, Result};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// pkexec puts up a dialog, which is the only thing that works when started
+// from a frontend without a terminal. sudo is better at a terminal.
+pub fn helper(graphical: bool, path: &OsStr) -> Option<PathBuf> {
+    let order = if graphical { ["pkexec", "sudo"] } else { ["sudo", "pkexec"] };
+    order.iter().find_map(|name| env::split_paths(path).map(|dir| dir.join(name)).find(|file| file.is_file()))
+}
+
+// The same binary, not whatever arrctl is first in root's PATH
+pub fn command(helper: &Path, exe: &Path, args: impl IntoIterator<Item = OsString>) -> process::Command {
+    let mut cmd = process::Command::new(helper);
+    cmd.arg(exe).args(args);
+    cmd
+}
+
+// Only comes back if there was nothing to run
+pub fn reexec() -> Result<()> {
+    let graphical = env::var_os("WAYLAND_DISPLAY").is_some() || env::var_os("DISPLAY").is_some();
+    let helper = helper(graphical, &env::var_os("PATH").unwrap_or_default())
+        .context("--sudo needs sudo or pkexec, neither is installed")?;
+    let exe = env::current_exe().context("Failed to find the arrctl binary")?;
+    let name = helper.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    eprintln!("Writing MSRs needs root, asking for it through {}", name);
+    // sudo and pkexec drop the environment, ARRCTL_SET goes along as flags
+    // ahead of the ones given, which still win
+    let env_set = env::var(profile::ENV_SET).unwrap_or_default();
+    let forwarded = profile::env_parts(&env_set).map(|part| OsString::from(format!("--set={}", part)));
+    let err = command(&helper, &exe, forwarded.chain(env::args_os().skip(1))).exec();
+    Err(err).with_context(|| format!("Failed to run {}", helper.display()))
+}
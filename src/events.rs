@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+// One line for people, for `arrctl events` without --json
+pub fn describe(event: &Value) -> String {
+    let str = |key: &str| event[key].as_str().unwrap_or_default().to_string();
+    match event["event"].as_str().unwrap_or_default() {
+        "sample" => {
+            let records = event["records"].as_array().map(Vec::as_slice).unwrap_or_default();
+            let watts = records.iter().find_map(|r| r["estimated_watts"].as_f64()).unwrap_or_default();
+            match records.iter().filter_map(|r| r["celsius"].as_u64()).max() {
+                Some(celsius) => format!("Sample: {:.1} estimated W, hottest core {} celsius", watts, celsius),
+                None => format!("Sample: {:.1} estimated W", watts),
+            }
+        }
+        "throttle" => match event["active"].as_bool() {
+            Some(true) => format!("CPU{} started throttling", event["cpu"]),
+            _ => format!("CPU{} stopped throttling", event["cpu"]),
+        },
+        "enforcement" => match event["applied"].as_bool() {
+            Some(true) => format!("Enforcement: {}, applied profile {}", str("reason"), str("profile")),
+            _ => format!("Enforcement: {}, failed to apply profile {}", str("reason"), str("profile")),
+        },
+        "profile-change" => {
+            let names: Vec<&str> = event["profiles"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+            format!("Profiles reloaded: {}", names.join(", "))
+        }
+        "power-source" => match event["ac"].as_bool() {
+            Some(true) => "On AC power".to_string(),
+            _ => "On battery".to_string(),
+        },
+        "lagged" => format!("Fell behind, skipped {} event(s)", event["skipped"]),
+        _ => event.to_string(),
+    }
+}
+
+// Follows the daemon's event stream until it goes away
+pub fn run(out: &mut dyn Write, socket: &Path, json: bool) -> Result<()> {
+    let mut stream = UnixStream::connect(socket)
+        .with_context(|| format!("Failed to connect to {}, is `arrctl daemon` running?", socket.display()))?;
+    stream.write_all(b"events\n")?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if json {
+            writeln!(out, "{}", line)?;
+        } else {
+            let event: Value = serde_json::from_str(&line).with_context(|| format!("Bad event from the daemon: {}", line))?;
+            writeln!(out, "{}", describe(&event))?;
+        }
+        // Frontends read this as it comes, not once a buffer fills
+        out.flush()?;
+    }
+    Ok(())
+}
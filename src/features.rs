@@ -0,0 +1,192 @@
+use crate::cpu;
+use crate::igp::Gt;
+use crate::msr::MsrAccess;
+use crate::output::{OutputSink, Record};
+use crate::profile::MAX_LIMIT;
+use crate::regs::{self, *};
+use crate::sku;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::json;
+use std::path::Path;
+
+// Bumped on any change that could break a consumer, additions included
+pub const SCHEMA_VERSION: u64 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    pub lsb: u8,
+    pub msb: u8,
+    pub writable: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Register {
+    pub name: &'static str,
+    pub address: u32,
+    pub scope: Scope,
+    pub readable: bool,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Setting {
+    pub name: &'static str,
+    pub register: Option<&'static str>,
+    pub unit: Option<&'static str>,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub values: Option<Vec<&'static str>>,
+    pub locked: bool,
+}
+
+// Everything a frontend needs to know to offer the right knobs
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Features {
+    pub schema_version: u64,
+    pub brand: String,
+    pub sku: Option<&'static str>,
+    pub registers: Vec<Register>,
+    pub settings: Vec<Setting>,
+}
+
+fn setting(name: &'static str, register: &'static str, unit: &'static str, range: (u64, u64), locked: bool) -> Setting {
+    Setting { name, register: Some(register), unit: Some(unit), min: Some(range.0), max: Some(range.1), values: None, locked }
+}
+
+pub fn describe(msr: &dyn MsrAccess, brand: &str, drm: &Path) -> Features {
+    // Unreadable means we can't tell, and can't tell is locked
+    let plat_info = msr_platform_info(msr).ok();
+    let limits_locked = !plat_info.as_ref().is_some_and(|p| p.programmable_tdc_tdp());
+
+    let model = cpu::model();
+    let registers = regs::dumped()
+        .map(|def| {
+            let locked = def.address == MSR_TURBO_LIMITS && limits_locked;
+            Register {
+                name: def.name,
+                address: def.address,
+                scope: def.scope,
+                readable: read_shared(msr, def.address).is_ok(),
+                fields: def
+                    .fields_for(model)
+                    .iter()
+                    .map(|f| Field { name: f.name, lsb: f.lsb, msb: f.msb, writable: f.writable && !locked })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    let mut settings = vec![
+        setting("tdp", "MSR_TURBO_LIMITS", "W", (1, MAX_LIMIT), limits_locked),
+        setting("tdc", "MSR_TURBO_LIMITS", "A", (1, MAX_LIMIT), limits_locked),
+        setting("clock_modulation", "IA32_CLOCK_MODULATION", "eighths", (0, 7), false),
+        setting("core_clock_modulation", "IA32_CLOCK_MODULATION", "eighths", (0, 7), false),
+        Setting {
+            name: "turbo",
+            register: Some("IA32_MISC_ENABLE"),
+            unit: None,
+            min: None,
+            max: None,
+            values: Some(vec!["on", "off"]),
+            locked: false,
+        },
+    ];
+    if Gt::find(drm).is_ok() {
+        settings.push(Setting {
+            name: "igp_cap",
+            register: None,
+            unit: None,
+            min: None,
+            max: None,
+            values: Some(vec!["low", "medium", "high", "max"]),
+            locked: false,
+        });
+    }
+
+    Features {
+        schema_version: SCHEMA_VERSION,
+        brand: brand.to_string(),
+        sku: sku::lookup(brand).map(|s| s.name),
+        registers,
+        settings,
+    }
+}
+
+pub fn schema() -> serde_json::Value {
+    let nullable = |ty: &str| json!({ "type": [ty, "null"] });
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "arrctl features",
+        "type": "object",
+        "required": ["schema_version", "brand", "sku", "registers", "settings"],
+        "additionalProperties": false,
+        "properties": {
+            "schema_version": { "const": SCHEMA_VERSION },
+            "brand": { "type": "string" },
+            "sku": nullable("string"),
+            "registers": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["name", "address", "scope", "readable", "fields"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "name": { "type": "string" },
+                        "address": { "type": "integer", "minimum": 0 },
+                        "scope": { "enum": ["thread", "core", "package"] },
+                        "readable": { "type": "boolean" },
+                        "fields": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "required": ["name", "lsb", "msb", "writable"],
+                                "additionalProperties": false,
+                                "properties": {
+                                    "name": { "type": "string" },
+                                    "lsb": { "type": "integer", "minimum": 0, "maximum": 63 },
+                                    "msb": { "type": "integer", "minimum": 0, "maximum": 63 },
+                                    "writable": { "type": "boolean" }
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            "settings": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["name", "register", "unit", "min", "max", "values", "locked"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "name": { "type": "string" },
+                        "register": nullable("string"),
+                        "unit": nullable("string"),
+                        "min": nullable("integer"),
+                        "max": nullable("integer"),
+                        "values": { "type": ["array", "null"], "items": { "type": "string" } },
+                        "locked": { "type": "boolean" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+// The short version for people, machine readable output gets the document
+pub fn records(out: &mut dyn OutputSink, features: &Features) -> Result<()> {
+    for setting in &features.settings {
+        let range = match (&setting.values, setting.min, setting.max) {
+            (Some(values), ..) => values.join("/"),
+            (None, Some(min), Some(max)) => format!("{}-{}", min, max),
+            _ => String::new(),
+        };
+        out.record(&Record::new("setting")
+            .title(setting.name)
+            .field("range", "", range, setting.unit.unwrap_or(""))
+            .field("locked", "", if setting.locked { "locked" } else { "writable" }, ""))?;
+    }
+    Ok(())
+}
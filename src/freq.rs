@@ -0,0 +1,130 @@
+use crate::cpu;
+use crate::error::{Error, ErrorKind};
+use crate::l10n;
+use crate::msr::MsrAccess;
+use crate::output::{Record, Value};
+use crate::profile::Profile;
+use crate::regs::*;
+use crate::sku::Sku;
+use crate::units;
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+// How `arrctl set max-freq` holds the CPU to a frequency. The flex ratio
+// isn't one of them: on these parts it only takes effect from a reset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mechanism {
+    // At or past the top bin, turbo on as fused
+    Uncapped,
+    // The turbo bins lowered to the ratio
+    TurboRatios,
+    // Turbo off, for the base ratio where the bins can't be programmed
+    TurboOff,
+    // Under the base ratio, turbo off and IA32_PERF_CTL held on the ratio
+    PerfCtl,
+}
+
+impl Mechanism {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mechanism::Uncapped => "uncapped",
+            Mechanism::TurboRatios => "turbo_ratios",
+            Mechanism::TurboOff => "turbo_off",
+            Mechanism::PerfCtl => "perf_ctl",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    pub ratio: u64,
+    pub bclk_mhz: f64,
+    pub mechanism: Mechanism,
+    pub profile: Profile,
+}
+
+impl Plan {
+    pub fn mhz(&self) -> f64 {
+        self.ratio as f64 * self.bclk_mhz
+    }
+
+    pub fn record(&self) -> Record {
+        Record::new("max_freq")
+            .title(l10n::text("max-freq-title"))
+            .field("mhz", l10n::text("max-freq-mhz"), Value::Fixed(self.mhz(), 0), "MHz")
+            .field("ratio", l10n::text("max-freq-ratio"), self.ratio, "")
+            .field("bclk_mhz", l10n::text("max-freq-bclk"), Value::Fixed(self.bclk_mhz, 2), "MHz")
+            .field("mechanism", l10n::text("max-freq-mechanism"), self.mechanism.name(), "")
+    }
+}
+
+// The nearest ratio and whichever knob this CPU has for it. fused is the
+// turbo bins as they left the factory, one active core first.
+pub fn plan(plat_info: &MsrPlatformInfo, fused: &[u64], mhz: f64, bclk_mhz: f64) -> Result<Plan> {
+    let ratio = units::nearest_ratio(mhz, bclk_mhz);
+    let base = plat_info.max_non_turbo_ratio();
+    let lowest = plat_info.minimum_ratio().max(1);
+    let top = fused.first().copied().unwrap_or(base).max(base);
+    let mut profile = Profile::default();
+
+    let mechanism = if ratio < lowest {
+        bail!(Error::new(ErrorKind::Validation, format!(
+            "{} MHz is under the lowest this CPU runs at, {:.0} MHz",
+            mhz, lowest as f64 * bclk_mhz
+        )));
+    } else if ratio < base {
+        profile.turbo = Some(false);
+        profile.pinned_ratio = Some(ratio);
+        Mechanism::PerfCtl
+    } else if ratio >= top {
+        profile.turbo = Some(true);
+        if plat_info.programmable_turbo_ratio() && !fused.is_empty() {
+            profile.turbo_ratios = Some(fused.to_vec());
+        }
+        Mechanism::Uncapped
+    } else if plat_info.programmable_turbo_ratio() {
+        profile.turbo = Some(true);
+        profile.turbo_ratios = Some(fused.iter().map(|&bin| bin.min(ratio)).collect());
+        Mechanism::TurboRatios
+    } else if ratio == base {
+        profile.turbo = Some(false);
+        Mechanism::TurboOff
+    } else {
+        bail!(Error::new(ErrorKind::RegisterLocked, format!(
+            "This CPU doesn't allow setting turbo ratios, the closest under {} MHz is turbo off at {:.0} MHz",
+            mhz, base as f64 * bclk_mhz
+        )).register(MSR_TURBO_RATIOS, 0));
+    };
+    Ok(Plan { ratio, bclk_mhz, mechanism, profile })
+}
+
+// With the BCLK measured and the stock bins where the SKU is known, the
+// register's own otherwise, which an earlier cap may have lowered
+pub fn for_cpu(msr: &dyn MsrAccess, stock: Option<&Sku>, value: &str) -> Result<Plan> {
+    let mhz = units::parse_mhz(value).map_err(|e| Error::new(ErrorKind::Validation, e))?;
+    let plat_info = msr_platform_info(msr)?;
+    let fused: Vec<u64> = match stock {
+        Some(sku) => sku.turbo_ratios.iter().map(|&bin| u64::from(bin)).collect(),
+        None => {
+            let now = msr_turbo_ratios(msr)?;
+            (0..4).map(|bin| now.bin(bin)).take_while(|&bin| bin > 0).collect()
+        }
+    };
+    let bclk = cpu::measure_bclk(msr, plat_info.max_non_turbo_ratio(), Duration::from_millis(100));
+    plan(&plat_info, &fused, mhz, bclk)
+}
+
+// Every cpufreq governor but userspace writes IA32_PERF_CTL on its next
+// step, which undoes a pinned ratio. The CPUs running one, with its name,
+// under sysfs as in cores::SYSFS. Without cpufreq nothing else writes it.
+pub fn stepping_governors(sysfs: &Path, cpus: &[u16]) -> Vec<(u16, String)> {
+    cpus.iter()
+        .filter_map(|&cpu| {
+            let governor = fs::read_to_string(sysfs.join(format!("cpu{}/cpufreq/scaling_governor", cpu))).ok()?;
+            let governor = governor.trim();
+            (governor != "userspace").then(|| (cpu, governor.to_string()))
+        })
+        .collect()
+}
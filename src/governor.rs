@@ -0,0 +1,154 @@
+use crate::apply;
+use crate::journal::Journal;
+use crate::l10n;
+use crate::monitor::Sample;
+use crate::msr::MsrAccess;
+use crate::output::{Record, Value};
+use crate::profile::{ControllerKind, Governor, Profile};
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+// The PID terms in watts taken off max_tdp, for monitor
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Terms {
+    pub p: f64,
+    pub i: f64,
+    pub d: f64,
+}
+
+// The controller behind [governor]. Stepping goes down a step when above the
+// target, up one when comfortably below it, nothing in between. The PID one
+// takes its output off max_tdp.
+pub struct Controller {
+    pub settings: Governor,
+    // target_celsius for this CPU's TJmax
+    pub target: u64,
+    watts: u64,
+    last_write: Option<Instant>,
+    // Within the last hour
+    recent: VecDeque<Instant>,
+    pub adjustments: u64,
+    // Celsius seconds over the target
+    integral: f64,
+    last_error: Option<(Instant, f64)>,
+    pub terms: Terms,
+}
+
+impl Controller {
+    // Starts from whatever TDP is set now
+    pub fn new(settings: Governor, watts: u64, tjmax: u64) -> Self {
+        Controller {
+            target: settings.target_celsius.resolve(tjmax),
+            settings,
+            watts,
+            last_write: None,
+            recent: VecDeque::new(),
+            adjustments: 0,
+            integral: 0.0,
+            last_error: None,
+            terms: Terms::default(),
+        }
+    }
+
+    pub fn watts(&self) -> u64 {
+        self.watts
+    }
+
+    // The TDP to write now, if it should change
+    pub fn update(&mut self, celsius: u64, now: Instant) -> Option<u64> {
+        let wanted = match self.settings.controller {
+            ControllerKind::Step => self.step(celsius),
+            ControllerKind::Pid => self.pid(celsius, now),
+        };
+        let s = &self.settings;
+        // Also pulls a TDP from before the governor took over into range
+        let wanted = wanted.clamp(s.min_tdp, s.max_tdp);
+        if wanted == self.watts {
+            return None;
+        }
+        if self.last_write.is_some_and(|t| now.duration_since(t) < Duration::from_secs(s.min_interval_secs)) {
+            return None;
+        }
+        self.watts = wanted;
+        self.last_write = Some(now);
+        self.recent.push_back(now);
+        self.adjustments += 1;
+        Some(wanted)
+    }
+
+    fn step(&self, celsius: u64) -> u64 {
+        let s = &self.settings;
+        if celsius > self.target {
+            self.watts.saturating_sub(s.step)
+        } else if celsius + s.hysteresis_celsius < self.target {
+            self.watts + s.step
+        } else {
+            self.watts
+        }
+    }
+
+    fn pid(&mut self, celsius: u64, now: Instant) -> u64 {
+        let s = &self.settings;
+        let (min, max) = (s.min_tdp as f64, s.max_tdp as f64);
+        let error = celsius as f64 - self.target as f64;
+        let (dt, derivative) = match self.last_error {
+            Some((then, last)) if now > then => {
+                let dt = now.duration_since(then).as_secs_f64();
+                (dt, (error - last) / dt)
+            }
+            _ => (0.0, 0.0),
+        };
+        self.last_error = Some((now, error));
+
+        // Anti-windup: the integral stops growing while the output is pinned
+        // at a limit and the error would only pin it harder. Otherwise a long
+        // cool spell at max_tdp takes as long to unwind once it heats up.
+        let integral = self.integral + error * dt;
+        let unclamped = max - (s.kp * error + s.ki * integral + s.kd * derivative);
+        if !((unclamped > max && error < 0.0) || (unclamped < min && error > 0.0)) {
+            self.integral = integral;
+        }
+        self.terms = Terms { p: s.kp * error, i: s.ki * self.integral, d: s.kd * derivative };
+        let output = max - (self.terms.p + self.terms.i + self.terms.d);
+        output.clamp(min, max).round() as u64
+    }
+
+    pub fn per_hour(&mut self, now: Instant) -> u64 {
+        while self.recent.front().is_some_and(|&t| now.duration_since(t) > HOUR) {
+            self.recent.pop_front();
+        }
+        self.recent.len() as u64
+    }
+
+    pub fn record(&mut self, celsius: u64, now: Instant) -> Record {
+        let per_hour = self.per_hour(now);
+        let mut record = Record::new("governor")
+            .title(l10n::text("governor"))
+            .field("watts", "", self.watts, "W")
+            .field("celsius", l10n::text("governor-at"), celsius, "celsius")
+            .field("target_celsius", l10n::text("governor-target"), self.target, "celsius")
+            .field("adjustments", "", self.adjustments, l10n::text("governor-adjustments"))
+            .field("adjustments_per_hour", "", per_hour, l10n::text("governor-per-hour"));
+        if self.settings.controller == ControllerKind::Pid {
+            record = record
+                .field("p", "P", Value::Fixed(self.terms.p, 2), "W")
+                .field("i", "I", Value::Fixed(self.terms.i, 2), "W")
+                .field("d", "D", Value::Fixed(self.terms.d, 2), "W");
+        }
+        record
+    }
+}
+
+// One `--monitor --govern` sample, None before there are temperatures
+pub fn tick(msr: &dyn MsrAccess, journal: &Journal, controller: &mut Controller, sample: &Sample, now: Instant) -> Result<Option<Record>> {
+    let Some(hottest) = sample.cpus.iter().filter_map(|c| c.celsius).max() else {
+        return Ok(None);
+    };
+    if let Some(watts) = controller.update(hottest, now) {
+        apply::apply(msr, journal, &Profile { tdp: Some(watts), ..Default::default() }, true)?;
+    }
+    Ok(Some(controller.record(hottest, now)))
+}
@@ -0,0 +1,101 @@
+use crate::cpu;
+use crate::history::Point;
+use crate::l10n;
+use crate::monitor::Sample;
+use crate::output::{Record, Value};
+use std::collections::BTreeMap;
+
+// Wide enough that a run at one temperature lands in one or two bands
+pub const TEMPERATURE_BAND: f64 = 5.0;
+// Characters the longest bar can take
+const BAR: f64 = 40.0;
+
+// Time spent in each bucket of something, how a run went at a glance
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub metric: &'static str,
+    width: f64,
+    // Frequencies go to the nearest ratio, temperatures down to the band
+    nearest: bool,
+    pub seconds: BTreeMap<i64, f64>,
+}
+
+impl Histogram {
+    // One bucket per ratio
+    pub fn frequency(bclk_mhz: f64) -> Self {
+        Histogram { metric: "frequency", width: bclk_mhz, nearest: true, seconds: BTreeMap::new() }
+    }
+
+    pub fn temperature() -> Self {
+        Histogram { metric: "temperature", width: TEMPERATURE_BAND, nearest: false, seconds: BTreeMap::new() }
+    }
+
+    pub fn add(&mut self, value: f64, seconds: f64) {
+        let scaled = value / self.width;
+        let bucket = if self.nearest { scaled.round() } else { scaled.floor() };
+        *self.seconds.entry(bucket as i64).or_default() += seconds;
+    }
+
+    fn label(&self, bucket: i64) -> String {
+        let from = bucket as f64 * self.width;
+        match self.nearest {
+            true => format!("{:.0} MHz", from),
+            false => format!("{:.0}-{:.0} celsius", from, from + self.width - 1.0),
+        }
+    }
+
+    // A bar per bucket, scaled to the fullest one
+    pub fn records(&self) -> Vec<Record> {
+        let total: f64 = self.seconds.values().sum();
+        let fullest = self.seconds.values().copied().fold(0.0, f64::max);
+        self.seconds
+            .iter()
+            .map(|(&bucket, &seconds)| {
+                let bar = "#".repeat((seconds / fullest.max(f64::EPSILON) * BAR).round() as usize);
+                Record::new("histogram")
+                    .title(format!("{:<16} {}", self.label(bucket), bar))
+                    .hidden("metric", self.metric)
+                    .hidden("from", Value::Float(bucket as f64 * self.width))
+                    .hidden("seconds", Value::Float(seconds))
+                    .field("percent", "", Value::Fixed(seconds / total.max(f64::EPSILON) * 100.0, 1), l10n::text("unit-of-the-time"))
+            })
+            .collect()
+    }
+}
+
+// Both together, which is what the summaries show
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histograms {
+    pub frequency: Histogram,
+    pub temperature: Histogram,
+}
+
+impl Default for Histograms {
+    fn default() -> Self {
+        Histograms { frequency: Histogram::frequency(cpu::BCLK_MHZ as f64), temperature: Histogram::temperature() }
+    }
+}
+
+impl Histograms {
+    // The mean clock over the CPUs and the hottest core, like history keeps
+    pub fn add_sample(&mut self, sample: &Sample, seconds: f64) {
+        let mhz = sample.cpus.iter().map(|c| c.activity.effective_mhz as f64).sum::<f64>() / sample.cpus.len().max(1) as f64;
+        self.frequency.add(mhz, seconds);
+        if let Some(celsius) = sample.cpus.iter().filter_map(|c| c.celsius).max() {
+            self.temperature.add(celsius as f64, seconds);
+        }
+    }
+
+    pub fn add_point(&mut self, point: &Point) {
+        self.frequency.add(point.mean_mhz as f64, point.seconds as f64);
+        if let Some(celsius) = point.max_celsius {
+            self.temperature.add(celsius as f64, point.seconds as f64);
+        }
+    }
+
+    pub fn records(&self) -> Vec<Record> {
+        let mut records = self.frequency.records();
+        records.extend(self.temperature.records());
+        records
+    }
+}
@@ -0,0 +1,184 @@
+use crate::atomic;
+use crate::error::{Error, ErrorKind};
+use crate::l10n;
+use crate::monitor::Sample;
+use crate::output::{Level, Record, Value};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+pub const DEFAULT_HOURS: &str = "24";
+// Kept across daemon restarts and reboots, the night worth looking at is
+// usually the one before the last restart
+pub const DEFAULT_PATH: &str = "/var/lib/arrctl/history.json";
+// How often the daemon writes it out, a crash loses at most this much
+pub const SAVE_EVERY: Duration = Duration::from_secs(300);
+
+// The last hour at this resolution, older than that gets merged down so a
+// day of one second samples stays in the tens of kilobytes
+pub const FINE: Duration = Duration::from_secs(10);
+pub const FINE_FOR: Duration = Duration::from_secs(3600);
+// Points over the whole retention after downsampling, at least a minute each
+const COARSE_POINTS: u64 = 1440;
+
+// Samples merged into one stretch of time
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub time_ms: u64,
+    pub seconds: u64,
+    pub samples: u64,
+    pub mean_watts: f32,
+    pub max_watts: f32,
+    pub mean_mhz: f32,
+    pub max_celsius: Option<u64>,
+    pub throttled: bool,
+}
+
+impl Point {
+    fn new(time_ms: u64, seconds: u64, sample: &Sample) -> Self {
+        let mhz = sample.cpus.iter().map(|c| c.activity.effective_mhz).sum::<f32>() / sample.cpus.len().max(1) as f32;
+        Point {
+            time_ms,
+            seconds,
+            samples: 1,
+            mean_watts: sample.package_watts,
+            max_watts: sample.package_watts,
+            mean_mhz: mhz,
+            max_celsius: sample.cpus.iter().filter_map(|c| c.celsius).max(),
+            throttled: sample.cpus.iter().any(|c| c.throttling),
+        }
+    }
+
+    // Means weighted by how many samples went into each
+    fn merge(&mut self, other: &Point) {
+        let (a, b) = (self.samples as f32, other.samples as f32);
+        self.mean_watts = (self.mean_watts * a + other.mean_watts * b) / (a + b);
+        self.mean_mhz = (self.mean_mhz * a + other.mean_mhz * b) / (a + b);
+        self.max_watts = self.max_watts.max(other.max_watts);
+        self.max_celsius = self.max_celsius.max(other.max_celsius);
+        self.throttled |= other.throttled;
+        self.samples += other.samples;
+    }
+
+    pub fn record(&self, now_ms: u64) -> Record {
+        let ago = Duration::from_millis(now_ms.saturating_sub(self.time_ms)).as_secs();
+        let mut record = Record::new("history")
+            .title(l10n::format("history-ago", &[("ago", &ago_text(ago))]))
+            .hidden("time_ms", self.time_ms)
+            .hidden("seconds", self.seconds)
+            .hidden("samples", self.samples)
+            .field("mean_watts", l10n::text("history-mean-watts"), Value::Fixed(self.mean_watts as f64, 1), l10n::text("unit-estimated-watts"))
+            .field("max_watts", l10n::text("history-max-watts"), Value::Fixed(self.max_watts as f64, 1), l10n::text("unit-estimated-watts"))
+            .field("mean_mhz", l10n::text("history-mean-mhz"), Value::Fixed(self.mean_mhz as f64, 0), "MHz");
+        if let Some(celsius) = self.max_celsius {
+            record = record.field("max_celsius", l10n::text("history-max-celsius"), celsius, "celsius");
+        }
+        record
+            .field("throttled", l10n::text("history-throttled"), self.throttled, "")
+            .level(if self.throttled { Level::Bad } else { Level::Plain })
+    }
+}
+
+fn ago_text(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+// Ring buffers at two resolutions, samples go into the fine one and age
+// out into the coarse one
+pub struct History {
+    retention: Duration,
+    coarse: Duration,
+    fine: VecDeque<Point>,
+    old: VecDeque<Point>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    fine: VecDeque<Point>,
+    old: VecDeque<Point>,
+}
+
+impl History {
+    pub fn new(retention: Duration) -> Self {
+        let coarse = Duration::from_secs((retention.as_secs() / COARSE_POINTS).max(60));
+        History { retention, coarse, fine: VecDeque::new(), old: VecDeque::new() }
+    }
+
+    // What an earlier run saved, dropping whatever is past retention by
+    // now. A missing file is an empty history.
+    pub fn load(path: &Path, retention: Duration, now_ms: u64) -> Result<Self> {
+        let mut history = History::new(retention);
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(history),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let value = serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        let saved: Saved = serde_json::from_value(atomic::unseal(path, value)?).with_context(|| format!("Bad history in {}", path.display()))?;
+        let keep_from = now_ms.saturating_sub(retention.as_millis() as u64);
+        let kept = |points: VecDeque<Point>| points.into_iter().filter(|p| p.time_ms >= keep_from && p.time_ms <= now_ms).collect();
+        (history.fine, history.old) = (kept(saved.fine), kept(saved.old));
+        Ok(history)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let saved = Saved { fine: self.fine.clone(), old: self.old.clone() };
+        atomic::write(path, atomic::seal(serde_json::to_value(saved)?).to_string().as_bytes())
+    }
+
+    pub fn push(&mut self, time_ms: u64, sample: &Sample) {
+        let point = Point::new(bucket(time_ms, FINE), FINE.as_secs(), sample);
+        match self.fine.back_mut() {
+            Some(last) if last.time_ms == point.time_ms => last.merge(&point),
+            _ => self.fine.push_back(point),
+        }
+
+        let fine_from = time_ms.saturating_sub(FINE_FOR.as_millis() as u64);
+        while let Some(point) = self.fine.front().filter(|p| p.time_ms < fine_from) {
+            let mut point = point.clone();
+            point.time_ms = bucket(point.time_ms, self.coarse);
+            point.seconds = self.coarse.as_secs();
+            match self.old.back_mut() {
+                Some(last) if last.time_ms == point.time_ms => last.merge(&point),
+                _ => self.old.push_back(point),
+            }
+            self.fine.pop_front();
+        }
+        let keep_from = time_ms.saturating_sub(self.retention.as_millis() as u64);
+        while self.old.front().is_some_and(|p| p.time_ms < keep_from) {
+            self.old.pop_front();
+        }
+    }
+
+    // Oldest first
+    pub fn since(&self, from_ms: u64) -> Vec<Point> {
+        self.old.iter().chain(&self.fine).filter(|p| p.time_ms + p.seconds * 1000 > from_ms).cloned().collect()
+    }
+}
+
+fn bucket(time_ms: u64, width: Duration) -> u64 {
+    let width = width.as_millis() as u64;
+    time_ms / width * width
+}
+
+// What `arrctl history show` asks a running daemon for
+pub fn fetch(socket: &Path, since: Duration) -> Result<Vec<Point>> {
+    let mut stream = UnixStream::connect(socket)
+        .with_context(|| format!("Failed to connect to {}, is `arrctl daemon` running?", socket.display()))?;
+    stream.write_all(format!("history {}\n", since.as_secs()).as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).context("The daemon didn't answer")?;
+    match serde_json::from_str(&reply) {
+        Ok(points) => Ok(points),
+        Err(_) => bail!(Error::new(ErrorKind::Failure, format!("The daemon: {}", reply.trim()))),
+    }
+}
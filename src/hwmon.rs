@@ -0,0 +1,158 @@
+use crate::cpu::CpuInfo;
+use crate::error::{Error, ErrorKind};
+use crate::output::Record;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CLASS: &str = "/sys/class/hwmon";
+
+// Celsius apart before the two readings of one core are worth a warning
+pub const DIVERGENCE: u64 = 10;
+
+// Where monitor gets temperatures from. Both come from the same DTS, but
+// coretemp applies the kernel's quirks for boards with a bad slope or TJmax.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TempSource {
+    #[default]
+    Msr,
+    Hwmon,
+}
+
+// The coretemp temp*_input of every logical CPU it covers
+#[derive(Clone, Debug, Default)]
+pub struct Coretemp {
+    inputs: Vec<(u16, PathBuf)>,
+}
+
+// "Core 2" is core_id 2, "Package id 0" names the package the device is for
+enum Label {
+    Core(u32),
+    Package(u32),
+}
+
+fn label(text: &str) -> Option<Label> {
+    let text = text.trim();
+    if let Some(id) = text.strip_prefix("Core ") {
+        return id.parse().ok().map(Label::Core);
+    }
+    text.strip_prefix("Package id ")?.parse().ok().map(Label::Package)
+}
+
+impl Coretemp {
+    // One device per package. Parts without a package sensor, Arrandale
+    // among them, have no "Package id" label, those are single socket anyway.
+    pub fn open(class: &Path, layout: &[CpuInfo]) -> Result<Coretemp> {
+        let entries = fs::read_dir(class).with_context(|| format!("Failed to read {}", class.display()))?;
+        let mut inputs = Vec::new();
+        for entry in entries {
+            let dir = entry?.path();
+            if fs::read_to_string(dir.join("name")).is_ok_and(|name| name.trim() == "coretemp") {
+                inputs.extend(device_inputs(&dir, layout)?);
+            }
+        }
+        if inputs.is_empty() {
+            bail!(Error::new(ErrorKind::Failure, "No coretemp sensors under /sys/class/hwmon, is the coretemp module loaded?"));
+        }
+        inputs.sort();
+        Ok(Coretemp { inputs })
+    }
+
+    pub fn read(&self, cpu: u16) -> Option<u64> {
+        let (_, input) = self.inputs.iter().find(|(c, _)| *c == cpu)?;
+        let millidegrees: u64 = fs::read_to_string(input).ok()?.trim().parse().ok()?;
+        Some((millidegrees + 500) / 1000)
+    }
+}
+
+fn device_inputs(dir: &Path, layout: &[CpuInfo]) -> Result<Vec<(u16, PathBuf)>> {
+    let mut package = 0;
+    let mut cores = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some(sensor) = name.strip_suffix("_label") else {
+            continue;
+        };
+        match fs::read_to_string(&path).ok().as_deref().and_then(label) {
+            Some(Label::Core(core)) => cores.push((core, dir.join(format!("{}_input", sensor)))),
+            Some(Label::Package(id)) => package = id,
+            None => (),
+        }
+    }
+    Ok(layout
+        .iter()
+        .filter(|info| info.package == package)
+        .filter_map(|info| cores.iter().find(|(core, _)| *core == info.core).map(|(_, input)| (info.cpu, input.clone())))
+        .collect())
+}
+
+// One core reading differently through the MSR and coretemp
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub cpu: u16,
+    pub msr_celsius: u64,
+    pub hwmon_celsius: u64,
+}
+
+pub fn diverges(cpu: u16, msr_celsius: Option<u64>, hwmon_celsius: Option<u64>) -> Option<Divergence> {
+    let (msr_celsius, hwmon_celsius) = (msr_celsius?, hwmon_celsius?);
+    (msr_celsius.abs_diff(hwmon_celsius) > DIVERGENCE).then_some(Divergence { cpu, msr_celsius, hwmon_celsius })
+}
+
+// A fan*_input of any hwmon driver, thinkpad, applesmc, dell_smm and the
+// Super I/O ones all use the same files
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fan {
+    // The driver's name, "thinkpad" and so on
+    pub device: String,
+    // fan*_label where the driver has one, otherwise fan1 and so on
+    pub label: String,
+    input: PathBuf,
+}
+
+impl Fan {
+    pub fn rpm(&self) -> Option<u64> {
+        fs::read_to_string(&self.input).ok()?.trim().parse().ok()
+    }
+}
+
+// Every fan there is, none without hwmon
+pub fn fans(class: &Path) -> Vec<Fan> {
+    let Ok(entries) = fs::read_dir(class) else {
+        return Vec::new();
+    };
+    let mut devices: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    // hwmon10 after hwmon9
+    devices.sort_by_key(|dir| dir.to_str().and_then(|d| d.rsplit("hwmon").next()?.parse::<u32>().ok()).unwrap_or(u32::MAX));
+    let mut fans = Vec::new();
+    for dir in devices {
+        let Ok(device) = fs::read_to_string(dir.join("name")) else {
+            continue;
+        };
+        let Ok(files) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut inputs: Vec<String> = files
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str()?.strip_suffix("_input").filter(|s| s.starts_with("fan")).map(str::to_string))
+            .collect();
+        inputs.sort_by_key(|name| name[3..].parse::<u32>().unwrap_or(u32::MAX));
+        for name in inputs {
+            let label = fs::read_to_string(dir.join(format!("{}_label", name))).map_or(name.clone(), |l| l.trim().to_string());
+            fans.push(Fan { device: device.trim().to_string(), label, input: dir.join(format!("{}_input", name)) });
+        }
+    }
+    fans
+}
+
+pub fn fan_record(fan: &Fan, rpm: u64) -> Record {
+    Record::new("fan")
+        .title(format!("{} {}", fan.device, fan.label))
+        .hidden("device", fan.device.as_str())
+        .hidden("fan", fan.label.as_str())
+        .field("rpm", "", rpm, "RPM")
+}
@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DRM: &str = "/sys/class/drm";
+
+// How much of the GT frequency range graphics may use
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IgpCap {
+    Low,
+    Medium,
+    High,
+    Max,
+}
+
+impl IgpCap {
+    fn fraction(self) -> f32 {
+        match self {
+            IgpCap::Low => 0.25,
+            IgpCap::Medium => 0.5,
+            IgpCap::High => 0.75,
+            IgpCap::Max => 1.0,
+        }
+    }
+}
+
+// The i915 GT frequency controls of one card
+pub struct Gt {
+    dir: PathBuf,
+}
+
+fn read_mhz(path: &Path) -> Result<u64> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    text.trim().parse().with_context(|| format!("Bad frequency in {}", path.display()))
+}
+
+impl Gt {
+    pub fn find(drm: &Path) -> Result<Gt> {
+        if let Ok(entries) = fs::read_dir(drm) {
+            let mut cards: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+            cards.sort();
+            if let Some(dir) = cards.into_iter().find(|dir| dir.join("gt_max_freq_mhz").exists()) {
+                return Ok(Gt { dir });
+            }
+        }
+        // Ironlake graphics leave the split to intel_ips, which has no knob
+        bail!("No GT frequency controls under {}, is i915 loaded and new enough?", drm.display());
+    }
+
+    // Hardware minimum and maximum
+    pub fn range(&self) -> Result<(u64, u64)> {
+        Ok((read_mhz(&self.dir.join("gt_RPn_freq_mhz"))?, read_mhz(&self.dir.join("gt_RP0_freq_mhz"))?))
+    }
+
+    pub fn max_mhz(&self) -> Result<u64> {
+        read_mhz(&self.dir.join("gt_max_freq_mhz"))
+    }
+
+    pub fn cap_mhz(&self, cap: IgpCap) -> Result<u64> {
+        let (min, max) = self.range()?;
+        Ok(min + ((max - min) as f32 * cap.fraction()).round() as u64)
+    }
+
+    pub fn set_cap(&self, cap: IgpCap) -> Result<u64> {
+        let mhz = self.cap_mhz(cap)?;
+        let path = self.dir.join("gt_max_freq_mhz");
+        fs::write(&path, mhz.to_string()).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(mhz)
+    }
+}
@@ -0,0 +1,112 @@
+use crate::output::{self, OutputSink, Record};
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Points wait this long at most before going out together
+const BATCH: Duration = Duration::from_secs(1);
+
+// Only plain http, Influx on a homelab box rarely has TLS in front of it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    // The full write endpoint, like http://grafana:8086/api/v2/write?org=home&bucket=arrctl
+    // or http://grafana:8086/write?db=arrctl for 1.x
+    pub fn parse(url: &str) -> Result<Url> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            bail!("Only http:// Influx URLs are supported, got {:?}", url);
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().with_context(|| format!("Bad port in {:?}", url))?),
+            None => (authority, 8086),
+        };
+        if host.is_empty() {
+            bail!("No host in {:?}", url);
+        }
+        Ok(Url { host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+// INFLUX_TOKEN from the environment, so it doesn't show up in ps
+pub fn push(url: &Url, token: Option<&str>, body: &str) -> Result<()> {
+    let addr = format!("{}:{}", url.host, url.port);
+    let mut stream = TcpStream::connect(&addr).with_context(|| format!("Failed to connect to {}", addr))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.host,
+        body.len()
+    );
+    if let Some(token) = token {
+        request += &format!("Authorization: Token {}\r\n", token);
+    }
+    request += "\r\n";
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+
+    let mut status = String::new();
+    BufReader::new(&stream).read_line(&mut status).context("No reply from Influx")?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => bail!("Influx refused the write: {}", status.trim()),
+    }
+}
+
+// Sends records as line protocol instead of printing them. A failed push
+// drops that batch with a warning, an Influx restart shouldn't end a monitor.
+pub struct Push {
+    url: Url,
+    token: Option<String>,
+    lines: String,
+    since: Instant,
+}
+
+impl Push {
+    pub fn new(url: Url) -> Self {
+        Push { url, token: std::env::var("INFLUX_TOKEN").ok(), lines: String::new(), since: Instant::now() }
+    }
+
+    fn flush(&mut self) {
+        if !self.lines.is_empty() {
+            if let Err(e) = push(&self.url, self.token.as_deref(), &self.lines) {
+                eprintln!("Warning: dropped points: {:#}", e);
+            }
+            self.lines.clear();
+        }
+        self.since = Instant::now();
+    }
+}
+
+impl OutputSink for Push {
+    fn record(&mut self, record: &Record) -> Result<()> {
+        if self.lines.is_empty() {
+            self.since = Instant::now();
+        }
+        self.lines += &output::influx_line(record, output::now_ns());
+        self.lines.push('\n');
+        if self.since.elapsed() >= BATCH {
+            self.flush();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush();
+        Ok(())
+    }
+}
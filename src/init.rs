@@ -0,0 +1,289 @@
+use crate::atomic;
+use crate::daemon;
+use crate::error::{Error, ErrorKind};
+use crate::l10n;
+use crate::msr::MsrAccess;
+use crate::output::{OutputSink, Record};
+use crate::privs;
+use crate::regs::msr_turbo_limits;
+use crate::sku::Sku;
+use crate::status;
+use crate::units;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+pub const SYSTEMD_DIR: &str = "/etc/systemd/system";
+pub const UNIT: &str = "arrctl.service";
+pub const SOCKET_UNIT: &str = "arrctl.socket";
+
+// Under this Arrandale loses more to the uncore than it saves on the cores
+pub const MIN_TDP: u64 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Goal {
+    Battery,
+    Quiet,
+    Performance,
+}
+
+impl Goal {
+    fn name(self) -> &'static str {
+        match self {
+            Goal::Battery => "battery",
+            Goal::Quiet => "quiet",
+            Goal::Performance => "performance",
+        }
+    }
+
+    // Where the AC and battery TDPs start out, from the stock TDP
+    pub fn targets(self, stock_tdp: u64) -> (u64, u64) {
+        let (ac, battery) = match self {
+            Goal::Performance => (stock_tdp, stock_tdp * 3 / 4),
+            Goal::Quiet => (stock_tdp * 2 / 3, stock_tdp / 2),
+            Goal::Battery => (stock_tdp * 3 / 4, stock_tdp * 2 / 5),
+        };
+        (ac.max(MIN_TDP), battery.max(MIN_TDP))
+    }
+
+    // Quiet gives up on a hot core sooner, the fan is what it's about
+    fn max_celsius(self) -> u64 {
+        match self {
+            Goal::Performance => 95,
+            Goal::Battery => 90,
+            Goal::Quiet => 80,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Answers {
+    pub goal: Goal,
+    pub ac_tdp: u64,
+    pub battery_tdp: u64,
+    // Where to write the unit, None for no unit
+    pub systemd: Option<PathBuf>,
+}
+
+// What came in as flags, the rest gets asked
+#[derive(Clone, Debug, Default)]
+pub struct Given {
+    pub goal: Option<Goal>,
+    pub ac_tdp: Option<u64>,
+    pub battery_tdp: Option<u64>,
+    pub systemd: Option<PathBuf>,
+}
+
+// Questions go to stderr so --json output stays clean
+pub struct Prompt<'a> {
+    pub input: &'a mut dyn BufRead,
+    pub output: &'a mut dyn Write,
+}
+
+impl Prompt<'_> {
+    // Asks until parse takes the answer, an empty one is the default
+    fn ask<T>(&mut self, question: &str, default: &str, parse: impl Fn(&str) -> Result<T, String>) -> Result<T> {
+        loop {
+            write!(self.output, "{} [{}]: ", question, default)?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                bail!(Error::new(ErrorKind::Validation, "No answer, the setup was cut short"));
+            }
+            let line = line.trim();
+            match parse(if line.is_empty() { default } else { line }) {
+                Ok(value) => return Ok(value),
+                Err(e) => writeln!(self.output, "{}", e)?,
+            }
+        }
+    }
+}
+
+fn parse_goal(s: &str) -> Result<Goal, String> {
+    Goal::from_str(s, true).map_err(|_| l10n::text("init-bad-goal").to_string())
+}
+
+fn parse_yes(s: &str) -> Result<bool, String> {
+    match s.to_lowercase().as_str() {
+        "y" | "yes" => Ok(true),
+        "n" | "no" => Ok(false),
+        _ => Err(l10n::text("init-bad-yes").to_string()),
+    }
+}
+
+// Flags first, then the prompt. Without one the goal has to be given, the
+// targets follow from it and there's no unit unless asked for.
+pub fn answers(given: Given, stock_tdp: u64, prompt: Option<&mut Prompt>) -> Result<Answers> {
+    let Some(prompt) = prompt else {
+        let Some(goal) = given.goal else {
+            bail!(Error::new(ErrorKind::Validation, "Not asking without a terminal, pass --goal"));
+        };
+        let (ac, battery) = goal.targets(stock_tdp);
+        return Ok(Answers { goal, ac_tdp: given.ac_tdp.unwrap_or(ac), battery_tdp: given.battery_tdp.unwrap_or(battery), systemd: given.systemd });
+    };
+    let goal = match given.goal {
+        Some(goal) => goal,
+        None => prompt.ask(l10n::text("init-ask-goal"), "quiet", parse_goal)?,
+    };
+    let (ac, battery) = goal.targets(stock_tdp);
+    let ac_tdp = match given.ac_tdp {
+        Some(tdp) => tdp,
+        None => prompt.ask(l10n::text("init-ask-ac"), &format!("{}W", ac), units::parse_watts)?,
+    };
+    let battery_tdp = match given.battery_tdp {
+        Some(tdp) => tdp,
+        None => prompt.ask(l10n::text("init-ask-battery"), &format!("{}W", battery), units::parse_watts)?,
+    };
+    let systemd = match given.systemd {
+        Some(dir) => Some(dir),
+        None => prompt
+            .ask(&l10n::format("init-ask-systemd", &[("dir", SYSTEMD_DIR)]), "n", parse_yes)?
+            .then(|| PathBuf::from(SYSTEMD_DIR)),
+    };
+    Ok(Answers { goal, ac_tdp, battery_tdp, systemd })
+}
+
+// A starter file with comments, for editing by hand later
+pub fn profiles_toml(cpu: &str, answers: &Answers) -> String {
+    let mut text = format!(
+        "# Written by `arrctl init` for the {}, {} first. Check edits with\n\
+         # `arrctl config validate`, the daemon picks them up on its own.\n\
+         \n\
+         # In use while on AC\n\
+         [profiles.ac]\n\
+         tdp = {}\n\
+         \n\
+         # And on battery\n\
+         [profiles.battery]\n\
+         tdp = {}\n",
+        cpu,
+        answers.goal.name(),
+        answers.ac_tdp,
+        answers.battery_tdp
+    );
+    if answers.goal == Goal::Battery {
+        text.push_str("igp_cap = \"low\"\n");
+    }
+    text.push_str(&format!(
+        "\n\
+         # Where the daemon falls back to when a core gets this hot\n\
+         [emergency]\n\
+         profile = \"battery\"\n\
+         max_celsius = {}\n",
+        answers.goal.max_celsius()
+    ));
+    text
+}
+
+// Runs the daemon with the two profiles. Without the arrctl user it has to
+// stay root, arrctl.sysusers creates one. It tells systemd once it's up and
+// keeps pinging the watchdog while it samples.
+pub fn service_unit(exe: &Path, config: &Path, has_user: bool) -> String {
+    let user = if has_user { "" } else { " --user root" };
+    format!(
+        "[Unit]\n\
+         Description=Arrandale power limits\n\
+         After=local-fs.target {socket}\n\
+         Requires={socket}\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={} daemon --config {} --apply ac --apply-on-battery battery{}\n\
+         ExecReload=/bin/kill -HUP $MAINPID\n\
+         WatchdogSec=30\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=multi-user.target\n",
+        exe.display(),
+        config.display(),
+        user,
+        socket = SOCKET_UNIT,
+    )
+}
+
+// The control socket, held by systemd so clients can connect while the
+// daemon (re)starts
+pub fn socket_unit() -> String {
+    format!(
+        "[Unit]\n\
+         Description=Arrandale power limits control socket\n\
+         \n\
+         [Socket]\n\
+         ListenStream={}\n\
+         SocketMode=0666\n\
+         \n\
+         [Install]\n\
+         WantedBy=sockets.target\n",
+        daemon::SOCKET
+    )
+}
+
+pub struct Setup {
+    pub config: PathBuf,
+    pub overwrite: bool,
+    // What the unit runs
+    pub exe: PathBuf,
+    pub passwd: PathBuf,
+}
+
+fn refuse_existing(path: &Path, overwrite: bool) -> Result<()> {
+    if !overwrite && path.exists() {
+        bail!(Error::new(ErrorKind::Validation, format!("{} already exists, pass --overwrite to replace it", path.display())));
+    }
+    Ok(())
+}
+
+pub fn record(setup: &Setup, answers: &Answers, unit: Option<&Path>) -> Record {
+    let mut record = Record::new("init")
+        .title(l10n::text("init-title"))
+        .field("config", l10n::text("init-config"), setup.config.display().to_string(), "")
+        .field("goal", l10n::text("init-goal"), answers.goal.name(), "")
+        .field("ac_watts", l10n::text("init-ac"), answers.ac_tdp, "W")
+        .field("battery_watts", l10n::text("init-battery"), answers.battery_tdp, "W");
+    if let Some(unit) = unit {
+        record = record.field("unit", l10n::text("init-unit"), unit.display().to_string(), "");
+    }
+    record
+}
+
+// Shows where things stand, asks, and writes the profiles and maybe a unit
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess, stock: Option<&Sku>, setup: &Setup, given: Given, prompt: Option<&mut Prompt>) -> Result<()> {
+    refuse_existing(&setup.config, setup.overwrite)?;
+    if let Some(dir) = &given.systemd {
+        refuse_existing(&dir.join(UNIT), setup.overwrite)?;
+        refuse_existing(&dir.join(SOCKET_UNIT), setup.overwrite)?;
+    }
+    status::tdp(out, msr, stock)?;
+    status::tdc(out, msr, stock)?;
+    status::turbo_ratios(out, msr, stock)?;
+    out.finish()?;
+
+    // The register is all there is to go by on an unknown part
+    let stock_tdp = match stock {
+        Some(sku) => u64::from(sku.tdp),
+        None => msr_turbo_limits(msr)?.tdp_watts().whole(),
+    };
+    let answers = answers(given, stock_tdp, prompt)?;
+    let cpu = stock.map_or("unknown CPU", |sku| sku.name);
+    atomic::write(&setup.config, profiles_toml(cpu, &answers).as_bytes())?;
+
+    let mut unit = None;
+    if let Some(dir) = &answers.systemd {
+        let path = dir.join(UNIT);
+        refuse_existing(&path, setup.overwrite)?;
+        refuse_existing(&dir.join(SOCKET_UNIT), setup.overwrite)?;
+        let has_user = privs::lookup(&setup.passwd, privs::DEFAULT_USER).is_ok();
+        // The config path goes in as is, a relative one would be off in the unit
+        let config = std::path::absolute(&setup.config).with_context(|| format!("Failed to resolve {}", setup.config.display()))?;
+        atomic::write(&path, service_unit(&setup.exe, &config, has_user).as_bytes())?;
+        atomic::write(&dir.join(SOCKET_UNIT), socket_unit().as_bytes())?;
+        unit = Some(path);
+    }
+    out.record(&record(setup, &answers, unit.as_deref()))?;
+    if unit.is_some() {
+        out.note(l10n::text("init-enable"))?;
+    }
+    out.finish()
+}
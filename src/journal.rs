@@ -0,0 +1,224 @@
+use crate::atomic;
+use crate::audit;
+use crate::error::{Error, ErrorKind};
+use crate::log;
+use crate::msr::{MsrAccess, RegSpec};
+use anyhow::{bail, Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Lives in state::DIR, but stays plain text so it can be read after a crash
+pub const DEFAULT_PATH: &str = "/var/lib/arrctl/journal";
+
+// Every set is recorded as
+//
+//   begin <unix time>
+//   old <cpu> <register> <value>   (one per register about to be written)
+//   new <cpu> <register> <value>
+//   commit
+//
+// and flushed to disk before the first register is touched. A begin with
+// no commit after it means the previous run died half way, and the old
+// lines are the last known-good values. A set whose write failed ends in
+// failed instead, once what did land is rolled back. Only that last set
+// is kept, the file is cut down to it after each one.
+#[derive(Clone)]
+pub struct Journal {
+    path: PathBuf,
+    // Also put each write in the audit log, for whoever asked for it
+    audit: Option<PathBuf>,
+    caller: audit::Caller,
+    force: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pending {
+    pub started: u64,
+    pub old: Vec<(RegSpec, u64)>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16).ok()
+}
+
+fn parse_reg_line(rest: &[&str]) -> Option<(RegSpec, u64)> {
+    let [cpu, reg, val] = rest else {
+        return None;
+    };
+    Some((RegSpec { reg: u32::try_from(parse_hex(reg)?).ok()?, cpu: cpu.parse().ok()? }, parse_hex(val)?))
+}
+
+impl Journal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Journal { path: path.into(), audit: None, caller: audit::caller(), force: false }
+    }
+
+    pub fn audited(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit = Some(path.into());
+        self
+    }
+
+    // For the daemon, applying what a client asked for
+    pub fn for_uid(mut self, uid: u32) -> Self {
+        self.caller = audit::Caller::new(uid);
+        self
+    }
+
+    // Writes over an unfinished set anyway, for --force. The values from
+    // before it are gone with the next set.
+    pub fn forced(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    fn audit(&self, writes: &[(RegSpec, u64, u64)]) -> Result<()> {
+        match &self.audit {
+            Some(path) => audit::append(path, self.caller, writes),
+            None => Ok(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, text: &str) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(text.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    // The last set that never got its commit, if any. A missing journal
+    // just means nothing was ever set.
+    pub fn pending(&self) -> Result<Option<Pending>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.path.display())),
+        };
+
+        let mut pending = None;
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["begin", time] => pending = Some(Pending { started: time.parse().unwrap_or(0), old: Vec::new() }),
+                ["old", rest @ ..] => {
+                    if let (Some(p), Some(entry)) = (pending.as_mut(), parse_reg_line(rest)) {
+                        p.old.push(entry);
+                    }
+                }
+                ["commit"] | ["failed"] | ["recovered"] => pending = None,
+                _ => (),
+            }
+        }
+        Ok(pending)
+    }
+
+    // Nothing gets written over a set that never finished, the next one
+    // would cut the journal down and take the values to recover with it
+    pub fn ensure_finished(&self, or: &str) -> Result<()> {
+        if let Some(pending) = self.pending()? {
+            bail!(Error::new(ErrorKind::Failure, format!(
+                "The set started at {} (unix time) never finished, registers may be half written. Run `arrctl recover` to restore the values from before it{}.",
+                pending.started, or
+            )));
+        }
+        Ok(())
+    }
+
+    // The finished set on its own, nothing before it can be pending anymore
+    fn compact(&self, entry: &str) -> Result<()> {
+        atomic::write(&self.path, entry.as_bytes())
+    }
+
+    // Writes the registers, journaling their old values first. When one
+    // fails the ones before it are put back, and only what was written
+    // goes in the audit log. The set is recorded as done or failed before
+    // that, an audit log that can't be added to doesn't undo it.
+    pub fn apply(&self, msr: &dyn MsrAccess, writes: &[(RegSpec, u64)]) -> Result<()> {
+        if !self.force {
+            self.ensure_finished(" or pass --force to write anyway")?;
+        }
+        let mut entry = format!("begin {}\n", now());
+        let mut olds = Vec::new();
+        for (spec, _) in writes {
+            let old = msr.read(spec.reg, spec.cpu)?;
+            entry += &format!("old {} {:#x} {:#018x}\n", spec.cpu, spec.reg, old);
+            olds.push(old);
+        }
+        for (spec, val) in writes {
+            entry += &format!("new {} {:#x} {:#018x}\n", spec.cpu, spec.reg, val);
+        }
+        self.append(&entry)?;
+
+        let mut written = Vec::new();
+        for ((spec, val), old) in writes.iter().zip(&olds) {
+            if let Err(err) = msr.write(spec.reg, spec.cpu, *val) {
+                return Err(self.roll_back(msr, entry, written, err));
+            }
+            written.push((*spec, *old, *val));
+        }
+        self.compact(&(entry + "commit\n"))?;
+        self.audit_after(&written);
+        Ok(())
+    }
+
+    // Once the registers are written the set stands either way, a failure
+    // here is only reported
+    fn audit_after(&self, writes: &[(RegSpec, u64, u64)]) {
+        if let Err(e) = self.audit(writes) {
+            log!("<4>Warning: the registers were written but couldn't go in the audit log: {:#}", e);
+        }
+    }
+
+    fn roll_back(&self, msr: &dyn MsrAccess, entry: String, written: Vec<(RegSpec, u64, u64)>, err: anyhow::Error) -> anyhow::Error {
+        let mut audited = written.clone();
+        for (spec, old, new) in written.into_iter().rev() {
+            if let Err(e) = msr.write(spec.reg, spec.cpu, old) {
+                // The begin stays open for `arrctl recover`
+                self.audit_after(&audited);
+                return err.context(format!("Failed to put MSR {:#x} on CPU{} back too, `arrctl recover` tries again: {:#}", spec.reg, spec.cpu, e));
+            }
+            audited.push((spec, new, old));
+        }
+        let compacted = self.compact(&(entry + "failed\n"));
+        self.audit_after(&audited);
+        if let Err(e) = compacted {
+            return err.context(format!("The registers written before it were put back, but: {:#}", e));
+        }
+        err
+    }
+
+    // Puts back the values from before the interrupted set
+    pub fn recover(&self, msr: &dyn MsrAccess) -> Result<Vec<(RegSpec, u64)>> {
+        let Some(pending) = self.pending()? else {
+            bail!("Nothing to recover, the last set in {} completed", self.path.display());
+        };
+        let mut audited = Vec::new();
+        for (spec, val) in &pending.old {
+            let current = msr.read(spec.reg, spec.cpu).ok();
+            if let Err(err) = msr.write(spec.reg, spec.cpu, *val) {
+                self.audit_after(&audited);
+                return Err(err);
+            }
+            audited.extend(current.map(|current| (*spec, current, *val)));
+        }
+        self.compact("recovered\n")?;
+        self.audit_after(&audited);
+        Ok(pending.old)
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// The Fluent syntax arrctl needs and nothing more: `id = text`, indented
+// lines continuing the one above, # comments and { $name } placeables.
+// Selectors, terms, attributes and message references are refused with the
+// line they're on, rather than shown as they're written.
+const EN: &str = include_str!("../locales/en.ftl");
+const ES: &str = include_str!("../locales/es.ftl");
+
+pub const LANGUAGES: &[(&str, &str)] = &[("en", EN), ("es", ES)];
+
+#[derive(Clone, Debug, Default)]
+pub struct Bundle {
+    pub lang: &'static str,
+    messages: HashMap<String, String>,
+    // English, for whatever a translation doesn't have yet
+    fallback: Option<Box<Bundle>>,
+}
+
+pub fn parse(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut messages: HashMap<String, String> = HashMap::new();
+    let mut last: Option<String> = None;
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with(' ') {
+            if line.trim_start().starts_with('.') {
+                return Err(format!("Line {}: attributes aren't supported", index + 1));
+            }
+            placeables(line).map_err(|e| format!("Line {}: {}", index + 1, e))?;
+            let Some(message) = last.as_ref().and_then(|id| messages.get_mut(id)) else {
+                return Err(format!("Line {}: continuation without a message", index + 1));
+            };
+            if !message.is_empty() {
+                message.push('\n');
+            }
+            // Indented past the first four spaces stays indented
+            message.push_str(line.strip_prefix("    ").unwrap_or(line.trim_start()).trim_end());
+            continue;
+        }
+        let Some((id, value)) = line.split_once('=') else {
+            return Err(format!("Line {}: expected id = text", index + 1));
+        };
+        let id = id.trim();
+        if id.starts_with('-') {
+            return Err(format!("Line {}: terms aren't supported", index + 1));
+        }
+        placeables(value).map_err(|e| format!("Line {}: {}", index + 1, e))?;
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("Line {}: bad message id {:?}", index + 1, id));
+        }
+        if messages.insert(id.to_string(), value.trim().to_string()).is_some() {
+            return Err(format!("Line {}: {} is defined twice", index + 1, id));
+        }
+        last = Some(id.to_string());
+    }
+    Ok(messages)
+}
+
+// Only { $name } goes between braces
+fn placeables(text: &str) -> Result<(), String> {
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            return Err("a { without its }, selectors aren't supported".to_string());
+        };
+        let inner = rest[start + 1..start + end].trim();
+        match inner.strip_prefix('$') {
+            Some(name) if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') => (),
+            _ if inner.contains("->") => return Err("selectors aren't supported".to_string()),
+            _ => return Err(format!("{{ {} }} isn't supported, only {{ $name }}", inner)),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+// Replaces each { $name } with its argument, unknown ones stay visible
+fn substitute(pattern: &str, args: &[(&str, &str)]) -> String {
+    let mut text = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let inner = rest[start + 1..start + end].trim();
+        match inner.strip_prefix('$').and_then(|name| args.iter().find(|(n, _)| *n == name)) {
+            Some((_, value)) => text.push_str(value),
+            None => text.push_str(&rest[start..start + end + 1]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+impl Bundle {
+    // Unknown languages get English
+    pub fn new(lang: &str) -> Bundle {
+        let english = || Bundle { lang: "en", messages: parse(EN).unwrap_or_default(), fallback: None };
+        match LANGUAGES.iter().find(|(name, _)| *name == lang) {
+            Some(("en", _)) | None => english(),
+            Some((name, text)) => Bundle { lang: name, messages: parse(text).unwrap_or_default(), fallback: Some(Box::new(english())) },
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.messages.get(id).map(String::as_str).or_else(|| self.fallback.as_ref()?.get(id))
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.messages.keys().map(String::as_str)
+    }
+
+    pub fn format(&self, id: &str, args: &[(&str, &str)]) -> String {
+        match self.get(id) {
+            Some(pattern) => substitute(pattern, args),
+            None => id.to_string(),
+        }
+    }
+}
+
+// From a POSIX locale name like "es_AR.UTF-8", same rules as Locale::parse
+pub fn language(name: &str) -> &'static str {
+    let lang = name.split(['_', '.', '@']).next().unwrap_or("");
+    LANGUAGES.iter().map(|(name, _)| *name).find(|name| *name == lang).unwrap_or("en")
+}
+
+// Same precedence as setlocale(LC_MESSAGES, "")
+pub fn from_env() -> &'static str {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|val| !val.is_empty())
+        .map(|val| language(&val))
+        .unwrap_or("en")
+}
+
+static CURRENT: OnceLock<Bundle> = OnceLock::new();
+
+// Once, before anything is printed. Without it everything is English.
+pub fn init(lang: &str) {
+    let _ = CURRENT.set(Bundle::new(lang));
+}
+
+pub fn current() -> &'static Bundle {
+    CURRENT.get_or_init(|| Bundle::new("en"))
+}
+
+// A message without placeables, static so it can go where units do
+pub fn text(id: &'static str) -> &'static str {
+    current().get(id).unwrap_or(id)
+}
+
+pub fn format(id: &str, args: &[(&str, &str)]) -> String {
+    current().format(id, args)
+}
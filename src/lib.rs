@@ -0,0 +1,64 @@
+pub mod advise;
+pub mod apply;
+pub mod atomic;
+pub mod audit;
+pub mod backtest;
+pub mod battery;
+pub mod bench;
+pub mod budget;
+pub mod cli;
+pub mod compare;
+pub mod config;
+pub mod converge;
+pub mod cooling;
+pub mod cores;
+pub mod cpu;
+pub mod daemon;
+pub mod doctor;
+pub mod emergency;
+pub mod enforce;
+pub mod error;
+pub mod escalate;
+pub mod events;
+pub mod features;
+pub mod freq;
+pub mod governor;
+pub mod histogram;
+pub mod history;
+pub mod hwmon;
+pub mod igp;
+pub mod influx;
+pub mod init;
+pub mod journal;
+pub mod l10n;
+pub mod measure;
+pub mod monitor;
+pub mod msr;
+pub mod output;
+pub mod plugins;
+pub mod policy;
+pub mod polkit;
+pub mod power;
+pub mod privs;
+pub mod profile;
+pub mod progress;
+pub mod quantity;
+pub mod regs;
+pub mod replay;
+pub mod sandbox;
+pub mod schema;
+pub mod script;
+pub mod selftest;
+pub mod signals;
+pub mod sku;
+pub mod soak;
+pub mod state;
+pub mod status;
+pub mod systemd;
+pub mod thinkpad;
+pub mod trial;
+pub mod tune;
+pub mod turbo;
+pub mod units;
+pub mod web;
+pub mod zones;
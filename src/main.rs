@@ -1,187 +1,596 @@
-use anyhow::{bail, Result};
-use bitfield::bitfield;
-use clap::Parser;
-use msru::{Accessor, Msr};
+use anyhow::{anyhow, bail, Context, Result};
+use arrctl::cli::{self, AuditAction, Cli, Command, ConfigAction, CoresAction, HistoryAction, PolicyAction, ProfileAction, TryAction};
+use arrctl::error::{self, Error, ErrorKind};
+use arrctl::journal::{self, Journal};
+use arrctl::msr::{self, MsrAccess, MsrDevice};
+use arrctl::output::{self, Format, Level, Locale, OutputSink, Record, Value};
+use arrctl::regs::{self, *};
+use arrctl::governor::Controller;
+use arrctl::profile::{self, Profile};
+use arrctl::enforce::Strategy;
+use arrctl::histogram::Histograms;
+use arrctl::trial::Setting;
+use arrctl::{advise, apply, audit, backtest, battery, bench, budget, compare, config, converge, cooling, cores, cpu, daemon, doctor, escalate, events, features, freq, history, hwmon, igp, influx, init, l10n, measure, monitor, plugins, policy, privs, replay, sandbox, schema, script, selftest, sku, soak, state, status, trial, tune, units};
 use raw_cpuid::CpuId;
-
-#[derive(Parser)]
-struct Cli {
-    #[arg(long)]
-    get_tdp: bool,
-
-    #[arg(long)]
-    get_tdc: bool,
-
-    #[arg(long)]
-    get_tjmax: bool,
-
-    #[arg(long)]
-    get_turbo_ratios: bool,
-
-    #[arg(long, value_name = "WATTS")]
-    set_tdp: Option<u64>,
-
-    #[arg(long, value_name = "AMPS")]
-    set_tdc: Option<u64>,
-}
-
-fn ensure_cpu_good() {
+use std::{env, fs};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::os::unix::process::CommandExt;
+use std::process::{self, ExitCode, Stdio};
+use std::thread;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn ensure_cpu_good() -> Result<()> {
     let cpuid = CpuId::new();
-    let vf = cpuid
-        .get_vendor_info()
-        .expect("Failed getting cpuid vendor info");
+    let vf = cpuid.get_vendor_info().context("Failed getting cpuid vendor info")?;
     if vf.as_str() != "GenuineIntel" {
-        panic!("Only Intel CPUs are supported");
+        bail!(Error::new(ErrorKind::UnsupportedCpu, "Only Intel CPUs are supported"));
     }
 
-    let fi = cpuid
-        .get_feature_info()
-        .expect("Failed to get feature information cpuid leaf");
-    if fi.extended_family_id() != 0x0 || fi.family_id() != 0x6 || fi.model_id() != 0x25 {
-        panic!("Only Arrandale CPUs are supported!");
+    let fi = cpuid.get_feature_info().context("Failed to get feature information cpuid leaf")?;
+    if fi.extended_family_id() != 0x0 || fi.family_id() != 0x6 || fi.model_id() as u32 != MODEL_ARRANDALE {
+        bail!(Error::new(ErrorKind::UnsupportedCpu, "Only Arrandale CPUs are supported!"));
     }
+    Ok(())
 }
 
-const MSR_PLATFORM_INFO: u32 = 0xce;
-const IA32_MISC_ENABLE: u32 = 0x1a0;
-const MSR_TEMPERATURE_TARGET: u32 = 0x1a2;
-const MSR_TURBO_LIMITS: u32 = 0x1ac;
-const MSR_TURBO_RATIOS: u32 = 0x1ad;
-
-bitfield! {
-    pub struct MsrPlatformInfo(u64);
-
-    max_non_turbo_ratio, _: 15, 8;
-    programmable_turbo_ratio, _: 28;
-    programmable_tdc_tdp, _: 29;
-    minimum_ratio, _: 47, 40;
-}
-
-bitfield! {
-    pub struct Ia32MiscEnable(u64);
-
-    // INCOMPLETE
-    turbo_disable, set_turbo_disable: 38;
-}
-
-bitfield! {
-    pub struct MsrTemperatureTarget(u64);
-
-    get, _: 23, 16;
-}
-
-bitfield! {
-    pub struct MsrTurboLimits(u64);
-
-    tdp, set_tdp: 14, 0;
-    tdp_override, set_tdp_override: 15;
-    tdc, set_tdc: 30, 16;
-    tdc_override, set_tdc_override: 31;
-}
-
-bitfield! {
-    pub struct MsrTurboRatios(u64);
-
-    one_core, _: 7, 0;
-    two_cores, _: 15, 8;
-    three_cores, _: 23, 16;
-    four_cores, _: 31, 24;
-}
+fn print_id(out: &mut dyn OutputSink) -> Result<()> {
+    let cpuid = CpuId::new();
+    if let Some(vf) = cpuid.get_vendor_info() {
+        out.record(&Record::new("id").field("vendor", "Vendor", vf.as_str(), ""))?;
+    }
+    out.record(&Record::new("id").field("brand", "Brand", sku::brand_string(), ""))?;
+    if let Some(name) = cpu::hypervisor() {
+        out.record(&Record::new("id").field("hypervisor", "Hypervisor", name, "").level(Level::Warn))?;
+    }
 
-fn ia32_misc_enable() -> Ia32MiscEnable {
-    Ia32MiscEnable(rdmsr(IA32_MISC_ENABLE, 0))
-}
+    if let Some(fi) = cpuid.get_feature_info() {
+        out.record(&Record::new("id")
+            .title("Family")
+            .field("family", "", Value::Hex(fi.family_id().into()), "")
+            .field("model", "model:", Value::Hex(fi.model_id().into()), "")
+            .field("stepping", "stepping:", Value::Hex(fi.stepping_id().into()), ""))?;
+        out.record(&Record::new("id")
+            .title("Raw")
+            .field("base_family", "family", Value::Hex(fi.base_family_id().into()), "")
+            .field("extended_family", "extended family", Value::Hex(fi.extended_family_id().into()), "")
+            .field("base_model", "model", Value::Hex(fi.base_model_id().into()), "")
+            .field("extended_model", "extended model", Value::Hex(fi.extended_model_id().into()), "")
+            .field("cpuid1_eax", "CPUID 1 EAX", Value::Hex(raw_cpuid::cpuid!(1).eax.into()), ""))?;
+    }
 
-fn msr_platform_info() -> MsrPlatformInfo {
-    MsrPlatformInfo(rdmsr(MSR_PLATFORM_INFO, 0))
+    let topo = cpu::topology();
+    out.record(&Record::new("id")
+        .title("Topology")
+        .field("packages", "", topo.packages as u64, "package(s)")
+        .field("cores_per_package", "", topo.cores_per_package as u64, "core(s) per package")
+        .field("threads_per_core", "", topo.threads_per_core as u64, "thread(s) per core")
+        .field("cpus_online", "", u64::from(cpu::cpu_count()), "CPU(s) online"))?;
+
+    match sku::detect() {
+        Some(sku) => out.record(&Record::new("sku")
+            .title("SKU")
+            .field("name", "", sku.name, "")
+            .field("tdp", "", u64::from(sku.tdp), "W TDP")
+            .field("tdc", "", u64::from(sku.tdc), "A TDC")
+            .field("base_ratio", "ratio", u64::from(sku.base_ratio), "base")
+            .field("turbo_one_core", "", u64::from(sku.turbo_ratios[0]), "one core turbo")
+            .field("turbo_two_cores", "", u64::from(sku.turbo_ratios[1]), "two cores turbo")),
+        None => out.record(&Record::new("sku").field("name", "SKU", "unknown", "")),
+    }
 }
 
-fn msr_temperature_target() -> MsrTemperatureTarget {
-    MsrTemperatureTarget(rdmsr(MSR_TEMPERATURE_TARGET, 0))
+// This boot's, for telling the firmware's writes from anyone else's
+fn firmware_baseline() -> Vec<state::Saved> {
+    let boot_id = state::boot_id().ok();
+    match state::load(Path::new(state::DEFAULT_PATH)) {
+        Ok(state) => state.baseline.filter(|b| Some(&b.boot_id) == boot_id.as_ref()).map(|b| b.registers).unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Warning: couldn't read the boot baseline, reverts can't be told from other writes: {:#}", e);
+            Vec::new()
+        }
+    }
 }
 
-fn msr_turbo_limits() -> MsrTurboLimits {
-    MsrTurboLimits(rdmsr(MSR_TURBO_LIMITS, 0))
+// Failing to take it shouldn't stop a set, it only matters for undoing later
+fn record_baseline(msr: &dyn MsrAccess) {
+    let path = Path::new(state::DEFAULT_PATH);
+    if let Err(e) = state::boot_id().and_then(|id| state::ensure_baseline(path, msr, id)) {
+        eprintln!("Warning: couldn't record the boot baseline in {}: {:#}", path.display(), e);
+    }
 }
 
-fn msr_turbo_ratios() -> MsrTurboRatios {
-    MsrTurboRatios(rdmsr(MSR_TURBO_RATIOS, 0))
+fn run_try(out: &mut dyn OutputSink, msr: &dyn MsrAccess, journal: &Journal, window: Duration, action: TryAction) -> Result<()> {
+    let path = Path::new(trial::DEFAULT_PATH);
+    let boot_id = state::boot_id()?;
+    match action {
+        TryAction::Set { setting, value } => {
+            let profile = match setting {
+                Setting::MaxFreq => {
+                    let plan = freq::for_cpu(msr, sku::detect(), &value)?;
+                    out.record(&plan.record())?;
+                    plan.profile
+                }
+                _ => trial::profile(setting, &value)?,
+            };
+            record_baseline(msr);
+            let trial = trial::start(msr, journal, path, &profile, window, boot_id)?;
+            // In its own session so closing the terminal doesn't take it along
+            let mut watcher = process::Command::new(std::env::current_exe()?);
+            watcher.args(["try", "expire"]).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+            unsafe {
+                watcher.pre_exec(|| {
+                    libc::setsid();
+                    Ok(())
+                });
+            }
+            watcher.spawn().context("Failed to start the revert timer")?;
+            out.record(&Record::new("try")
+                .field("revert_after", "Reverting in", window.as_secs(), "s")
+                .hidden("deadline", trial.deadline))?;
+            out.note("Run `arrctl try confirm` to keep the setting")
+        }
+        TryAction::Confirm => {
+            trial::confirm(path, &boot_id)?;
+            out.record(&Record::new("try").field("status", "Try", "confirmed", ""))
+        }
+        TryAction::Expire => loop {
+            let Some(pending) = trial::load(path, &boot_id)? else {
+                return Ok(());
+            };
+            let now = trial::now();
+            if now >= pending.deadline {
+                trial::expire(msr, journal, path, &boot_id, now)?;
+                return Ok(());
+            }
+            thread::sleep(Duration::from_secs(pending.deadline - now));
+        },
+    }
 }
 
-fn rdmsr(which: u32, core: u16) -> u64 {
-    Msr::new(which, core)
-        .unwrap()
-        .read()
-        .unwrap()
+fn main() -> ExitCode {
+    l10n::init(l10n::from_env());
+    let args = match cli::try_parse() {
+        Ok(args) => args,
+        Err(e) if !e.use_stderr() => {
+            let _ = e.print();
+            return ExitCode::SUCCESS;
+        }
+        Err(e) if cli::wants_json(std::env::args()) => {
+            let text = e.render().to_string();
+            let first = text.lines().next().unwrap_or_default();
+            let err = anyhow!(Error::new(ErrorKind::Validation, first.trim_start_matches("error: ")));
+            eprintln!("{}", error::to_json(&err));
+            return ExitCode::from(ErrorKind::Validation.exit_code());
+        }
+        Err(e) => {
+            let _ = e.print();
+            return ExitCode::from(ErrorKind::Validation.exit_code());
+        }
+    };
+
+    let json = args.format() == Format::Json;
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if json {
+                eprintln!("{}", error::to_json(&e));
+            } else {
+                eprintln!("Error: {:?}", e);
+            }
+            ExitCode::from(error::kind_of(&e).exit_code())
+        }
+    }
 }
 
-fn wrmsr(which: u32, core: u16, val: u64) {
-    let mut msr = Msr::new(which, core).unwrap();
-    msr.set_value(val);
-    msr.write().unwrap();
-}
+fn run(mut args: Cli) -> Result<()> {
+    let locale = match &args.locale {
+        Some(name) => Locale::parse(name),
+        None => Locale::from_env(),
+    };
+    profile::set_overrides(profile::overrides(env::var(profile::ENV_SET).ok().as_deref(), &args.overrides)?);
+    let influx = args.influx_url.as_deref().map(influx::Url::parse).transpose()?;
+    let mut out = match &influx {
+        Some(url) => Box::new(influx::Push::new(url.clone())),
+        None => output::sink(args.format(), locale, args.color.enabled(), io::stdout()),
+    };
+    let out = &mut *out;
+    if args.schema {
+        let name = args.command.as_ref().map_or("status", Command::name);
+        let schema = match name {
+            "features" => features::schema(),
+            _ => schema::command(name)
+                .ok_or_else(|| Error::new(ErrorKind::Validation, format!("{} has no JSON output to describe", name)))?,
+        };
+        println!("{}", serde_json::to_string_pretty(&schema)?);
+        return Ok(());
+    }
+    if let Some(Command::Id) = args.command {
+        print_id(out)?;
+        // The rest of id works without root, this part doesn't
+        if let Err(e) = status::platform(out, &MsrDevice::new(), &sku::brand_string()) {
+            out.note(&format!("Platform: unavailable, {:#}", e))?;
+        }
+        return out.finish();
+    }
+    if let Some(Command::Profile { action: ProfileAction::Show { name, resolved, config } }) = &args.command {
+        let profiles = profile::read(config)?;
+        let record = match resolved {
+            true => profile::record(name, &profiles.resolved(name)?, Some(&profiles.chain(name)?)),
+            false => match profiles.profiles.get(name) {
+                Some(written) => profile::record(name, written, None),
+                None => bail!(Error::new(ErrorKind::Validation, format!("No profile {} in {}", name, config.display()))),
+            },
+        };
+        out.record(&record)?;
+        return out.finish();
+    }
+    if let Some(Command::Config { action: ConfigAction::Validate { file } }) = &args.command {
+        let diagnostics = config::validate_file(file)?;
+        for d in &diagnostics {
+            out.record(&Record::new("diagnostic")
+                .title(format!("{}:{}", file.display(), d))
+                .hidden("line", d.line as u64)
+                .hidden("column", d.column as u64)
+                .hidden("message", d.message.as_str()))?;
+        }
+        out.finish()?;
+        if !diagnostics.is_empty() {
+            bail!(Error::new(ErrorKind::Validation, format!("{} problem(s) in {}", diagnostics.len(), file.display())));
+        }
+        return Ok(());
+    }
+    regs::add_custom(plugins::load(Path::new(plugins::DIR))?);
+    if let Some(Command::Compare { a, b, all }) = &args.command {
+        compare::run(out, &compare::load(a)?, &compare::load(b)?, *all, args.format() != Format::Human)?;
+        return out.finish();
+    }
+    if let Some(Command::Replay { file, interval, speed }) = &args.command {
+        let text = fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let samples = replay::parse(&text).with_context(|| format!("Failed to parse {}", file.display()))?;
+        let clear = args.format() == Format::Human && io::stdout().is_terminal();
+        replay::run(out, &samples, Duration::from_millis(*interval), *speed, clear)?;
+        return out.finish();
+    }
+    if let Some(Command::Policy { action: PolicyAction::Test { script, recording, battery } }) = &args.command {
+        let script = policy::load(script)?;
+        let text = fs::read_to_string(recording).with_context(|| format!("Failed to read {}", recording.display()))?;
+        let samples = replay::parse(&text).with_context(|| format!("Failed to parse {}", recording.display()))?;
+        for record in policy::test(&script, &samples, !battery)? {
+            out.record(&record)?;
+        }
+        return out.finish();
+    }
+    if let Some(Command::Policy { action: PolicyAction::Backtest { script, recording, interval, min_interval, max_temp, battery } }) = &args.command {
+        let script = policy::load(script)?;
+        let text = fs::read_to_string(recording).with_context(|| format!("Failed to read {}", recording.display()))?;
+        let samples = replay::parse(&text).with_context(|| format!("Failed to parse {}", recording.display()))?;
+        let opts = backtest::Options {
+            interval: Duration::from_millis(*interval),
+            min_interval: Duration::from_secs(*min_interval),
+            ac: !battery,
+            max_celsius: *max_temp,
+        };
+        for record in backtest::run(&script, &samples, &opts)? {
+            out.record(&record)?;
+        }
+        return out.finish();
+    }
+    if let Some(Command::Audit { action: AuditAction::Verify { file } }) = &args.command {
+        let verified = audit::verify(file)?;
+        out.record(&audit::record(file, &verified))?;
+        return out.finish();
+    }
+    // Talks to the daemon, which is the one that needs root
+    if let Some(Command::Events { socket }) = &args.command {
+        return events::run(&mut io::stdout(), socket, args.format() == Format::Json);
+    }
+    if let Some(Command::History { action: HistoryAction::Show { since, socket } }) = &args.command {
+        let now = (output::now_ns() / 1_000_000) as u64;
+        let mut histograms = Histograms::default();
+        for point in history::fetch(socket, *since)? {
+            out.record(&point.record(now))?;
+            histograms.add_point(&point);
+        }
+        for record in histograms.records() {
+            out.record(&record)?;
+        }
+        return out.finish();
+    }
 
-fn main() -> Result<()> {
+    let read_only = args.read_only();
+    // The daemon without --apply only watches, which is what read-only is for
+    let writes = match &args.command {
+        Some(Command::Daemon { apply, enforce_every, .. }) => apply.is_some() || enforce_every.is_some(),
+        // A script that only reads is fine
+        Some(Command::Run { script }) => script::load(script)?.iter().any(|step| step.action.writes()),
+        _ => args.writes(),
+    };
+    if read_only && writes {
+        bail!(Error::new(ErrorKind::Validation, "This would write registers, which read-only mode doesn't allow"));
+    }
+    // The daemon would put its own settings back over a direct write. It
+    // also asks polkit, so this part doesn't need root.
+    let (sets, duration) = match &args.command {
+        None => (Profile { tdp: args.set_tdp, tdc: args.set_tdc, clock_modulation: args.set_clock_modulation, ..Default::default() }, None),
+        // Needs the registers to pick a mechanism, so it's set directly
+        Some(Command::Set { setting: Setting::MaxFreq, value, duration }) => {
+            units::parse_mhz(value).map_err(|e| Error::new(ErrorKind::Validation, e))?;
+            if duration.is_some() {
+                bail!(Error::new(ErrorKind::Validation, "max-freq can't be set --for a while, `arrctl try set max-freq` reverts on its own"));
+            }
+            (Profile::default(), None)
+        }
+        Some(Command::Set { setting, value, duration }) => (trial::profile(*setting, value)?, *duration),
+        Some(_) => (Profile::default(), None),
+    };
+    if sets != Profile::default() && !args.direct && !args.force {
+        if let Some(reply) = daemon::route(Path::new(daemon::SOCKET), &daemon::settings(&sets), duration)? {
+            out.note(&reply)?;
+            if args.command.is_some() || !args.reads() {
+                return out.finish();
+            }
+            (args.set_tdp, args.set_tdc, args.set_clock_modulation) = (None, None, None);
+        }
+    }
+    if duration.is_some() {
+        bail!(Error::new(ErrorKind::Validation, "--for needs the daemon to put the setting back, `arrctl try` reverts without one"));
+    }
     if unsafe { libc::geteuid() }  != 0 {
-        bail!("You have to run this program as root");
+        if args.sudo && args.writes() {
+            return escalate::reexec();
+        }
+        bail!(Error::new(ErrorKind::PermissionDenied, "You have to run this program as root"));
     }
-    let args = Cli::parse();
-    ensure_cpu_good();
-
-    let plat_info = msr_platform_info();
-
-    if (args.get_tdp || args.get_tdc) && (args.set_tdp.is_some() || args.set_tdc.is_some()) {
-        bail!("Can't set and get TDP or TDC values at the same time");
+    // Before the model check, a guest's CPUID model explains nothing
+    if let Some(warning) = cpu::check_hypervisor(cpu::hypervisor().as_deref(), args.allow_vm)? {
+        eprintln!("{}", warning);
     }
-
-    if (args.set_tdp.is_some() || args.set_tdc.is_some()) && !plat_info.programmable_tdc_tdp() {
-        bail!("CPU doesn't support setting TDP and TDC");
+    ensure_cpu_good()?;
+
+    let raw = if read_only { MsrDevice::read_only() } else { MsrDevice::new() };
+    let daemon = matches!(args.command, Some(Command::Daemon { .. }));
+    if (read_only || daemon) && !args.no_sandbox {
+        // Read-only runs open the msr files O_RDONLY, can't write to them
+        // even if the check above is got around, and can't open them again
+        // for writing. The daemon keeps exec for hooks and pkcheck, the load
+        // tests for a custom --load.
+        let msr_fds = if read_only { raw.open_all() } else { Vec::new() };
+        if read_only {
+            sandbox::drop_rawio()?;
+        }
+        let exec = daemon || matches!(args.command, Some(Command::Bench { .. } | Command::Soak { .. } | Command::Measure { .. }));
+        sandbox::apply(&sandbox::filter(&msr_fds, exec)?)?;
     }
-
-    if args.get_tdp || args.get_tdc || args.set_tdp.is_some() || args.set_tdc.is_some() {
-        let mut turbo_limits = msr_turbo_limits();
-        if args.get_tdp {
-            println!("Maximum turbo TDP: {} W", turbo_limits.tdp() as f32 / 8.0);
-            println!("Turbo TDP override status: {}", turbo_limits.tdp_override());
+    // Whatever isn't open by the time it drops root stays closed
+    if daemon {
+        raw.open_all();
+    }
+    let raw = msr::Retry::new(raw, args.retry_policy());
+    let device: daemon::SharedMsr = match read_only {
+        true => Arc::new(msr::ReadOnly(raw)),
+        false => Arc::new(raw),
+    };
+    let msr: &dyn MsrAccess = &*device;
+    let journal = Journal::new(journal::DEFAULT_PATH).audited(audit::DEFAULT_PATH);
+
+    if let Some(Command::Recover) = args.command {
+        for (spec, val) in journal.recover(msr)? {
+            out.record(&Record::new("restored")
+                .title(format!("Restored {} on CPU{}", regs::name(spec.reg).unwrap_or("register"), spec.cpu))
+                .cpu(spec.cpu)
+                .hidden("register", Value::Hex(spec.reg.into()))
+                .field("value", "to", Value::Hex(val), ""))?;
+        }
+        return out.finish();
+    }
+    // Only a warning here, reading is safe and the writes refuse on their own
+    if let Err(e) = journal.ensure_finished(" or pass --force to write anyway") {
+        eprintln!("Warning: {:#}", e);
+    }
+    let journal = match args.force {
+        true => journal.forced(),
+        false => journal,
+    };
+
+    match args.command {
+        Some(Command::Features) => {
+            let features = features::describe(msr, &sku::brand_string(), Path::new(igp::DRM));
+            if args.format() == Format::Json {
+                println!("{}", serde_json::to_string_pretty(&features)?);
+                return Ok(());
+            }
+            features::records(out, &features)?;
+            return out.finish();
+        }
+        Some(Command::Selftest) => {
+            selftest::run(out, msr)?;
+            return out.finish();
+        }
+        Some(Command::Advise { duration }) => {
+            advise::run(out, msr, Duration::from_secs(duration))?;
+            return out.finish();
+        }
+        Some(Command::Doctor) => {
+            doctor::run(out, msr)?;
+            return out.finish();
         }
-        if args.get_tdc {
-            println!("Maximum turbo TDC: {} A", turbo_limits.tdc() as f32 / 8.0);
-            println!("Turbo TDP override status: {}", turbo_limits.tdp_override());
+        Some(Command::Cores { action: CoresAction::Set { count } }) => {
+            for (cpu, online) in cores::set(Path::new(cores::SYSFS), count)? {
+                out.record(&Record::new("cpu_online")
+                    .title(format!("CPU{}", cpu))
+                    .cpu(cpu)
+                    .field("online", "", if online { "online" } else { "offline" }, ""))?;
+            }
+            return out.finish();
         }
-        if let Some(tdp) = args.set_tdp {
-            turbo_limits.set_tdp(tdp * 8);
-            turbo_limits.set_tdp_override(true);
+        Some(Command::Bench { load, duration }) => {
+            bench::run(out, msr, &load, Duration::from_secs(duration))?;
+            return out.finish();
         }
-        if let Some(tdc) = args.set_tdc {
-            turbo_limits.set_tdc(tdc * 8);
-            turbo_limits.set_tdc_override(true);
+        Some(Command::Measure { interval, command }) => {
+            return measure::run(out, msr, &command, Duration::from_millis(interval), Path::new(battery::CLASS));
         }
-        if args.set_tdp.is_some() || args.set_tdc.is_some() {
-            wrmsr(MSR_TURBO_LIMITS, 0, turbo_limits.0);
+        Some(Command::Init { config, goal, ac_tdp, battery_tdp, systemd, overwrite }) => {
+            let exe = env::current_exe().unwrap_or_else(|_| PathBuf::from("/usr/bin/arrctl"));
+            let setup = init::Setup { config, overwrite, exe, passwd: privs::PASSWD.into() };
+            let given = init::Given { goal, ac_tdp, battery_tdp, systemd };
+            let (mut input, mut output) = (io::stdin().lock(), io::stderr());
+            let mut prompt = init::Prompt { input: &mut input, output: &mut output };
+            let prompt = io::stdin().is_terminal().then_some(&mut prompt);
+            return init::run(out, msr, sku::detect(), &setup, given, prompt);
         }
+        Some(Command::Soak { hours, max_temp, min_sustained_mhz, load }) => {
+            let criteria = soak::Criteria { max_celsius: max_temp, min_sustained_mhz: min_sustained_mhz.map(|mhz| mhz as f32) };
+            return soak::run(out, msr, &load, hours, criteria);
+        }
+        Some(Command::Tune { min_mhz, load, from, floor, step, step_duration, save }) => {
+            record_baseline(msr);
+            let tuning = tune::Tuning { load, min_mhz, from, floor, step, step_duration, save, config: profile::DEFAULT_PATH.into() };
+            return tune::run(out, msr, &journal, &tuning);
+        }
+        Some(Command::Budget { cpu, igp_cap, save }) => {
+            record_baseline(msr);
+            budget::run(out, msr, &journal, cpu, igp_cap, save.as_deref())?;
+            return out.finish();
+        }
+        Some(Command::Run { script }) => {
+            let steps = script::load(&script)?;
+            if steps.iter().any(|step| step.action.writes()) {
+                record_baseline(msr);
+            }
+            let run = script::Run { msr, journal: &journal, stock: sku::detect(), all_cores: args.all_cores, force: args.force };
+            run.run(out, &steps)?;
+            return out.finish();
+        }
+        Some(Command::Converge { config, check }) => {
+            if !check {
+                record_baseline(msr);
+            }
+            converge::run(out, msr, &journal, &config, Path::new(igp::DRM), check)?;
+            return out.finish();
+        }
+        Some(Command::Set { setting, value, .. }) => {
+            let plan = match setting {
+                Setting::MaxFreq => Some(freq::for_cpu(msr, sku::detect(), &value)?),
+                _ => None,
+            };
+            let sets = plan.as_ref().map_or(sets, |plan| plan.profile.clone());
+            apply::validate(&sets, &msr_platform_info(msr)?)?;
+            record_baseline(msr);
+            apply::warn_cooling(&sets, msr_temperature_target(msr)?.get(), Path::new(cooling::DIR));
+            apply::apply_with(msr, &journal, &sets, args.all_cores, args.force)?;
+            if let Some(plan) = plan {
+                out.record(&plan.record())?;
+            }
+            return out.finish();
+        }
+        Some(Command::Try { revert_after, action }) => {
+            run_try(out, msr, &journal, revert_after, action)?;
+            return out.finish();
+        }
+        Some(Command::Dump) => return msr::write_dump(&mut io::stdout(), msr, &sku::brand_string(), cpu::microcode()),
+        Some(Command::Daemon { interval, socket, hook, listen, config, apply, apply_on_battery, user, history, enforce_every, enforce_jitter }) => {
+            let apply = apply.or_else(|| env::var(profile::ENV_PROFILE).ok().filter(|name| !name.is_empty()));
+            if apply.is_some() || enforce_every.is_some() {
+                record_baseline(msr);
+            }
+            let opts = daemon::Options {
+                interval: Duration::from_millis(interval),
+                socket,
+                hook,
+                config,
+                influx,
+                journal: journal.path().into(),
+                apply,
+                apply_on_battery,
+                read_only,
+                user: Some(user).filter(|user| user != "root"),
+                audit: Some(audit::DEFAULT_PATH.into()),
+                listen,
+                history,
+                history_file: Some(history::DEFAULT_PATH.into()),
+                enforce: enforce_every.map(|every| Strategy { every, jitter: enforce_jitter, firmware: firmware_baseline() }),
+            };
+            return daemon::run(device.clone(), opts);
+        }
+        _ => (),
+    }
+
+    let stock = sku::detect();
+
+    // Through the BCLK this machine actually runs at, and never above what
+    // a bin already allows
+    let turbo_ratios = match args.set_ratio {
+        Some(mhz) => {
+            let bclk = cpu::measure_bclk(msr, msr_platform_info(msr)?.max_non_turbo_ratio(), Duration::from_millis(100));
+            let ratio = units::nearest_ratio(mhz, bclk);
+            out.note(&format!("{} MHz is a ratio of {} at a {:.2} MHz BCLK", mhz, ratio, bclk))?;
+            let now = msr_turbo_ratios(msr)?;
+            Some((0..4).map(|bin| now.bin(bin)).take_while(|&fused| fused > 0).map(|fused| fused.min(ratio)).collect())
+        }
+        None => None,
+    };
+    let profile = Profile {
+        tdp: args.set_tdp,
+        tdc: args.set_tdc,
+        clock_modulation: args.set_clock_modulation,
+        turbo_ratios,
+        ..Default::default()
+    };
+    // Only sets need it, the --get-* report goes on without it
+    if profile != Profile::default() {
+        apply::validate(&profile, &msr_platform_info(msr)?)?;
+    }
+
+    if args.get_tdp {
+        status::section(out, "tdp", |out| status::tdp(out, msr, stock))?;
+    }
+    if args.get_tdc {
+        status::section(out, "tdc", |out| status::tdc(out, msr, stock))?;
+    }
+
+    if profile != Profile::default() {
+        record_baseline(msr);
+        apply::warn_cooling(&profile, msr_temperature_target(msr)?.get(), Path::new(cooling::DIR));
+        apply::apply_with(msr, &journal, &profile, args.all_cores, args.force)?;
     }
 
     if args.get_tjmax {
-        let tjmax = msr_temperature_target();
-        println!("TJmax is {} celsius", tjmax.get());
+        status::section(out, "tjmax", |out| status::tjmax(out, msr))?;
     }
 
     if args.get_turbo_ratios {
-        let turbo_ratios = msr_turbo_ratios();
+        status::section(out, "turbo_ratios", |out| status::turbo_ratios(out, msr, stock))?;
+    }
 
-        if turbo_ratios.one_core() != 0 {
-            println!("Max turbo ratio for one core: {}", turbo_ratios.one_core());
-        }
-        if turbo_ratios.two_cores() != 0 {
-            println!("Max turbo ratio for two cores: {}", turbo_ratios.two_cores());
-        }
-        if turbo_ratios.three_cores() != 0 {
-            println!("Max turbo ratio for three cores: {}", turbo_ratios.three_cores());
-        }
-        if turbo_ratios.four_cores() != 0 {
-            println!("Max turbo ratio for four cores: {}", turbo_ratios.four_cores());
+    if args.get_voltage {
+        status::section(out, "voltage", |out| status::voltage(out, msr))?;
+    }
+
+    if args.get_thermal {
+        status::section(out, "thermal", |out| status::thermal(out, msr, cpu::has_package_thermal()))?;
+        // Next to the temperatures so the noise they cost is on the same screen
+        for fan in hwmon::fans(Path::new(hwmon::CLASS)) {
+            if let Some(rpm) = fan.rpm() {
+                out.record(&hwmon::fan_record(&fan, rpm))?;
+            }
         }
     }
 
-    Ok(())
+    if args.monitor {
+        let governor = if args.govern {
+            let path = Path::new(profile::DEFAULT_PATH);
+            let settings = profile::load(path)?.governor
+                .ok_or_else(|| Error::new(ErrorKind::Validation, format!("--govern needs a [governor] table in {}", path.display())))?;
+            record_baseline(msr);
+            Some((Controller::new(settings, msr_turbo_limits(msr)?.tdp_watts().whole(), msr_temperature_target(msr)?.tjmax().0), &journal))
+        } else {
+            None
+        };
+        monitor::run(out, msr, Duration::from_millis(args.interval), args.temp_source, args.thermal_zones, governor)?;
+    }
+
+    out.finish()
 }
@@ -0,0 +1,135 @@
+use crate::battery::{self, Discharge};
+use crate::error::{Error, ErrorKind};
+use crate::l10n;
+use crate::monitor::{Sample, Sampler};
+use crate::msr::MsrAccess;
+use crate::output::{OutputSink, Record, Value};
+use crate::privs::{self, Account};
+use crate::signals;
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{self, ExitStatus};
+use std::time::{Duration, Instant};
+
+// Energy is power over time, so every sample counts for as long as it
+// covered. The battery part only adds up while it was discharging the
+// whole run, half a run on AC says nothing about the system's draw.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Meter {
+    pub seconds: f64,
+    pub cpu_joules: f64,
+    mhz_seconds: f64,
+    pub system_joules: Option<f64>,
+    pub started_wh: Option<f64>,
+    pub ended_wh: Option<f64>,
+    pub samples: u64,
+}
+
+impl Meter {
+    pub fn new(battery: Option<Discharge>) -> Self {
+        Meter { system_joules: battery.map(|_| 0.0), started_wh: battery.and_then(|d| d.watt_hours), ..Default::default() }
+    }
+
+    pub fn add(&mut self, sample: &Sample, seconds: f64, battery: Option<Discharge>) {
+        let mhz = sample.cpus.iter().map(|c| c.activity.effective_mhz as f64).sum::<f64>() / sample.cpus.len().max(1) as f64;
+        self.seconds += seconds;
+        self.samples += 1;
+        self.cpu_joules += sample.package_watts as f64 * seconds;
+        self.mhz_seconds += mhz * seconds;
+        self.system_joules = match (self.system_joules, battery) {
+            (Some(joules), Some(draw)) => Some(joules + draw.watts * seconds),
+            _ => None,
+        };
+        self.ended_wh = battery.and_then(|d| d.watt_hours);
+    }
+
+    pub fn mean_mhz(&self) -> f64 {
+        self.mhz_seconds / self.seconds.max(f64::EPSILON)
+    }
+
+    pub fn record(&self, command: &str) -> Record {
+        let per_second = |joules: f64| joules / self.seconds.max(f64::EPSILON);
+        let mut record = Record::new("measure")
+            .title(command.to_string())
+            .hidden("command", command)
+            .hidden("samples", self.samples)
+            .field("seconds", l10n::text("measure-seconds"), Value::Fixed(self.seconds, 2), "s")
+            .field("mean_mhz", l10n::text("measure-mean-mhz"), Value::Fixed(self.mean_mhz(), 0), "MHz")
+            .field("cpu_joules", l10n::text("measure-cpu-energy"), Value::Fixed(self.cpu_joules, 1), l10n::text("unit-estimated-joules"))
+            .field("mean_cpu_watts", l10n::text("measure-cpu-power"), Value::Fixed(per_second(self.cpu_joules), 1), l10n::text("unit-estimated-watts"));
+        if let Some(joules) = self.system_joules {
+            record = record
+                .field("system_joules", l10n::text("measure-system-energy"), Value::Fixed(joules, 1), "J")
+                .field("mean_system_watts", l10n::text("measure-system-power"), Value::Fixed(per_second(joules), 1), "W");
+        }
+        // Batteries only update this every few seconds, so it's left to
+        // the long runs where it means something
+        if let (Some(start), Some(end)) = (self.started_wh, self.ended_wh) {
+            record = record.field("battery_wh", l10n::text("measure-battery-drop"), Value::Fixed(start - end, 2), "Wh");
+        }
+        record
+    }
+}
+
+// Under sudo the command runs as whoever ran sudo, root is only for the
+// MSRs
+fn invoking_user() -> Option<Account> {
+    let uid = env::var("SUDO_UID").ok()?.parse().ok()?;
+    let gid = env::var("SUDO_GID").ok()?.parse().ok()?;
+    Some(Account { uid, gid }).filter(|account| account.uid != 0)
+}
+
+fn describe(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exited with {}", code),
+        (None, Some(signal)) => format!("was killed by signal {}", signal),
+        _ => "exited".to_string(),
+    }
+}
+
+// Fails after the report when the command did, so scripts get both
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess, command: &[String], interval: Duration, batteries: &Path) -> Result<()> {
+    let Some((program, args)) = command.split_first() else {
+        bail!(Error::new(ErrorKind::Validation, "measure needs a command to run, as in `arrctl measure -- make`"));
+    };
+    signals::install()?;
+    let batteries = battery::batteries(batteries);
+    let mut sampler = Sampler::new(msr)?;
+    let mut last = Instant::now();
+    let mut meter = Meter::new(battery::discharge(&batteries));
+    let mut child = process::Command::new(program);
+    child.args(args);
+    if let Some(account) = invoking_user() {
+        unsafe {
+            child.pre_exec(move || privs::drop_to(account).map_err(io::Error::other));
+        }
+    }
+    let mut child = child.spawn().with_context(|| format!("Failed to run {}", program))?;
+
+    let status = loop {
+        // A Ctrl-C reaches the command too, the run ends when it does
+        let status = match signals::sleep(interval) {
+            true => child.try_wait(),
+            false => child.wait().map(Some),
+        };
+        let status = status.context("Failed to wait for the command")?;
+        // The last one covers however much of an interval was left
+        let now = Instant::now();
+        meter.add(&sampler.sample(msr)?, (now - last).as_secs_f64(), battery::discharge(&batteries));
+        last = now;
+        if let Some(status) = status {
+            break status;
+        }
+    };
+
+    let line = command.join(" ");
+    out.record(&meter.record(&line))?;
+    out.finish()?;
+    if !status.success() {
+        bail!(Error::new(ErrorKind::Failure, format!("{} {}", line, describe(status))));
+    }
+    Ok(())
+}
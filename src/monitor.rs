@@ -0,0 +1,382 @@
+use crate::msr::{MsrAccess, RegSpec};
+use crate::output::{Level, OutputSink, Record, Value};
+use crate::power::{self, CoreSample, PowerCoefficients};
+use crate::regs::*;
+use crate::hwmon::{self, Coretemp, Divergence, TempSource};
+use crate::governor::{self, Controller};
+use crate::journal::Journal;
+use crate::histogram::Histograms;
+use crate::turbo::Residency;
+use crate::{battery, cpu, l10n, signals, sku, zones};
+use anyhow::Result;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub struct Counters {
+    pub tsc: u64,
+    pub aperf: u64,
+    pub mperf: u64,
+}
+
+impl Counters {
+    pub fn sample_since(&self, old: &Counters, base_mhz: f32, volts: f32) -> CoreSample {
+        let tsc = self.tsc.wrapping_sub(old.tsc) as f32;
+        let aperf = self.aperf.wrapping_sub(old.aperf) as f32;
+        let mperf = self.mperf.wrapping_sub(old.mperf) as f32;
+
+        CoreSample {
+            effective_mhz: if mperf > 0.0 { base_mhz * aperf / mperf } else { 0.0 },
+            active: if tsc > 0.0 { (mperf / tsc).min(1.0) } else { 0.0 },
+            volts,
+        }
+    }
+}
+
+// Everything read on each sample, per CPU
+const SAMPLED: [u32; 5] = [IA32_TIME_STAMP_COUNTER, IA32_APERF, IA32_MPERF, IA32_PERF_STATUS, IA32_THERM_STATUS];
+
+pub struct CpuSample {
+    pub cpu: u16,
+    pub activity: CoreSample,
+    pub celsius: Option<u64>,
+    // Thermally throttled or PROCHOT asserted right now
+    pub throttling: bool,
+}
+
+pub struct Sample {
+    pub cpus: Vec<CpuSample>,
+    pub package_watts: f32,
+    pub core_amps: f32,
+    // The same load's draw with the die at TJmax
+    pub core_amps_at_tjmax: f32,
+}
+
+
+impl Sample {
+    pub fn max_volts(&self) -> f32 {
+        self.cpus.iter().map(|c| c.activity.volts).fold(0.0, f32::max)
+    }
+
+    pub fn records(&self) -> Vec<Record> {
+        let mut records: Vec<Record> = self
+            .cpus
+            .iter()
+            .map(|cpu| {
+                let a = &cpu.activity;
+                let mut record = Record::new("cpu")
+                    .title(format!("CPU{}", cpu.cpu))
+                    .cpu(cpu.cpu)
+                    .field("effective_mhz", "", Value::Fixed(a.effective_mhz as f64, 0), "MHz")
+                    .level(if cpu.throttling { Level::Bad } else { Level::Plain })
+                    .field("busy_percent", "", Value::Fixed(a.active as f64 * 100.0, 0), l10n::text("unit-busy"))
+                    .field("volts", "", Value::Fixed(a.volts as f64, 4), "V")
+                    .hidden("throttling", cpu.throttling);
+                if let Some(temp) = cpu.celsius {
+                    record = record.field("celsius", "", temp, "celsius");
+                }
+                record
+            })
+            .collect();
+        records.push(Record::new("package")
+            .field("estimated_watts", l10n::text("package"), Value::Fixed(self.package_watts as f64, 1), l10n::text("unit-estimated-watts"))
+            .hidden("estimated_core_amps", Value::Float(self.core_amps as f64))
+            .hidden("estimated_core_amps_at_tjmax", Value::Float(self.core_amps_at_tjmax as f64)));
+        records
+    }
+}
+
+// What a monitor run saw, printed when it's stopped
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    pub samples: u64,
+    pub max_package_watts: f32,
+    pub max_celsius: Option<u64>,
+    pub throttled: bool,
+    pub externally_throttled: bool,
+}
+
+impl Summary {
+    pub fn update(&mut self, sample: &Sample) {
+        self.samples += 1;
+        self.max_package_watts = self.max_package_watts.max(sample.package_watts);
+        self.max_celsius = self.max_celsius.max(sample.cpus.iter().filter_map(|c| c.celsius).max());
+        self.throttled |= sample.cpus.iter().any(|c| c.throttling);
+    }
+
+    pub fn record(&self, elapsed: Duration) -> Record {
+        let mut record = Record::new("summary")
+            .title(l10n::format("summary", &[("samples", &self.samples.to_string()), ("seconds", &format!("{:.0}", elapsed.as_secs_f64()))]))
+            .hidden("samples", self.samples)
+            .hidden("seconds", Value::Float(elapsed.as_secs_f64()))
+            .field("max_package_watts", l10n::text("summary-peak"), Value::Fixed(self.max_package_watts as f64, 1), l10n::text("unit-estimated-watts"));
+        if let Some(temp) = self.max_celsius {
+            record = record.field("max_celsius", l10n::text("summary-max"), temp, "celsius");
+        }
+        record
+            .field("throttled", l10n::text("summary-throttled"), self.throttled, "")
+            .hidden("externally_throttled", self.externally_throttled)
+            .level(if self.throttled || self.externally_throttled { Level::Bad } else { Level::Plain })
+    }
+}
+
+// How long the pattern has to hold, a CPU that just woke up can sit at the
+// lowest ratio for a moment before the governor ramps it
+const PINNED_FOR: Duration = Duration::from_secs(2);
+const PINNED_BUSY: f32 = 0.5;
+// Degrees below TJmax that count as cool
+const COOL_MARGIN: u64 = 20;
+// Share of TDP and TDC under which neither can be what's holding it back
+const UNDER_LIMIT: f32 = 0.8;
+
+// Busy at the lowest ratio while cool and well under TDP and TDC: none of
+// the CPU's own limits explain that, so something outside is forcing it
+// down. On these laptops it's mostly the EC asserting PROCHOT because of a
+// failed sensor, battery or charger.
+#[derive(Clone, Debug, Default)]
+pub struct ExternalThrottle {
+    pub min_mhz: f32,
+    pub tjmax: u64,
+    pub tdp_watts: f32,
+    pub tdc_amps: f32,
+    since: Option<Duration>,
+}
+
+impl ExternalThrottle {
+    pub fn new(msr: &dyn MsrAccess) -> Result<Self> {
+        let limits = msr_turbo_limits(msr)?;
+        Ok(ExternalThrottle {
+            min_mhz: msr_platform_info(msr)?.minimum_ratio() as f32 * cpu::BCLK_MHZ,
+            tjmax: msr_temperature_target(msr)?.get(),
+            tdp_watts: limits.tdp_watts().0 as f32,
+            tdc_amps: limits.tdc_amps().0 as f32,
+            since: None,
+        })
+    }
+
+    // Just this sample, without the time it has to last
+    pub fn pinned(&self, sample: &Sample) -> bool {
+        let under_limits = sample.package_watts < self.tdp_watts * UNDER_LIMIT && sample.core_amps < self.tdc_amps * UNDER_LIMIT;
+        under_limits && sample.cpus.iter().any(|c| {
+            c.activity.active >= PINNED_BUSY
+                && c.activity.effective_mhz < self.min_mhz + cpu::BCLK_MHZ / 2.0
+                // Without a temperature there's no telling it from thermal throttling
+                && c.celsius.is_some_and(|temp| temp + COOL_MARGIN <= self.tjmax)
+        })
+    }
+
+    // Whether it's been pinned for long enough, at is the time since start
+    pub fn update(&mut self, sample: &Sample, at: Duration) -> bool {
+        if !self.pinned(sample) {
+            self.since = None;
+            return false;
+        }
+        at - *self.since.get_or_insert(at) >= PINNED_FOR
+    }
+}
+
+pub fn external_throttle_record(active: bool) -> Record {
+    let record = Record::new("external_throttle").hidden("active", active);
+    if active {
+        record
+            .field("text", "", "Stuck at the lowest ratio while cool and under TDP and TDC, something outside the CPU is \
+                   throttling it, usually the EC over a failed sensor, battery or charger", "")
+            .level(Level::Bad)
+    } else {
+        record.field("text", "", "No longer stuck at the lowest ratio", "").level(Level::Good)
+    }
+}
+
+pub struct Sampler {
+    cpus: Vec<u16>,
+    specs: Vec<RegSpec>,
+    prev: Vec<u64>,
+    base_mhz: f32,
+    tjmax: u64,
+    coeffs: &'static PowerCoefficients,
+    threads_per_core: usize,
+    // Read alongside the MSR whenever it's there, to cross-check
+    coretemp: Option<Coretemp>,
+    source: TempSource,
+    divergent: Vec<Divergence>,
+}
+
+impl Sampler {
+    pub fn new(msr: &dyn MsrAccess) -> Result<Self> {
+        let cpus = msr.cpus();
+        let specs: Vec<RegSpec> = cpus.iter().flat_map(|&cpu| SAMPLED.map(|reg| RegSpec { reg, cpu })).collect();
+
+        Ok(Sampler {
+            cpus,
+            prev: msr.batch_read(&specs)?,
+            specs,
+            base_mhz: msr_platform_info(msr)?.base().mhz(),
+            tjmax: msr_temperature_target(msr)?.get(),
+            coeffs: sku::detect()
+                .map(|sku| &sku.power)
+                .unwrap_or(&power::DEFAULT_COEFFICIENTS),
+            threads_per_core: cpu::threads_per_core(),
+            coretemp: None,
+            source: TempSource::Msr,
+            divergent: Vec::new(),
+        })
+    }
+
+    pub fn with_coretemp(mut self, coretemp: Coretemp, source: TempSource) -> Self {
+        self.coretemp = Some(coretemp);
+        self.source = source;
+        self
+    }
+
+    // CPUs whose two readings disagreed in the last sample
+    pub fn divergent(&self) -> &[Divergence] {
+        &self.divergent
+    }
+
+    pub fn cpus(&self) -> &[u16] {
+        &self.cpus
+    }
+
+    // Averages since the previous call, or since new() for the first one
+    pub fn sample(&mut self, msr: &dyn MsrAccess) -> Result<Sample> {
+        let vals = msr.batch_read(&self.specs)?;
+        let counters = |v: &[u64]| Counters { tsc: v[0], aperf: v[1], mperf: v[2] };
+
+        let mut cpus: Vec<CpuSample> = vals
+            .chunks(SAMPLED.len())
+            .zip(self.prev.chunks(SAMPLED.len()))
+            .zip(self.specs.chunks(SAMPLED.len()))
+            .map(|((now, old), specs)| CpuSample {
+                cpu: specs[0].cpu,
+                activity: counters(now).sample_since(&counters(old), self.base_mhz, Ia32PerfStatus(now[3]).volts()),
+                celsius: Ia32ThermStatus(now[4]).celsius(self.tjmax),
+                throttling: Ia32ThermStatus(now[4]).thermal_status() || Ia32ThermStatus(now[4]).prochot(),
+            })
+            .collect();
+        self.prev = vals;
+        self.divergent.clear();
+        if let Some(coretemp) = &self.coretemp {
+            for cpu in &mut cpus {
+                let hwmon = coretemp.read(cpu.cpu);
+                self.divergent.extend(hwmon::diverges(cpu.cpu, cpu.celsius, hwmon));
+                if self.source == TempSource::Hwmon {
+                    cpu.celsius = hwmon;
+                }
+            }
+        }
+
+        let activity: Vec<CoreSample> = cpus.iter().map(|c| c.activity).collect();
+        let tjmax = self.tjmax as f32;
+        // Without a reading assume a warm but not hot die
+        let celsius = cpus.iter().filter_map(|c| c.celsius).max().map_or(tjmax - 40.0, |t| t as f32);
+        let amps = |at| power::estimate_core_amps(self.coeffs, &activity, self.threads_per_core, celsius, at);
+        Ok(Sample {
+            package_watts: power::estimate_package_watts(self.coeffs, &activity, self.threads_per_core),
+            core_amps: amps(celsius),
+            core_amps_at_tjmax: amps(tjmax),
+            cpus,
+        })
+    }
+}
+
+pub fn divergence_record(d: &Divergence) -> Record {
+    Record::new("temp_divergence")
+        .cpu(d.cpu)
+        .field("text", "", l10n::format("temp-divergence", &[
+            ("cpu", &d.cpu.to_string()),
+            ("msr", &d.msr_celsius.to_string()),
+            ("hwmon", &d.hwmon_celsius.to_string()),
+        ]), "")
+        .level(Level::Warn)
+        .hidden("msr_celsius", d.msr_celsius)
+        .hidden("hwmon_celsius", d.hwmon_celsius)
+}
+
+// With a governor it also holds the temperature in the foreground, for
+// machines that don't run the daemon
+pub fn run(
+    out: &mut dyn OutputSink,
+    msr: &dyn MsrAccess,
+    interval: Duration,
+    temps: TempSource,
+    thermal_zones: bool,
+    mut governor: Option<(Controller, &Journal)>,
+) -> Result<()> {
+    if ia32_misc_enable(msr)?.turbo_disable() {
+        out.note(l10n::text("turbo-disabled"))?;
+    }
+    out.note(l10n::text("estimated-power"))?;
+    out.note(l10n::text("estimated-power-rough"))?;
+
+    signals::install()?;
+    let start = Instant::now();
+    let mut sampler = Sampler::new(msr)?;
+    // Asked for it has to be there, otherwise it's only a cross-check
+    match Coretemp::open(Path::new(hwmon::CLASS), &cpu::layout()) {
+        Ok(coretemp) => sampler = sampler.with_coretemp(coretemp, temps),
+        Err(e) if temps == TempSource::Hwmon => return Err(e),
+        Err(_) => (),
+    }
+    let zones = if thermal_zones { zones::zones(Path::new(zones::CLASS)) } else { Vec::new() };
+    let tjmax = msr_temperature_target(msr)?.get();
+    for zone in &zones {
+        for trip in &zone.trips {
+            out.record(&zones::trip_record(zone, trip, tjmax))?;
+        }
+    }
+    let fans = hwmon::fans(Path::new(hwmon::CLASS));
+    let batteries = battery::batteries(Path::new(battery::CLASS));
+    let places = cpu::Places::current();
+    let mut warned = Vec::new();
+    let mut summary = Summary::default();
+    let mut residency = Residency::for_cpu(msr)?;
+    let mut histograms = Histograms::default();
+    let mut external = ExternalThrottle::new(msr)?;
+    let mut was_external = false;
+    while signals::sleep(interval) {
+        let sample = sampler.sample(msr)?;
+        summary.update(&sample);
+        residency.update(&sample, interval.as_secs_f64());
+        histograms.add_sample(&sample, interval.as_secs_f64());
+        let mut records: Vec<Record> = sample.records().into_iter().map(|record| places.label(record)).collect();
+        places.group(&mut records);
+        for record in &records {
+            out.record(record)?;
+        }
+        if let Some(draw) = battery::discharge(&batteries) {
+            out.record(&battery::record(&draw, sample.package_watts))?;
+        }
+        if let Some((controller, journal)) = &mut governor {
+            if let Some(record) = governor::tick(msr, journal, controller, &sample, Instant::now())? {
+                out.record(&record)?;
+            }
+        }
+        for zone in &zones {
+            if let Some(celsius) = zone.read() {
+                out.record(&zones::reading_record(zone, celsius))?;
+            }
+        }
+        for fan in &fans {
+            if let Some(rpm) = fan.rpm() {
+                out.record(&hwmon::fan_record(fan, rpm))?;
+            }
+        }
+        // Once per CPU, a bad sensor stays bad
+        for d in sampler.divergent() {
+            if !warned.contains(&d.cpu) {
+                out.record(&divergence_record(d))?;
+                warned.push(d.cpu);
+            }
+        }
+        let is_external = external.update(&sample, start.elapsed());
+        if is_external != was_external {
+            out.record(&external_throttle_record(is_external))?;
+            was_external = is_external;
+        }
+        summary.externally_throttled |= is_external;
+    }
+    out.record(&summary.record(start.elapsed()))?;
+    for record in residency.records().into_iter().chain(histograms.records()) {
+        out.record(&record)?;
+    }
+    Ok(())
+}
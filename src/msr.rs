@@ -0,0 +1,380 @@
+use crate::error::{Error, ErrorKind};
+use crate::regs;
+use anyhow::{bail, Context, Result};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const LOCKDOWN: &str = "/sys/kernel/security/lockdown";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegSpec {
+    pub reg: u32,
+    pub cpu: u16,
+}
+
+pub trait MsrAccess {
+    fn cpus(&self) -> Vec<u16>;
+    fn read(&self, reg: u32, cpu: u16) -> Result<u64>;
+    fn write(&self, reg: u32, cpu: u16, val: u64) -> Result<()>;
+
+    // Values come back in the same order as the specs
+    fn batch_read(&self, specs: &[RegSpec]) -> Result<Vec<u64>> {
+        specs.iter().map(|spec| self.read(spec.reg, spec.cpu)).collect()
+    }
+
+    // Called after the set of online CPUs changed
+    fn refresh(&self) {}
+}
+
+// The real thing, through /dev/cpu/N/msr. Device files are opened on first
+// use of each CPU and kept open until refresh finds it offline.
+#[derive(Default)]
+pub struct MsrDevice {
+    files: Mutex<HashMap<u16, Arc<File>>>,
+    // Opened O_RDONLY, the kernel refuses writes on them whatever gets called
+    read_only: bool,
+}
+
+impl MsrDevice {
+    pub fn new() -> Self {
+        Self { read_only: cfg!(feature = "read-only"), ..Self::default() }
+    }
+
+    pub fn read_only() -> Self {
+        Self { read_only: true, ..Self::default() }
+    }
+
+    fn file(&self, cpu: u16) -> Result<Arc<File>> {
+        let mut files = self.files.lock().unwrap();
+        let file = match files.entry(cpu) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let path = format!("/dev/cpu/{}/msr", cpu);
+                let file = match OpenOptions::new().read(true).write(!self.read_only).open(&path) {
+                    Ok(file) => file,
+                    // The driver makes one per online CPU, none at all is no driver
+                    Err(err) if matches!(err.raw_os_error(), Some(libc::ENOENT | libc::ENXIO)) && !driver_loaded() => {
+                        bail!(Error::new(ErrorKind::NoMsrDriver, format!("{} doesn't exist, is the msr kernel module loaded?", path)))
+                    }
+                    Err(err) if matches!(err.raw_os_error(), Some(libc::ENOENT | libc::ENXIO)) => {
+                        return Err(err).with_context(|| format!("CPU{} is offline, {} isn't there", cpu, path))
+                    }
+                    Err(err) => return Err(err).with_context(|| format!("Failed to open {}", path)),
+                };
+                e.insert(Arc::new(file))
+            }
+        };
+        Ok(file.clone())
+    }
+
+    // Opens every online CPU's file up front, for the sandbox to know the
+    // fds. Failures turn up again on first use, with a better message.
+    pub fn open_all(&self) -> Vec<RawFd> {
+        crate::cpu::online_cpus().into_iter().filter_map(|cpu| self.file(cpu).ok()).map(|file| file.as_raw_fd()).collect()
+    }
+}
+
+fn driver_loaded() -> bool {
+    std::fs::read_dir("/dev/cpu").is_ok_and(|entries| entries.filter_map(|e| e.ok()).any(|e| e.path().join("msr").exists()))
+}
+
+impl MsrAccess for MsrDevice {
+    fn cpus(&self) -> Vec<u16> {
+        crate::cpu::online_cpus()
+    }
+
+    fn read(&self, reg: u32, cpu: u16) -> Result<u64> {
+        let mut buf = [0; 8];
+        self.file(cpu)?
+            .read_exact_at(&mut buf, reg.into())
+            .with_context(|| format!("Failed to read MSR {:#x} on CPU{}", reg, cpu))?;
+        Ok(u64::from_ne_bytes(buf))
+    }
+
+    fn write(&self, reg: u32, cpu: u16, val: u64) -> Result<()> {
+        if self.read_only {
+            bail!(read_only(reg, cpu));
+        }
+        match self.file(cpu)?.write_all_at(&val.to_ne_bytes(), reg.into()) {
+            Ok(()) => Ok(()),
+            // wrmsr faulting comes back as EIO, which is what a lock bit does
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => bail!(Error::new(
+                ErrorKind::RegisterLocked,
+                format!("Failed to write MSR {:#x} on CPU{}, it is probably locked: {}", reg, cpu, e),
+            ).register(reg, cpu)),
+            // The msr driver refuses every write under lockdown with EPERM, as
+            // if we weren't root
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => match lockdown_error(Path::new(LOCKDOWN)) {
+                Some(err) => bail!(err.register(reg, cpu)),
+                None => Err(e).with_context(|| format!("Failed to write MSR {:#x} on CPU{}", reg, cpu)),
+            },
+            Err(e) => Err(e).with_context(|| format!("Failed to write MSR {:#x} on CPU{}", reg, cpu)),
+        }
+    }
+
+    // Closes the files of CPUs that went offline and opens the ones that came
+    // online, which only works while still root and, in read-only mode, not
+    // at all once the sandbox is up. Those fail again on first use.
+    // Read-only mode keeps its files, the sandbox refuses writes by fd
+    // number and a file opened later could get one of theirs.
+    fn refresh(&self) {
+        let online = crate::cpu::online_cpus();
+        if !self.read_only {
+            self.files.lock().unwrap().retain(|cpu, _| online.contains(cpu));
+        }
+        for cpu in online {
+            let _ = self.file(cpu);
+        }
+    }
+}
+
+// The bracketed mode out of "none [integrity] confidentiality", None
+// without securityfs or the lockdown LSM
+pub fn lockdown(path: &Path) -> Option<String> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let mode = text.split_whitespace().find_map(|mode| mode.strip_prefix('[')?.strip_suffix(']'))?;
+    Some(mode.to_string())
+}
+
+// Both integrity and confidentiality block writes, reads still work.
+// Distributions turn it on by themselves when booted with Secure Boot.
+pub fn lockdown_error(path: &Path) -> Option<Error> {
+    let mode = lockdown(path).filter(|mode| mode != "none")?;
+    Some(Error::new(
+        ErrorKind::PermissionDenied,
+        format!("MSR writes blocked by kernel lockdown (Secure Boot), lockdown is in {} mode", mode),
+    ))
+}
+
+fn read_only(reg: u32, cpu: u16) -> Error {
+    Error::new(ErrorKind::PermissionDenied, format!("Refusing to write MSR {:#x} on CPU{}, arrctl is in read-only mode", reg, cpu))
+        .register(reg, cpu)
+}
+
+// --read-only, every write fails before it gets near the device
+pub struct ReadOnly<M>(pub M);
+
+impl<M: MsrAccess> MsrAccess for ReadOnly<M> {
+    fn cpus(&self) -> Vec<u16> {
+        self.0.cpus()
+    }
+
+    fn read(&self, reg: u32, cpu: u16) -> Result<u64> {
+        self.0.read(reg, cpu)
+    }
+
+    fn write(&self, reg: u32, cpu: u16, _val: u64) -> Result<()> {
+        bail!(read_only(reg, cpu))
+    }
+
+    fn batch_read(&self, specs: &[RegSpec]) -> Result<Vec<u64>> {
+        self.0.batch_read(specs)
+    }
+
+    fn refresh(&self) {
+        self.0.refresh()
+    }
+}
+
+// What went wrong with an access, which decides whether another try can help
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    // Interrupted or busy, the next try usually works
+    Transient,
+    // rdmsr faulted, either the CPU was going offline or the register
+    // doesn't exist on this stepping. Only trying again tells them apart.
+    Io,
+    Offline,
+    Locked,
+    Other,
+}
+
+impl Fault {
+    pub fn retryable(self) -> bool {
+        matches!(self, Fault::Transient | Fault::Io | Fault::Offline)
+    }
+}
+
+pub fn classify(err: &anyhow::Error) -> Fault {
+    if crate::error::kind_of(err) == ErrorKind::RegisterLocked {
+        return Fault::Locked;
+    }
+    let errno = err.chain().find_map(|cause| cause.downcast_ref::<std::io::Error>()?.raw_os_error());
+    match errno {
+        Some(libc::EINTR | libc::EAGAIN | libc::EBUSY) => Fault::Transient,
+        Some(libc::EIO) => Fault::Io,
+        // ENOENT is an offline CPU's /dev/cpu/N/msr not being there
+        Some(libc::ENXIO | libc::ENODEV | libc::ENOENT) => Fault::Offline,
+        _ => Fault::Other,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    // Tries after the first one
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { retries: 2, delay: Duration::from_millis(50) }
+    }
+}
+
+// Tries failed accesses again, so a CPU being hotplugged or a busy driver
+// doesn't end an hours long monitor run. Writes only go again for faults
+// that mean they never happened, a locked register stays locked.
+pub struct Retry<M> {
+    pub inner: M,
+    pub policy: RetryPolicy,
+}
+
+impl<M: MsrAccess> Retry<M> {
+    pub fn new(inner: M, policy: RetryPolicy) -> Self {
+        Retry { inner, policy }
+    }
+
+    fn attempt<T>(&self, reg: u32, cpu: u16, mut access: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut tries = 0;
+        loop {
+            let err = match access() {
+                Ok(val) => return Ok(val),
+                Err(err) => err,
+            };
+            let fault = classify(&err);
+            if !fault.retryable() {
+                return Err(err);
+            }
+            if tries == self.policy.retries {
+                return Err(self.explain(err, fault, reg, cpu));
+            }
+            tries += 1;
+            std::thread::sleep(self.policy.delay);
+            self.inner.refresh();
+        }
+    }
+
+    fn explain(&self, err: anyhow::Error, fault: Fault, reg: u32, cpu: u16) -> anyhow::Error {
+        let tries = self.policy.retries + 1;
+        let message = match fault {
+            Fault::Io if self.inner.cpus().contains(&cpu) => {
+                format!("MSR {:#x} faulted on CPU{} {} times in a row, this stepping probably doesn't have it", reg, cpu, tries)
+            }
+            Fault::Io | Fault::Offline => format!("CPU{} went offline, MSR {:#x} can't be reached on it", cpu, reg),
+            _ => format!("MSR {:#x} on CPU{} kept failing after {} tries", reg, cpu, tries),
+        };
+        err.context(Error::new(ErrorKind::Failure, message).register(reg, cpu))
+    }
+}
+
+impl<M: MsrAccess> MsrAccess for Retry<M> {
+    fn cpus(&self) -> Vec<u16> {
+        self.inner.cpus()
+    }
+
+    fn read(&self, reg: u32, cpu: u16) -> Result<u64> {
+        self.attempt(reg, cpu, || self.inner.read(reg, cpu))
+    }
+
+    fn write(&self, reg: u32, cpu: u16, val: u64) -> Result<()> {
+        self.attempt(reg, cpu, || self.inner.write(reg, cpu, val))
+    }
+
+    fn refresh(&self) {
+        self.inner.refresh()
+    }
+}
+
+// Serves registers out of a dump made with `arrctl dump`, writes only
+// change the in-memory copy
+pub struct MockMsr {
+    pub brand: Option<String>,
+    pub microcode: Option<u64>,
+    regs: Mutex<BTreeMap<(u16, u32), u64>>,
+}
+
+fn parse_hex(s: &str) -> Result<u64> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    Ok(u64::from_str_radix(s, 16)?)
+}
+
+impl MockMsr {
+    pub fn from_dump(dump: &str) -> Result<Self> {
+        let mut brand = None;
+        let mut microcode = None;
+        let mut regs = BTreeMap::new();
+
+        for (lineno, line) in dump.lines().enumerate() {
+            let line = line.trim();
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some(b) = comment.trim().strip_prefix("brand:") {
+                    brand = Some(b.trim().to_string());
+                }
+                if let Some(m) = comment.trim().strip_prefix("microcode:") {
+                    microcode = Some(parse_hex(m.trim()).with_context(|| format!("Line {}: bad microcode revision", lineno + 1))?);
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [cpu, reg, val] = fields[..] else {
+                bail!("Line {}: expected \"cpu register value\"", lineno + 1);
+            };
+            let cpu = cpu.parse().with_context(|| format!("Line {}: bad cpu number", lineno + 1))?;
+            let reg = parse_hex(reg).ok().and_then(|reg| u32::try_from(reg).ok()).with_context(|| format!("Line {}: bad register", lineno + 1))?;
+            let val = parse_hex(val).with_context(|| format!("Line {}: bad value", lineno + 1))?;
+            regs.insert((cpu, reg), val);
+        }
+
+        Ok(MockMsr { brand, microcode, regs: Mutex::new(regs) })
+    }
+}
+
+impl MsrAccess for MockMsr {
+    fn cpus(&self) -> Vec<u16> {
+        let mut cpus: Vec<u16> = self.regs.lock().unwrap().keys().map(|(cpu, _)| *cpu).collect();
+        cpus.dedup();
+        cpus
+    }
+
+    fn read(&self, reg: u32, cpu: u16) -> Result<u64> {
+        match self.regs.lock().unwrap().get(&(cpu, reg)) {
+            Some(val) => Ok(*val),
+            None => bail!("Failed to read MSR {:#x} on CPU{}", reg, cpu),
+        }
+    }
+
+    fn write(&self, reg: u32, cpu: u16, val: u64) -> Result<()> {
+        self.regs.lock().unwrap().insert((cpu, reg), val);
+        Ok(())
+    }
+}
+
+pub fn write_dump(out: &mut dyn Write, msr: &dyn MsrAccess, brand: &str, microcode: Option<u64>) -> Result<()> {
+    writeln!(out, "# arrctl register dump")?;
+    writeln!(out, "# brand: {}", brand)?;
+    if let Some(microcode) = microcode {
+        writeln!(out, "# microcode: {:#x}", microcode)?;
+    }
+    for cpu in msr.cpus() {
+        for reg in regs::dumped() {
+            match msr.read(reg.address, cpu) {
+                Ok(val) => writeln!(out, "{} {:#x} {:#018x}", cpu, reg.address, val)?,
+                // Kept as a comment, so the dump still loads as a mock
+                Err(e) => writeln!(out, "# {} on CPU{}: unavailable ({:#})", reg.name, cpu, e)?,
+            }
+        }
+    }
+    Ok(())
+}
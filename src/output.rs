@@ -0,0 +1,494 @@
+use crate::schema;
+use anyhow::Result;
+use clap::ValueEnum;
+use serde_json::{json, Map};
+use std::collections::HashSet;
+use std::io::{IsTerminal, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Bool(bool),
+    Int(u64),
+    // Shown in hex to humans, a plain number everywhere else
+    Hex(u64),
+    Float(f64),
+    // Float with a fixed number of decimals in human output
+    Fixed(f64, usize),
+    Text(String),
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+impl Value {
+    fn human(&self, locale: Locale) -> String {
+        match self {
+            Value::Bool(v) => v.to_string(),
+            Value::Int(v) => v.to_string(),
+            Value::Hex(v) => format!("{:#x}", v),
+            Value::Float(v) => locale.decimal(v.to_string()),
+            Value::Fixed(v, prec) => locale.decimal(format!("{:.*}", prec, v)),
+            Value::Text(v) => v.clone(),
+        }
+    }
+
+    fn json(&self) -> serde_json::Value {
+        match self {
+            Value::Bool(v) => json!(v),
+            Value::Int(v) | Value::Hex(v) => json!(v),
+            Value::Float(v) | Value::Fixed(v, _) => json!(v),
+            Value::Text(v) => json!(v),
+        }
+    }
+
+    fn plain(&self) -> String {
+        match self {
+            Value::Bool(v) => (*v as u8).to_string(),
+            Value::Int(v) | Value::Hex(v) => v.to_string(),
+            Value::Float(v) | Value::Fixed(v, _) => v.to_string(),
+            Value::Text(v) => v.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub key: &'static str,
+    pub label: String,
+    pub value: Value,
+    pub unit: &'static str,
+    // Only shown in machine readable output
+    pub hidden: bool,
+    pub level: Level,
+}
+
+// How a value compares to what's expected, only ever shown as color
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Level {
+    #[default]
+    Plain,
+    Good,
+    Warn,
+    Bad,
+}
+
+impl Level {
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Level::Plain => None,
+            Level::Good => Some("32"),
+            Level::Warn => Some("33"),
+            Level::Bad => Some("31"),
+        }
+    }
+}
+
+// One logical group of values, like the turbo limits or a single CPU's sample.
+//
+// Human output puts records with a title on one line as
+// "title: label value unit, ...", and everything else as one
+// "label: value unit" line per field.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub kind: &'static str,
+    pub title: Option<String>,
+    pub cpu: Option<u16>,
+    pub fields: Vec<Field>,
+}
+
+impl Record {
+    pub fn new(kind: &'static str) -> Self {
+        Record { kind, title: None, cpu: None, fields: Vec::new() }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn cpu(mut self, cpu: u16) -> Self {
+        self.cpu = Some(cpu);
+        self
+    }
+
+    pub fn field(mut self, key: &'static str, label: impl Into<String>, value: impl Into<Value>, unit: &'static str) -> Self {
+        self.fields.push(Field { key, label: label.into(), value: value.into(), unit, hidden: false, level: Level::Plain });
+        self
+    }
+
+    // Applies to the field added last
+    pub fn level(mut self, level: Level) -> Self {
+        if let Some(field) = self.fields.last_mut() {
+            field.level = level;
+        }
+        self
+    }
+
+    pub fn hidden(mut self, key: &'static str, value: impl Into<Value>) -> Self {
+        self.fields.push(Field { key, label: String::new(), value: value.into(), unit: "", hidden: true, level: Level::Plain });
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.fields.iter().find(|f| f.key == key).map(|f| &f.value)
+    }
+}
+
+pub trait OutputSink {
+    fn record(&mut self, record: &Record) -> Result<()>;
+
+    // Free-form text meant for people, dropped by machine readable sinks
+    fn note(&mut self, _text: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    // Auto follows https://no-color.org and only colors terminals
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::env::var_os("TERM").is_none_or(|t| t != "dumb")
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    #[default]
+    Human,
+    Json,
+    Csv,
+    Prometheus,
+    Influx,
+}
+
+// Only the decimal separator for now, that's what gets misread
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Locale {
+    pub decimal_point: char,
+}
+
+// Languages that write 25,5 rather than 25.5
+const COMMA_DECIMAL: &[&str] = &[
+    "az", "be", "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr", "hu",
+    "id", "is", "it", "kk", "lt", "lv", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl",
+    "sq", "sr", "sv", "tr", "uk", "vi",
+];
+
+// Territories that use a period even though their language usually doesn't
+const PERIOD_DECIMAL: &[&str] = &["CH", "LI", "MX"];
+
+impl Locale {
+    pub const C: Locale = Locale { decimal_point: '.' };
+
+    // Accepts POSIX locale names like "de_DE.UTF-8" or "fr_CH@euro"
+    pub fn parse(name: &str) -> Locale {
+        let name = name.split(['.', '@']).next().unwrap_or("");
+        let (lang, territory) = name.split_once('_').unwrap_or((name, ""));
+        if COMMA_DECIMAL.contains(&lang) && !PERIOD_DECIMAL.contains(&territory) {
+            Locale { decimal_point: ',' }
+        } else {
+            Locale::C
+        }
+    }
+
+    // Same precedence as setlocale(LC_NUMERIC, "")
+    pub fn from_env() -> Locale {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|val| !val.is_empty())
+            .map(|val| Locale::parse(&val))
+            .unwrap_or(Locale::C)
+    }
+
+    fn decimal(self, s: String) -> String {
+        match self.decimal_point {
+            '.' => s,
+            c => s.replace('.', c.encode_utf8(&mut [0; 4])),
+        }
+    }
+}
+
+pub fn sink<'a>(format: Format, locale: Locale, color: bool, out: impl Write + 'a) -> Box<dyn OutputSink + 'a> {
+    match format {
+        Format::Human => Box::new(Human { out, locale, color }),
+        Format::Json => Box::new(Json(out)),
+        Format::Csv => Box::new(Csv { out, header: false }),
+        Format::Prometheus => Box::new(Prometheus { out, seen: HashSet::new() }),
+        Format::Influx => Box::new(Influx(out)),
+    }
+}
+
+fn unit_suffix(unit: &str) -> String {
+    match unit {
+        "" => String::new(),
+        u if u.starts_with('%') => u.to_string(),
+        u => format!(" {}", u),
+    }
+}
+
+// Machine readable sinks always use a period, only this one follows the locale
+pub struct Human<W: Write> {
+    out: W,
+    locale: Locale,
+    color: bool,
+}
+
+impl<W: Write> Human<W> {
+    pub fn new(out: W) -> Self {
+        Human { out, locale: Locale::C, color: false }
+    }
+
+    pub fn with_locale(out: W, locale: Locale) -> Self {
+        Human { out, locale, color: false }
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn value(&self, f: &Field) -> String {
+        let text = format!("{}{}", f.value.human(self.locale), unit_suffix(f.unit));
+        match f.level.ansi().filter(|_| self.color) {
+            Some(code) => format!("\x1b[{}m{}\x1b[0m", code, text),
+            None => text,
+        }
+    }
+}
+
+impl<W: Write> OutputSink for Human<W> {
+    fn record(&mut self, record: &Record) -> Result<()> {
+        let fields = record.fields.iter().filter(|f| !f.hidden);
+        match &record.title {
+            Some(title) => {
+                let parts: Vec<String> = fields
+                    .map(|f| match f.label.as_str() {
+                        "" => self.value(f),
+                        label => format!("{} {}", label, self.value(f)),
+                    })
+                    .collect();
+                match parts.is_empty() {
+                    true => writeln!(self.out, "{}", title)?,
+                    false => writeln!(self.out, "{}: {}", title, parts.join(", "))?,
+                }
+            }
+            None => {
+                for f in fields {
+                    let value = self.value(f);
+                    writeln!(self.out, "{}: {}", f.label, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn note(&mut self, text: &str) -> Result<()> {
+        writeln!(self.out, "{}", text)?;
+        Ok(())
+    }
+}
+
+pub fn record_json(record: &Record) -> serde_json::Value {
+    let mut obj = Map::new();
+    obj.insert("kind".into(), json!(record.kind));
+    if let Some(cpu) = record.cpu {
+        obj.insert("cpu".into(), json!(cpu));
+    }
+    for f in &record.fields {
+        obj.insert(f.key.into(), f.value.json());
+    }
+    serde_json::Value::Object(obj)
+}
+
+// One JSON object per line, so a monitor can be consumed as it runs
+pub struct Json<W: Write>(pub W);
+
+impl<W: Write> OutputSink for Json<W> {
+    fn record(&mut self, record: &Record) -> Result<()> {
+        let mut obj = record_json(record);
+        obj["schema_version"] = json!(schema::VERSION);
+        writeln!(self.0, "{}", obj)?;
+        Ok(())
+    }
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains(['"', ',', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// Long format, one row per field, so records of different kinds can
+// share a single header
+pub struct Csv<W: Write> {
+    out: W,
+    header: bool,
+}
+
+impl<W: Write> OutputSink for Csv<W> {
+    fn record(&mut self, record: &Record) -> Result<()> {
+        if !self.header {
+            writeln!(self.out, "kind,cpu,field,value")?;
+            self.header = true;
+        }
+        let cpu = record.cpu.map(|c| c.to_string()).unwrap_or_default();
+        for f in &record.fields {
+            writeln!(self.out, "{},{},{},{}", record.kind, cpu, f.key, csv_escape(&f.value.plain()))?;
+        }
+        Ok(())
+    }
+}
+
+fn prometheus_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Text values can't be samples, so they go into the labels of
+// an arrctl_<kind>_info metric
+pub struct Prometheus<W: Write> {
+    out: W,
+    seen: HashSet<String>,
+}
+
+impl<W: Write> Prometheus<W> {
+    fn sample(&mut self, name: String, labels: &[(String, String)], value: String) -> Result<()> {
+        if self.seen.insert(name.clone()) {
+            writeln!(self.out, "# TYPE {} gauge", name)?;
+        }
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, prometheus_escape(v)))
+            .collect();
+        if labels.is_empty() {
+            writeln!(self.out, "{} {}", name, value)?;
+        } else {
+            writeln!(self.out, "{}{{{}}} {}", name, labels.join(","), value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> OutputSink for Prometheus<W> {
+    fn record(&mut self, record: &Record) -> Result<()> {
+        let mut labels = Vec::new();
+        if let Some(cpu) = record.cpu {
+            labels.push(("cpu".to_string(), cpu.to_string()));
+        }
+
+        let mut info = labels.clone();
+        for f in &record.fields {
+            match &f.value {
+                Value::Text(v) => info.push((f.key.to_string(), v.clone())),
+                v => self.sample(format!("arrctl_{}_{}", record.kind, f.key), &labels, v.plain())?,
+            }
+        }
+        if info.len() > labels.len() {
+            self.sample(format!("arrctl_{}_info", record.kind), &info, "1".to_string())?;
+        }
+        Ok(())
+    }
+}
+
+fn influx_escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// One line protocol point per record, text values become tags the same way
+// they become Prometheus labels. A record with nothing numeric keeps its
+// text as string fields, a point needs at least one field.
+pub fn influx_line(record: &Record, time_ns: u128) -> String {
+    let mut tags = Vec::new();
+    if let Some(cpu) = record.cpu {
+        tags.push(format!("cpu={}", cpu));
+    }
+    let mut fields = Vec::new();
+    for f in &record.fields {
+        match &f.value {
+            // Empty tag values aren't allowed
+            Value::Text(v) if v.is_empty() => (),
+            Value::Text(v) => tags.push(format!("{}={}", f.key, influx_escape(v, &[',', '=', ' ']))),
+            Value::Bool(v) => fields.push(format!("{}={}", f.key, v)),
+            Value::Int(v) | Value::Hex(v) => fields.push(format!("{}={}i", f.key, v)),
+            Value::Float(v) | Value::Fixed(v, _) => fields.push(format!("{}={}", f.key, v)),
+        }
+    }
+    if fields.is_empty() {
+        tags.retain(|tag| tag.starts_with("cpu="));
+        for f in &record.fields {
+            if let Value::Text(v) = &f.value {
+                fields.push(format!("{}=\"{}\"", f.key, influx_escape(v, &['"'])));
+            }
+        }
+    }
+
+    let mut line = influx_escape(&format!("arrctl_{}", record.kind), &[',', ' ']);
+    for tag in tags {
+        line += ",";
+        line += &tag;
+    }
+    format!("{} {} {}", line, fields.join(","), time_ns)
+}
+
+pub fn now_ns() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0)
+}
+
+pub struct Influx<W: Write>(pub W);
+
+impl<W: Write> OutputSink for Influx<W> {
+    fn record(&mut self, record: &Record) -> Result<()> {
+        writeln!(self.0, "{}", influx_line(record, now_ns()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use crate::error::{Error, ErrorKind};
+use crate::regs::{self, FieldSpec, Register, Scope};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Registers arrctl doesn't know about, one or more per file:
+//
+//   [[register]]
+//   name = "MSR_POWER_CTL"
+//   address = 0x1fc
+//   scope = "package"
+//
+//   [[register.field]]
+//   name = "c1e_promotion"
+//   lsb = 1
+//
+// They're dumped and decoded like the built-in ones, but never written.
+pub const DIR: &str = "/etc/arrctl/registers.d";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default, rename = "register")]
+    registers: Vec<Definition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    name: String,
+    address: u32,
+    // Thread when not given, like any register arrctl doesn't know
+    scope: Option<Scope>,
+    // The ones arrctl supports when not given
+    models: Option<Vec<u32>>,
+    // Left out of dump and selftest, for ones that fault on some CPUs
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default, rename = "field")]
+    fields: Vec<FieldDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldDefinition {
+    name: String,
+    lsb: u8,
+    // A single bit when not given
+    msb: Option<u8>,
+    valid: Option<(u64, u64)>,
+    // What the codes mean, "name = code"
+    #[serde(default)]
+    names: BTreeMap<String, u64>,
+}
+
+fn leak(s: String) -> &'static str {
+    String::leak(s)
+}
+
+fn field(def: FieldDefinition) -> Result<FieldSpec> {
+    let msb = def.msb.unwrap_or(def.lsb);
+    if def.lsb > msb || msb > 63 {
+        bail!("Field {} has bits {}..{}, expected lsb <= msb <= 63", def.name, def.lsb, msb);
+    }
+    let names: Vec<(u64, &'static str)> = def.names.into_iter().map(|(name, code)| (code, leak(name))).collect();
+    Ok(FieldSpec { name: leak(def.name), lsb: def.lsb, msb, writable: false, valid: def.valid, names: names.leak() })
+}
+
+fn register(def: Definition) -> Result<Register> {
+    let fields = def.fields.into_iter().map(field).collect::<Result<Vec<_>>>().with_context(|| def.name.clone())?;
+    let models: &'static [u32] = match def.models {
+        Some(models) => models.leak(),
+        None => regs::MODELS,
+    };
+    Ok(Register {
+        name: leak(def.name),
+        address: def.address,
+        scope: def.scope.unwrap_or(Scope::Thread),
+        models,
+        writable: false,
+        dumped: !def.hidden,
+        fields: fields.leak(),
+        overrides: &[],
+    })
+}
+
+// Every *.toml in dir, in name order. Nothing there is no registers, a
+// register arrctl already has or one given twice is an error.
+pub fn load(dir: &Path) -> Result<Vec<Register>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.extension().is_some_and(|ext| ext == "toml")).collect();
+    paths.sort();
+
+    let mut registers: Vec<Register> = Vec::new();
+    for path in paths {
+        let text = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let file: File = toml::from_str(&text).map_err(|e| Error::new(ErrorKind::Validation, format!("{}: {}", path.display(), e.message())))?;
+        for def in file.registers {
+            let reg = register(def).map_err(|e| Error::new(ErrorKind::Validation, format!("{}: {:#}", path.display(), e)))?;
+            let taken = regs::lookup(reg.address).or_else(|| registers.iter().find(|r| r.address == reg.address));
+            if let Some(other) = taken {
+                bail!(Error::new(ErrorKind::Validation, format!("{}: {} at {:#x} is already {}", path.display(), reg.name, reg.address, other.name)));
+            }
+            registers.push(reg);
+        }
+    }
+    Ok(registers)
+}
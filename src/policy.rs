@@ -0,0 +1,528 @@
+use crate::error::{Error, ErrorKind};
+use crate::l10n;
+use crate::monitor::Sample;
+use crate::output::{Record, Value};
+use crate::profile::{Profile, MAX_LIMIT};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+// A policy works out the limits from each sample, for what [governor]
+// can't express. One assignment per line, # starts a comment:
+//
+//   hot = temperature - 80
+//   tdp = if ac then clamp(35 - hot, 15, 35) else 18
+//   turbo = temperature < 95
+//
+// The inputs are read-only, the outputs are whatever gets assigned and
+// everything else is a name for later lines. Comparisons are 1 or 0. There
+// are no loops, calls out or ways to touch files, and the size and nesting
+// are capped, so a policy always finishes and can only ever pick limits.
+
+// Of a sample, see Inputs
+pub const INPUTS: &[&str] = &["temperature", "mhz", "busy", "watts", "amps", "ac"];
+// tdp in watts, tdc in amps, clock_modulation as a duty cycle in percent
+// where 100 is off, turbo 0 or not
+pub const OUTPUTS: &[&str] = &["tdp", "tdc", "clock_modulation", "turbo"];
+
+const MAX_BYTES: usize = 64 * 1024;
+// Per line, which also bounds how deep evaluating one can go
+const MAX_TOKENS: usize = 256;
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Inputs {
+    // Hottest core, celsius
+    pub temperature: f64,
+    // Mean effective clock over the CPUs
+    pub mhz: f64,
+    // Mean percent of the time the CPUs weren't idle
+    pub busy: f64,
+    // Package estimates
+    pub watts: f64,
+    pub amps: f64,
+    pub ac: bool,
+}
+
+impl Inputs {
+    // None without a temperature to go on, like the governor
+    pub fn from_sample(sample: &Sample, ac: bool) -> Option<Self> {
+        let temperature = sample.cpus.iter().filter_map(|c| c.celsius).max()? as f64;
+        let n = sample.cpus.len().max(1) as f64;
+        Some(Inputs {
+            temperature,
+            mhz: sample.cpus.iter().map(|c| c.activity.effective_mhz as f64).sum::<f64>() / n,
+            busy: sample.cpus.iter().map(|c| c.activity.active as f64).sum::<f64>() / n * 100.0,
+            watts: sample.package_watts as f64,
+            amps: sample.core_amps as f64,
+            ac,
+        })
+    }
+
+    fn get(&self, name: &str) -> f64 {
+        match name {
+            "temperature" => self.temperature,
+            "mhz" => self.mhz,
+            "busy" => self.busy,
+            "watts" => self.watts,
+            "amps" => self.amps,
+            "ac" => truth(self.ac),
+            _ => unreachable!("{} isn't an input", name),
+        }
+    }
+}
+
+fn truth(b: bool) -> f64 {
+    if b { 1.0 } else { 0.0 }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Bin {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Func {
+    Min,
+    Max,
+    Clamp,
+    Abs,
+    Round,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(f64),
+    Input(&'static str),
+    // Index into the variables
+    Var(usize),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Bin, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Statement {
+    line: usize,
+    var: usize,
+    expr: Expr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Script {
+    vars: Vec<String>,
+    statements: Vec<Statement>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Word(String),
+    Sym(&'static str),
+}
+
+const SYMBOLS: &[&str] = &["<=", ">=", "==", "!=", "<", ">", "=", "+", "-", "*", "/", "(", ")", ","];
+
+fn tokens(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_digit() || c == '.' {
+            let len = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+            let number = rest[..len].parse().map_err(|_| format!("Bad number {:?}", &rest[..len]))?;
+            tokens.push(Token::Number(number));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..len].to_string()));
+            len
+        } else if let Some(sym) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Sym(sym));
+            sym.len()
+        } else {
+            return Err(format!("Unexpected {:?}", c));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    vars: &'a [String],
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn word(&mut self, word: &str) -> bool {
+        if self.peek() == Some(&Token::Word(word.to_string())) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn sym(&mut self, sym: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Sym(s)) if *s == sym) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), String> {
+        match self.sym(sym) {
+            true => Ok(()),
+            false => Err(format!("Expected {:?}", sym)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("Nested deeper than {}", MAX_DEPTH));
+        }
+        let expr = if self.word("if") {
+            let cond = self.expr()?;
+            if !self.word("then") {
+                return Err("Expected then".to_string());
+            }
+            let then = self.expr()?;
+            if !self.word("else") {
+                return Err("Expected else, an if has to give a value either way".to_string());
+            }
+            Expr::If(Box::new(cond), Box::new(then), Box::new(self.expr()?))
+        } else {
+            self.or()?
+        };
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.word("or") {
+            left = Expr::Binary(Bin::Or, Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        while self.word("and") {
+            left = Expr::Binary(Bin::And, Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        match self.word("not") {
+            true => Ok(Expr::Not(Box::new(self.not()?))),
+            false => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        let ops = [("<", Bin::Lt), ("<=", Bin::Le), (">", Bin::Gt), (">=", Bin::Ge), ("==", Bin::Eq), ("!=", Bin::Ne)];
+        for (sym, op) in ops {
+            if self.sym(sym) {
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(self.sum()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        loop {
+            let op = match () {
+                _ if self.sym("+") => Bin::Add,
+                _ if self.sym("-") => Bin::Sub,
+                _ => return Ok(left),
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match () {
+                _ if self.sym("*") => Bin::Mul,
+                _ if self.sym("/") => Bin::Div,
+                _ => return Ok(left),
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.sym("-") {
+            true => Ok(Expr::Neg(Box::new(self.unary()?))),
+            false => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Sym("(")) => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Word(word)) if word == "true" => Ok(Expr::Number(1.0)),
+            Some(Token::Word(word)) if word == "false" => Ok(Expr::Number(0.0)),
+            Some(Token::Word(word)) if self.sym("(") => {
+                let (func, arity) = match word.as_str() {
+                    "min" => (Func::Min, None),
+                    "max" => (Func::Max, None),
+                    "clamp" => (Func::Clamp, Some(3)),
+                    "abs" => (Func::Abs, Some(1)),
+                    "round" => (Func::Round, Some(1)),
+                    _ => return Err(format!("No function {}, there's min, max, clamp, abs and round", word)),
+                };
+                let mut args = Vec::new();
+                if !self.sym(")") {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.sym(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                match arity {
+                    Some(n) if args.len() != n => Err(format!("{} takes {} argument(s), got {}", word, n, args.len())),
+                    None if args.is_empty() => Err(format!("{} needs at least one argument", word)),
+                    _ => Ok(Expr::Call(func, args)),
+                }
+            }
+            Some(Token::Word(word)) => {
+                if let Some(input) = INPUTS.iter().find(|i| **i == word) {
+                    return Ok(Expr::Input(input));
+                }
+                match self.vars.iter().position(|v| *v == word) {
+                    Some(index) => Ok(Expr::Var(index)),
+                    None => Err(format!("{} isn't an input or set on an earlier line", word)),
+                }
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("The line ends in the middle of an expression".to_string()),
+        }
+    }
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Self> {
+        if text.len() > MAX_BYTES {
+            bail!(Error::new(ErrorKind::Validation, format!("Policies are capped at {} KiB", MAX_BYTES / 1024)));
+        }
+        let mut script = Script { vars: Vec::new(), statements: Vec::new() };
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            script.statement(index + 1, line).map_err(|e| Error::new(ErrorKind::Validation, format!("Line {}: {}", index + 1, e)))?;
+        }
+        Ok(script)
+    }
+
+    fn statement(&mut self, line: usize, text: &str) -> Result<(), String> {
+        let tokens = tokens(text)?;
+        if tokens.len() > MAX_TOKENS {
+            return Err(format!("More than {} tokens, split it over a few lines", MAX_TOKENS));
+        }
+        let [Token::Word(name), Token::Sym("="), rest @ ..] = &tokens[..] else {
+            return match tokens.is_empty() {
+                true => Ok(()),
+                false => Err("Expected name = expression".to_string()),
+            };
+        };
+        if INPUTS.contains(&name.as_str()) {
+            return Err(format!("{} is an input, it can't be assigned", name));
+        }
+        let mut parser = Parser { tokens: rest, pos: 0, vars: &self.vars, depth: 0 };
+        let expr = parser.expr()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {:?} after the expression", token));
+        }
+        let var = match self.vars.iter().position(|v| v == name) {
+            Some(index) => index,
+            None => {
+                self.vars.push(name.clone());
+                self.vars.len() - 1
+            }
+        };
+        self.statements.push(Statement { line, var, expr });
+        Ok(())
+    }
+
+    // The limits for these inputs, only the ones the policy assigned
+    pub fn evaluate(&self, inputs: &Inputs) -> Result<Profile> {
+        let mut values: Vec<Option<f64>> = vec![None; self.vars.len()];
+        for statement in &self.statements {
+            let value = eval(&statement.expr, inputs, &values).with_context(|| format!("Line {}", statement.line))?;
+            values[statement.var] = Some(value);
+        }
+        let output = |name: &str| self.vars.iter().position(|v| v == name).and_then(|index| values[index]);
+
+        let limit = |name: &str, unit: &str| -> Result<Option<u64>> {
+            let Some(value) = output(name) else {
+                return Ok(None);
+            };
+            let whole = value.round();
+            if !(1.0..=MAX_LIMIT as f64).contains(&whole) {
+                bail!(Error::new(ErrorKind::Validation, format!("{} came out as {} {}, expected 1 to {}", name, value, unit, MAX_LIMIT)));
+            }
+            Ok(Some(whole as u64))
+        };
+        let mut profile = Profile { tdp: limit("tdp", "W")?, tdc: limit("tdc", "A")?, ..Default::default() };
+        if let Some(percent) = output("clock_modulation") {
+            // The nearest eighth, and all of them is off
+            let eighths = (percent / 12.5).round();
+            if !(1.0..=8.0).contains(&eighths) {
+                bail!(Error::new(ErrorKind::Validation, format!("clock_modulation came out as {} %, expected 12.5 to 100", percent)));
+            }
+            profile.clock_modulation = Some(eighths as u64 % 8);
+        }
+        profile.turbo = output("turbo").map(|v| v != 0.0);
+        Ok(profile)
+    }
+
+    // Outputs the policy ever assigns, which the daemon leaves to it
+    pub fn outputs(&self) -> impl Iterator<Item = &str> {
+        self.vars.iter().map(String::as_str).filter(|v| OUTPUTS.contains(v))
+    }
+}
+
+fn eval(expr: &Expr, inputs: &Inputs, vars: &[Option<f64>]) -> Result<f64> {
+    let value = match expr {
+        Expr::Number(n) => *n,
+        Expr::Input(name) => inputs.get(name),
+        // Assigned on an earlier line, but maybe only in a branch never taken
+        Expr::Var(index) => vars[*index].unwrap_or(0.0),
+        Expr::Neg(e) => -eval(e, inputs, vars)?,
+        Expr::Not(e) => truth(eval(e, inputs, vars)? == 0.0),
+        Expr::If(cond, then, otherwise) => match eval(cond, inputs, vars)? != 0.0 {
+            true => eval(then, inputs, vars)?,
+            false => eval(otherwise, inputs, vars)?,
+        },
+        Expr::Binary(op, a, b) => {
+            let a = eval(a, inputs, vars)?;
+            // Short circuits so a guard can keep a division from running
+            match op {
+                Bin::And if a == 0.0 => return Ok(0.0),
+                Bin::Or if a != 0.0 => return Ok(1.0),
+                _ => (),
+            }
+            let b = eval(b, inputs, vars)?;
+            match op {
+                Bin::Add => a + b,
+                Bin::Sub => a - b,
+                Bin::Mul => a * b,
+                Bin::Div if b == 0.0 => bail!(Error::new(ErrorKind::Validation, "Division by zero")),
+                Bin::Div => a / b,
+                Bin::Lt => truth(a < b),
+                Bin::Le => truth(a <= b),
+                Bin::Gt => truth(a > b),
+                Bin::Ge => truth(a >= b),
+                Bin::Eq => truth(a == b),
+                Bin::Ne => truth(a != b),
+                Bin::And | Bin::Or => truth(b != 0.0),
+            }
+        }
+        Expr::Call(func, args) => {
+            let args = args.iter().map(|a| eval(a, inputs, vars)).collect::<Result<Vec<_>>>()?;
+            match func {
+                Func::Min => args.iter().copied().fold(f64::INFINITY, f64::min),
+                Func::Max => args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                Func::Clamp if args[1] > args[2] => bail!(Error::new(ErrorKind::Validation, format!("clamp between {} and {} is backwards", args[1], args[2]))),
+                Func::Clamp => args[0].clamp(args[1], args[2]),
+                Func::Abs => args[0].abs(),
+                Func::Round => args[0].round(),
+            }
+        }
+    };
+    if !value.is_finite() {
+        bail!(Error::new(ErrorKind::Validation, "The result isn't a finite number"));
+    }
+    Ok(value)
+}
+
+pub fn load(path: &Path) -> Result<Script> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Script::parse(&text).with_context(|| format!("In {}", path.display()))
+}
+
+// The limits a policy picked, as fields of record
+pub(crate) fn limit_fields(mut record: Record, profile: &Profile) -> Record {
+    if let Some(tdp) = profile.tdp {
+        record = record.field("tdp", "TDP", tdp, "W");
+    }
+    if let Some(tdc) = profile.tdc {
+        record = record.field("tdc", "TDC", tdc, "A");
+    }
+    if let Some(eighths) = profile.clock_modulation {
+        let percent = if eighths == 0 { 100.0 } else { eighths as f64 * 12.5 };
+        record = record.field("clock_modulation", l10n::text("policy-clock-modulation"), Value::Fixed(percent, 1), "%");
+    }
+    if let Some(turbo) = profile.turbo {
+        record = record.field("turbo", l10n::text("policy-turbo"), turbo, "");
+    }
+    record
+}
+
+// What the policy picks over a recording, a record each time that changes
+pub fn test(script: &Script, samples: &[Sample], ac: bool) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut last = None;
+    let (mut changes, mut skipped) = (0u64, 0u64);
+    for (index, sample) in samples.iter().enumerate() {
+        let Some(inputs) = Inputs::from_sample(sample, ac) else {
+            skipped += 1;
+            continue;
+        };
+        let profile = script.evaluate(&inputs).with_context(|| format!("Sample {}", index + 1))?;
+        if last.as_ref() == Some(&profile) {
+            continue;
+        }
+        changes += 1;
+        let record = Record::new("policy")
+            .title(l10n::format("policy-sample", &[("sample", &(index + 1).to_string())]))
+            .hidden("sample", index as u64 + 1)
+            .field("celsius", l10n::text("policy-celsius"), Value::Float(inputs.temperature), "celsius");
+        records.push(limit_fields(record, &profile));
+        last = Some(profile);
+    }
+    records.push(Record::new("policy_summary")
+        .title(l10n::text("policy-summary"))
+        .field("samples", l10n::text("policy-samples"), samples.len() as u64, "")
+        .field("changes", l10n::text("policy-changes"), changes, "")
+        .field("skipped", l10n::text("policy-skipped"), skipped, ""));
+    Ok(records)
+}
@@ -0,0 +1,142 @@
+use crate::error::{Error, ErrorKind};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::process::Command;
+
+// Defined in dist/org.arrctl.policy
+pub const APPLY_PROFILE: &str = "org.arrctl.apply-profile";
+pub const SET_LIMITS: &str = "org.arrctl.set-limits";
+
+// The subject the way pkcheck takes it. With the start time a process that
+// got the pid of an authorized one after it exited doesn't inherit it.
+pub fn subject(proc_root: &Path, pid: i32, uid: u32) -> Result<String> {
+    let path = proc_root.join(pid.to_string()).join("stat");
+    let stat = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    // The command name can have spaces and parentheses in it, field 22 is
+    // counted from after the last one
+    let (_, fields) = stat.rsplit_once(')').context("No command name in the process's stat")?;
+    let start = fields.split_whitespace().nth(19).context("No start time in the process's stat")?;
+    Ok(format!("{},{},{}", pid, start, uid))
+}
+
+// How /proc/net/tcp{,6} writes addresses, the words in host byte order
+fn proc_addr(addr: SocketAddr) -> String {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => format!("{:08X}", u32::from_ne_bytes(ip.octets())),
+        IpAddr::V6(ip) => ip.octets().chunks(4).map(|w| format!("{:08X}", u32::from_ne_bytes([w[0], w[1], w[2], w[3]]))).collect(),
+    };
+    format!("{}:{:04X}", ip, addr.port())
+}
+
+// The pid and uid on the other end of a TCP connection from this machine,
+// what UCred is for unix sockets. The uid comes with the socket, the pid
+// takes finding which process has it open, and /proc/<pid>/fd of other
+// users is only readable while still root, see PeerLookup.
+pub fn tcp_peer(proc_root: &Path, peer: SocketAddr, local: SocketAddr) -> Option<(i32, u32)> {
+    // An IPv4 client of a listener on [::] shows up as ::ffff:a.b.c.d on
+    // this end, its own socket has the plain address
+    let forms = |addr: SocketAddr| [proc_addr(addr), proc_addr(SocketAddr::new(addr.ip().to_canonical(), addr.port()))];
+    let (peer, local) = (forms(peer), forms(local));
+    let (uid, inode) = ["net/tcp", "net/tcp6"].iter().find_map(|table| {
+        let text = fs::read_to_string(proc_root.join(table)).ok()?;
+        text.lines().skip(1).find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [_, from, to, _, _, _, _, uid, _, inode, ..] = fields[..] else {
+                return None;
+            };
+            (peer.iter().zip(&local).any(|(p, l)| from == p && to == l)).then(|| Some((uid.parse::<u32>().ok()?, inode.to_string())))?
+        })
+    })?;
+    let target = format!("socket:[{}]", inode);
+    let pid = fs::read_dir(proc_root).ok()?.flatten().find_map(|entry| {
+        let pid: i32 = entry.file_name().to_str()?.parse().ok()?;
+        let mut fds = fs::read_dir(entry.path().join("fd")).ok()?;
+        fds.any(|fd| fd.is_ok_and(|fd| fs::read_link(fd.path()).is_ok_and(|link| link.as_os_str() == target.as_str()))).then_some(pid)
+    })?;
+    Some((pid, uid))
+}
+
+// tcp_peer for a daemon that drops root: a process forked before that
+// keeps it and does nothing but the lookups, one line each way over a
+// socket pair. It goes when the daemon does, with the other end.
+pub struct PeerLookup(Mutex<UnixStream>);
+
+impl PeerLookup {
+    // Before any threads, the child carries on with only this one
+    pub fn spawn() -> Result<Self> {
+        let (daemon, helper) = UnixStream::pair().context("Failed to create the peer lookup socket")?;
+        match unsafe { libc::fork() } {
+            -1 => Err(io::Error::last_os_error()).context("Failed to fork the peer lookup helper"),
+            0 => {
+                drop(daemon);
+                serve_lookups(helper)
+            }
+            _ => Ok(PeerLookup(Mutex::new(daemon))),
+        }
+    }
+
+    // Blocks on the helper, it scans all of /proc
+    pub fn find(&self, peer: SocketAddr, local: SocketAddr) -> Option<(i32, u32)> {
+        let mut stream = self.0.lock().unwrap();
+        writeln!(stream, "{} {}", peer, local).ok()?;
+        let mut line = String::new();
+        BufReader::new(&*stream).read_line(&mut line).ok()?;
+        let (pid, uid) = line.trim().split_once(' ')?;
+        Some((pid.parse().ok()?, uid.parse().ok()?))
+    }
+}
+
+fn serve_lookups(stream: UnixStream) -> ! {
+    // Nothing else it inherited stays open, the msr files least of all
+    let keep = stream.as_raw_fd();
+    let fds: Vec<i32> = fs::read_dir("/proc/self/fd").map(|dir| dir.flatten().filter_map(|fd| fd.file_name().to_str()?.parse().ok()).collect()).unwrap_or_default();
+    for fd in fds.into_iter().filter(|&fd| fd != keep) {
+        unsafe { libc::close(fd) };
+    }
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|read| read > 0) {
+        let addrs = line.trim().split_once(' ').and_then(|(peer, local)| Some((peer.parse().ok()?, local.parse().ok()?)));
+        let reply = match addrs.and_then(|(peer, local)| tcp_peer(Path::new("/proc"), peer, local)) {
+            Some((pid, uid)) => format!("{} {}\n", pid, uid),
+            None => "-\n".to_string(),
+        };
+        if (&stream).write_all(reply.as_bytes()).is_err() {
+            break;
+        }
+        line.clear();
+    }
+    unsafe { libc::_exit(0) }
+}
+
+// pkcheck says it all in the exit code
+pub fn verdict(code: Option<i32>) -> Result<bool> {
+    match code {
+        Some(0) => Ok(true),
+        // 2 is for when it would have had to ask
+        Some(1 | 2) => Ok(false),
+        Some(3) => bail!(Error::new(ErrorKind::PermissionDenied, "Authentication was dismissed")),
+        _ => bail!(Error::new(ErrorKind::Failure, "pkcheck failed, is polkit running?")),
+    }
+}
+
+// Root always may, everyone else gets asked through their session's
+// authentication agent
+pub async fn check(action: &str, pid: i32, uid: u32, details: &[(&str, &str)]) -> Result<bool> {
+    if uid == 0 {
+        return Ok(true);
+    }
+    let mut cmd = Command::new("pkcheck");
+    cmd.args(["--action-id", action, "--process", &subject(Path::new("/proc"), pid, uid)?, "--allow-user-interaction"]);
+    for (key, value) in details {
+        cmd.args(["--detail", key, value]);
+    }
+    let status = cmd.status().await.context("Failed to run pkcheck, is polkit installed?")?;
+    verdict(status.code())
+}
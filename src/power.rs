@@ -0,0 +1,78 @@
+// Arrandale has no RAPL, so there is no way to actually measure package power.
+// This is a rough CMOS model (dynamic C*V^2*f plus leakage) fitted by hand
+// against the rated TDPs, good enough to tell "way under" from "hitting the limit".
+
+pub struct PowerCoefficients {
+    // Effective switched capacitance per core, in W / (V^2 * GHz)
+    pub dynamic: f32,
+    // Leakage per core, in W / V
+    pub leakage: f32,
+    // Everything outside of the cores that is counted against TDP, in W
+    pub uncore: f32,
+}
+
+pub const DEFAULT_COEFFICIENTS: PowerCoefficients = PowerCoefficients {
+    dynamic: 3.0,
+    leakage: 1.6,
+    uncore: 4.0,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct CoreSample {
+    // Average frequency while not halted, in MHz
+    pub effective_mhz: f32,
+    // Fraction of the interval spent in C0
+    pub active: f32,
+    pub volts: f32,
+}
+
+// Leakage roughly doubles every this many degrees
+const LEAKAGE_DOUBLING_CELSIUS: f32 = 25.0;
+
+fn core_watts(coeffs: &PowerCoefficients, threads: &[CoreSample], threads_per_core: usize, leakage_scale: f32) -> f32 {
+    let cores = threads.len() as f32 / threads_per_core.max(1) as f32;
+
+    let dynamic: f32 = threads
+        .iter()
+        .map(|t| coeffs.dynamic * t.volts * t.volts * (t.effective_mhz / 1000.0) * t.active)
+        .sum::<f32>()
+        / threads_per_core.max(1) as f32;
+
+    let volts = threads.iter().map(|t| t.volts).fold(0.0, f32::max);
+    let leakage = coeffs.leakage * volts * cores * leakage_scale;
+
+    dynamic + leakage
+}
+
+pub fn estimate_package_watts(
+    coeffs: &PowerCoefficients,
+    threads: &[CoreSample],
+    threads_per_core: usize,
+) -> f32 {
+    core_watts(coeffs, threads, threads_per_core, 1.0) + coeffs.uncore
+}
+
+// Every thread busy at one frequency and voltage, the least the cores
+// draw while running at the lowest ratio
+pub fn floor_amps(coeffs: &PowerCoefficients, threads: usize, threads_per_core: usize, mhz: f32, volts: f32) -> f32 {
+    let busy = CoreSample { effective_mhz: mhz, active: 1.0, volts };
+    estimate_core_amps(coeffs, &vec![busy; threads], threads_per_core, 0.0, 0.0)
+}
+
+// Current on the core plane, which is what TDC limits. The uncore is on
+// its own rail so it doesn't count. `at_celsius` rescales leakage to what
+// the same load would draw at another temperature.
+pub fn estimate_core_amps(
+    coeffs: &PowerCoefficients,
+    threads: &[CoreSample],
+    threads_per_core: usize,
+    celsius: f32,
+    at_celsius: f32,
+) -> f32 {
+    let volts = threads.iter().map(|t| t.volts).fold(0.0, f32::max);
+    if volts <= 0.0 {
+        return 0.0;
+    }
+    let scale = 2f32.powf((at_celsius - celsius) / LEAKAGE_DOUBLING_CELSIUS);
+    core_watts(coeffs, threads, threads_per_core, scale) / volts
+}
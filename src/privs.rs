@@ -0,0 +1,74 @@
+use crate::error::{Error, ErrorKind};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io;
+use std::os::unix::fs::chown;
+use std::path::Path;
+
+pub const PASSWD: &str = "/etc/passwd";
+
+// What dist/arrctl.sysusers creates
+pub const DEFAULT_USER: &str = "arrctl";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Account {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+// Straight out of the file, system users don't come from LDAP
+pub fn lookup(passwd: &Path, name: &str) -> Result<Account> {
+    let text = fs::read_to_string(passwd).with_context(|| format!("Failed to read {}", passwd.display()))?;
+    let account = text.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        let [user, _, uid, gid, ..] = fields[..] else {
+            return None;
+        };
+        (user == name).then(|| Some(Account { uid: uid.parse().ok()?, gid: gid.parse().ok()? }))?
+    });
+    match account {
+        Some(account) => Ok(account),
+        None => bail!(Error::new(
+            ErrorKind::Validation,
+            format!("No user {} to run the daemon as, create it with `useradd --system {}` or pass --user root", name, name)
+        )),
+    }
+}
+
+// The journal and audit log have to stay writable, together with the
+// directories they're in, for adding new files next to them
+pub fn hand_over(paths: &[&Path], account: Account) -> Result<()> {
+    for path in paths {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+            chown(dir, Some(account.uid), Some(account.gid)).with_context(|| format!("Failed to hand {} over", dir.display()))?;
+        }
+        match chown(path, Some(account.uid), Some(account.gid)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e).with_context(|| format!("Failed to hand {} over", path.display())),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+// For good, files opened before stay usable. The msr driver only checks
+// CAP_SYS_RAWIO on open, so writes through those keep working.
+pub fn drop_to(account: Account) -> Result<()> {
+    let err = |what: &str| Error::new(ErrorKind::Failure, format!("Failed to {}: {}", what, io::Error::last_os_error()));
+    unsafe {
+        if libc::setgroups(0, std::ptr::null()) != 0 {
+            bail!(err("drop supplementary groups"));
+        }
+        if libc::setresgid(account.gid, account.gid, account.gid) != 0 {
+            bail!(err("change group"));
+        }
+        if libc::setresuid(account.uid, account.uid, account.uid) != 0 {
+            bail!(err("change user"));
+        }
+        // Checking it stuck, there's no going back
+        if account.uid != 0 && libc::setuid(0) == 0 {
+            bail!(Error::new(ErrorKind::Failure, "Still able to get root back after dropping privileges"));
+        }
+    }
+    Ok(())
+}
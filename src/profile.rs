@@ -0,0 +1,578 @@
+use crate::atomic;
+use crate::error::{Error, ErrorKind};
+use crate::igp::IgpCap;
+use crate::l10n;
+use crate::output::Record;
+use crate::quantity;
+use crate::thinkpad::FanLevel;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+pub const DEFAULT_PATH: &str = "/etc/arrctl/profiles.toml";
+
+// One named set of limits, as in
+//
+//   [profiles.gaming]
+//   tdp = 18
+//   igp_cap = "high"
+//
+// Anything left out stays as it is when the profile is applied. A profile
+// can start from another and change only what differs:
+//
+//   [profiles.battery-quiet]
+//   extends = "quiet"
+//   tdp = 12
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub tdp: Option<u64>,
+    pub tdc: Option<u64>,
+    pub turbo: Option<bool>,
+    pub clock_modulation: Option<u64>,
+    // One duty cycle per core, core 0 first, for keeping one responsive
+    // while the others are held back. Cores left off the end stay.
+    pub core_clock_modulation: Option<Vec<u64>>,
+    pub igp_cap: Option<IgpCap>,
+    // With 1, 2, 3 and 4 cores active, the ones left off the end stay
+    pub turbo_ratios: Option<Vec<u64>>,
+    // Holds every thread at this ratio through IA32_PERF_CTL, for caps
+    // under the base ratio where the turbo bins don't reach. cpufreq's next
+    // step replaces it unless the governor is userspace.
+    pub pinned_ratio: Option<u64>,
+    // ThinkPads only, and only with fan_control under [thinkpad]
+    pub fan_level: Option<FanLevel>,
+    // Another profile in the same file, gone once the file is loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+}
+
+// Whole watts or amps, the most the eighths in the register can hold
+pub const MAX_LIMIT: u64 = quantity::MAX_EIGHTHS / 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    // The setting the problem is about, or the first of two that conflict
+    pub key: &'static str,
+    pub message: String,
+}
+
+impl Profile {
+    // Everything that can be checked without touching the hardware
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for (key, name, value) in [("tdp", "TDP", self.tdp), ("tdc", "TDC", self.tdc)] {
+            if value.is_some_and(|v| v == 0 || v > MAX_LIMIT) {
+                problems.push(Problem { key, message: format!("{} must be between 1 and {}", name, MAX_LIMIT) });
+            }
+        }
+        if self.clock_modulation.is_some_and(|duty| duty > 7) {
+            problems.push(Problem {
+                key: "clock_modulation",
+                message: "Clock modulation duty cycle must be between 0 and 7 eighths".to_string(),
+            });
+        }
+        if let Some(duties) = &self.core_clock_modulation {
+            if self.clock_modulation.is_some() {
+                problems.push(Problem {
+                    key: "clock_modulation",
+                    message: "clock_modulation conflicts with core_clock_modulation, set one or the other".to_string(),
+                });
+            } else if duties.is_empty() {
+                problems.push(Problem { key: "core_clock_modulation", message: "core_clock_modulation needs a duty cycle for at least one core".to_string() });
+            } else if duties.iter().any(|&duty| duty > 7) {
+                problems.push(Problem {
+                    key: "core_clock_modulation",
+                    message: "Clock modulation duty cycles must be between 0 and 7 eighths".to_string(),
+                });
+            }
+        }
+        if let Some(ratios) = &self.turbo_ratios {
+            if ratios.is_empty() || ratios.len() > 4 {
+                problems.push(Problem { key: "turbo_ratios", message: "turbo_ratios needs between 1 and 4 ratios".to_string() });
+            } else if ratios.iter().any(|&ratio| ratio == 0 || ratio > 0xff) {
+                problems.push(Problem { key: "turbo_ratios", message: "Turbo ratios must be between 1 and 255".to_string() });
+            } else if ratios.windows(2).any(|pair| pair[1] > pair[0]) {
+                problems.push(Problem {
+                    key: "turbo_ratios",
+                    message: "Turbo ratios can't go up with more cores active".to_string(),
+                });
+            }
+        }
+        if self.pinned_ratio.is_some_and(|ratio| ratio == 0 || ratio > 0xff) {
+            problems.push(Problem { key: "pinned_ratio", message: "pinned_ratio must be between 1 and 255".to_string() });
+        }
+        // The turbo limits only apply while turbo is on
+        if self.turbo == Some(false) && (self.tdp.is_some() || self.tdc.is_some()) {
+            problems.push(Problem {
+                key: "turbo",
+                message: "turbo = false conflicts with tdp/tdc, those only limit turbo".to_string(),
+            });
+        }
+        if self.turbo == Some(false) && self.turbo_ratios.is_some() {
+            problems.push(Problem {
+                key: "turbo",
+                message: "turbo = false conflicts with turbo_ratios, they'd never be used".to_string(),
+            });
+        }
+        if let Some(base) = &self.extends {
+            problems.push(Problem {
+                key: "extends",
+                message: format!("extends = {:?} only works between profiles in {}", base, DEFAULT_PATH),
+            });
+        }
+        problems
+    }
+
+    // This one's settings, and base's where it has none
+    pub fn over(&self, base: &Profile) -> Profile {
+        Profile {
+            tdp: self.tdp.or(base.tdp),
+            tdc: self.tdc.or(base.tdc),
+            turbo: self.turbo.or(base.turbo),
+            clock_modulation: self.clock_modulation.or(base.clock_modulation),
+            core_clock_modulation: self.core_clock_modulation.clone().or_else(|| base.core_clock_modulation.clone()),
+            igp_cap: self.igp_cap.or(base.igp_cap),
+            turbo_ratios: self.turbo_ratios.clone().or_else(|| base.turbo_ratios.clone()),
+            pinned_ratio: self.pinned_ratio.or(base.pinned_ratio),
+            fan_level: self.fan_level.or(base.fan_level),
+            extends: None,
+        }
+    }
+}
+
+// A temperature in the config, in celsius or under the TJmax of the CPU it
+// ends up running on, so one file fits parts with different TJmax:
+//
+//   target_celsius = 85
+//   target_celsius = "tjmax-15"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawThreshold", into = "RawThreshold")]
+pub enum Threshold {
+    Celsius(u64),
+    BelowTjmax(u64),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawThreshold {
+    Celsius(u64),
+    Relative(String),
+}
+
+impl Threshold {
+    pub fn resolve(self, tjmax: u64) -> u64 {
+        match self {
+            Threshold::Celsius(celsius) => celsius,
+            Threshold::BelowTjmax(below) => tjmax.saturating_sub(below),
+        }
+    }
+}
+
+impl From<u64> for Threshold {
+    fn from(celsius: u64) -> Self {
+        Threshold::Celsius(celsius)
+    }
+}
+
+impl TryFrom<RawThreshold> for Threshold {
+    type Error = String;
+
+    fn try_from(raw: RawThreshold) -> std::result::Result<Self, Self::Error> {
+        match raw {
+            RawThreshold::Celsius(celsius) => Ok(Threshold::Celsius(celsius)),
+            RawThreshold::Relative(text) => text.parse(),
+        }
+    }
+}
+
+impl From<Threshold> for RawThreshold {
+    fn from(threshold: Threshold) -> Self {
+        match threshold {
+            Threshold::Celsius(celsius) => RawThreshold::Celsius(celsius),
+            relative => RawThreshold::Relative(relative.to_string()),
+        }
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let below = match s.trim().strip_prefix("tjmax") {
+            Some("") => Some(0),
+            Some(rest) => rest.trim_start().strip_prefix('-').and_then(|n| n.trim().parse().ok()),
+            None => None,
+        };
+        match below {
+            Some(below) => Ok(Threshold::BelowTjmax(below)),
+            None => s.trim().parse().map(Threshold::Celsius).map_err(|_| format!("Expected celsius or \"tjmax-N\", got {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Threshold::Celsius(celsius) => write!(f, "{}", celsius),
+            Threshold::BelowTjmax(0) => write!(f, "tjmax"),
+            Threshold::BelowTjmax(below) => write!(f, "tjmax-{}", below),
+        }
+    }
+}
+
+// What the daemon falls back to when a profile turns out too aggressive
+//
+//   [emergency]
+//   profile = "quiet"
+//   max_celsius = 95
+//   max_throttle_events = 5
+//
+// Either trigger is enough, throttle events count within window_secs.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Emergency {
+    pub profile: String,
+    pub max_celsius: Option<Threshold>,
+    pub max_throttle_events: Option<u64>,
+    #[serde(default = "default_window")]
+    pub window_secs: u64,
+}
+
+fn default_window() -> u64 {
+    60
+}
+
+// Holds the hottest core at a temperature by stepping the TDP
+//
+//   [governor]
+//   target_celsius = 85
+//   min_tdp = 10
+//   max_tdp = 25
+//
+// It only goes back up once hysteresis_celsius under the target, and
+// writes at most once every min_interval_secs. controller = "pid" uses
+// the gains instead of steps, in watts per celsius (and second).
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Governor {
+    pub target_celsius: Threshold,
+    pub min_tdp: u64,
+    pub max_tdp: u64,
+    #[serde(default)]
+    pub controller: ControllerKind,
+    // Watts per adjustment
+    #[serde(default = "default_step")]
+    pub step: u64,
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis_celsius: u64,
+    #[serde(default = "default_min_interval")]
+    pub min_interval_secs: u64,
+    #[serde(default = "default_kp")]
+    pub kp: f64,
+    #[serde(default = "default_ki")]
+    pub ki: f64,
+    #[serde(default)]
+    pub kd: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControllerKind {
+    #[default]
+    Step,
+    Pid,
+}
+
+fn default_step() -> u64 {
+    1
+}
+
+fn default_hysteresis() -> u64 {
+    3
+}
+
+fn default_min_interval() -> u64 {
+    5
+}
+
+// Gentle enough for a 35 W part, a degree over costs half a watt right
+// away and another every 40 seconds it stays there
+fn default_kp() -> f64 {
+    0.5
+}
+
+fn default_ki() -> f64 {
+    0.025
+}
+
+// Opting in to fan control, quiet profiles need the fan to go along
+//
+//   [thinkpad]
+//   fan_control = true
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thinkpad {
+    #[serde(default)]
+    pub fan_control: bool,
+}
+
+// A script that picks the limits from each sample, see policy.rs. It owns
+// what it assigns the way the governor owns the TDP, and writes no more
+// often than min_interval_secs.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub script: PathBuf,
+    #[serde(default = "default_min_interval")]
+    pub min_interval_secs: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    pub emergency: Option<Emergency>,
+    pub governor: Option<Governor>,
+    pub policy: Option<Policy>,
+    pub thinkpad: Option<Thinkpad>,
+}
+
+impl Profiles {
+    // A fan level without the opt-in is a mistake, not something to skip
+    pub fn check_fan_control(&self) -> Result<()> {
+        let allowed = self.thinkpad.as_ref().is_some_and(|t| t.fan_control);
+        if let Some((name, _)) = self.profiles.iter().find(|(_, p)| p.fan_level.is_some()).filter(|_| !allowed) {
+            bail!(Error::new(ErrorKind::Validation, format!("Profile {} sets fan_level, which needs fan_control = true under [thinkpad]", name)));
+        }
+        Ok(())
+    }
+
+    // The profile name, the one it extends and so on, name first
+    pub fn chain(&self, name: &str) -> Result<Vec<&str>> {
+        let Some((mut name, mut profile)) = self.profiles.get_key_value(name) else {
+            bail!(Error::new(ErrorKind::Validation, format!("No profile {}", name)));
+        };
+        let mut chain = vec![name.as_str()];
+        while let Some(base) = &profile.extends {
+            let Some((base, next)) = self.profiles.get_key_value(base) else {
+                bail!(Error::new(ErrorKind::Validation, format!("Profile {} extends {}, which isn't defined", name, base)));
+            };
+            if let Some(start) = chain.iter().position(|n| *n == base) {
+                let looped = [&chain[start..], &[base.as_str()]].concat();
+                bail!(Error::new(ErrorKind::Validation, format!("Profile {} extends itself through {}", base, looped.join(" > "))));
+            }
+            chain.push(base);
+            (name, profile) = (base, next);
+        }
+        Ok(chain)
+    }
+
+    // What applying name sets, everything it extends folded in
+    pub fn resolved(&self, name: &str) -> Result<Profile> {
+        let chain = self.chain(name)?;
+        Ok(chain.iter().rev().fold(Profile::default(), |base, name| self.profiles[*name].over(&base)))
+    }
+
+    pub fn resolve(&mut self) -> Result<()> {
+        let resolved = self.profiles.keys().map(|name| Ok((name.clone(), self.resolved(name)?))).collect::<Result<BTreeMap<_, _>>>()?;
+        self.profiles = resolved;
+        Ok(())
+    }
+
+    // They'd fight over the TDP
+    pub fn check_policy(&self) -> Result<()> {
+        if self.governor.is_some() && self.policy.is_some() {
+            bail!(Error::new(ErrorKind::Validation, "[governor] and [policy] both pick the limits, keep one of them"));
+        }
+        Ok(())
+    }
+}
+
+// For `profile show`, with the profiles --resolved folded together
+pub fn record(name: &str, profile: &Profile, layers: Option<&[&str]>) -> Record {
+    let list = |values: &[u64]| values.iter().map(u64::to_string).collect::<Vec<_>>().join(", ");
+    let mut record = Record::new("profile")
+        .title(l10n::format("profile-title", &[("name", name)]))
+        .hidden("name", name);
+    if let Some(base) = &profile.extends {
+        record = record.field("extends", l10n::text("profile-extends"), base.as_str(), "");
+    }
+    if let Some(layers) = layers.filter(|l| l.len() > 1) {
+        record = record.field("layers", l10n::text("profile-layers"), layers.join(" > "), "");
+    }
+    if let Some(tdp) = profile.tdp {
+        record = record.field("tdp", "TDP", tdp, "W");
+    }
+    if let Some(tdc) = profile.tdc {
+        record = record.field("tdc", "TDC", tdc, "A");
+    }
+    if let Some(turbo) = profile.turbo {
+        record = record.field("turbo", l10n::text("profile-turbo"), turbo, "");
+    }
+    if let Some(eighths) = profile.clock_modulation {
+        record = record.field("clock_modulation", l10n::text("profile-clock-modulation"), eighths, "/8");
+    }
+    if let Some(duties) = &profile.core_clock_modulation {
+        record = record.field("core_clock_modulation", l10n::text("profile-core-clock-modulation"), list(duties), "/8");
+    }
+    if let Some(cap) = profile.igp_cap {
+        record = record.field("igp_cap", l10n::text("profile-igp-cap"), cap.to_possible_value().unwrap().get_name(), "");
+    }
+    if let Some(ratios) = &profile.turbo_ratios {
+        record = record.field("turbo_ratios", l10n::text("profile-turbo-ratios"), list(ratios), "");
+    }
+    if let Some(ratio) = profile.pinned_ratio {
+        record = record.field("pinned_ratio", l10n::text("profile-pinned-ratio"), ratio, "");
+    }
+    if let Some(level) = profile.fan_level {
+        record = record.field("fan_level", l10n::text("profile-fan-level"), level.to_string(), "");
+    }
+    record
+}
+
+// A config key set from outside the file, for images and containers that
+// can't edit it:
+//
+//   --set profiles.quiet.tdp=18
+//   ARRCTL_SET='governor.target_celsius="tjmax-20"; profiles.quiet.turbo=false'
+//
+// The value is TOML, a bare word that isn't is taken as a string. Last one
+// wins: the file, then ARRCTL_SET in order, then every --set in order.
+#[derive(Clone, Debug)]
+pub struct Override {
+    pub key: Vec<String>,
+    pub value: toml_edit::Value,
+}
+
+pub const ENV_SET: &str = "ARRCTL_SET";
+// The profile the daemon applies when --apply isn't given
+pub const ENV_PROFILE: &str = "ARRCTL_PROFILE";
+
+const TABLES: &[&str] = &["profiles", "emergency", "governor", "policy", "thinkpad"];
+
+impl FromStr for Override {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let Some((key, value)) = s.split_once('=') else {
+            return Err(format!("Expected KEY=VALUE, got {:?}", s));
+        };
+        let mut key: Vec<String> = key.trim().split('.').map(|part| part.trim().to_string()).collect();
+        // As in `arrctl profile show`
+        if key[0] == "profile" {
+            key[0] = "profiles".to_string();
+        }
+        let depth = if key[0] == "profiles" { 3 } else { 2 };
+        if !TABLES.contains(&key[0].as_str()) || key.len() != depth || key.iter().any(String::is_empty) {
+            return Err(format!("Expected a key like profiles.NAME.tdp or governor.target_celsius, got {:?}", key.join(".")));
+        }
+        let value = value.trim();
+        let value = value.parse::<toml_edit::Value>().unwrap_or_else(|_| value.into());
+        Ok(Override { key, value })
+    }
+}
+
+// ARRCTL_SET holds one or more, split on ; or newlines
+pub fn env_parts(env: &str) -> impl Iterator<Item = &str> {
+    env.split([';', '\n']).map(str::trim).filter(|p| !p.is_empty())
+}
+
+// ARRCTL_SET's, the value of that, then the flags
+pub fn overrides(env: Option<&str>, flags: &[Override]) -> Result<Vec<Override>> {
+    let mut overrides = Vec::new();
+    for part in env_parts(env.unwrap_or("")) {
+        overrides.push(part.parse().map_err(|e: String| Error::new(ErrorKind::Validation, format!("{}: {}", ENV_SET, e)))?);
+    }
+    overrides.extend_from_slice(flags);
+    Ok(overrides)
+}
+
+// Set once at startup, every read of a profiles file goes through them
+static OVERRIDES: OnceLock<Vec<Override>> = OnceLock::new();
+
+// False if they were set already, only the first set counts
+pub fn set_overrides(overrides: Vec<Override>) -> bool {
+    OVERRIDES.set(overrides).is_ok()
+}
+
+// The file's text with overrides on top, comments and all
+pub fn with_overrides(text: &str, overrides: &[Override]) -> Result<String> {
+    let mut doc: toml_edit::DocumentMut = text.parse()?;
+    for o in overrides {
+        let (last, tables) = o.key.split_last().unwrap();
+        let mut table = doc.as_table_mut();
+        for (depth, name) in tables.iter().enumerate() {
+            let item = table.entry(name).or_insert_with(|| {
+                let mut table = toml_edit::Table::new();
+                // profiles itself stays implicit, like save leaves it
+                table.set_implicit(depth == 0 && tables.len() > 1);
+                toml_edit::Item::Table(table)
+            });
+            let Some(next) = item.as_table_mut() else {
+                bail!(Error::new(ErrorKind::Validation, format!("Can't set {}, {} isn't a table", o.key.join("."), tables[..=depth].join("."))));
+            };
+            table = next;
+        }
+        table.insert(last, toml_edit::Item::Value(o.value.clone()));
+    }
+    Ok(doc.to_string())
+}
+
+// With every profile resolved, what everything but `profile show` wants
+pub fn load(path: &Path) -> Result<Profiles> {
+    let mut profiles = read(path)?;
+    profiles.resolve().with_context(|| format!("In {}", path.display()))?;
+    Ok(profiles)
+}
+
+// As written
+pub fn read(path: &Path) -> Result<Profiles> {
+    let overrides = OVERRIDES.get().map_or(&[][..], Vec::as_slice);
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && overrides.is_empty() => return Ok(Profiles::default()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let profiles: Profiles = match overrides {
+        [] => toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?,
+        _ => {
+            let text = with_overrides(&text, overrides).with_context(|| format!("Failed to parse {}", path.display()))?;
+            toml::from_str(&text).with_context(|| format!("Failed to parse {} with the overrides from {} and --set", path.display(), ENV_SET))?
+        }
+    };
+    profiles.check_fan_control()?;
+    profiles.check_policy()?;
+    Ok(profiles)
+}
+
+// Edits the file in place so comments and other profiles survive
+pub fn save(path: &Path, name: &str, profile: &Profile) -> Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let mut doc: toml_edit::DocumentMut = text.parse().with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let profiles = doc
+        .entry("profiles")
+        .or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        })
+        .as_table_mut()
+        .with_context(|| format!("profiles in {} isn't a table", path.display()))?;
+    let table: toml_edit::Table = toml_edit::ser::to_document(profile)?.as_table().clone();
+    profiles.insert(name, toml_edit::Item::Table(table));
+
+    atomic::write(path, doc.to_string().as_bytes())
+}
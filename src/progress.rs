@@ -0,0 +1,73 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 30;
+
+// A progress line on stderr for anything that takes more than a moment.
+// Draws nothing unless stderr is a terminal, so pipes and logs stay clean.
+pub struct Progress {
+    label: String,
+    total: u64,
+    done: u64,
+    start: Instant,
+    draw: bool,
+}
+
+pub fn bar(label: &str, done: u64, total: u64, elapsed: Duration) -> String {
+    let frac = if total == 0 { 1.0 } else { (done as f64 / total as f64).min(1.0) };
+    let filled = (frac * WIDTH as f64).round() as usize;
+    let mut line = format!("{} [{}{}] {:3.0}%", label, "#".repeat(filled), " ".repeat(WIDTH - filled), frac * 100.0);
+    // No guess until there's something to extrapolate from
+    if done > 0 && done < total {
+        let left = elapsed.as_secs_f64() * (total - done) as f64 / done as f64;
+        line += &format!(", {:.0} s left", left.ceil());
+    }
+    line
+}
+
+impl Progress {
+    pub fn new(label: impl Into<String>, total: u64) -> Self {
+        let mut progress = Progress {
+            label: label.into(),
+            total,
+            done: 0,
+            start: Instant::now(),
+            draw: io::stderr().is_terminal(),
+        };
+        progress.redraw();
+        progress
+    }
+
+    fn redraw(&mut self) {
+        if self.draw {
+            let line = bar(&self.label, self.done, self.total, self.start.elapsed());
+            let _ = write!(io::stderr(), "\r{}\x1b[K", line);
+        }
+    }
+
+    pub fn set(&mut self, done: u64) {
+        self.done = done.min(self.total);
+        self.redraw();
+    }
+
+    // A finished step's result, printed above the bar as soon as it's known
+    pub fn message(&mut self, text: &str) {
+        if self.draw {
+            let _ = write!(io::stderr(), "\r\x1b[K");
+        }
+        eprintln!("{}", text);
+        self.redraw();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.draw {
+            let _ = write!(io::stderr(), "\r\x1b[K");
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::cpu;
+use crate::output::Value;
+use std::fmt;
+
+// What the registers hold, in the units people think in. The turbo limits
+// count eighths of a watt and of an amp, so a bare number is easy to put in
+// the wrong field; going through these makes that a type error.
+
+// TDP and TDC are 15 bit fields, 4095.875 W or A is as high as they go
+pub const MAX_EIGHTHS: u64 = 0x7fff;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Watts(pub f64);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Amps(pub f64);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Celsius(pub u64);
+
+// A multiple of BCLK
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ratio(pub u64);
+
+macro_rules! eighths {
+    ($type:ident, $unit:literal) => {
+        impl $type {
+            pub const MAX: $type = $type(MAX_EIGHTHS as f64 / 8.0);
+
+            // Bits above the field are ignored, like the CPU does
+            pub fn from_eighths(raw: u64) -> Self {
+                $type((raw & MAX_EIGHTHS) as f64 / 8.0)
+            }
+
+            // Rounded to the nearest eighth, None when that's negative, not
+            // a number or more than the field holds
+            pub fn checked_eighths(self) -> Option<u64> {
+                let eighths = (self.0 * 8.0).round();
+                (eighths >= 0.0 && eighths <= MAX_EIGHTHS as f64).then_some(eighths as u64)
+            }
+
+            // The same, but clamped into the field. NaN comes out as 0.
+            pub fn saturating_eighths(self) -> u64 {
+                (self.0 * 8.0).round().clamp(0.0, MAX_EIGHTHS as f64) as u64
+            }
+
+            // Down to the whole unit, what profiles and the SKU table use.
+            // Negative and NaN come out as 0.
+            pub fn whole(self) -> u64 {
+                self.0 as u64
+            }
+        }
+
+        impl From<u64> for $type {
+            fn from(whole: u64) -> Self {
+                $type(whole as f64)
+            }
+        }
+
+        impl From<$type> for Value {
+            fn from(v: $type) -> Self {
+                Value::Float(v.0)
+            }
+        }
+
+        impl fmt::Display for $type {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{} {}", self.0, $unit)
+            }
+        }
+    };
+}
+
+eighths!(Watts, "W");
+eighths!(Amps, "A");
+
+impl From<Celsius> for Value {
+    fn from(v: Celsius) -> Self {
+        Value::Int(v.0)
+    }
+}
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} celsius", self.0)
+    }
+}
+
+impl Ratio {
+    // At the nominal BCLK, close enough unless it was measured
+    pub fn mhz(self) -> f32 {
+        self.at(cpu::BCLK_MHZ)
+    }
+
+    pub fn at(self, bclk_mhz: f32) -> f32 {
+        self.0 as f32 * bclk_mhz
+    }
+}
+
+impl From<Ratio> for Value {
+    fn from(v: Ratio) -> Self {
+        Value::Int(v.0)
+    }
+}
+
+impl fmt::Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x", self.0)
+    }
+}
@@ -0,0 +1,687 @@
+use crate::cpu;
+use crate::error::{Error, ErrorKind};
+use crate::msr::{self, Fault, MsrAccess};
+use crate::quantity::{Amps, Celsius, Ratio, Watts};
+use anyhow::{bail, Result};
+use bitfield::bitfield;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub const IA32_TIME_STAMP_COUNTER: u32 = 0x10;
+pub const IA32_PLATFORM_ID: u32 = 0x17;
+pub const MSR_PLATFORM_INFO: u32 = 0xce;
+pub const MSR_PKG_CST_CONFIG_CONTROL: u32 = 0xe2;
+pub const IA32_MPERF: u32 = 0xe7;
+pub const IA32_APERF: u32 = 0xe8;
+pub const IA32_PERF_STATUS: u32 = 0x198;
+pub const IA32_PERF_CTL: u32 = 0x199;
+pub const IA32_CLOCK_MODULATION: u32 = 0x19a;
+pub const IA32_THERM_STATUS: u32 = 0x19c;
+pub const IA32_MISC_ENABLE: u32 = 0x1a0;
+pub const MSR_TEMPERATURE_TARGET: u32 = 0x1a2;
+pub const MSR_TURBO_LIMITS: u32 = 0x1ac;
+pub const MSR_TURBO_RATIOS: u32 = 0x1ad;
+// Where CPUID.06H:ECX[3] says so, a hint for the firmware and the OS
+pub const IA32_ENERGY_PERF_BIAS: u32 = 0x1b0;
+// Only where CPUID.06H:EAX[6] says so and Arrandale predates that
+pub const IA32_PACKAGE_THERM_STATUS: u32 = 0x1b1;
+
+// Westmere mobile and desktop dual cores, Arrandale and Clarkdale
+pub const MODEL_ARRANDALE: u32 = 0x25;
+// Gulftown and Westmere-EP, up to six cores
+pub const MODEL_WESTMERE_EP: u32 = 0x2c;
+
+// Which logical CPUs share one copy of a register, per the Nehalem/Westmere
+// MSR tables
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Thread,
+    Core,
+    Package,
+}
+
+// A bit range of a register, as the bitfields below lay it out
+pub struct FieldSpec {
+    pub name: &'static str,
+    pub lsb: u8,
+    pub msb: u8,
+    // Whether arrctl ever writes it, locks aside
+    pub writable: bool,
+    // Values outside this are a misread, not a setting
+    pub valid: Option<(u64, u64)>,
+    // What the values mean, for fields that are codes rather than numbers
+    pub names: &'static [(u64, &'static str)],
+}
+
+impl FieldSpec {
+    pub fn get(&self, value: u64) -> u64 {
+        let width = self.msb - self.lsb + 1;
+        let mask = if width >= 64 { u64::MAX } else { (1 << width) - 1 };
+        (value >> self.lsb) & mask
+    }
+
+    pub fn is_valid(&self, value: u64) -> bool {
+        self.valid.is_none_or(|(min, max)| (min..=max).contains(&self.get(value)))
+    }
+
+    pub fn name_of(&self, value: u64) -> Option<&'static str> {
+        let value = self.get(value);
+        self.names.iter().find(|(code, _)| *code == value).map(|(_, name)| *name)
+    }
+}
+
+// A model that lays a register out differently, its fields replace the
+// default ones
+pub struct Override {
+    pub model: u32,
+    pub fields: &'static [FieldSpec],
+}
+
+// Everything arrctl knows about one register. dump, selftest, features,
+// compare and the error messages all go through this table.
+pub struct Register {
+    pub name: &'static str,
+    pub address: u32,
+    pub scope: Scope,
+    // CPUID models of family 6 it exists on
+    pub models: &'static [u32],
+    // Whether arrctl ever writes it
+    pub writable: bool,
+    // Read by dump and selftest, the rest not every BIOS lets be read
+    pub dumped: bool,
+    // Only what arrctl decodes, the counters are plain numbers
+    pub fields: &'static [FieldSpec],
+    pub overrides: &'static [Override],
+}
+
+impl Register {
+    pub fn supported(&self, model: u32) -> bool {
+        self.models.contains(&model)
+    }
+
+    // The layout on a CPUID model, the default one unless it has its own
+    pub fn fields_for(&self, model: u32) -> &'static [FieldSpec] {
+        self.overrides.iter().find(|o| o.model == model).map_or(self.fields, |o| o.fields)
+    }
+
+    pub fn field(&self, model: u32, name: &str) -> Option<&'static FieldSpec> {
+        self.fields_for(model).iter().find(|f| f.name == name)
+    }
+
+    // Every field with its value
+    pub fn decode(&self, model: u32, value: u64) -> Vec<(&'static str, u64)> {
+        self.fields_for(model).iter().map(|f| (f.name, f.get(value))).collect()
+    }
+
+    // {"tdp": 200, "tdp_override": 1, ...}, codes by their names
+    pub fn to_json(&self, model: u32, value: u64) -> serde_json::Value {
+        let json = |f: &FieldSpec| f.name_of(value).map_or_else(|| f.get(value).into(), serde_json::Value::from);
+        self.fields_for(model).iter().map(|f| (f.name.to_string(), json(f))).collect::<serde_json::Map<_, _>>().into()
+    }
+
+    // "tdp 200, tdp_override 1, ...", or the value in hex without fields
+    pub fn describe(&self, model: u32, value: u64) -> String {
+        let fields = self.fields_for(model);
+        if fields.is_empty() {
+            return format!("{:#x}", value);
+        }
+        let describe = |f: &FieldSpec| match f.name_of(value) {
+            Some(name) => format!("{} {}", f.name, name),
+            None => format!("{} {}", f.name, f.get(value)),
+        };
+        fields.iter().map(describe).collect::<Vec<_>>().join(", ")
+    }
+}
+
+pub(crate) const MODELS: &[u32] = &[MODEL_ARRANDALE, MODEL_WESTMERE_EP];
+
+// For the counters, which have no fields
+const fn counter(name: &'static str, address: u32) -> Register {
+    Register { name, address, scope: Scope::Thread, models: MODELS, writable: false, dumped: true, fields: &[], overrides: &[] }
+}
+
+macro_rules! field_name {
+    ($getter:ident) => { stringify!($getter) };
+    ($getter:ident $name:literal) => { $name };
+}
+
+macro_rules! field_lsb {
+    ($msb:literal) => { $msb };
+    ($msb:literal $lsb:literal) => { $lsb };
+}
+
+macro_rules! field_writable {
+    (_) => { false };
+    ($setter:ident) => { true };
+}
+
+macro_rules! field_valid {
+    () => { None };
+    ($min:literal $max:literal) => { Some(($min, $max)) };
+}
+
+macro_rules! field_names {
+    () => { &[] };
+    ($names:ty) => { <$names>::NAMES };
+}
+
+// One register in one place: the bitfield struct with its getters and
+// setters, the field list the registry decodes with and the registry entry
+// itself as REGISTER. Fields are written as for bitfield!, then optionally
+// the name they go by when it isn't the getter's and the range a sane
+// reading falls in, or the enum that names its codes. A field with a
+// setter is one arrctl writes.
+macro_rules! register {
+    (
+        $(#[$attr:meta])*
+        $ty:ident = $name:literal at $address:ident, $scope:ident $(, $flag:ident)* {
+            $($getter:ident, $setter:tt: $msb:literal $(, $lsb:literal)? $(as $label:literal)? $(in $min:literal ..= $max:literal)? $(named $names:ty)?;)*
+        }
+    ) => {
+        bitfield! {
+            $(#[$attr])*
+            pub struct $ty(u64);
+
+            $(pub $getter, $setter: $msb $(, $lsb)?;)*
+        }
+
+        impl $ty {
+            pub const FIELDS: &'static [FieldSpec] = &[$(FieldSpec {
+                name: field_name!($getter $($label)?),
+                lsb: field_lsb!($msb $($lsb)?),
+                msb: $msb,
+                writable: field_writable!($setter),
+                valid: field_valid!($($min $max)?),
+                names: field_names!($($names)?),
+            }),*];
+
+            pub const REGISTER: Register = Register {
+                name: $name,
+                address: $address,
+                scope: Scope::$scope,
+                models: MODELS,
+                writable: false $(|| register!(@$flag writable))*,
+                dumped: true $(&& !register!(@$flag hidden))*,
+                fields: Self::FIELDS,
+                overrides: &[],
+            };
+        }
+    };
+    (@writable writable) => { true };
+    (@hidden hidden) => { true };
+    (@$flag:ident $other:ident) => { false };
+}
+
+// An enum for a field's codes, each variant with its code, any other codes
+// that mean the same, and its name. Shown and parsed by the name.
+macro_rules! named {
+    ($(#[$attr:meta])* $ty:ident { $($variant:ident = $code:literal $(| $alias:literal)*, $name:literal;)* }) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $ty {
+            $($variant,)*
+        }
+
+        impl $ty {
+            pub const ALL: &'static [$ty] = &[$($ty::$variant),*];
+            pub const NAMES: &'static [(u64, &'static str)] = &[$(($code, $name), $(($alias, $name),)*)*];
+
+            pub fn from_code(code: u64) -> Option<Self> {
+                match code {
+                    $($code $(| $alias)* => Some($ty::$variant),)*
+                    _ => None,
+                }
+            }
+
+            pub fn code(self) -> u64 {
+                match self {
+                    $($ty::$variant => $code,)*
+                }
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($ty::$variant => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                Self::ALL.iter().copied().find(|v| v.name().eq_ignore_ascii_case(name))
+            }
+
+            // "C1, C3, C6", for error messages
+            pub fn names() -> String {
+                Self::ALL.iter().map(|v| v.name()).collect::<Vec<_>>().join(", ")
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+
+        impl Serialize for $ty {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.name())
+            }
+        }
+    };
+}
+
+named! {
+    // Deepest package C-state the BIOS allows
+    CStateLimit {
+        C1 = 1 | 0, "C1";
+        C3 = 2, "C3";
+        C6 = 3, "C6";
+        C7 = 4, "C7";
+        NoLimit = 7, "no-limit";
+    }
+}
+
+named! {
+    // How much of the clock clock modulation lets through, in eighths. The
+    // duty cycle field alone, off is the enable bit.
+    DutyCycle {
+        Off = 0, "off";
+        Percent12_5 = 1, "12.5%";
+        Percent25 = 2, "25%";
+        Percent37_5 = 3, "37.5%";
+        Percent50 = 4, "50%";
+        Percent62_5 = 5, "62.5%";
+        Percent75 = 6, "75%";
+        Percent87_5 = 7, "87.5%";
+    }
+}
+
+impl DutyCycle {
+    pub fn percent(self) -> f64 {
+        self.code() as f64 * 12.5
+    }
+}
+
+// The names and percentages, a near miss like 37% included, or the eighths
+// as before
+impl std::str::FromStr for DutyCycle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let s = s.trim();
+        let found = match s.strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<f64>().ok().and_then(|p| Self::ALL.iter().copied().find(|d| (d.percent() - p).abs() < 1.0)),
+            None => Self::from_name(s).or_else(|| s.parse().ok().and_then(Self::from_code)),
+        };
+        found.ok_or_else(|| format!("Expected a duty cycle, one of {} or 0 to 7 eighths, got {:?}", Self::names(), s))
+    }
+}
+
+named! {
+    // The named points of the energy/performance hint, 0 to 15 in between
+    EnergyPerfBias {
+        Performance = 0, "performance";
+        BalancePerformance = 4, "balance-performance";
+        Normal = 6, "normal";
+        BalancePower = 8, "balance-power";
+        Power = 15, "power";
+    }
+}
+
+impl std::str::FromStr for CStateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        Self::from_name(s).ok_or_else(|| format!("Expected a C-state limit, one of {}, got {:?}", Self::names(), s))
+    }
+}
+
+impl std::str::FromStr for EnergyPerfBias {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        Self::from_name(s).ok_or_else(|| format!("Expected an energy/performance bias, one of {}, got {:?}", Self::names(), s))
+    }
+}
+
+// In address order, which is the order dump writes them in
+pub const REGISTERS: &[Register] = &[
+    counter("IA32_TIME_STAMP_COUNTER", IA32_TIME_STAMP_COUNTER),
+    Ia32PlatformId::REGISTER,
+    MsrPlatformInfo::REGISTER,
+    MsrPkgCstConfigControl::REGISTER,
+    counter("IA32_MPERF", IA32_MPERF),
+    counter("IA32_APERF", IA32_APERF),
+    Ia32PerfStatus::REGISTER,
+    Ia32PerfCtl::REGISTER,
+    Ia32ClockModulation::REGISTER,
+    Ia32ThermStatus::REGISTER,
+    Ia32MiscEnable::REGISTER,
+    Register { overrides: &[Override { model: MODEL_WESTMERE_EP, fields: MsrTemperatureTargetEp::FIELDS }], ..MsrTemperatureTarget::REGISTER },
+    MsrTurboLimits::REGISTER,
+    Register { overrides: &[Override { model: MODEL_WESTMERE_EP, fields: MsrTurboRatiosEp::FIELDS }], ..MsrTurboRatios::REGISTER },
+    Ia32EnergyPerfBias::REGISTER,
+    Ia32PackageThermStatus::REGISTER,
+];
+
+// From plugins::DIR, added once at startup
+static CUSTOM: OnceLock<&'static [Register]> = OnceLock::new();
+
+// False if some were added already, only the first set counts
+pub fn add_custom(registers: Vec<Register>) -> bool {
+    CUSTOM.set(registers.leak()).is_ok()
+}
+
+pub fn custom() -> &'static [Register] {
+    CUSTOM.get().copied().unwrap_or(&[])
+}
+
+// The built-in registers, then the custom ones
+pub fn all() -> impl Iterator<Item = &'static Register> {
+    REGISTERS.iter().chain(custom())
+}
+
+pub fn lookup(reg: u32) -> Option<&'static Register> {
+    all().find(|r| r.address == reg)
+}
+
+// The ones dump and selftest read on every CPU
+pub fn dumped() -> impl Iterator<Item = &'static Register> {
+    all().filter(|r| r.dumped)
+}
+
+// Unknown registers are taken to be per thread, which writes them everywhere
+pub fn scope(reg: u32) -> Scope {
+    lookup(reg).map_or(Scope::Thread, |r| r.scope)
+}
+
+pub fn name(reg: u32) -> Option<&'static str> {
+    lookup(reg).map(|r| r.name)
+}
+
+register! {
+    Ia32PlatformId = "IA32_PLATFORM_ID" at IA32_PLATFORM_ID, Package {
+        // Highest bus ratio the part was fused for
+        max_bus_ratio, _: 12, 8;
+        // Picks the microcode update, together with the CPUID signature
+        platform_id, _: 52, 50;
+    }
+}
+
+register! {
+    MsrPlatformInfo = "MSR_PLATFORM_INFO" at MSR_PLATFORM_INFO, Package {
+        max_non_turbo_ratio, _: 15, 8;
+        programmable_turbo_ratio, _: 28;
+        programmable_tdc_tdp, _: 29;
+        minimum_ratio, _: 47, 40;
+    }
+}
+
+impl MsrPlatformInfo {
+    // The highest ratio without turbo
+    pub fn base(&self) -> Ratio {
+        Ratio(self.max_non_turbo_ratio())
+    }
+}
+
+// Not dumped, only doctor reads it and not every BIOS lets it be read
+register! {
+    MsrPkgCstConfigControl = "MSR_PKG_CST_CONFIG_CONTROL" at MSR_PKG_CST_CONFIG_CONTROL, Core, hidden {
+        limit, _: 2, 0 named CStateLimit;
+        lock, _: 15;
+    }
+}
+
+impl MsrPkgCstConfigControl {
+    // None for the reserved codes
+    pub fn limit_state(&self) -> Option<CStateLimit> {
+        CStateLimit::from_code(self.limit())
+    }
+}
+
+register! {
+    Ia32PerfStatus = "IA32_PERF_STATUS" at IA32_PERF_STATUS, Core {
+        ratio, _: 7, 0;
+        vid, _: 47, 32;
+    }
+}
+
+impl Ia32PerfStatus {
+    pub fn volts(&self) -> f32 {
+        self.vid() as f32 / 8192.0
+    }
+}
+
+// The ratio the OS asks for, cpufreq writes it on every step
+register! {
+    Ia32PerfCtl = "IA32_PERF_CTL" at IA32_PERF_CTL, Thread, writable {
+        ratio, set_ratio: 7, 0;
+    }
+}
+
+register! {
+    Ia32ClockModulation = "IA32_CLOCK_MODULATION" at IA32_CLOCK_MODULATION, Thread, writable {
+        duty_cycle, set_duty_cycle: 3, 1 named DutyCycle;
+        enable, set_enable: 4;
+    }
+}
+
+impl Ia32ClockModulation {
+    // Off unless enabled, whatever the duty cycle field says
+    pub fn duty(&self) -> DutyCycle {
+        match self.enable() {
+            true => DutyCycle::from_code(self.duty_cycle()).unwrap_or(DutyCycle::Off),
+            false => DutyCycle::Off,
+        }
+    }
+}
+
+register! {
+    Ia32ThermStatus = "IA32_THERM_STATUS" at IA32_THERM_STATUS, Core {
+        thermal_status, _: 0;
+        // Sticky since boot or the last time software cleared it
+        thermal_log, _: 1;
+        prochot, _: 2;
+        prochot_log, _: 3;
+        digital_readout, _: 22, 16;
+        reading_valid, _: 31;
+    }
+}
+
+impl Ia32ThermStatus {
+    pub fn celsius(&self, tjmax: u64) -> Option<u64> {
+        self.reading_valid().then(|| tjmax.saturating_sub(self.digital_readout()))
+    }
+}
+
+// The same bits for the whole package, which is what the TCC acts on. No
+// valid bit, the package reading always is. Only where CPUID.06H:EAX[6]
+// says so, which Arrandale predates, so not dumped.
+register! {
+    Ia32PackageThermStatus = "IA32_PACKAGE_THERM_STATUS" at IA32_PACKAGE_THERM_STATUS, Package, hidden {
+        thermal_status, _: 0;
+        thermal_log, _: 1;
+        prochot, _: 2;
+        prochot_log, _: 3;
+        digital_readout, _: 22, 16;
+    }
+}
+
+impl Ia32PackageThermStatus {
+    pub fn celsius(&self, tjmax: u64) -> u64 {
+        tjmax.saturating_sub(self.digital_readout())
+    }
+}
+
+// Not every Westmere has it, so not dumped. arrctl only reads it.
+register! {
+    Ia32EnergyPerfBias = "IA32_ENERGY_PERF_BIAS" at IA32_ENERGY_PERF_BIAS, Thread, hidden {
+        hint, _: 3, 0 named EnergyPerfBias;
+    }
+}
+
+impl Ia32EnergyPerfBias {
+    // None for the values between the named points
+    pub fn bias(&self) -> Option<EnergyPerfBias> {
+        EnergyPerfBias::from_code(self.hint())
+    }
+}
+
+register! {
+    Ia32MiscEnable = "IA32_MISC_ENABLE" at IA32_MISC_ENABLE, Thread, writable {
+        // INCOMPLETE
+        turbo_disable, set_turbo_disable: 38;
+    }
+}
+
+// Arrandale comes with 90 or 105, the Xeons run cooler
+register! {
+    MsrTemperatureTarget = "MSR_TEMPERATURE_TARGET" at MSR_TEMPERATURE_TARGET, Thread {
+        get, _: 23, 16 as "tjmax" in 60..=110;
+    }
+}
+
+impl MsrTemperatureTarget {
+    pub fn tjmax(&self) -> Celsius {
+        Celsius(self.get())
+    }
+}
+
+register! {
+    MsrTemperatureTargetEp = "MSR_TEMPERATURE_TARGET" at MSR_TEMPERATURE_TARGET, Thread {
+        get, _: 23, 16 as "tjmax" in 75..=100;
+    }
+}
+
+register! {
+    MsrTurboLimits = "MSR_TURBO_LIMITS" at MSR_TURBO_LIMITS, Package, writable {
+        tdp, set_tdp: 14, 0;
+        tdp_override, set_tdp_override: 15;
+        tdc, set_tdc: 30, 16;
+        tdc_override, set_tdc_override: 31;
+    }
+}
+
+// The limits count eighths, these take and give whole units. A value the
+// field can't hold is an error and leaves the register as it was, the raw
+// setters would keep the low bits and write something else entirely.
+impl MsrTurboLimits {
+    pub fn tdp_watts(&self) -> Watts {
+        Watts::from_eighths(self.tdp())
+    }
+
+    pub fn set_tdp_watts(&mut self, watts: Watts) -> Result<()> {
+        let Some(eighths) = watts.checked_eighths() else {
+            bail!(Error::new(ErrorKind::Validation, format!("TDP of {} is outside 0 to {}", watts, Watts::MAX)));
+        };
+        self.set_tdp(eighths);
+        Ok(())
+    }
+
+    pub fn tdc_amps(&self) -> Amps {
+        Amps::from_eighths(self.tdc())
+    }
+
+    pub fn set_tdc_amps(&mut self, amps: Amps) -> Result<()> {
+        let Some(eighths) = amps.checked_eighths() else {
+            bail!(Error::new(ErrorKind::Validation, format!("TDC of {} is outside 0 to {}", amps, Amps::MAX)));
+        };
+        self.set_tdc(eighths);
+        Ok(())
+    }
+}
+
+// Written whole through set_bin, there are no per field setters
+register! {
+    MsrTurboRatios = "MSR_TURBO_RATIOS" at MSR_TURBO_RATIOS, Package, writable {
+        one_core, _: 7, 0;
+        two_cores, _: 15, 8;
+        three_cores, _: 23, 16;
+        four_cores, _: 31, 24;
+    }
+}
+
+impl MsrTurboRatios {
+    // Index 0 is the ratio with one core active
+    pub fn bin(&self, index: usize) -> u64 {
+        (self.0 >> (index * 8)) & 0xff
+    }
+
+    pub fn set_bin(&mut self, index: usize, ratio: u64) {
+        let shift = index * 8;
+        self.0 = (self.0 & !(0xff << shift)) | (ratio & 0xff) << shift;
+    }
+}
+
+// Six bins on the six core parts
+register! {
+    MsrTurboRatiosEp = "MSR_TURBO_RATIOS" at MSR_TURBO_RATIOS, Package, writable {
+        one_core, _: 7, 0;
+        two_cores, _: 15, 8;
+        three_cores, _: 23, 16;
+        four_cores, _: 31, 24;
+        five_cores, _: 39, 32;
+        six_cores, _: 47, 40;
+    }
+}
+
+// For the registers read without saying which CPU: CPU0 where it's online,
+// otherwise the lowest numbered online CPU of the first package, or of any
+// package once all of that one's are offline. Sockets of the same machine
+// share the SKU, so their package registers agree. CPU0 counts as offline
+// once reading it says so, even while the online list still has it.
+pub fn read_shared(msr: &dyn MsrAccess, reg: u32) -> Result<u64> {
+    match msr.read(reg, 0) {
+        Err(e) if !msr.cpus().contains(&0) || msr::classify(&e) == Fault::Offline => {
+            let others: Vec<u16> = msr.cpus().into_iter().filter(|&cpu| cpu != 0).collect();
+            let layout = cpu::layout_from(Path::new("/sys/devices/system/cpu"), others);
+            match cpu::stand_in(&layout, 0) {
+                Some(cpu) => msr.read(reg, cpu),
+                None => Err(e),
+            }
+        }
+        res => res,
+    }
+}
+
+pub fn ia32_platform_id(msr: &dyn MsrAccess) -> Result<Ia32PlatformId> {
+    Ok(Ia32PlatformId(read_shared(msr, IA32_PLATFORM_ID)?))
+}
+
+pub fn ia32_perf_status(msr: &dyn MsrAccess, core: u16) -> Result<Ia32PerfStatus> {
+    Ok(Ia32PerfStatus(msr.read(IA32_PERF_STATUS, core)?))
+}
+
+pub fn ia32_clock_modulation(msr: &dyn MsrAccess, core: u16) -> Result<Ia32ClockModulation> {
+    Ok(Ia32ClockModulation(msr.read(IA32_CLOCK_MODULATION, core)?))
+}
+
+pub fn ia32_therm_status(msr: &dyn MsrAccess, core: u16) -> Result<Ia32ThermStatus> {
+    Ok(Ia32ThermStatus(msr.read(IA32_THERM_STATUS, core)?))
+}
+
+pub fn ia32_package_therm_status(msr: &dyn MsrAccess) -> Result<Ia32PackageThermStatus> {
+    Ok(Ia32PackageThermStatus(read_shared(msr, IA32_PACKAGE_THERM_STATUS)?))
+}
+
+pub fn ia32_misc_enable(msr: &dyn MsrAccess) -> Result<Ia32MiscEnable> {
+    Ok(Ia32MiscEnable(read_shared(msr, IA32_MISC_ENABLE)?))
+}
+
+pub fn msr_platform_info(msr: &dyn MsrAccess) -> Result<MsrPlatformInfo> {
+    Ok(MsrPlatformInfo(read_shared(msr, MSR_PLATFORM_INFO)?))
+}
+
+pub fn msr_temperature_target(msr: &dyn MsrAccess) -> Result<MsrTemperatureTarget> {
+    Ok(MsrTemperatureTarget(read_shared(msr, MSR_TEMPERATURE_TARGET)?))
+}
+
+pub fn msr_turbo_limits(msr: &dyn MsrAccess) -> Result<MsrTurboLimits> {
+    Ok(MsrTurboLimits(read_shared(msr, MSR_TURBO_LIMITS)?))
+}
+
+pub fn msr_turbo_ratios(msr: &dyn MsrAccess) -> Result<MsrTurboRatios> {
+    Ok(MsrTurboRatios(read_shared(msr, MSR_TURBO_RATIOS)?))
+}
@@ -0,0 +1,100 @@
+use crate::histogram::Histograms;
+use crate::monitor::{CpuSample, Sample};
+use crate::output::OutputSink;
+use crate::power::CoreSample;
+use crate::signals;
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::time::Duration;
+
+fn number(value: &str, line: usize) -> Result<f32> {
+    value.parse().with_context(|| format!("Line {}: expected a number, got {:?}", line, value))
+}
+
+// Rebuilds the samples of a `--monitor --format csv` recording. Each
+// sample is its CPU rows followed by one package row.
+pub fn parse(text: &str) -> Result<Vec<Sample>> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, "kind,cpu,field,value")) => (),
+        _ => bail!("Not an arrctl CSV recording, the header is missing"),
+    }
+
+    let mut samples = Vec::new();
+    let empty = || Sample { cpus: Vec::new(), package_watts: 0.0, core_amps: 0.0, core_amps_at_tjmax: 0.0 };
+    let mut current = empty();
+    let mut last_kind = "";
+    for (index, row) in lines {
+        let line = index + 1;
+        let [kind, cpu, field, value] = row.splitn(4, ',').collect::<Vec<_>>()[..] else {
+            bail!("Line {}: expected 4 columns", line);
+        };
+        if kind == "cpu" && last_kind == "package" {
+            samples.push(std::mem::replace(&mut current, empty()));
+        }
+        match kind {
+            "cpu" => {
+                let cpu: u16 = cpu.parse().with_context(|| format!("Line {}: bad CPU {:?}", line, cpu))?;
+                if current.cpus.last().map(|c| c.cpu) != Some(cpu) {
+                    current.cpus.push(CpuSample {
+                        cpu,
+                        activity: CoreSample { effective_mhz: 0.0, active: 0.0, volts: 0.0 },
+                        celsius: None,
+                        throttling: false,
+                    });
+                }
+                let sample = current.cpus.last_mut().unwrap();
+                match field {
+                    "effective_mhz" => sample.activity.effective_mhz = number(value, line)?,
+                    "busy_percent" => sample.activity.active = number(value, line)? / 100.0,
+                    "volts" => sample.activity.volts = number(value, line)?,
+                    "celsius" => sample.celsius = Some(number(value, line)? as u64),
+                    "throttling" => sample.throttling = value == "1",
+                    _ => (),
+                }
+            }
+            "package" => match field {
+                "estimated_watts" => current.package_watts = number(value, line)?,
+                "estimated_core_amps" => current.core_amps = number(value, line)?,
+                "estimated_core_amps_at_tjmax" => current.core_amps_at_tjmax = number(value, line)?,
+                _ => (),
+            },
+            // Anything else, like the summary at the end, isn't part of a sample
+            _ => continue,
+        }
+        last_kind = kind;
+    }
+    if last_kind == "package" {
+        samples.push(current);
+    }
+    Ok(samples)
+}
+
+// Plays samples back at the pace they were recorded at times speed. With
+// clear set every sample replaces the last one on screen, like watch(1).
+pub fn run(out: &mut dyn OutputSink, samples: &[Sample], interval: Duration, speed: f64, clear: bool) -> Result<()> {
+    if speed <= 0.0 {
+        bail!("Replay speed has to be above 0");
+    }
+    signals::install()?;
+    let pause = interval.div_f64(speed);
+    let mut histograms = Histograms::default();
+    for (index, sample) in samples.iter().enumerate() {
+        if index > 0 && !signals::sleep(pause) {
+            break;
+        }
+        if clear {
+            print!("\x1b[H\x1b[2J");
+            std::io::stdout().flush()?;
+        }
+        for record in sample.records() {
+            out.record(&record)?;
+        }
+        histograms.add_sample(sample, interval.as_secs_f64());
+    }
+    // How the part that was played back went
+    for record in histograms.records() {
+        out.record(&record)?;
+    }
+    Ok(())
+}
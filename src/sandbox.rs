@@ -0,0 +1,186 @@
+use crate::error::{Error, ErrorKind};
+use anyhow::{bail, Result};
+use libc::sock_filter;
+use std::os::fd::RawFd;
+
+// seccomp_data: the syscall number, then the architecture, then the
+// instruction pointer and the arguments
+const NR: u32 = 0;
+const ARCH: u32 = 4;
+const ARG0: u32 = 16;
+
+// AUDIT_ARCH_X86_64, which libc doesn't have. The syscall numbers are
+// x86_64's too, anything else gets killed.
+const AUDIT_ARCH: u32 = 0xc000003e;
+// x32 syscalls are the x86_64 ones with this bit set
+const X32: u32 = 0x40000000;
+
+// Nothing arrctl does, and what a foothold in a root process would go for
+// next: other processes, the kernel, mounts and namespaces, the clock
+const DENIED: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_setns,
+    libc::SYS_unshare,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_kexec_load,
+    libc::SYS_kexec_file_load,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_iopl,
+    libc::SYS_ioperm,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_settimeofday,
+    libc::SYS_clock_settime,
+    libc::SYS_acct,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_keyctl,
+];
+
+// Every way of writing to an fd. The msr driver takes the file position
+// as the register, so an lseek and a write is as good as a pwrite.
+const WRITES: &[libc::c_long] = &[libc::SYS_write, libc::SYS_writev, libc::SYS_pwrite64, libc::SYS_pwritev, libc::SYS_pwritev2];
+
+// The msr driver checks for it on open and nowhere else
+const CAP_SYS_RAWIO: u32 = 17;
+const CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const EXECS: &[libc::c_long] = &[libc::SYS_execve, libc::SYS_execveat];
+
+fn stmt(code: u32, k: u32) -> sock_filter {
+    sock_filter { code: code as u16, jt: 0, jf: 0, k }
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter { code: (libc::BPF_JMP | code | libc::BPF_K) as u16, jt, jf, k }
+}
+
+fn load(offset: u32) -> sock_filter {
+    stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset)
+}
+
+// EPERM rather than killing, so a blocked call is an error message and not
+// a dead daemon
+fn deny() -> sock_filter {
+    stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32)
+}
+
+// Runs of consecutive fds, the msr files are opened one after another so
+// there's usually just the one
+pub fn ranges(fds: &[RawFd]) -> Vec<(u32, u32)> {
+    let mut fds: Vec<u32> = fds.iter().filter_map(|&fd| u32::try_from(fd).ok()).collect();
+    fds.sort();
+    fds.dedup();
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for fd in fds {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == fd => *last = fd,
+            _ => ranges.push((fd, fd)),
+        }
+    }
+    ranges
+}
+
+// Writes to msr_fds are refused, and so is starting other programs unless
+// exec is set
+pub fn filter(msr_fds: &[RawFd], exec: bool) -> Result<Vec<sock_filter>> {
+    let mut prog = vec![
+        load(ARCH),
+        jump(libc::BPF_JEQ, AUDIT_ARCH, 1, 0),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        load(NR),
+        jump(libc::BPF_JGE, X32, 0, 1),
+        deny(),
+    ];
+    let denied = DENIED.iter().chain(if exec { &[][..] } else { EXECS });
+    for &nr in denied {
+        prog.extend([jump(libc::BPF_JEQ, nr as u32, 0, 1), deny()]);
+    }
+
+    let ranges = ranges(msr_fds);
+    if !ranges.is_empty() {
+        // A range is three instructions, the jump past all of them has to fit a u8
+        let Ok(skip) = u8::try_from(ranges.len() * 3 + 2) else {
+            bail!(Error::new(ErrorKind::Failure, format!("Too many scattered MSR files to sandbox ({} runs of fds)", ranges.len())));
+        };
+        for &nr in WRITES {
+            prog.extend([jump(libc::BPF_JEQ, nr as u32, 0, skip), load(ARG0)]);
+            for &(first, last) in &ranges {
+                prog.extend([jump(libc::BPF_JGE, first, 0, 2), jump(libc::BPF_JGT, last, 1, 0), deny()]);
+            }
+            prog.push(load(NR));
+        }
+    }
+    prog.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+    Ok(prog)
+}
+
+// The filter can't see which path an open is for, so instead of it no
+// /dev/cpu/N/msr opens again, for writing or otherwise. What's already open
+// keeps working, a CPU coming online later can't be read. Only for the
+// calling thread and the ones it starts after, so before any others.
+pub fn drop_rawio() -> Result<()> {
+    let failed = |what: &str| Error::new(ErrorKind::Failure, format!("Failed to drop CAP_SYS_RAWIO, {}: {}", what, std::io::Error::last_os_error()));
+    // Or a program run later would get it back
+    if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, CAP_SYS_RAWIO as libc::c_ulong, 0, 0, 0) } != 0 {
+        bail!(failed("from the bounding set"));
+    }
+    let mut header = CapHeader { version: CAPABILITY_VERSION_3, pid: 0 };
+    let mut data = [CapData::default(); 2];
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } != 0 {
+        bail!(failed("reading the current ones"));
+    }
+    data[0].effective &= !(1 << CAP_SYS_RAWIO);
+    data[0].permitted &= !(1 << CAP_SYS_RAWIO);
+    data[0].inheritable &= !(1 << CAP_SYS_RAWIO);
+    if unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) } != 0 {
+        bail!(failed("setting the rest"));
+    }
+    Ok(())
+}
+
+// For every thread of the process, and whatever it starts after. It can't
+// be undone, which is the point.
+pub fn apply(prog: &[sock_filter]) -> Result<()> {
+    let fprog = libc::sock_fprog { len: prog.len() as u16, filter: prog.as_ptr() as *mut sock_filter };
+    // Required to install a filter at all, and means setuid helpers a hook
+    // runs don't get their privileges
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        bail!(Error::new(ErrorKind::Failure, format!("Failed to set no_new_privs: {}", std::io::Error::last_os_error())));
+    }
+    let ret = unsafe { libc::syscall(libc::SYS_seccomp, libc::SECCOMP_SET_MODE_FILTER, libc::SECCOMP_FILTER_FLAG_TSYNC, &fprog) };
+    if ret != 0 {
+        bail!(Error::new(
+            ErrorKind::Failure,
+            format!("Failed to install the seccomp filter, --no-sandbox runs without it: {}", std::io::Error::last_os_error())
+        ));
+    }
+    Ok(())
+}
+
@@ -0,0 +1,285 @@
+use serde_json::{json, Map, Value};
+
+// Every JSON record, event and error carries this. Within a version fields
+// only ever get added, so consumers should ignore keys they don't know.
+// Renaming, removing or retyping one bumps it.
+pub const VERSION: u64 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Num,
+    Bool,
+    Str,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Int => "integer",
+            Type::Num => "number",
+            Type::Bool => "boolean",
+            Type::Str => "string",
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            Type::Int => value.is_u64() || value.is_i64(),
+            Type::Num => value.is_number(),
+            Type::Bool => value.is_boolean(),
+            Type::Str => value.is_string(),
+        }
+    }
+}
+
+use Type::*;
+
+// Every record kind and the keys it can have besides kind and cpu, most
+// are optional since records leave out what a CPU doesn't report
+pub const KINDS: &[(&str, &[(&str, Type)])] = &[
+    ("advice", &[("text", Str), ("suggested_tdp", Int), ("suggested_tdc", Int), ("gain_mhz", Int)]),
+    ("audit", &[("entries", Int), ("head", Str)]),
+    ("backtest", &[("samples", Int), ("seconds", Int), ("writes", Int), ("skipped", Int), ("max_celsius", Num), ("violations", Int), ("violation_seconds", Int)]),
+    ("backtest_step", &[("at_seconds", Int), ("celsius", Num), ("tdp", Int), ("tdc", Int), ("clock_modulation", Num), ("turbo", Bool)]),
+    ("battery", &[("system_watts", Num), ("cpu_percent", Num), ("minutes_left", Int), ("minutes_left_after_cut", Int)]),
+    ("bench", &[("load", Str), ("peak_amps", Num), ("tdc_amps", Num), ("threads", Int), ("limit", Str)]),
+    ("bench_cpu", &[("sustained_mhz", Num), ("max_celsius", Int), ("throttled_at_seconds", Num), ("early", Bool), ("core", Int), ("package", Int), ("apic", Int)]),
+    ("budget", &[("cpu_watts", Int), ("igp_max_mhz", Int), ("igp_watts", Int), ("package_watts", Int)]),
+    ("change", &[("register", Int), ("from", Int), ("to", Int)]),
+    ("check", &[("check", Str), ("passed", Bool), ("detail", Str)]),
+    ("compare", &[("a", Str), ("b", Str), ("same", Bool)]),
+    ("compare_summary", &[("fields", Int), ("differing", Int)]),
+    ("converge", &[("status", Str), ("changed", Bool), ("check", Bool)]),
+    ("cpu", &[("effective_mhz", Num), ("busy_percent", Num), ("volts", Num), ("throttling", Bool), ("celsius", Int), ("core", Int), ("package", Int), ("apic", Int)]),
+    ("cpu_online", &[("online", Str)]),
+    ("diagnostic", &[("line", Int), ("column", Int), ("message", Str)]),
+    ("enforcer", &[("checks", Int), ("rewrites", Int), ("reverted", Int), ("last_reverted_seconds", Int)]),
+    ("external_throttle", &[("text", Str), ("active", Bool)]),
+    ("fan", &[("device", Str), ("fan", Str), ("rpm", Int)]),
+    ("finding", &[("text", Str), ("cause", Str)]),
+    ("governor", &[("watts", Int), ("celsius", Int), ("target_celsius", Int), ("adjustments", Int), ("adjustments_per_hour", Int), ("p", Num), ("i", Num), ("d", Num)]),
+    ("histogram", &[("metric", Str), ("from", Num), ("seconds", Num), ("percent", Num)]),
+    ("history", &[("time_ms", Int), ("seconds", Int), ("samples", Int), ("mean_watts", Num), ("max_watts", Num), ("mean_mhz", Num), ("max_celsius", Int), ("throttled", Bool)]),
+    ("id", &[
+        ("vendor", Str),
+        ("brand", Str),
+        ("hypervisor", Str),
+        ("family", Int),
+        ("model", Int),
+        ("stepping", Int),
+        ("base_family", Int),
+        ("extended_family", Int),
+        ("base_model", Int),
+        ("extended_model", Int),
+        ("cpuid1_eax", Int),
+        ("packages", Int),
+        ("cores_per_package", Int),
+        ("threads_per_core", Int),
+        ("cpus_online", Int),
+    ]),
+    ("init", &[("config", Str), ("goal", Str), ("ac_watts", Int), ("battery_watts", Int), ("unit", Str)]),
+    ("max_freq", &[("mhz", Num), ("ratio", Int), ("bclk_mhz", Num), ("mechanism", Str)]),
+    ("measure", &[("command", Str), ("samples", Int), ("seconds", Num), ("mean_mhz", Num), ("cpu_joules", Num), ("mean_cpu_watts", Num), ("system_joules", Num), ("mean_system_watts", Num), ("battery_wh", Num)]),
+    ("observed", &[
+        ("sustained_mhz", Num),
+        ("busy_percent", Num),
+        ("max_package_watts", Num),
+        ("tdp_watts", Num),
+        ("max_celsius", Int),
+        ("throttled", Bool),
+        ("externally_throttled", Bool),
+    ]),
+    ("package", &[("estimated_watts", Num), ("estimated_core_amps", Num), ("estimated_core_amps_at_tjmax", Num)]),
+    ("platform", &[("platform_id", Int), ("max_bus_ratio", Int), ("ratio_unlocked", Bool), ("engineering_sample", Bool)]),
+    ("policy", &[("sample", Int), ("celsius", Num), ("tdp", Int), ("tdc", Int), ("clock_modulation", Num), ("turbo", Bool)]),
+    ("policy_summary", &[("samples", Int), ("changes", Int), ("skipped", Int)]),
+    ("profile", &[("name", Str), ("extends", Str), ("layers", Str), ("tdp", Int), ("tdc", Int), ("turbo", Bool), ("clock_modulation", Int), ("core_clock_modulation", Str), ("igp_cap", Str), ("turbo_ratios", Str), ("pinned_ratio", Int), ("fan_level", Str)]),
+    ("restored", &[("register", Int), ("value", Int)]),
+    ("setting", &[("range", Str), ("locked", Str)]),
+    ("soak", &[
+        ("seconds", Num),
+        ("complete", Bool),
+        ("max_celsius", Int),
+        ("min_sustained_mhz", Num),
+        ("throttled", Bool),
+        ("passed", Bool),
+    ]),
+    ("sku", &[
+        ("name", Str),
+        ("tdp", Int),
+        ("tdc", Int),
+        ("base_ratio", Int),
+        ("turbo_one_core", Int),
+        ("turbo_two_cores", Int),
+    ]),
+    ("stock_ratios", &[("sku", Str), ("base", Int), ("one_core", Int), ("two_cores", Int)]),
+    ("summary", &[("samples", Int), ("seconds", Num), ("max_package_watts", Num), ("max_celsius", Int), ("throttled", Bool), ("externally_throttled", Bool)]),
+    ("tdc", &[("amps", Num), ("sku", Str), ("stock_amps", Int), ("override", Bool)]),
+    ("tdp", &[("watts", Num), ("sku", Str), ("stock_watts", Int), ("override", Bool)]),
+    ("temp_divergence", &[("text", Str), ("msr_celsius", Int), ("hwmon_celsius", Int)]),
+    ("thermal", &[("scope", Str), ("celsius", Int), ("throttling", Bool), ("thermal_log", Bool), ("prochot", Bool), ("prochot_log", Bool)]),
+    ("tjmax", &[("celsius", Int)]),
+    ("trip", &[("zone", Str), ("zone_type", Str), ("trip", Str), ("celsius", Int), ("before_tjmax", Bool)]),
+    ("try", &[("revert_after", Int), ("deadline", Int), ("status", Str)]),
+    ("tune", &[("recommended_watts", Int), ("min_mhz", Num), ("load", Str), ("steps", Int)]),
+    ("tune_step", &[("tdp_watts", Int), ("mhz", Num), ("package_watts", Num), ("max_celsius", Int)]),
+    ("turbo_duration", &[("bound_spells", Int), ("median_seconds", Num), ("longest_seconds", Num), ("ongoing_seconds", Num)]),
+    ("turbo_mhz", &[("active_cores", Int), ("mhz", Num), ("fused_mhz", Num), ("overridden", Str)]),
+    ("turbo_ratios", &[("one_core", Int), ("two_cores", Int), ("three_cores", Int), ("four_cores", Int)]),
+    ("turbo_residency", &[("ratio", Int), ("turbo", Bool), ("seconds", Num), ("percent", Num)]),
+    ("unavailable", &[("section", Str), ("reason", Str)]),
+    ("violation", &[("at_seconds", Int), ("text", Str)]),
+    ("voltage", &[("volts", Num)]),
+    ("zone", &[("zone", Str), ("zone_type", Str), ("celsius", Int), ("next_trip", Str), ("to_trip", Int)]),
+];
+
+// Which kinds each command prints, "status" being the --get-*, --set-*
+// and --monitor flags without a command
+pub const COMMANDS: &[(&str, &[&str])] = &[
+    ("status", &["tdp", "tdc", "tjmax", "turbo_ratios", "stock_ratios", "turbo_mhz", "voltage", "thermal", "fan", "cpu", "package", "battery", "temp_divergence", "trip", "zone", "external_throttle", "governor", "enforcer", "summary", "turbo_residency", "turbo_duration", "histogram", "unavailable"]),
+    ("id", &["id", "sku", "platform"]),
+    ("selftest", &["check"]),
+    ("recover", &["restored"]),
+    ("advise", &["observed", "advice"]),
+    ("doctor", &["observed", "finding"]),
+    ("bench", &["observed", "bench", "turbo_residency", "turbo_duration", "bench_cpu"]),
+    ("soak", &["violation", "soak"]),
+    ("budget", &["budget"]),
+    ("tune", &["tune_step", "tune"]),
+    ("cores", &["cpu_online"]),
+    ("config", &["diagnostic"]),
+    ("profile", &["profile"]),
+    ("audit", &["audit"]),
+    ("history", &["history", "histogram"]),
+    ("compare", &["compare", "compare_summary"]),
+    ("run", &["tdp", "tdc", "tjmax", "turbo_ratios", "stock_ratios", "turbo_mhz", "voltage", "thermal", "unavailable", "max_freq"]),
+    ("converge", &["change", "converge"]),
+    ("set", &["max_freq"]),
+    ("try", &["try", "max_freq"]),
+    ("measure", &["measure"]),
+    ("init", &["tdp", "tdc", "turbo_ratios", "init"]),
+    ("replay", &["cpu", "package", "histogram"]),
+    ("policy", &["policy", "policy_summary", "backtest_step", "violation", "backtest"]),
+];
+
+// Lines of `arrctl events`, with the keys besides event and time_ms
+pub const EVENTS: &[(&str, &[(&str, Type)])] = &[
+    ("power-source", &[("ac", Bool)]),
+    ("enforcement", &[("profile", Str), ("reason", Str), ("applied", Bool)]),
+    ("throttle", &[("cpu", Int), ("active", Bool)]),
+    // Plus profiles, an array of names
+    ("profile-change", &[]),
+    // Plus records, an array of cpu and package records
+    ("sample", &[]),
+    ("lagged", &[("skipped", Int)]),
+];
+
+// additionalProperties stays open, a new field isn't a breaking change
+fn object(tag: &str, name: &str, keys: &[(&str, Type)], extra: &[(&str, Value)]) -> Value {
+    let mut properties = Map::new();
+    properties.insert(tag.into(), json!({ "const": name }));
+    properties.insert("schema_version".into(), json!({ "const": VERSION }));
+    for (key, ty) in keys {
+        properties.insert(key.to_string(), json!({ "type": ty.name() }));
+    }
+    for (key, schema) in extra {
+        properties.insert(key.to_string(), schema.clone());
+    }
+    json!({
+        "type": "object",
+        "required": [tag, "schema_version"],
+        "properties": properties,
+    })
+}
+
+fn kind(name: &str) -> Option<Value> {
+    let (_, keys) = KINDS.iter().find(|(kind, _)| *kind == name)?;
+    Some(object("kind", name, keys, &[("cpu", json!({ "type": "integer" }))]))
+}
+
+fn document(title: String, variants: Vec<Value>) -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+        "description": "One JSON object per line on stdout, failures print $defs/error on stderr",
+        "oneOf": variants,
+        "$defs": { "error": error() },
+    })
+}
+
+pub fn events() -> Value {
+    let variants = EVENTS
+        .iter()
+        .map(|(name, keys)| {
+            let extra = match *name {
+                "profile-change" => vec![("profiles", json!({ "type": "array", "items": { "type": "string" } }))],
+                "sample" => vec![("records", json!({ "type": "array", "items": { "oneOf": [kind("cpu"), kind("package")] } }))],
+                _ => vec![],
+            };
+            let mut schema = object("event", name, keys, &[extra, vec![("time_ms", json!({ "type": "integer" }))]].concat());
+            schema["required"] = json!(["event", "schema_version", "time_ms"]);
+            schema
+        })
+        .collect();
+    document("arrctl events".to_string(), variants)
+}
+
+// None for commands without JSON records of their own
+pub fn command(name: &str) -> Option<Value> {
+    if name == "events" {
+        return Some(events());
+    }
+    let (_, kinds) = COMMANDS.iter().find(|(command, _)| *command == name)?;
+    Some(document(format!("arrctl {} --json", name), kinds.iter().filter_map(|k| kind(k)).collect()))
+}
+
+// What a failed command prints on stderr with --json
+pub fn error() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "arrctl error",
+        "type": "object",
+        "required": ["error", "schema_version"],
+        "properties": {
+            "schema_version": { "const": VERSION },
+            "error": {
+                "type": "object",
+                "required": ["kind", "exit_code", "message"],
+                "properties": {
+                    "kind": { "type": "string" },
+                    "exit_code": { "type": "integer" },
+                    "message": { "type": "string" },
+                    "register": { "type": "string" },
+                    "address": { "type": "string" },
+                    "cpu": { "type": "integer" }
+                }
+            }
+        }
+    })
+}
+
+// For tests: a record of a kind the command lists, with every key known
+// and of the listed type. Stricter than the schema, which allows new keys.
+pub fn check(command: &str, record: &Value) -> Result<(), String> {
+    let kind = record["kind"].as_str().ok_or("no kind")?;
+    let (_, kinds) = COMMANDS.iter().find(|(c, _)| *c == command).ok_or(format!("unknown command {}", command))?;
+    if !kinds.contains(&kind) {
+        return Err(format!("{} doesn't list kind {}", command, kind));
+    }
+    let (_, keys) = KINDS.iter().find(|(k, _)| *k == kind).ok_or(format!("unknown kind {}", kind))?;
+    for (key, value) in record.as_object().ok_or("not an object")? {
+        let ty = match key.as_str() {
+            "kind" => Type::Str,
+            "cpu" | "schema_version" => Type::Int,
+            _ => keys.iter().find(|(k, _)| k == key).map(|(_, ty)| *ty).ok_or(format!("{} has no key {}", kind, key))?,
+        };
+        if !ty.matches(value) {
+            return Err(format!("{}.{} should be {}, got {}", kind, key, ty.name(), value));
+        }
+    }
+    if record["schema_version"] != VERSION {
+        return Err(format!("{} has no schema_version {}", kind, VERSION));
+    }
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use crate::apply;
+use crate::cpu;
+use crate::error::{Error, ErrorKind};
+use crate::freq;
+use crate::journal::Journal;
+use crate::msr::MsrAccess;
+use crate::output::OutputSink;
+use crate::profile::Profile;
+use crate::regs::*;
+use crate::sku::Sku;
+use crate::status;
+use crate::trial::{self, Setting};
+use crate::units;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// A batch of commands for `arrctl run`, one per line:
+//
+//   # back off while it runs hot
+//   set tdp 25W
+//   sleep 30s
+//   if temperature > 85 set clock-modulation 50%
+//   get thermal
+//
+// get takes what the --get-* flags do, set what `arrctl set` does. An if
+// compares a reading with a number and runs the rest of the line when that
+// holds. The whole file is checked before the first line runs.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    Tdp,
+    Tdc,
+    Tjmax,
+    TurboRatios,
+    Voltage,
+    Thermal,
+}
+
+// What an if can look at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reading {
+    // In watts and amps
+    Tdp,
+    Tdc,
+    Tjmax,
+    // Of the hottest core
+    Temperature,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Get(Section),
+    Set(Setting, String),
+    Sleep(Duration),
+    If(Reading, Op, f64, Box<Action>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub line: usize,
+    pub action: Action,
+}
+
+impl Action {
+    pub fn writes(&self) -> bool {
+        match self {
+            Action::Set(..) => true,
+            Action::If(.., then) => then.writes(),
+            _ => false,
+        }
+    }
+}
+
+impl Op {
+    fn holds(self, a: f64, b: f64) -> bool {
+        match self {
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+        }
+    }
+}
+
+fn section(word: &str) -> Option<Section> {
+    Some(match word {
+        "tdp" => Section::Tdp,
+        "tdc" => Section::Tdc,
+        "tjmax" => Section::Tjmax,
+        "turbo-ratios" => Section::TurboRatios,
+        "voltage" => Section::Voltage,
+        "thermal" => Section::Thermal,
+        _ => return None,
+    })
+}
+
+fn reading(word: &str) -> Option<Reading> {
+    Some(match word {
+        "tdp" => Reading::Tdp,
+        "tdc" => Reading::Tdc,
+        "tjmax" => Reading::Tjmax,
+        "temperature" => Reading::Temperature,
+        _ => return None,
+    })
+}
+
+fn op(word: &str) -> Option<Op> {
+    Some(match word {
+        "<" => Op::Lt,
+        "<=" => Op::Le,
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        "==" => Op::Eq,
+        "!=" => Op::Ne,
+        _ => return None,
+    })
+}
+
+fn action(words: &[&str]) -> Result<Action, String> {
+    match words {
+        ["get", name] => section(name).map(Action::Get).ok_or_else(|| format!("Can't get {:?}, expected tdp, tdc, tjmax, turbo-ratios, voltage or thermal", name)),
+        ["set", setting, value] => {
+            let setting = Setting::from_str(setting, true)?;
+            // Checked now so a typo on the last line doesn't leave half a script applied
+            match setting {
+                Setting::MaxFreq => units::parse_mhz(value).map(drop)?,
+                _ => trial::profile(setting, value).map(drop).map_err(|e| e.to_string())?,
+            }
+            Ok(Action::Set(setting, value.to_string()))
+        }
+        ["sleep", duration] => units::parse_millis(duration).map(|ms| Action::Sleep(Duration::from_millis(ms))),
+        ["if", name, cmp, number, then @ ..] => {
+            let reading = reading(name).ok_or_else(|| format!("Can't compare {:?}, expected tdp, tdc, tjmax or temperature", name))?;
+            let op = op(cmp).ok_or_else(|| format!("Expected <, <=, >, >=, == or !=, got {:?}", cmp))?;
+            let number = number.parse::<f64>().map_err(|_| format!("Expected a number to compare with, got {:?}", number))?;
+            if then.is_empty() {
+                return Err("Nothing to do after the condition".to_string());
+            }
+            Ok(Action::If(reading, op, number, Box::new(action(then)?)))
+        }
+        [word, ..] if ["get", "set", "sleep", "if"].contains(word) => Err(format!("Wrong number of arguments to {}", word)),
+        [word, ..] => Err(format!("Unknown command {:?}", word)),
+        [] => unreachable!(),
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let action = action(&words).map_err(|e| Error::new(ErrorKind::Validation, format!("Line {}: {}", lineno + 1, e)))?;
+        steps.push(Step { line: lineno + 1, action });
+    }
+    Ok(steps)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Step>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse(&text).with_context(|| format!("In {}", path.display()))
+}
+
+pub fn read(msr: &dyn MsrAccess, reading: Reading) -> Result<f64> {
+    Ok(match reading {
+        Reading::Tdp => msr_turbo_limits(msr)?.tdp_watts().0,
+        Reading::Tdc => msr_turbo_limits(msr)?.tdc_amps().0,
+        Reading::Tjmax => msr_temperature_target(msr)?.tjmax().0 as f64,
+        Reading::Temperature => {
+            let tjmax = msr_temperature_target(msr)?.get();
+            let mut hottest = None;
+            for cpu in msr.cpus() {
+                hottest = hottest.max(Ia32ThermStatus(msr.read(IA32_THERM_STATUS, cpu)?).celsius(tjmax));
+            }
+            match hottest {
+                Some(celsius) => celsius as f64,
+                None => bail!(Error::new(ErrorKind::Failure, "No core has a valid temperature reading")),
+            }
+        }
+    })
+}
+
+pub struct Run<'a> {
+    pub msr: &'a dyn MsrAccess,
+    pub journal: &'a Journal,
+    pub stock: Option<&'static Sku>,
+    pub all_cores: bool,
+    pub force: bool,
+}
+
+impl Run<'_> {
+    fn set(&self, profile: &Profile) -> Result<()> {
+        apply::validate(profile, &msr_platform_info(self.msr)?)?;
+        apply::apply_with(self.msr, self.journal, profile, self.all_cores, self.force)
+    }
+
+    fn perform(&self, out: &mut dyn OutputSink, action: &Action) -> Result<()> {
+        let msr = self.msr;
+        match action {
+            Action::Get(Section::Tdp) => status::section(out, "tdp", |out| status::tdp(out, msr, self.stock)),
+            Action::Get(Section::Tdc) => status::section(out, "tdc", |out| status::tdc(out, msr, self.stock)),
+            Action::Get(Section::Tjmax) => status::section(out, "tjmax", |out| status::tjmax(out, msr)),
+            Action::Get(Section::TurboRatios) => status::section(out, "turbo_ratios", |out| status::turbo_ratios(out, msr, self.stock)),
+            Action::Get(Section::Voltage) => status::section(out, "voltage", |out| status::voltage(out, msr)),
+            Action::Get(Section::Thermal) => status::section(out, "thermal", |out| status::thermal(out, msr, cpu::has_package_thermal())),
+            Action::Set(Setting::MaxFreq, value) => {
+                let plan = freq::for_cpu(msr, self.stock, value)?;
+                self.set(&plan.profile)?;
+                out.record(&plan.record())
+            }
+            Action::Set(setting, value) => self.set(&trial::profile(*setting, value)?),
+            Action::Sleep(duration) => {
+                thread::sleep(*duration);
+                Ok(())
+            }
+            Action::If(reading, op, number, then) => match op.holds(read(msr, *reading)?, *number) {
+                true => self.perform(out, then),
+                false => Ok(()),
+            },
+        }
+    }
+
+    // Stops at the first line that fails, what ran before it stays applied
+    pub fn run(&self, out: &mut dyn OutputSink, steps: &[Step]) -> Result<()> {
+        for step in steps {
+            self.perform(out, &step.action).with_context(|| format!("Line {}", step.line))?;
+        }
+        Ok(())
+    }
+}
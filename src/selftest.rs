@@ -0,0 +1,108 @@
+use crate::cpu;
+use crate::msr::MsrAccess;
+use crate::output::{OutputSink, Record};
+use crate::regs::*;
+use anyhow::{bail, Result};
+use std::thread;
+use std::time::Duration;
+
+struct Report<'a> {
+    out: &'a mut dyn OutputSink,
+    failed: usize,
+}
+
+impl Report<'_> {
+    fn check(&mut self, name: &str, ok: bool, detail: String) -> Result<()> {
+        if !ok {
+            self.failed += 1;
+        }
+        self.out.record(&Record::new("check")
+            .title(format!("{} {}", if ok { "PASS" } else { "FAIL" }, name))
+            .hidden("check", name)
+            .hidden("passed", ok)
+            .field("detail", "", detail, ""))
+    }
+}
+
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess) -> Result<()> {
+    let mut report = Report { out, failed: 0 };
+    let cpus = msr.cpus();
+    let model = cpu::model();
+
+    for &core in &cpus {
+        for reg in dumped() {
+            match msr.read(reg.address, core) {
+                Ok(val) => report.check(&format!("read {} on CPU{}", reg.name, core), true, format!("{:#018x}, {}", val, reg.describe(model, val)))?,
+                Err(e) => report.check(&format!("read {} on CPU{}", reg.name, core), false, format!("{:#}", e))?,
+            }
+        }
+    }
+
+    if let Ok(val) = read_shared(msr, MSR_TEMPERATURE_TARGET) {
+        let tjmax = MsrTemperatureTarget(val).tjmax();
+        let valid = lookup(MSR_TEMPERATURE_TARGET).and_then(|reg| reg.field(model, "tjmax")).is_none_or(|field| field.is_valid(val));
+        report.check("TJmax range", valid, tjmax.to_string())?;
+
+        for &core in &cpus {
+            if let Ok(val) = msr.read(IA32_THERM_STATUS, core) {
+                let therm = Ia32ThermStatus(val);
+                report.check(
+                    &format!("temperature reading on CPU{}", core),
+                    therm.reading_valid() && therm.digital_readout() < tjmax.0,
+                    format!("{} below TJmax, valid: {}", therm.digital_readout(), therm.reading_valid()),
+                )?;
+            }
+        }
+    }
+
+    if let Ok(plat_info) = msr_platform_info(msr) {
+        report.check(
+            "max ratio >= min ratio",
+            plat_info.max_non_turbo_ratio() >= plat_info.minimum_ratio() && plat_info.minimum_ratio() > 0,
+            format!("max {}, min {}", plat_info.max_non_turbo_ratio(), plat_info.minimum_ratio()),
+        )?;
+
+        if let Ok(ratios) = msr_turbo_ratios(msr) {
+            report.check(
+                "turbo ratios ordered",
+                ratios.one_core() >= ratios.two_cores() && ratios.two_cores() >= plat_info.max_non_turbo_ratio(),
+                format!("one core {}, two cores {}, max non-turbo {}",
+                    ratios.one_core(), ratios.two_cores(), plat_info.max_non_turbo_ratio()),
+            )?;
+        }
+    }
+
+    if let Ok(limits) = msr_turbo_limits(msr) {
+        report.check(
+            "TDP and TDC nonzero",
+            limits.tdp() > 0 && limits.tdc() > 0,
+            format!("{}, {}", limits.tdp_watts(), limits.tdc_amps()),
+        )?;
+    }
+
+    for &core in &cpus {
+        if let Ok(val) = msr.read(IA32_PERF_STATUS, core) {
+            let volts = Ia32PerfStatus(val).volts();
+            report.check(&format!("voltage on CPU{}", core), (0.6..=1.55).contains(&volts), format!("{:.4} V", volts))?;
+        }
+    }
+
+    for &core in &cpus {
+        let before = msr.read(IA32_APERF, core).and_then(|a| Ok((a, msr.read(IA32_MPERF, core)?)));
+        thread::sleep(Duration::from_millis(10));
+        let after = msr.read(IA32_APERF, core).and_then(|a| Ok((a, msr.read(IA32_MPERF, core)?)));
+        if let (Ok(before), Ok(after)) = (before, after) {
+            report.check(
+                &format!("APERF/MPERF counting on CPU{}", core),
+                after.0 != before.0 && after.1 != before.1,
+                format!("APERF +{}, MPERF +{}", after.0.wrapping_sub(before.0), after.1.wrapping_sub(before.1)),
+            )?;
+        }
+    }
+
+    if report.failed > 0 {
+        bail!("{} check(s) failed", report.failed);
+    }
+    report.out.note("All checks passed")?;
+    Ok(())
+}
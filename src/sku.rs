@@ -0,0 +1,85 @@
+use crate::power::PowerCoefficients;
+use raw_cpuid::CpuId;
+
+// Factory values as listed in Intel's ARK and the Arrandale datasheets.
+// TDC is the rated ICC max of the core voltage plane.
+pub struct Sku {
+    pub name: &'static str,
+    // Brand string with whitespace collapsed and the "@ x.xxGHz" part dropped
+    pub brand: &'static str,
+    pub tdp: u32,
+    pub tdc: u32,
+    pub base_ratio: u8,
+    // Maximum ratio with one and two cores active, equal to base_ratio on
+    // parts without turbo
+    pub turbo_ratios: [u8; 2],
+    pub power: PowerCoefficients,
+}
+
+const SV: PowerCoefficients = PowerCoefficients { dynamic: 3.1, leakage: 1.7, uncore: 4.0 };
+const LV: PowerCoefficients = PowerCoefficients { dynamic: 3.0, leakage: 1.2, uncore: 3.0 };
+const ULV: PowerCoefficients = PowerCoefficients { dynamic: 2.9, leakage: 0.9, uncore: 2.5 };
+
+pub const SKUS: &[Sku] = &[
+    Sku { name: "i3-330M", brand: "i3 CPU M 330", tdp: 35, tdc: 48, base_ratio: 16, turbo_ratios: [16, 16], power: SV },
+    Sku { name: "i3-350M", brand: "i3 CPU M 350", tdp: 35, tdc: 48, base_ratio: 17, turbo_ratios: [17, 17], power: SV },
+    Sku { name: "i3-370M", brand: "i3 CPU M 370", tdp: 35, tdc: 48, base_ratio: 18, turbo_ratios: [18, 18], power: SV },
+    Sku { name: "i3-380M", brand: "i3 CPU M 380", tdp: 35, tdc: 48, base_ratio: 19, turbo_ratios: [19, 19], power: SV },
+    Sku { name: "i3-330UM", brand: "i3 CPU U 330", tdp: 18, tdc: 26, base_ratio: 9, turbo_ratios: [9, 9], power: ULV },
+    Sku { name: "i5-430M", brand: "i5 CPU M 430", tdp: 35, tdc: 48, base_ratio: 17, turbo_ratios: [19, 18], power: SV },
+    Sku { name: "i5-450M", brand: "i5 CPU M 450", tdp: 35, tdc: 48, base_ratio: 18, turbo_ratios: [20, 19], power: SV },
+    Sku { name: "i5-460M", brand: "i5 CPU M 460", tdp: 35, tdc: 48, base_ratio: 19, turbo_ratios: [21, 20], power: SV },
+    Sku { name: "i5-480M", brand: "i5 CPU M 480", tdp: 35, tdc: 48, base_ratio: 20, turbo_ratios: [22, 21], power: SV },
+    Sku { name: "i5-520M", brand: "i5 CPU M 520", tdp: 35, tdc: 48, base_ratio: 18, turbo_ratios: [22, 20], power: SV },
+    Sku { name: "i5-540M", brand: "i5 CPU M 540", tdp: 35, tdc: 48, base_ratio: 19, turbo_ratios: [23, 21], power: SV },
+    Sku { name: "i5-560M", brand: "i5 CPU M 560", tdp: 35, tdc: 48, base_ratio: 20, turbo_ratios: [24, 22], power: SV },
+    Sku { name: "i5-580M", brand: "i5 CPU M 580", tdp: 35, tdc: 48, base_ratio: 20, turbo_ratios: [25, 23], power: SV },
+    Sku { name: "i5-520UM", brand: "i5 CPU U 520", tdp: 18, tdc: 26, base_ratio: 8, turbo_ratios: [14, 13], power: ULV },
+    Sku { name: "i5-540UM", brand: "i5 CPU U 540", tdp: 18, tdc: 26, base_ratio: 9, turbo_ratios: [15, 14], power: ULV },
+    Sku { name: "i7-620M", brand: "i7 CPU M 620", tdp: 35, tdc: 48, base_ratio: 20, turbo_ratios: [25, 23], power: SV },
+    Sku { name: "i7-640M", brand: "i7 CPU M 640", tdp: 35, tdc: 48, base_ratio: 21, turbo_ratios: [26, 24], power: SV },
+    Sku { name: "i7-620LM", brand: "i7 CPU L 620", tdp: 25, tdc: 32, base_ratio: 15, turbo_ratios: [21, 19], power: LV },
+    Sku { name: "i7-640LM", brand: "i7 CPU L 640", tdp: 25, tdc: 32, base_ratio: 16, turbo_ratios: [22, 20], power: LV },
+    Sku { name: "i7-660LM", brand: "i7 CPU L 660", tdp: 25, tdc: 32, base_ratio: 17, turbo_ratios: [23, 21], power: LV },
+    Sku { name: "i7-620UM", brand: "i7 CPU U 620", tdp: 18, tdc: 26, base_ratio: 8, turbo_ratios: [16, 15], power: ULV },
+    Sku { name: "i7-640UM", brand: "i7 CPU U 640", tdp: 18, tdc: 26, base_ratio: 9, turbo_ratios: [17, 16], power: ULV },
+];
+
+pub fn brand_string() -> String {
+    CpuId::new()
+        .get_processor_brand_string()
+        .map(|b| b.as_str().to_string())
+        .unwrap_or_default()
+}
+
+pub fn lookup(brand: &str) -> Option<&'static Sku> {
+    let brand = brand.split_whitespace().collect::<Vec<_>>().join(" ");
+    SKUS.iter().find(|sku| {
+        brand
+            .split(" @ ")
+            .next()
+            .is_some_and(|b| b.ends_with(sku.brand))
+    })
+}
+
+// Engineering and qualification samples carry a placeholder brand string
+// instead of a model number
+pub fn engineering_sample(brand: &str) -> bool {
+    let brand = brand.split_whitespace().collect::<Vec<_>>().join(" ");
+    brand.starts_with("Genuine Intel(R) CPU") || brand.contains(" 0000 ") || brand.starts_with("Intel(R) Core(TM) CPU @")
+}
+
+pub fn detect() -> Option<&'static Sku> {
+    lookup(&brand_string())
+}
+
+// How far a limit may stray from the factory value before the user gets
+// a warning, as a fraction of the stock value
+pub const SPEC_MARGIN_ABOVE: f32 = 0.5;
+pub const SPEC_MARGIN_BELOW: f32 = 0.6;
+
+pub fn far_outside_spec(stock: u32, value: u64) -> bool {
+    let stock = stock as f32;
+    let value = value as f32;
+    value > stock * (1.0 + SPEC_MARGIN_ABOVE) || value < stock * (1.0 - SPEC_MARGIN_BELOW)
+}
@@ -0,0 +1,158 @@
+use crate::bench::{self, Load};
+use crate::error::{Error, ErrorKind};
+use crate::monitor::{Sample, Sampler};
+use crate::msr::MsrAccess;
+use crate::output::{Level, OutputSink, Record, Value};
+use crate::progress::Progress;
+use crate::{cpu, signals};
+use anyhow::{bail, Result};
+use std::time::{Duration, Instant};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Sustained means averaged over this long, so a single slow sample while
+// the load threads get scheduled doesn't fail a four hour run
+pub const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Criteria {
+    pub max_celsius: Option<u64>,
+    pub min_sustained_mhz: Option<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub at: Duration,
+    pub text: String,
+}
+
+// Keeps score while the soak runs
+#[derive(Clone, Debug, Default)]
+pub struct Judge {
+    pub criteria: Criteria,
+    pub max_celsius: Option<u64>,
+    // Lowest average over a whole window
+    pub min_window_mhz: Option<f32>,
+    pub throttled: bool,
+    pub violation: Option<Violation>,
+    window_start: Duration,
+    window_mhz: f32,
+    window_samples: u32,
+}
+
+// Frequency of the CPUs while awake, weighted by how awake they were
+pub fn busy_mhz(sample: &Sample) -> f32 {
+    let active: f32 = sample.cpus.iter().map(|c| c.activity.active).sum();
+    if active == 0.0 {
+        return 0.0;
+    }
+    sample.cpus.iter().map(|c| c.activity.effective_mhz * c.activity.active).sum::<f32>() / active
+}
+
+impl Judge {
+    pub fn new(criteria: Criteria) -> Self {
+        Judge { criteria, ..Default::default() }
+    }
+
+    // Only the first violation is kept, that's where the run stops
+    fn violate(&mut self, at: Duration, text: String) {
+        if self.violation.is_none() {
+            self.violation = Some(Violation { at, text });
+        }
+    }
+
+    pub fn update(&mut self, sample: &Sample, at: Duration) {
+        if let Some(temp) = sample.cpus.iter().filter_map(|c| c.celsius).max() {
+            self.max_celsius = Some(self.max_celsius.map_or(temp, |max| max.max(temp)));
+            if let Some(limit) = self.criteria.max_celsius.filter(|&limit| temp > limit) {
+                self.violate(at, format!("Reached {} celsius, above the {} allowed", temp, limit));
+            }
+        }
+        self.throttled |= sample.cpus.iter().any(|c| c.throttling);
+
+        self.window_mhz += busy_mhz(sample);
+        self.window_samples += 1;
+        if at - self.window_start >= WINDOW {
+            let mhz = self.window_mhz / self.window_samples as f32;
+            self.min_window_mhz = Some(self.min_window_mhz.map_or(mhz, |min| min.min(mhz)));
+            if let Some(limit) = self.criteria.min_sustained_mhz.filter(|&limit| mhz < limit) {
+                self.violate(at, format!("Sustained {:.0} MHz over {} s, below the {:.0} required", mhz, WINDOW.as_secs(), limit));
+            }
+            (self.window_start, self.window_mhz, self.window_samples) = (at, 0.0, 0);
+        }
+    }
+
+    pub fn records(&self, elapsed: Duration, planned: Duration) -> Vec<Record> {
+        let complete = elapsed >= planned;
+        let passed = complete && self.violation.is_none();
+        let mut records = Vec::new();
+        if let Some(violation) = &self.violation {
+            records.push(Record::new("violation")
+                .title(format!("After {} s", violation.at.as_secs()))
+                .hidden("at_seconds", violation.at.as_secs())
+                .field("text", "", violation.text.as_str(), "")
+                .level(Level::Bad));
+        }
+        let mut record = Record::new("soak")
+            .title(format!("Soaked for {:.0} of {:.0} minutes", elapsed.as_secs_f64() / 60.0, planned.as_secs_f64() / 60.0))
+            .hidden("seconds", Value::Float(elapsed.as_secs_f64()))
+            .hidden("complete", complete);
+        if let Some(temp) = self.max_celsius {
+            record = record.field("max_celsius", "max", temp, "celsius");
+        }
+        if let Some(mhz) = self.min_window_mhz {
+            record = record.field("min_sustained_mhz", "lowest sustained", Value::Fixed(mhz as f64, 0), "MHz");
+        }
+        records.push(record
+            .field("throttled", "throttled", self.throttled, "")
+            .field("passed", "passed", passed, "")
+            .level(if passed { Level::Good } else { Level::Bad }));
+        records
+    }
+}
+
+pub fn parse_hours(s: &str) -> Result<Duration, String> {
+    match s.parse::<f64>() {
+        Ok(hours) if hours > 0.0 && hours.is_finite() => Ok(Duration::from_secs_f64(hours * 3600.0)),
+        _ => Err(format!("Expected a positive number of hours, got {:?}", s)),
+    }
+}
+
+// Exits with an error when a criterion isn't met or the run is cut short,
+// after the report so scripts get both
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess, load: &Load, duration: Duration, criteria: Criteria) -> Result<()> {
+    signals::install()?;
+    let mut running = bench::start(load, &cpu::online_cpus())?;
+    let mut judge = Judge::new(criteria);
+    let start = Instant::now();
+    let mut progress = Progress::new("Soaking", duration.as_secs());
+    let mut sampler = Sampler::new(msr)?;
+    let mut early = None;
+    while start.elapsed() < duration && judge.violation.is_none() {
+        if !signals::sleep(SAMPLE_INTERVAL) {
+            break;
+        }
+        if let Some(status) = running.exited_early()? {
+            early = Some(status);
+            break;
+        }
+        judge.update(&sampler.sample(msr)?, start.elapsed());
+        progress.set(start.elapsed().as_secs());
+    }
+    running.stop();
+    drop(progress);
+
+    let elapsed = start.elapsed();
+    if let Some(status) = early {
+        out.note(&format!("The load command exited with {}, the soak can't go on without it", status))?;
+    }
+    for record in judge.records(elapsed, duration) {
+        out.record(&record)?;
+    }
+    out.finish()?;
+    match &judge.violation {
+        Some(violation) => bail!(Error::new(ErrorKind::Failure, format!("Soak failed: {}", violation.text))),
+        None if elapsed < duration => bail!(Error::new(ErrorKind::Failure, format!("Soak stopped after {} s, before it was over", elapsed.as_secs()))),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,110 @@
+use crate::atomic;
+use crate::msr::{MsrAccess, RegSpec};
+use crate::regs::*;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+pub const DIR: &str = "/var/lib/arrctl";
+pub const DEFAULT_PATH: &str = "/var/lib/arrctl/state.json";
+
+// Bump this and add a step to MIGRATIONS whenever State changes shape
+pub const VERSION: u64 = 1;
+
+// Turns the JSON of version n + 1 into version n + 2, indexed by n
+pub type Migration = fn(Value) -> Result<Value>;
+const MIGRATIONS: &[Migration] = &[];
+
+// The registers arrctl ever writes
+pub const BASELINE_REGISTERS: [u32; 5] = [MSR_TURBO_LIMITS, MSR_TURBO_RATIOS, IA32_MISC_ENABLE, IA32_CLOCK_MODULATION, IA32_PERF_CTL];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Saved {
+    pub cpu: u16,
+    pub register: u32,
+    pub value: u64,
+}
+
+// What the firmware left in the registers, taken once per boot before
+// anything gets written
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub boot_id: String,
+    pub registers: Vec<Saved>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    pub version: u64,
+    pub baseline: Option<Baseline>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State { version: VERSION, baseline: None }
+    }
+}
+
+// Walks an older file up to the current version one step at a time
+pub fn upgrade(mut value: Value, migrations: &[Migration]) -> Result<Value> {
+    let current = migrations.len() as u64 + 1;
+    let mut version = value.get("version").and_then(Value::as_u64).context("State has no version")?;
+    if version > current {
+        bail!("State is version {}, this arrctl only knows up to {}, was it written by a newer one?", version, current);
+    }
+    while version < current {
+        let index = version.checked_sub(1).context("State version 0 doesn't exist")?;
+        value = migrations[index as usize](value).with_context(|| format!("Failed to migrate state from version {}", version))?;
+        version += 1;
+        value["version"] = version.into();
+    }
+    Ok(value)
+}
+
+pub fn load(path: &Path) -> Result<State> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(State::default()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let value = serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let value = atomic::unseal(path, value)?;
+    let value = upgrade(value, MIGRATIONS).with_context(|| format!("Failed to load {}", path.display()))?;
+    serde_json::from_value(value).with_context(|| format!("Bad state in {}", path.display()))
+}
+
+// Through a rename, so a crash leaves either the old or the new file, and
+// checksummed for whatever gets past that
+pub fn save(path: &Path, state: &State) -> Result<()> {
+    let value = atomic::seal(serde_json::to_value(state)?);
+    atomic::write(path, (serde_json::to_string_pretty(&value)? + "\n").as_bytes())
+}
+
+pub fn boot_id() -> Result<String> {
+    let id = fs::read_to_string("/proc/sys/kernel/random/boot_id").context("Failed to read the boot id")?;
+    Ok(id.trim().to_string())
+}
+
+pub fn read_baseline(msr: &dyn MsrAccess, boot_id: String) -> Result<Baseline> {
+    let mut registers = Vec::new();
+    for cpu in msr.cpus() {
+        let specs: Vec<RegSpec> = BASELINE_REGISTERS.iter().map(|&reg| RegSpec { reg, cpu }).collect();
+        for (spec, value) in specs.iter().zip(msr.batch_read(&specs)?) {
+            registers.push(Saved { cpu, register: spec.reg, value });
+        }
+    }
+    Ok(Baseline { boot_id, registers })
+}
+
+// Takes the baseline on the first run after a boot. Returns true when it did.
+pub fn ensure_baseline(path: &Path, msr: &dyn MsrAccess, boot_id: String) -> Result<bool> {
+    let mut state = load(path)?;
+    if state.baseline.as_ref().is_some_and(|b| b.boot_id == boot_id) {
+        return Ok(false);
+    }
+    state.baseline = Some(read_baseline(msr, boot_id)?);
+    save(path, &state)?;
+    Ok(true)
+}
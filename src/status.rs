@@ -0,0 +1,240 @@
+use crate::cpu;
+use crate::error::{self, ErrorKind};
+use crate::l10n;
+use crate::msr::MsrAccess;
+use crate::output::{Level, OutputSink, Record, Value};
+use crate::regs::*;
+use crate::sku::{self, Sku};
+use anyhow::Result;
+
+// Green within the SKU's spec, yellow well outside it
+fn spec_level(stock: Option<u32>, value: u64) -> Level {
+    match stock {
+        Some(stock) if sku::far_outside_spec(stock, value) => Level::Warn,
+        Some(_) => Level::Good,
+        None => Level::Plain,
+    }
+}
+
+fn override_level(overridden: bool) -> Level {
+    if overridden { Level::Warn } else { Level::Plain }
+}
+
+// Whether a failure is only about the part being read. No driver, no
+// permission or a closed stdout would fail every other part the same way.
+pub fn degradable(err: &anyhow::Error) -> bool {
+    let broken_pipe = err.chain().any(|cause| cause.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe));
+    !broken_pipe && !matches!(error::kind_of(err), ErrorKind::NoMsrDriver | ErrorKind::PermissionDenied)
+}
+
+fn section_label(section: &str) -> &'static str {
+    match section {
+        "tdp" => l10n::text("section-tdp"),
+        "tdc" => l10n::text("section-tdc"),
+        "tjmax" => l10n::text("section-tjmax"),
+        "turbo_ratios" => l10n::text("section-turbo-ratios"),
+        "voltage" => l10n::text("section-voltage"),
+        _ => l10n::text("section-thermal"),
+    }
+}
+
+// Stands in for a part of the report whose register couldn't be read
+pub fn unavailable(section: &'static str, cpu: Option<u16>, err: &anyhow::Error) -> Record {
+    let reason = err.to_string();
+    let mut record = Record::new("unavailable")
+        .title(l10n::format("unavailable", &[("section", section_label(section)), ("reason", &reason)]))
+        .hidden("section", section)
+        .hidden("reason", reason)
+        .level(Level::Warn);
+    if let Some(cpu) = cpu {
+        record = record.cpu(cpu);
+    }
+    record
+}
+
+// Runs one part of the report, so a register this stepping doesn't have
+// only takes out its own part
+pub fn section(out: &mut dyn OutputSink, name: &'static str, part: impl FnOnce(&mut dyn OutputSink) -> Result<()>) -> Result<()> {
+    match part(out) {
+        Err(err) if degradable(&err) => out.record(&unavailable(name, None, &err)),
+        result => result,
+    }
+}
+
+pub fn tdp(out: &mut dyn OutputSink, msr: &dyn MsrAccess, stock: Option<&Sku>) -> Result<()> {
+    let turbo_limits = msr_turbo_limits(msr)?;
+    let mut record = Record::new("tdp")
+        .field("watts", l10n::text("tdp-max"), turbo_limits.tdp_watts(), "W")
+        .level(spec_level(stock.map(|sku| sku.tdp), turbo_limits.tdp_watts().whole()));
+    if let Some(sku) = stock {
+        record = record
+            .hidden("sku", sku.name)
+            .field("stock_watts", l10n::format("tdp-stock", &[("sku", sku.name)]), sku.tdp as u64, "W");
+    }
+    out.record(&record
+        .field("override", l10n::text("tdp-override"), turbo_limits.tdp_override(), "")
+        .level(override_level(turbo_limits.tdp_override())))
+}
+
+pub fn tdc(out: &mut dyn OutputSink, msr: &dyn MsrAccess, stock: Option<&Sku>) -> Result<()> {
+    let turbo_limits = msr_turbo_limits(msr)?;
+    let mut record = Record::new("tdc")
+        .field("amps", l10n::text("tdc-max"), turbo_limits.tdc_amps(), "A")
+        .level(spec_level(stock.map(|sku| sku.tdc), turbo_limits.tdc_amps().whole()));
+    if let Some(sku) = stock {
+        record = record
+            .hidden("sku", sku.name)
+            .field("stock_amps", l10n::format("tdc-stock", &[("sku", sku.name)]), sku.tdc as u64, "A");
+    }
+    out.record(&record
+        .field("override", l10n::text("tdc-override"), turbo_limits.tdc_override(), "")
+        .level(override_level(turbo_limits.tdc_override())))
+}
+
+pub fn tjmax(out: &mut dyn OutputSink, msr: &dyn MsrAccess) -> Result<()> {
+    let tjmax = msr_temperature_target(msr)?;
+    out.record(&Record::new("tjmax").field("celsius", l10n::text("tjmax"), tjmax.tjmax(), "celsius"))
+}
+
+pub fn turbo_ratios(out: &mut dyn OutputSink, msr: &dyn MsrAccess, stock: Option<&Sku>) -> Result<()> {
+    let turbo_ratios = msr_turbo_ratios(msr)?;
+
+    let mut record = Record::new("turbo_ratios");
+    let bins = [
+        ("one_core", "turbo-ratio-one", turbo_ratios.one_core()),
+        ("two_cores", "turbo-ratio-two", turbo_ratios.two_cores()),
+        ("three_cores", "turbo-ratio-three", turbo_ratios.three_cores()),
+        ("four_cores", "turbo-ratio-four", turbo_ratios.four_cores()),
+    ];
+    for (key, label, ratio) in bins {
+        if ratio != 0 {
+            record = record.field(key, l10n::text(label), ratio, "");
+        }
+    }
+    out.record(&record)?;
+
+    if let Some(sku) = stock {
+        out.record(&Record::new("stock_ratios")
+            .title(l10n::format("stock-ratios", &[("sku", sku.name)]))
+            .hidden("sku", sku.name)
+            .field("base", "", sku.base_ratio as u64, l10n::text("unit-base"))
+            .field("one_core", "", sku.turbo_ratios[0] as u64, l10n::text("unit-for-one-core"))
+            .field("two_cores", "", sku.turbo_ratios[1] as u64, l10n::text("unit-for-two-cores")))?;
+    }
+
+    // What the multipliers mean in MHz. Turbo bins count active physical
+    // cores, so both threads of one core busy is still the one core bin.
+    let turbo_disabled = ia32_misc_enable(msr)?.turbo_disable();
+    let base = msr_platform_info(msr)?.max_non_turbo_ratio();
+    let fused = stock.map(|sku| sku.turbo_ratios);
+    for (active, (_, _, ratio)) in bins.iter().enumerate().filter(|(_, (.., ratio))| *ratio != 0) {
+        let ratio = if turbo_disabled { base } else { *ratio };
+        let mut record = Record::new("turbo_mhz")
+            .title(match active {
+                0 => l10n::text("turbo-active-one").to_string(),
+                _ => l10n::format("turbo-active-many", &[("count", &(active + 1).to_string())]),
+            })
+            .hidden("active_cores", active as u64 + 1)
+            .field("mhz", "", Value::Fixed(ratio as f64 * cpu::BCLK_MHZ as f64, 0), "MHz");
+        if let Some(fused) = fused.and_then(|f| f.get(active).copied()) {
+            record = record.field("fused_mhz", l10n::text("turbo-fused"), Value::Fixed(fused as f64 * cpu::BCLK_MHZ as f64, 0), "MHz");
+            if !turbo_disabled && fused as u64 != ratio {
+                record = record.field("overridden", "", "overridden", "").level(Level::Warn);
+            }
+        }
+        out.record(&record)?;
+    }
+    if turbo_disabled {
+        out.note(l10n::text("turbo-disabled-bins"))?;
+    }
+    out.note(l10n::text("turbo-physical-cores"))?;
+    Ok(())
+}
+
+pub fn voltage(out: &mut dyn OutputSink, msr: &dyn MsrAccess) -> Result<()> {
+    for core in msr.cpus() {
+        let volts = match ia32_perf_status(msr, core) {
+            Ok(status) => status.volts(),
+            Err(err) if degradable(&err) => {
+                out.record(&unavailable("voltage", Some(core), &err))?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        out.record(&Record::new("voltage")
+            .title(l10n::format("voltage", &[("cpu", &core.to_string())]))
+            .cpu(core)
+            .field("volts", "", Value::Fixed(volts as f64, 4), "V"))?;
+    }
+    Ok(())
+}
+
+// The four event bits, current and since boot, the same in both scopes
+fn thermal_events(record: Record, bits: [bool; 4]) -> Record {
+    let [throttling, throttled, prochot, prochot_log] = bits;
+    let now = |set| if set { Level::Bad } else { Level::Plain };
+    let logged = |set| if set { Level::Warn } else { Level::Plain };
+    record
+        .field("throttling", l10n::text("thermal-throttling"), throttling, "")
+        .level(now(throttling))
+        .field("thermal_log", l10n::text("thermal-log"), throttled, "")
+        .level(logged(throttled))
+        .field("prochot", "PROCHOT", prochot, "")
+        .level(now(prochot))
+        .field("prochot_log", l10n::text("prochot-log"), prochot_log, "")
+        .level(logged(prochot_log))
+}
+
+// Each core's own sensor and, where the CPU has one, the package's. Every
+// record says which scope it is since the two can disagree by a lot.
+pub fn thermal(out: &mut dyn OutputSink, msr: &dyn MsrAccess, package: bool) -> Result<()> {
+    let tjmax = msr_temperature_target(msr)?.get();
+    for core in msr.cpus() {
+        let therm = match ia32_therm_status(msr, core) {
+            Ok(therm) => therm,
+            Err(err) if degradable(&err) => {
+                out.record(&unavailable("thermal", Some(core), &err))?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        let mut record = Record::new("thermal")
+            .title(l10n::format("thermal-core", &[("cpu", &core.to_string())]))
+            .cpu(core)
+            .hidden("scope", "core");
+        if let Some(celsius) = therm.celsius(tjmax) {
+            record = record.field("celsius", "", celsius, "celsius");
+        }
+        out.record(&thermal_events(record, [therm.thermal_status(), therm.thermal_log(), therm.prochot(), therm.prochot_log()]))?;
+    }
+    if !package {
+        return out.note(l10n::text("thermal-no-package"));
+    }
+    let therm = ia32_package_therm_status(msr)?;
+    let record = Record::new("thermal")
+        .title(l10n::text("thermal-package"))
+        .hidden("scope", "package")
+        .field("celsius", "", therm.celsius(tjmax), "celsius");
+    out.record(&thermal_events(record, [therm.thermal_status(), therm.thermal_log(), therm.prochot(), therm.prochot_log()]))?;
+    out.note(l10n::text("thermal-package-drives"))
+}
+
+pub fn platform(out: &mut dyn OutputSink, msr: &dyn MsrAccess, brand: &str) -> Result<()> {
+    let platform_id = ia32_platform_id(msr)?;
+    let plat_info = msr_platform_info(msr)?;
+    let es = sku::engineering_sample(brand);
+    let unlocked = plat_info.programmable_turbo_ratio();
+
+    out.record(&Record::new("platform")
+        .title("Platform")
+        .field("platform_id", "ID", platform_id.platform_id(), "")
+        .field("max_bus_ratio", "fused max ratio", platform_id.max_bus_ratio(), "")
+        .field("ratio_unlocked", "ratio unlocked:", unlocked, "")
+        .level(if unlocked { Level::Good } else { Level::Bad })
+        .field("engineering_sample", "engineering sample:", es, ""))?;
+    if es || unlocked {
+        out.note("Ratio limits look unlocked, flex ratio changes have a chance of working")
+    } else {
+        out.note("Ratios are fused on this part, flex ratio changes won't do anything")
+    }
+}
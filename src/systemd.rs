@@ -0,0 +1,148 @@
+use std::env;
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// What the daemon tells systemd and gets from it when it runs in a unit:
+// readiness and watchdog pings over NOTIFY_SOCKET, the control socket from
+// arrctl.socket, and log lines straight into the journal. Outside a unit
+// none of the variables are there and all of it does nothing.
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+// Passed sockets start here, after stdin, stdout and stderr
+const LISTEN_FDS_START: RawFd = 3;
+
+// A path, or an abstract socket when it starts with @
+fn address(path: &str) -> io::Result<SocketAddr> {
+    match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(path),
+    }
+}
+
+pub fn notify_to(socket: &str, state: &str) -> io::Result<()> {
+    let sock = UnixDatagram::unbound()?;
+    sock.send_to_addr(state.as_bytes(), &address(socket)?)?;
+    Ok(())
+}
+
+// "READY=1", "WATCHDOG=1", "STATUS=..." and so on, one per line. False when
+// not started by systemd or it couldn't be told.
+pub fn notify(state: &str) -> bool {
+    match env::var("NOTIFY_SOCKET") {
+        Ok(socket) if !socket.is_empty() => notify_to(&socket, state).is_ok(),
+        _ => false,
+    }
+}
+
+// How often systemd wants a WATCHDOG=1, half its WatchdogSec so a slow
+// tick doesn't get the daemon killed
+pub fn watchdog(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse() != Ok(own_pid)) {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok().filter(|&usec| usec > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+pub fn watchdog_interval() -> Option<Duration> {
+    watchdog(env::var("WATCHDOG_USEC").ok().as_deref(), env::var("WATCHDOG_PID").ok().as_deref(), std::process::id())
+}
+
+// The descriptors systemd passed, if they're meant for this process
+pub fn listen_fd_range(pid: Option<&str>, fds: Option<&str>, own_pid: u32) -> std::ops::Range<RawFd> {
+    let count = match (pid.and_then(|p| p.parse::<u32>().ok()), fds.and_then(|n| n.parse::<RawFd>().ok())) {
+        (Some(pid), Some(count)) if pid == own_pid && count > 0 => count,
+        _ => 0,
+    };
+    LISTEN_FDS_START..LISTEN_FDS_START + count
+}
+
+// Only the first call gets them, they're owned from then on
+pub fn listen_fds() -> Vec<OwnedFd> {
+    static TAKEN: OnceLock<()> = OnceLock::new();
+    if TAKEN.set(()).is_err() {
+        return Vec::new();
+    }
+    let range = listen_fd_range(env::var("LISTEN_PID").ok().as_deref(), env::var("LISTEN_FDS").ok().as_deref(), std::process::id());
+    range
+        .map(|fd| {
+            // Hooks and sudo shouldn't inherit them
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            unsafe { OwnedFd::from_raw_fd(fd) }
+        })
+        .collect()
+}
+
+// The journal's native format: KEY=value lines, or for a value with a
+// newline in it the key, a newline, its length as 64 bits little endian and
+// the value
+pub fn journal_entry(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+    for (key, value) in fields {
+        entry.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+// A "<3>Something failed" line as systemd reads them off stderr, info
+// without a prefix
+pub fn priority(line: &str) -> (u8, &str) {
+    let bytes = line.as_bytes();
+    match bytes {
+        [b'<', level @ b'0'..=b'7', b'>', ..] => (level - b'0', &line[3..]),
+        _ => (6, line),
+    }
+}
+
+// Whether stderr is the journal, systemd says which stream in JOURNAL_STREAM
+fn stderr_is_journal() -> bool {
+    static JOURNAL: OnceLock<bool> = OnceLock::new();
+    *JOURNAL.get_or_init(|| {
+        let Some((dev, ino)) = env::var("JOURNAL_STREAM").ok().and_then(|s| {
+            let (dev, ino) = s.split_once(':')?;
+            Some((dev.parse::<u64>().ok()?, ino.parse::<u64>().ok()?))
+        }) else {
+            return false;
+        };
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        unsafe { libc::fstat(2, &mut stat) == 0 && stat.st_dev == dev && stat.st_ino == ino }
+    })
+}
+
+// What the daemon, and whatever it calls, logs with. Under systemd each line
+// becomes one journal entry with its priority, multi-line errors included,
+// otherwise it goes to stderr without the prefix.
+pub fn log(line: &str) {
+    let (level, message) = priority(line);
+    if stderr_is_journal() {
+        let level = level.to_string();
+        let entry = journal_entry(&[("MESSAGE", message), ("PRIORITY", &level), ("SYSLOG_IDENTIFIER", "arrctl")]);
+        let sent = UnixDatagram::unbound().and_then(|sock| sock.send_to(&entry, JOURNAL_SOCKET));
+        // journald reads the prefix off the stream too
+        if sent.is_err() {
+            eprintln!("{}", line);
+        }
+        return;
+    }
+    eprintln!("{}", message);
+}
+
+// eprintln! for the daemon, see log
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::systemd::log(&format!($($arg)*))
+    };
+}
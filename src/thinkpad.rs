@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "thinkpad")]
+use anyhow::{bail, Context, Result};
+#[cfg(feature = "thinkpad")]
+use std::fs;
+#[cfg(feature = "thinkpad")]
+use std::path::{Path, PathBuf};
+
+pub const FAN: &str = "/proc/acpi/ibm/fan";
+
+// What thinkpad_acpi takes after "level": 0 is off and 7 the fastest the
+// EC picks by itself, full-speed runs it past that
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawLevel", into = "RawLevel")]
+pub enum FanLevel {
+    Level(u8),
+    Auto,
+    FullSpeed,
+}
+
+// A number or a name in profiles.toml
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawLevel {
+    Level(u64),
+    Name(String),
+}
+
+impl TryFrom<RawLevel> for FanLevel {
+    type Error = String;
+
+    fn try_from(raw: RawLevel) -> Result<Self, Self::Error> {
+        match raw {
+            RawLevel::Level(level) => level.to_string().parse(),
+            RawLevel::Name(name) => name.parse(),
+        }
+    }
+}
+
+impl From<FanLevel> for RawLevel {
+    fn from(level: FanLevel) -> Self {
+        match level {
+            FanLevel::Level(level) => RawLevel::Level(level as u64),
+            named => RawLevel::Name(named.to_string()),
+        }
+    }
+}
+
+impl FromStr for FanLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(FanLevel::Auto),
+            "full-speed" => Ok(FanLevel::FullSpeed),
+            n => match n.parse() {
+                Ok(level) if level <= 7 => Ok(FanLevel::Level(level)),
+                _ => Err("fan_level must be 0 to 7, \"auto\" or \"full-speed\"".to_string()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for FanLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FanLevel::Level(level) => write!(f, "{}", level),
+            FanLevel::Auto => write!(f, "auto"),
+            FanLevel::FullSpeed => write!(f, "full-speed"),
+        }
+    }
+}
+
+// The procfs fan interface, only writable with thinkpad_acpi.fan_control=1
+#[cfg(feature = "thinkpad")]
+pub struct Fan {
+    path: PathBuf,
+}
+
+#[cfg(feature = "thinkpad")]
+impl Fan {
+    pub fn find(path: &Path) -> Result<Fan> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}, is this a ThinkPad with thinkpad_acpi loaded?", path.display()))?;
+        // The commands are only listed when writes are allowed
+        if !text.lines().any(|line| line.starts_with("commands:") && line.contains("level")) {
+            bail!("thinkpad_acpi doesn't allow fan control, load it with fan_control=1");
+        }
+        Ok(Fan { path: path.to_path_buf() })
+    }
+
+    pub fn level(&self) -> Result<String> {
+        let text = fs::read_to_string(&self.path).with_context(|| format!("Failed to read {}", self.path.display()))?;
+        let line = text.lines().find_map(|line| line.strip_prefix("level:"));
+        Ok(line.context("No fan level in thinkpad_acpi's fan file")?.trim().to_string())
+    }
+
+    pub fn set_level(&self, level: FanLevel) -> Result<()> {
+        fs::write(&self.path, format!("level {}", level)).with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
@@ -0,0 +1,117 @@
+use crate::apply;
+use crate::atomic;
+use crate::cpu;
+use crate::error::{Error, ErrorKind};
+use crate::journal::Journal;
+use crate::msr::{MsrAccess, RegSpec};
+use crate::profile::Profile;
+use crate::state::Saved;
+use crate::units;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_PATH: &str = "/var/lib/arrctl/try.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Setting {
+    Tdp,
+    Tdc,
+    Turbo,
+    ClockModulation,
+    // Worked out from the CPU, see freq::for_cpu
+    MaxFreq,
+}
+
+pub fn profile(setting: Setting, value: &str) -> Result<Profile> {
+    let mut profile = Profile::default();
+    match setting {
+        Setting::Tdp => profile.tdp = Some(units::parse_watts(value).map_err(|e| Error::new(ErrorKind::Validation, e))?),
+        Setting::Tdc => profile.tdc = Some(units::parse_amps(value).map_err(|e| Error::new(ErrorKind::Validation, e))?),
+        Setting::ClockModulation => profile.clock_modulation = Some(units::parse_duty_cycle(value).map_err(|e| Error::new(ErrorKind::Validation, e))?),
+        Setting::MaxFreq => bail!(Error::new(ErrorKind::Validation, "max-freq depends on the CPU's ratios, it has to be planned with the registers at hand")),
+        Setting::Turbo => {
+            profile.turbo = Some(match value {
+                "on" | "true" => true,
+                "off" | "false" => false,
+                _ => bail!(Error::new(ErrorKind::Validation, format!("Expected on or off, got {:?}", value))),
+            })
+        }
+    }
+    Ok(profile)
+}
+
+// A set that goes back on its own unless confirmed before the deadline
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trial {
+    pub boot_id: String,
+    // Unix time
+    pub deadline: u64,
+    pub old: Vec<Saved>,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// A trial from before a reboot is moot, the registers reset with it
+pub fn load(path: &Path, boot_id: &str) -> Result<Option<Trial>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let value = serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let trial: Trial = serde_json::from_value(atomic::unseal(path, value)?).with_context(|| format!("Bad try in {}", path.display()))?;
+    Ok(Some(trial).filter(|t| t.boot_id == boot_id))
+}
+
+pub fn start(msr: &dyn MsrAccess, journal: &Journal, path: &Path, profile: &Profile, window: Duration, boot_id: String) -> Result<Trial> {
+    if load(path, &boot_id)?.is_some() {
+        bail!(Error::new(ErrorKind::Validation, "Another try is still waiting, confirm it or let it revert first"));
+    }
+    let writes = apply::register_writes(msr, profile, &cpu::layout(), true)?;
+    if writes.is_empty() {
+        bail!(Error::new(ErrorKind::Validation, "Nothing to try"));
+    }
+    let mut old = Vec::new();
+    for (spec, _) in &writes {
+        old.push(Saved { cpu: spec.cpu, register: spec.reg, value: msr.read(spec.reg, spec.cpu)? });
+    }
+    let trial = Trial { boot_id, deadline: now() + window.as_secs(), old };
+
+    // Saved before the set, a crash in between leaves at worst a revert to
+    // the values that are already there
+    atomic::write(path, atomic::seal(serde_json::to_value(&trial)?).to_string().as_bytes())?;
+    if let Err(e) = apply::apply(msr, journal, profile, true) {
+        let _ = fs::remove_file(path);
+        return Err(e);
+    }
+    Ok(trial)
+}
+
+pub fn confirm(path: &Path, boot_id: &str) -> Result<Trial> {
+    let Some(trial) = load(path, boot_id)? else {
+        bail!(Error::new(ErrorKind::Validation, "No try is waiting to be confirmed"));
+    };
+    fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(trial)
+}
+
+// Puts the old values back once the deadline has passed. Returns what was
+// restored, or nothing if the trial got confirmed or isn't due yet.
+pub fn expire(msr: &dyn MsrAccess, journal: &Journal, path: &Path, boot_id: &str, now: u64) -> Result<Option<Vec<Saved>>> {
+    let Some(trial) = load(path, boot_id)? else {
+        return Ok(None);
+    };
+    if now < trial.deadline {
+        return Ok(None);
+    }
+    let writes: Vec<(RegSpec, u64)> = trial.old.iter().map(|s| (RegSpec { reg: s.register, cpu: s.cpu }, s.value)).collect();
+    journal.apply(msr, &writes)?;
+    fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(Some(trial.old))
+}
@@ -0,0 +1,181 @@
+use crate::apply;
+use crate::bench::{self, Load, Running};
+use crate::error::{Error, ErrorKind};
+use crate::journal::Journal;
+use crate::l10n;
+use crate::monitor::Sampler;
+use crate::msr::{MsrAccess, RegSpec};
+use crate::output::{Level, OutputSink, Record, Value};
+use crate::profile::{self, Profile};
+use crate::regs::msr_turbo_limits;
+use crate::soak;
+use crate::{cpu, signals};
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+// How one TDP did under the load
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub tdp: u64,
+    pub mhz: f32,
+    pub package_watts: f32,
+    pub max_celsius: Option<u64>,
+}
+
+// Walks the TDP down a step at a time until the clock gives or the floor
+// is reached
+#[derive(Clone, Debug, PartialEq)]
+pub struct Search {
+    pub min_mhz: f32,
+    pub step: u64,
+    pub floor: u64,
+    pub steps: Vec<Step>,
+}
+
+impl Search {
+    pub fn new(min_mhz: f32, step: u64, floor: u64) -> Self {
+        Search { min_mhz, step, floor, steps: Vec::new() }
+    }
+
+    // The TDP to try after this one, None once it's done
+    pub fn next(&mut self, step: Step) -> Option<u64> {
+        self.steps.push(step);
+        if step.mhz < self.min_mhz {
+            return None;
+        }
+        let next = step.tdp.saturating_sub(self.step);
+        (next >= self.floor && next < step.tdp).then_some(next)
+    }
+
+    // The lowest TDP that still held the clock
+    pub fn knee(&self) -> Option<u64> {
+        self.steps.iter().filter(|s| s.mhz >= self.min_mhz).map(|s| s.tdp).min()
+    }
+
+    // Whether it never gave, all the way down
+    pub fn reached_floor(&self) -> bool {
+        self.steps.last().is_some_and(|s| s.mhz >= self.min_mhz && s.tdp.saturating_sub(self.step) < self.floor)
+    }
+}
+
+pub fn step_record(step: &Step, min_mhz: f32) -> Record {
+    let mut record = Record::new("tune_step")
+        .title(format!("{} W", step.tdp))
+        .field("tdp_watts", l10n::text("tune-tdp"), step.tdp, "W")
+        .field("mhz", l10n::text("tune-mhz"), Value::Fixed(step.mhz as f64, 0), "MHz")
+        .level(if step.mhz >= min_mhz { Level::Good } else { Level::Bad })
+        .field("package_watts", l10n::text("tune-package"), Value::Fixed(step.package_watts as f64, 1), l10n::text("unit-estimated-watts"));
+    if let Some(temp) = step.max_celsius {
+        record = record.field("max_celsius", l10n::text("tune-max-temp"), temp, "celsius");
+    }
+    record
+}
+
+pub fn record(search: &Search, knee: u64, load: &Load) -> Record {
+    Record::new("tune")
+        .title(l10n::text("tune-title"))
+        .field("recommended_watts", l10n::text("tune-recommended"), knee, "W")
+        .level(Level::Good)
+        .field("min_mhz", l10n::text("tune-holding"), Value::Fixed(search.min_mhz as f64, 0), "MHz")
+        .field("load", l10n::text("tune-load"), load.to_string(), "")
+        .hidden("steps", search.steps.len() as u64)
+}
+
+pub struct Tuning {
+    pub load: Load,
+    pub min_mhz: u64,
+    // None starts from the TDP that's set
+    pub from: Option<u64>,
+    pub floor: u64,
+    pub step: u64,
+    // The first half of each lets the limit settle and isn't counted
+    pub step_duration: Duration,
+    // Profile to store the result as, and the file it goes in
+    pub save: Option<String>,
+    pub config: PathBuf,
+}
+
+// None when a signal cut it short
+fn measure(msr: &dyn MsrAccess, tdp: u64, duration: Duration, running: &mut Running) -> Result<Option<Step>> {
+    let mut sampler = Sampler::new(msr)?;
+    let start = Instant::now();
+    let (mut mhz, mut watts, mut samples) = (0.0, 0.0, 0);
+    let mut max_celsius = None;
+    while start.elapsed() < duration {
+        if !signals::sleep(SAMPLE_INTERVAL) {
+            return Ok(None);
+        }
+        if let Some(status) = running.exited_early()? {
+            bail!(Error::new(ErrorKind::Failure, format!("The load command exited with {}, tuning can't go on without it", status)));
+        }
+        let sample = sampler.sample(msr)?;
+        if start.elapsed() < duration / 2 {
+            continue;
+        }
+        mhz += soak::busy_mhz(&sample);
+        watts += sample.package_watts;
+        samples += 1;
+        max_celsius = max_celsius.max(sample.cpus.iter().filter_map(|c| c.celsius).max());
+    }
+    let samples = samples.max(1) as f32;
+    Ok(Some(Step { tdp, mhz: mhz / samples, package_watts: watts / samples, max_celsius }))
+}
+
+fn search(out: &mut dyn OutputSink, msr: &dyn MsrAccess, journal: &Journal, tuning: &Tuning, from: u64, running: &mut Running) -> Result<(Search, bool)> {
+    let mut search = Search::new(tuning.min_mhz as f32, tuning.step, tuning.floor);
+    let mut tdp = Some(from);
+    while let Some(watts) = tdp {
+        apply::apply(msr, journal, &Profile { tdp: Some(watts), ..Default::default() }, true)?;
+        let Some(step) = measure(msr, watts, tuning.step_duration, running)? else {
+            return Ok((search, true));
+        };
+        out.record(&step_record(&step, search.min_mhz))?;
+        tdp = search.next(step);
+    }
+    Ok((search, false))
+}
+
+// Puts the limits back as they were however it ends, the result is only a
+// recommendation until it's saved and applied
+pub fn run(out: &mut dyn OutputSink, msr: &dyn MsrAccess, journal: &Journal, tuning: &Tuning) -> Result<()> {
+    let from = match tuning.from {
+        Some(watts) => watts,
+        None => msr_turbo_limits(msr)?.tdp_watts().whole(),
+    };
+    if tuning.step == 0 || from < tuning.floor {
+        bail!(Error::new(ErrorKind::Validation, format!("Nothing to tune from {} W down to {} W in steps of {} W", from, tuning.floor, tuning.step)));
+    }
+    signals::install()?;
+    let writes = apply::register_writes(msr, &Profile { tdp: Some(from), ..Default::default() }, &cpu::layout(), true)?;
+    let before = writes.iter().map(|&(spec, _)| Ok((spec, msr.read(spec.reg, spec.cpu)?))).collect::<Result<Vec<(RegSpec, u64)>>>()?;
+
+    let mut running = bench::start(&tuning.load, &cpu::online_cpus())?;
+    let result = search(out, msr, journal, tuning, from, &mut running);
+    running.stop();
+    let restored = journal.apply(msr, &before).context("Failed to put the TDP back");
+    let (search, interrupted) = result?;
+    restored?;
+
+    if interrupted {
+        out.note(l10n::text("tune-interrupted"))?;
+    }
+    let Some(knee) = search.knee() else {
+        out.finish()?;
+        if search.steps.is_empty() {
+            bail!(Error::new(ErrorKind::Failure, "Stopped before the first step was over"));
+        }
+        bail!(Error::new(ErrorKind::Failure, format!("Under {} MHz already at {} W, there's nothing to give up", tuning.min_mhz, from)));
+    };
+    out.record(&record(&search, knee, &tuning.load))?;
+    if search.reached_floor() {
+        out.note(&l10n::format("tune-floor", &[("watts", &tuning.floor.to_string())]))?;
+    }
+    if let Some(name) = &tuning.save {
+        profile::save(&tuning.config, name, &Profile { tdp: Some(knee), ..Default::default() })?;
+        out.note(&l10n::format("tune-saved", &[("profile", name.as_str()), ("path", &tuning.config.display().to_string())]))?;
+    }
+    out.finish()
+}
@@ -0,0 +1,101 @@
+use crate::cpu;
+use crate::l10n;
+use crate::monitor::Sample;
+use crate::msr::MsrAccess;
+use crate::output::{Level, Record, Value};
+use crate::regs::msr_platform_info;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+// Below this a CPU was mostly idle, its ratio says nothing about limits
+const BUSY: f32 = 0.5;
+
+// These parts have no documented turbo time window, so this watches for
+// it: the time busy CPUs spent at each ratio, and how long turbo lasted
+// before it dropped back to base or under with the load still there.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Residency {
+    pub base_ratio: u64,
+    pub bclk_mhz: f32,
+    // CPU seconds at each ratio, while busy
+    pub seconds: BTreeMap<u64, f64>,
+    // Turbo spells a limit ended, in seconds
+    pub bound: Vec<f64>,
+    // How long each CPU in turbo right now has been
+    running: BTreeMap<u16, f64>,
+}
+
+impl Residency {
+    pub fn new(base_ratio: u64, bclk_mhz: f32) -> Self {
+        Residency { base_ratio, bclk_mhz, ..Default::default() }
+    }
+
+    pub fn for_cpu(msr: &dyn MsrAccess) -> Result<Self> {
+        Ok(Residency::new(msr_platform_info(msr)?.max_non_turbo_ratio(), cpu::BCLK_MHZ))
+    }
+
+    // seconds is how long the sample covered
+    pub fn update(&mut self, sample: &Sample, seconds: f64) {
+        for c in &sample.cpus {
+            // The load going away ends a spell without a limit to blame
+            if c.activity.active < BUSY {
+                self.running.remove(&c.cpu);
+                continue;
+            }
+            let ratio = (c.activity.effective_mhz / self.bclk_mhz).round() as u64;
+            *self.seconds.entry(ratio).or_default() += seconds;
+            if ratio > self.base_ratio {
+                *self.running.entry(c.cpu).or_default() += seconds;
+            } else if let Some(spell) = self.running.remove(&c.cpu) {
+                self.bound.push(spell);
+            }
+        }
+    }
+
+    // Still in turbo at the end, for as long as the longest has been
+    pub fn ongoing(&self) -> Option<f64> {
+        self.running.values().copied().reduce(f64::max)
+    }
+
+    pub fn median_bound(&self) -> Option<f64> {
+        let mut spells = self.bound.clone();
+        spells.sort_by(f64::total_cmp);
+        spells.get(spells.len() / 2).copied()
+    }
+
+    // One record per ratio seen, then the turbo spells if there was turbo
+    pub fn records(&self) -> Vec<Record> {
+        let total: f64 = self.seconds.values().sum();
+        let mut records: Vec<Record> = self
+            .seconds
+            .iter()
+            .map(|(&ratio, &seconds)| {
+                let mhz = ratio as f64 * self.bclk_mhz as f64;
+                Record::new("turbo_residency")
+                    .title(format!("{:.0} MHz", mhz))
+                    .hidden("ratio", ratio)
+                    .hidden("turbo", ratio > self.base_ratio)
+                    .field("seconds", l10n::text("turbo-residency-time"), Value::Fixed(seconds, 1), "s")
+                    .field("percent", "", Value::Fixed(seconds / total.max(f64::EPSILON) * 100.0, 0), l10n::text("unit-busy-time"))
+            })
+            .collect();
+        let turbo: f64 = self.seconds.iter().filter(|(&ratio, _)| ratio > self.base_ratio).map(|(_, &s)| s).sum();
+        if turbo == 0.0 {
+            return records;
+        }
+        let mut record = Record::new("turbo_duration")
+            .title(l10n::text("turbo-duration-title"))
+            .field("bound_spells", l10n::text("turbo-duration-spells"), self.bound.len() as u64, l10n::text("unit-times"));
+        if let Some(longest) = self.bound.iter().copied().reduce(f64::max) {
+            record = record
+                .field("median_seconds", l10n::text("turbo-duration-median"), Value::Fixed(self.median_bound().unwrap_or(longest), 1), "s")
+                .field("longest_seconds", l10n::text("turbo-duration-longest"), Value::Fixed(longest, 1), "s")
+                .level(Level::Warn);
+        }
+        if let Some(ongoing) = self.ongoing() {
+            record = record.field("ongoing_seconds", l10n::text("turbo-duration-ongoing"), Value::Fixed(ongoing, 1), "s");
+        }
+        records.push(record);
+        records
+    }
+}
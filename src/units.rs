@@ -0,0 +1,70 @@
+use crate::regs::DutyCycle;
+
+// Values on the command line, with or without a unit. Bare numbers keep
+// meaning what they always did, a unit that doesn't fit is an error rather
+// than a guess, since these go straight into registers.
+
+// The number and the unit after it, lowercased
+fn split(s: &str) -> Option<(f64, String)> {
+    let s = s.trim();
+    let end = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let number = s[..end].parse::<f64>().ok().filter(|n| n.is_finite())?;
+    Some((number, s[end..].trim().to_ascii_lowercase()))
+}
+
+// Whole units only: 25500mW isn't a TDP the register takes from arrctl
+fn whole(value: f64, unit: &str, s: &str) -> Result<u64, String> {
+    if value.fract() != 0.0 {
+        return Err(format!("{:?} is {} {}, only whole {} can be set", s, value, unit, unit));
+    }
+    Ok(value as u64)
+}
+
+// Each unit is a factor over one, as a multiplier and a divisor so 25000mW
+// comes out as exactly 25
+fn scaled(s: &str, units: &[(&str, f64, f64)], example: &str) -> Result<f64, String> {
+    let expected = || format!("Expected {}, got {:?}", example, s);
+    let (number, unit) = split(s).ok_or_else(expected)?;
+    let (_, times, over) = units.iter().find(|(name, ..)| *name == unit).ok_or_else(expected)?;
+    Ok(number * times / over)
+}
+
+pub fn parse_watts(s: &str) -> Result<u64, String> {
+    let watts = scaled(s, &[("", 1.0, 1.0), ("w", 1.0, 1.0), ("mw", 1.0, 1000.0)], "watts like 25, 25W or 25000mW")?;
+    whole(watts, "W", s)
+}
+
+pub fn parse_amps(s: &str) -> Result<u64, String> {
+    let amps = scaled(s, &[("", 1.0, 1.0), ("a", 1.0, 1.0), ("ma", 1.0, 1000.0)], "amps like 30, 30A or 30000mA")?;
+    whole(amps, "A", s)
+}
+
+// In eighths, which is what the register and the profiles take
+pub fn parse_duty_cycle(s: &str) -> Result<u64, String> {
+    s.parse::<DutyCycle>().map(DutyCycle::code)
+}
+
+// Bare numbers are milliseconds, like --interval always took
+pub fn parse_millis(s: &str) -> Result<u64, String> {
+    let ms = scaled(s, &[("", 1.0, 1.0), ("ms", 1.0, 1.0), ("s", 1000.0, 1.0)], "milliseconds like 500, 500ms or 2s")?;
+    whole(ms, "ms", s)
+}
+
+// Bare numbers are MHz
+pub fn parse_mhz(s: &str) -> Result<f64, String> {
+    let mhz = scaled(s, &[("", 1.0, 1.0), ("mhz", 1.0, 1.0), ("ghz", 1000.0, 1.0)], "a frequency like 2660, 2660MHz or 2.66GHz")?;
+    if mhz <= 0.0 {
+        return Err(format!("Expected a frequency above 0, got {:?}", s));
+    }
+    Ok(mhz)
+}
+
+// Soak's --min-sustained-mhz and the like, rounded since they're only
+// compared against
+pub fn parse_whole_mhz(s: &str) -> Result<u64, String> {
+    parse_mhz(s).map(|mhz| mhz.round() as u64)
+}
+
+pub fn nearest_ratio(mhz: f64, bclk_mhz: f64) -> u64 {
+    (mhz / bclk_mhz).round().max(1.0) as u64
+}
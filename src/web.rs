@@ -0,0 +1,251 @@
+use anyhow::{bail, Result};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// Just enough HTTP/1.1 and RFC 6455 websockets for the daemon's --listen,
+// browsers are the only clients it's meant for
+
+// Request heads past this are someone up to something
+pub const MAX_HEAD: usize = 8192;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    // Header names are case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    // Connection can list more than upgrade, Firefox sends "keep-alive, Upgrade"
+    pub fn wants_websocket(&self) -> bool {
+        self.header("upgrade").is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+            && self.header("connection").is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case("upgrade")))
+    }
+}
+
+// Everything up to the blank line, None for anything that isn't HTTP
+pub fn parse_request(head: &str) -> Option<Request> {
+    let mut lines = head.split("\r\n");
+    let mut start = lines.next()?.split(' ');
+    let (method, path, version) = (start.next()?, start.next()?, start.next()?);
+    if !version.starts_with("HTTP/1.") {
+        return None;
+    }
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Request { method: method.to_string(), path: path.to_string(), headers })
+}
+
+pub async fn read_head<R: AsyncRead + Unpin>(reader: &mut R) -> Result<String> {
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD {
+            bail!("Request head over {} bytes", MAX_HEAD);
+        }
+        if reader.read(&mut byte).await? == 0 {
+            bail!("Connection closed before the request was over");
+        }
+        head.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+// %XX escapes in a path segment, None if they don't make UTF-8
+pub fn percent_decode(text: &str) -> Option<String> {
+    let mut out = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                out.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            byte => out.push(byte),
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+// The status page at /, all of it
+pub const UI: &str = include_str!("ui.html");
+
+// For anything that changes something: the request has to come from a page
+// the daemon served, and by address or as localhost, so another site can't
+// post to it or resolve its own name to 127.0.0.1 to get around that
+pub fn same_origin(request: &Request) -> bool {
+    let (Some(host), Some(origin)) = (request.header("host"), request.header("origin")) else {
+        return false;
+    };
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !port.ends_with(']') => name,
+        _ => host,
+    };
+    let literal = name == "localhost" || name.trim_matches(['[', ']']).parse::<std::net::IpAddr>().is_ok();
+    literal && origin == format!("http://{}", host)
+}
+
+// For what only reads: browsers send an Origin with every websocket and
+// tools like websocat don't, so a request with one has to come from a page
+// the daemon served and one without isn't a browser. Websockets aren't
+// held to the same origin otherwise, any page could read the stream.
+pub fn not_cross_site(request: &Request) -> bool {
+    request.header("origin").is_none() || same_origin(request)
+}
+
+pub fn response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let mut text = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    text.extend_from_slice(body);
+    text
+}
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// Sec-WebSocket-Accept, proving the server read the key
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+pub fn upgrade(request: &Request) -> Option<Vec<u8>> {
+    let key = request.header("sec-websocket-key")?;
+    let text = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    );
+    Some(text.into_bytes())
+}
+
+pub const TEXT: u8 = 0x1;
+pub const CLOSE: u8 = 0x8;
+pub const PING: u8 = 0x9;
+pub const PONG: u8 = 0xa;
+
+// One unfragmented frame. Servers don't mask theirs.
+pub fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => out.push(len as u8),
+        len @ 126..=0xffff => {
+            out.push(126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            out.push(127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(payload);
+    out
+}
+
+// Browsers only send close, pong and the odd ping, nothing that needs
+// more than this
+pub const MAX_CLIENT_FRAME: u64 = 4096;
+
+// The opcode and unmasked payload, None once the client is gone
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<(u8, Vec<u8>)>> {
+    let mut start = [0; 2];
+    if let Err(e) = reader.read_exact(&mut start).await {
+        return match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => Ok(None),
+            _ => Err(e.into()),
+        };
+    }
+    let opcode = start[0] & 0x0f;
+    let masked = start[1] & 0x80 != 0;
+    let len = match start[1] & 0x7f {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if !masked {
+        bail!("Unmasked frame from a client");
+    }
+    if len > MAX_CLIENT_FRAME {
+        bail!("Client frame of {} bytes", len);
+    }
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Some((opcode, payload)))
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// FIPS 180-4 again, the handshake is the one place SHA-1 is still asked for
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (state, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(v);
+        }
+    }
+    let mut out = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
@@ -0,0 +1,111 @@
+use crate::l10n;
+use crate::output::{Level, Record};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CLASS: &str = "/sys/class/thermal";
+
+// Celsius under a passive or hot trip that's worth a warning while monitoring
+const NEAR_TRIP: u64 = 5;
+
+// What the firmware does at a trip point: active spins fans up, passive
+// has the kernel throttle, hot and critical suspend or shut down
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trip {
+    pub kind: String,
+    pub celsius: u64,
+}
+
+impl Trip {
+    // Everything but fans costs performance
+    pub fn throttles(&self) -> bool {
+        self.kind != "active"
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Zone {
+    // thermal_zone0 and so on
+    pub name: String,
+    // acpitz for ACPI zones, x86_pkg_temp and friends are drivers
+    pub kind: String,
+    pub trips: Vec<Trip>,
+    dir: PathBuf,
+}
+
+fn millidegrees(path: &Path) -> Option<u64> {
+    let value: i64 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    // Negative for sensors that aren't wired up
+    u64::try_from(value).ok().map(|m| (m + 500) / 1000)
+}
+
+// Every zone, none if the kernel has no thermal class
+pub fn zones(class: &Path) -> Vec<Zone> {
+    let Ok(entries) = fs::read_dir(class) else {
+        return Vec::new();
+    };
+    let mut zones: Vec<(u32, Zone)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let index: u32 = name.strip_prefix("thermal_zone")?.parse().ok()?;
+            let dir = entry.path();
+            let kind = fs::read_to_string(dir.join("type")).ok()?.trim().to_string();
+            let mut trips = Vec::new();
+            for trip in 0.. {
+                let Ok(trip_kind) = fs::read_to_string(dir.join(format!("trip_point_{}_type", trip))) else {
+                    break;
+                };
+                if let Some(celsius) = millidegrees(&dir.join(format!("trip_point_{}_temp", trip))) {
+                    trips.push(Trip { kind: trip_kind.trim().to_string(), celsius });
+                }
+            }
+            Some((index, Zone { name, kind, trips, dir }))
+        })
+        .collect();
+    // Numerically, thermal_zone10 goes after thermal_zone9
+    zones.sort_by_key(|(index, _)| *index);
+    zones.into_iter().map(|(_, zone)| zone).collect()
+}
+
+impl Zone {
+    pub fn read(&self) -> Option<u64> {
+        millidegrees(&self.dir.join("temp"))
+    }
+
+    // The lowest trip that throttles and hasn't been reached
+    pub fn next_trip(&self, celsius: u64) -> Option<&Trip> {
+        self.trips.iter().filter(|t| t.throttles() && t.celsius > celsius).min_by_key(|t| t.celsius)
+    }
+}
+
+// Printed once at the start: which trips go off before the CPU gets to
+// throttle itself at TJmax
+pub fn trip_record(zone: &Zone, trip: &Trip, tjmax: u64) -> Record {
+    let early = trip.throttles() && trip.celsius < tjmax;
+    Record::new("trip")
+        .title(l10n::format("zone-trip", &[("zone", &zone.name), ("type", &zone.kind), ("trip", &trip.kind)]))
+        .hidden("zone", zone.name.as_str())
+        .hidden("zone_type", zone.kind.as_str())
+        .hidden("trip", trip.kind.as_str())
+        .field("celsius", l10n::text("zone-at"), trip.celsius, "celsius")
+        .field("before_tjmax", l10n::text("zone-before-tjmax"), early, "")
+        .level(if early { Level::Warn } else { Level::Plain })
+}
+
+pub fn reading_record(zone: &Zone, celsius: u64) -> Record {
+    let mut record = Record::new("zone")
+        .title(format!("{} {}", zone.name, zone.kind))
+        .hidden("zone", zone.name.as_str())
+        .hidden("zone_type", zone.kind.as_str())
+        .field("celsius", "", celsius, "celsius");
+    if let Some(trip) = zone.next_trip(celsius) {
+        record = record
+            .field("next_trip", l10n::text("zone-next-trip"), trip.kind.as_str(), "")
+            .field("to_trip", l10n::text("zone-to-trip"), trip.celsius - celsius, "celsius");
+        if trip.celsius - celsius <= NEAR_TRIP {
+            record = record.level(Level::Warn);
+        }
+    }
+    record
+}
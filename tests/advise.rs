@@ -0,0 +1,59 @@
+use arrctl::advise::{advise, tdc_advice, Observation, PeakDraw};
+use arrctl::sku;
+
+fn loaded() -> Observation {
+    Observation {
+        seconds: 10.0,
+        tdp_watts: 25.0,
+        tdc_amps: 48.0,
+        tjmax: 105,
+        sustained_mhz: 2400.0,
+        busy: 1.0,
+        max_turbo_mhz: 2933.0,
+        avg_package_watts: 24.0,
+        max_package_watts: 25.0,
+        max_celsius: Some(75),
+        throttled: false,
+        externally_throttled: false,
+        peak: PeakDraw { amps: 30.0, amps_at_tjmax: 38.0, volts: 1.2 },
+        cpus: Vec::new(),
+        turbo: Default::default(),
+    }
+}
+
+#[test]
+fn tdp_limited_with_headroom() {
+    let advice = advise(&loaded(), sku::lookup("Intel(R) Core(TM) i5 CPU M 520 @ 2.40GHz"));
+    assert_eq!(advice.len(), 2);
+    assert_eq!(advice[0].suggested_tdp, Some(30));
+    assert!(advice[0].text.contains("raising TDP to 30 W"), "{}", advice[0].text);
+    assert!(advice[0].gain_mhz.unwrap() > 0);
+}
+
+#[test]
+fn thermal_limited_suggests_stock() {
+    let obs = Observation { tdp_watts: 40.0, max_celsius: Some(103), ..loaded() };
+    let advice = advise(&obs, sku::lookup("Intel(R) Core(TM) i5 CPU M 520 @ 2.40GHz"));
+    assert!(advice[0].text.starts_with("Thermal limited"));
+    assert_eq!(advice[0].suggested_tdp, Some(35));
+}
+
+#[test]
+fn idle_gives_no_suggestion() {
+    let obs = Observation { busy: 0.1, ..loaded() };
+    let advice = advise(&obs, None);
+    assert_eq!(advice[0].suggested_tdp, None);
+}
+
+#[test]
+fn tdc_covers_hot_draw() {
+    let stock = sku::lookup("Intel(R) Core(TM) i5 CPU M 520 @ 2.40GHz");
+    let advice = tdc_advice(&loaded().peak, 30.0, stock).unwrap();
+    assert_eq!(advice.suggested_tdc, Some(42));
+    assert!(advice.text.contains("limits turbo"), "{}", advice.text);
+
+    // Never past the rated current
+    let hot = PeakDraw { amps_at_tjmax: 60.0, ..loaded().peak };
+    assert_eq!(tdc_advice(&hot, 48.0, stock).unwrap().suggested_tdc, Some(48));
+    assert_eq!(tdc_advice(&PeakDraw::default(), 48.0, stock), None);
+}
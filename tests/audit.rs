@@ -0,0 +1,86 @@
+use arrctl::audit;
+use arrctl::error::{self, ErrorKind};
+use arrctl::journal::Journal;
+use arrctl::msr::{MockMsr, RegSpec};
+use arrctl::regs::MSR_TURBO_LIMITS;
+use std::{env, fs};
+
+#[test]
+fn hashes_like_sha256() {
+    assert_eq!(audit::sha256(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(audit::sha256(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    // Padding spills into a second block
+    assert_eq!(
+        audit::sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    assert_eq!(audit::sha256(&vec![b'a'; 1_000_000]), "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+}
+
+#[test]
+fn goes_on_from_the_head() {
+    let dir = env::temp_dir().join(format!("arrctl-audit-head-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let log = dir.join("audit.log");
+    let write = [(RegSpec { reg: MSR_TURBO_LIMITS, cpu: 0 }, 0x1, 0x2)];
+    audit::append(&log, audit::Caller { uid: 0, claimed: Some(1000) }, &write).unwrap();
+    let head = fs::read_to_string(audit::head_path(&log)).unwrap();
+    audit::append(&log, audit::Caller::new(0), &write).unwrap();
+    assert!(fs::read_to_string(&log).unwrap().lines().next().unwrap().contains(" uid=0 claimed_uid=1000 cpu=0 "));
+
+    // As if it crashed before the head caught up, or the head went missing
+    fs::write(audit::head_path(&log), head).unwrap();
+    audit::append(&log, audit::Caller::new(0), &write).unwrap();
+    fs::remove_file(audit::head_path(&log)).unwrap();
+    audit::append(&log, audit::Caller::new(0), &write).unwrap();
+    assert_eq!(audit::verify(&log).unwrap().entries, 4);
+
+    // Cut off, the next write mustn't make it look whole again
+    let text = fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    fs::write(&log, lines[..3].join("\n") + "\n").unwrap();
+    let err = audit::append(&log, audit::Caller::new(0), &write).unwrap_err();
+    assert!(err.to_string().contains("doesn't reach entry 4"), "{}", err);
+    fs::write(&log, lines[..3].join("\n") + "\n" + lines[3].rsplit_once(' ').unwrap().0 + " " + &"0".repeat(64) + "\n").unwrap();
+    assert!(audit::append(&log, audit::Caller::new(0), &write).is_err());
+    fs::write(&log, "").unwrap();
+    assert!(audit::append(&log, audit::Caller::new(0), &write).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn chains_writes_and_catches_tampering() {
+    let dir = env::temp_dir().join(format!("arrctl-audit-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let log = dir.join("audit.log");
+    let journal = Journal::new(dir.join("journal")).audited(&log).for_uid(1000);
+    let msr = MockMsr::from_dump("0 0x1ac 0x1\n1 0x1ac 0x1").unwrap();
+    let writes = [0, 1].map(|cpu| (RegSpec { reg: MSR_TURBO_LIMITS, cpu }, 0x2));
+    journal.apply(&msr, &writes).unwrap();
+    journal.clone().for_uid(0).apply(&msr, &writes[..1]).unwrap();
+
+    let verified = audit::verify(&log).unwrap();
+    assert_eq!(verified.entries, 3);
+    let text = fs::read_to_string(&log).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].contains(" uid=1000 cpu=0 reg=0x1ac old=0x0000000000000001 new=0x0000000000000002 "), "{}", lines[0]);
+    assert!(lines[2].contains(" uid=0 cpu=0 reg=0x1ac old=0x0000000000000002 "), "{}", lines[2]);
+    assert!(lines[2].ends_with(&verified.head));
+
+    // Someone making it look like a smaller change
+    fs::write(&log, text.replacen("new=0x0000000000000002", "new=0x0000000000000001", 1)).unwrap();
+    let err = audit::verify(&log).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+    assert!(err.to_string().contains("audit.log:1: hash doesn't match"), "{}", err);
+
+    // Dropping a line in the middle
+    fs::write(&log, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    assert!(audit::verify(&log).unwrap_err().to_string().contains("audit.log:2: expected entry 1"));
+
+    // Cutting off the last one only shows against the head
+    fs::write(&log, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+    assert!(audit::verify(&log).unwrap_err().to_string().contains("was truncated"));
+    fs::remove_file(audit::head_path(&log)).unwrap();
+    assert!(audit::verify(&log).unwrap_err().to_string().contains("is missing"));
+    fs::remove_dir_all(&dir).unwrap();
+}
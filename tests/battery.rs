@@ -0,0 +1,55 @@
+use arrctl::battery::{self, Discharge};
+use arrctl::output::{self, Format, Locale};
+use arrctl::schema;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+fn supply(class: &Path, name: &str, files: &[(&str, &str)]) {
+    let dir = class.join(name);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, value) in files {
+        fs::write(dir.join(file), format!("{}\n", value)).unwrap();
+    }
+}
+
+fn class(name: &str) -> PathBuf {
+    let class = env::temp_dir().join(format!("arrctl-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&class);
+    supply(&class, "AC", &[("type", "Mains"), ("online", "0")]);
+    class
+}
+
+#[test]
+fn adds_up_the_batteries() {
+    let class = class("battery");
+    supply(&class, "BAT0", &[("type", "Battery"), ("status", "Discharging"), ("power_now", "12500000"), ("energy_now", "25000000")]);
+    // Charge and current only, at 12 V
+    supply(&class, "BAT1", &[("type", "Battery"), ("status", "Discharging"), ("voltage_now", "12000000"), ("current_now", "625000"), ("charge_now", "2500000")]);
+    let batteries = battery::batteries(&class);
+    assert_eq!(batteries.iter().map(|b| b.name.as_str()).collect::<Vec<_>>(), ["BAT0", "BAT1"]);
+    assert_eq!(battery::discharge(&batteries), Some(Discharge { watts: 20.0, watt_hours: Some(55.0) }));
+
+    // Plugged in, nothing to show
+    supply(&class, "BAT0", &[("status", "Charging")]);
+    supply(&class, "BAT1", &[("status", "Full")]);
+    assert_eq!(battery::discharge(&batteries), None);
+    fs::remove_dir_all(&class).unwrap();
+}
+
+#[test]
+fn shows_what_a_cut_buys() {
+    let draw = Discharge { watts: 20.0, watt_hours: Some(40.0) };
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    out.record(&battery::record(&draw, 15.0)).unwrap();
+    drop(out);
+    let value: serde_json::Value = serde_json::from_slice(&text).unwrap();
+    assert_eq!(value["cpu_percent"], 75.0);
+    assert_eq!(value["minutes_left"], 120);
+    assert_eq!(value["minutes_left_after_cut"], 160);
+    schema::check("status", &value).unwrap();
+
+    // Missing energy leaves out the runtime
+    let record = battery::record(&Discharge { watts: 20.0, watt_hours: None }, 15.0);
+    assert!(record.fields.iter().all(|f| f.key != "minutes_left"));
+}
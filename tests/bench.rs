@@ -0,0 +1,65 @@
+use arrctl::advise::{CpuObservation, Observation, PeakDraw};
+use arrctl::output::Level;
+use arrctl::bench::{self, Load};
+use std::time::{Duration, Instant};
+
+#[test]
+fn load_names() {
+    assert_eq!("spin".parse(), Ok(Load::Spin));
+    assert_eq!("memory".parse(), Ok(Load::Memory));
+    assert_eq!("custom:stress -c 4".parse(), Ok(Load::Custom("stress -c 4".to_string())));
+    assert!("custom:".parse::<Load>().is_err());
+    assert!("prime95".parse::<Load>().is_err());
+    assert_eq!(Load::Custom("x".to_string()).to_string(), "custom:x");
+}
+
+#[test]
+fn loads_stop_promptly() {
+    for load in [Load::Spin, Load::Avx, Load::Memory, Load::Custom("sleep 10".to_string())] {
+        let running = bench::start(&load, &[0, 0]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let start = Instant::now();
+        running.stop();
+        assert!(start.elapsed() < Duration::from_secs(2), "{} took {:?} to stop", load, start.elapsed());
+    }
+}
+
+#[test]
+fn closer_limit_binds() {
+    let obs = |watts, amps| Observation {
+        tdp_watts: 35.0,
+        tdc_amps: 48.0,
+        max_package_watts: watts,
+        peak: PeakDraw { amps, ..Default::default() },
+        ..Default::default()
+    };
+    assert_eq!(bench::binding_limit(&obs(20.0, 20.0)), None);
+    assert_eq!(bench::binding_limit(&obs(34.0, 30.0)), Some("TDP"));
+    assert_eq!(bench::binding_limit(&obs(20.0, 47.0)), Some("TDC"));
+    assert_eq!(bench::binding_limit(&obs(34.0, 48.0)), Some("TDC"));
+}
+
+#[test]
+fn pins_one_thread_per_cpu() {
+    bench::start(&Load::Spin, &[0]).unwrap().stop();
+    // Not a CPU this machine has, the threads started so far still stop
+    let err = bench::start(&Load::Spin, &[0, 1023]).err().unwrap();
+    assert!(err.to_string().contains("CPU1023"), "{}", err);
+}
+
+#[test]
+fn flags_cores_that_throttle_first() {
+    let cpu = |cpu, throttled_at| CpuObservation { cpu, sustained_mhz: 2400.0, max_celsius: Some(90), throttled_at };
+    // Siblings share a sensor, CPU0 and 1 are one core
+    let cpus = [cpu(0, Some(12.0)), cpu(1, Some(12.5)), cpu(2, Some(40.0)), cpu(3, Some(40.5))];
+    assert_eq!(bench::early_throttlers(&cpus, 60.0), [0, 1]);
+    // Never throttling in a short window isn't evidence of anything
+    assert_eq!(bench::early_throttlers(&[cpu(0, Some(12.0)), cpu(2, None)], 15.0), Vec::<u16>::new());
+    assert_eq!(bench::early_throttlers(&[cpu(0, Some(12.0)), cpu(2, Some(20.0))], 60.0), Vec::<u16>::new());
+
+    let record = bench::cpu_record(&cpus[0], true);
+    assert_eq!(record.cpu, Some(0));
+    assert_eq!(record.fields[2].key, "throttled_at_seconds");
+    assert_eq!(record.fields[2].level, Level::Warn);
+    assert_eq!(bench::cpu_record(&cpu(3, None), false).fields.len(), 3);
+}
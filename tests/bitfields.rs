@@ -0,0 +1,126 @@
+use arrctl::regs::*;
+use arrctl::error::{self, ErrorKind};
+use arrctl::profile;
+use arrctl::quantity::{self, Amps, Celsius, Ratio, Watts};
+use proptest::prelude::*;
+
+// Setting a field must read back the same value and leave every bit
+// outside of it untouched
+macro_rules! field_round_trip {
+    ($name:ident, $reg:ident, $get:ident, $set:ident, $msb:expr, $lsb:expr) => {
+        proptest! {
+            #[test]
+            fn $name(raw: u64, value in 0u64..(1 << ($msb - $lsb + 1))) {
+                let mask = ((1u64 << ($msb - $lsb + 1)) - 1) << $lsb;
+                let mut reg = $reg(raw);
+                reg.$set(value);
+                prop_assert_eq!(reg.$get(), value);
+                prop_assert_eq!(reg.0 & !mask, raw & !mask);
+            }
+        }
+    };
+}
+
+macro_rules! bit_round_trip {
+    ($name:ident, $reg:ident, $get:ident, $set:ident, $bit:expr) => {
+        proptest! {
+            #[test]
+            fn $name(raw: u64, value: bool) {
+                let mut reg = $reg(raw);
+                reg.$set(value);
+                prop_assert_eq!(reg.$get(), value);
+                prop_assert_eq!(reg.0 & !(1 << $bit), raw & !(1 << $bit));
+            }
+        }
+    };
+}
+
+field_round_trip!(tdp, MsrTurboLimits, tdp, set_tdp, 14, 0);
+bit_round_trip!(tdp_override, MsrTurboLimits, tdp_override, set_tdp_override, 15);
+field_round_trip!(tdc, MsrTurboLimits, tdc, set_tdc, 30, 16);
+bit_round_trip!(tdc_override, MsrTurboLimits, tdc_override, set_tdc_override, 31);
+bit_round_trip!(turbo_disable, Ia32MiscEnable, turbo_disable, set_turbo_disable, 38);
+field_round_trip!(duty_cycle, Ia32ClockModulation, duty_cycle, set_duty_cycle, 3, 1);
+bit_round_trip!(clock_modulation_enable, Ia32ClockModulation, enable, set_enable, 4);
+
+proptest! {
+    #[test]
+    fn tdp_and_tdc_independent(raw: u64, tdp in 0u64..1 << 15, tdc in 0u64..1 << 15) {
+        let mut limits = MsrTurboLimits(raw);
+        limits.set_tdp(tdp);
+        limits.set_tdc(tdc);
+        limits.set_tdp_override(true);
+        limits.set_tdc_override(true);
+        prop_assert_eq!(limits.tdp(), tdp);
+        prop_assert_eq!(limits.tdc(), tdc);
+        prop_assert_eq!(limits.0 >> 32, raw >> 32);
+    }
+}
+
+proptest! {
+    // Whole watts and amps are eighths in the register, and back
+    #[test]
+    fn limits_in_whole_units(raw: u64, tdp in 0u64..1 << 12, tdc in 0u64..1 << 12) {
+        let mut limits = MsrTurboLimits(raw);
+        limits.set_tdp_watts(Watts::from(tdp)).unwrap();
+        limits.set_tdc_amps(Amps::from(tdc)).unwrap();
+        prop_assert_eq!(limits.tdp(), tdp * 8);
+        prop_assert_eq!(limits.tdc(), tdc * 8);
+        prop_assert_eq!(limits.tdp_watts().whole(), tdp);
+        prop_assert_eq!(limits.tdc_amps(), Amps(tdc as f64));
+    }
+}
+
+#[test]
+fn quantities_from_registers() {
+    // 25.5 W goes in as 204 eighths, 35 A as 280
+    let limits = MsrTurboLimits(0x8118_80cc);
+    assert_eq!(limits.tdp_watts(), Watts(25.5));
+    assert_eq!(limits.tdp_watts().whole(), 25);
+    assert_eq!(limits.tdc_amps().to_string(), "35 A");
+    assert_eq!(MsrTemperatureTarget(0x0069_0000).tjmax(), Celsius(105));
+    assert_eq!(MsrPlatformInfo(0x1600).base(), Ratio(22));
+    assert_eq!(Ratio(22).at(133.0), 2926.0);
+}
+
+proptest! {
+    // Every eighth the field holds makes it there and back
+    #[test]
+    fn eighths_round_trip(eighths in 0u64..=quantity::MAX_EIGHTHS) {
+        prop_assert_eq!(Watts::from_eighths(eighths).checked_eighths(), Some(eighths));
+        prop_assert_eq!(Amps::from_eighths(eighths).saturating_eighths(), eighths);
+    }
+
+    // Nothing too large gets through the setters by wrapping into the field
+    #[test]
+    fn oversized_limits_leave_the_register(raw: u64, watts in 4096.0f64..1e12) {
+        let mut limits = MsrTurboLimits(raw);
+        prop_assert!(limits.set_tdp_watts(Watts(watts)).is_err());
+        prop_assert!(limits.set_tdc_amps(Amps(watts)).is_err());
+        prop_assert_eq!(limits.0, raw);
+        prop_assert_eq!(Watts(watts).saturating_eighths(), quantity::MAX_EIGHTHS);
+    }
+}
+
+#[test]
+fn limits_at_the_edges() {
+    assert_eq!(Watts::MAX.checked_eighths(), Some(0x7fff));
+    assert_eq!(Watts::MAX.whole(), profile::MAX_LIMIT);
+    // Rounding to the nearest eighth can land on the top or just past it
+    assert_eq!(Watts(4095.9).checked_eighths(), Some(0x7fff));
+    assert_eq!(Watts(4095.95).checked_eighths(), None);
+    assert_eq!(Watts(-0.5).checked_eighths(), None);
+    assert_eq!(Watts(-0.5).saturating_eighths(), 0);
+    assert_eq!(Amps(f64::NAN).checked_eighths(), None);
+    assert_eq!(Amps(f64::NAN).saturating_eighths(), 0);
+    assert_eq!(Amps(f64::INFINITY).saturating_eighths(), quantity::MAX_EIGHTHS);
+    // A stray bit above the field isn't part of the value
+    assert_eq!(Watts::from_eighths(0x8008), Watts(1.0));
+
+    let mut limits = MsrTurboLimits(0);
+    limits.set_tdp_watts(Watts::MAX).unwrap();
+    assert_eq!(limits.0, 0x7fff);
+    assert!(!limits.tdp_override());
+    let err = limits.set_tdp_watts(Watts::from(4096)).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+}
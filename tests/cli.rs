@@ -0,0 +1,141 @@
+use arrctl::error::{self, Error, ErrorKind};
+use std::process::Command;
+
+mod common;
+
+fn arrctl(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_arrctl")).args(args).output().unwrap()
+}
+
+#[test]
+fn bad_arguments_exit_with_validation_code() {
+    let out = arrctl(&["--no-such-flag"]);
+    assert_eq!(out.status.code(), Some(5));
+}
+
+#[test]
+fn help_documents_exit_codes() {
+    let out = arrctl(&["--help"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("register locked by firmware"));
+}
+
+#[test]
+fn kind_survives_context() {
+    let err = anyhow::Error::new(Error::new(ErrorKind::RegisterLocked, "locked")).context("Setting TDP");
+    assert_eq!(error::kind_of(&err), ErrorKind::RegisterLocked);
+    assert_eq!(error::kind_of(&anyhow::anyhow!("something else")), ErrorKind::Failure);
+}
+
+#[test]
+fn json_errors() {
+    let out = arrctl(&["--json", "--no-such-flag"]);
+    assert_eq!(out.status.code(), Some(5));
+    let err: serde_json::Value = serde_json::from_slice(&out.stderr).unwrap();
+    assert_eq!(err["error"]["kind"], "Validation");
+
+    let err = anyhow::Error::new(Error::new(ErrorKind::RegisterLocked, "locked").register(0x1ac, 0));
+    let err = error::to_json(&err.context("Setting TDP"));
+    assert_eq!(err["error"]["kind"], "RegisterLocked");
+    assert_eq!(err["error"]["register"], "MSR_TURBO_LIMITS");
+    assert_eq!(err["error"]["cpu"], 0);
+}
+
+#[test]
+fn refuses_virtual_machines() {
+    assert_eq!(arrctl::cpu::check_hypervisor(None, false).unwrap(), None);
+
+    let err = arrctl::cpu::check_hypervisor(Some("KVM"), false).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::UnsupportedCpu);
+    assert!(err.to_string().contains("--allow-vm"), "{}", err);
+
+    let warning = arrctl::cpu::check_hypervisor(Some("KVM"), true).unwrap().unwrap();
+    assert!(warning.starts_with("Warning: running under KVM"), "{}", warning);
+}
+
+#[test]
+fn explains_lockdown() {
+    let path = std::env::temp_dir().join(format!("arrctl-lockdown-{}", std::process::id()));
+    std::fs::write(&path, "none [integrity] confidentiality\n").unwrap();
+    assert_eq!(arrctl::msr::lockdown(&path).as_deref(), Some("integrity"));
+    let err = arrctl::msr::lockdown_error(&path).unwrap();
+    assert_eq!(err.kind, ErrorKind::PermissionDenied);
+    assert!(err.message.starts_with("MSR writes blocked by kernel lockdown (Secure Boot)"), "{}", err);
+
+    // A plain EPERM stays one
+    std::fs::write(&path, "[none] integrity confidentiality\n").unwrap();
+    assert!(arrctl::msr::lockdown_error(&path).is_none());
+    std::fs::remove_file(&path).unwrap();
+    assert!(arrctl::msr::lockdown_error(&path).is_none());
+}
+
+#[test]
+fn sudo_only_for_writes() {
+    use clap::Parser;
+    let writes = |args: &[&str]| arrctl::cli::Cli::try_parse_from([&["arrctl"], args].concat()).unwrap().writes();
+    assert!(writes(&["--set-tdp", "25"]));
+    assert!(writes(&["converge", "-c", "x.toml"]));
+    assert!(!writes(&["converge", "-c", "x.toml", "--check"]));
+    assert!(!writes(&["--get-tdp", "--sudo"]));
+    assert!(!writes(&["doctor"]));
+
+    let dir = std::env::temp_dir().join(format!("arrctl-sudo-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = std::env::join_paths([dir.join("missing"), dir.clone()]).unwrap();
+    assert_eq!(arrctl::escalate::helper(false, &path), None);
+    std::fs::write(dir.join("pkexec"), "").unwrap();
+    assert_eq!(arrctl::escalate::helper(false, &path), Some(dir.join("pkexec")));
+    std::fs::write(dir.join("sudo"), "").unwrap();
+    assert_eq!(arrctl::escalate::helper(false, &path), Some(dir.join("sudo")));
+    assert_eq!(arrctl::escalate::helper(true, &path), Some(dir.join("pkexec")));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let cmd = arrctl::escalate::command("/usr/bin/pkexec".as_ref(), "/opt/arrctl".as_ref(), ["--set-tdp".into(), "25".into()]);
+    assert_eq!(cmd.get_program(), "/usr/bin/pkexec");
+    assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["/opt/arrctl", "--set-tdp", "25"]);
+}
+
+#[test]
+fn read_only_never_writes() {
+    use arrctl::msr::{MsrAccess, ReadOnly};
+    let msr = ReadOnly(common::msr());
+    assert_eq!(msr.cpus(), [0, 1, 2, 3]);
+    let limit = msr.read(0x1ac, 0).unwrap();
+    let err = msr.write(0x1ac, 0, limit).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::PermissionDenied);
+    assert!(err.to_string().contains("read-only mode"), "{}", err);
+
+    // Turned away before anything gets opened
+    let out = arrctl(&["--read-only", "--set-tdp", "25"]);
+    assert_eq!(out.status.code(), Some(5));
+    let out = arrctl(&["--read-only", "converge", "-c", "/nonexistent.toml"]);
+    assert_eq!(out.status.code(), Some(5));
+}
+
+#[test]
+fn conflicts_caught_while_parsing() {
+    use clap::{error::ErrorKind as ClapError, Parser};
+    let parse = |args: &[&str]| arrctl::cli::Cli::try_parse_from([&["arrctl"], args].concat());
+    for args in [&["--get-tdp", "--set-tdc", "30"][..], &["--get-tdc", "--set-tdp", "25"], &["--json", "--format", "human"], &["--schema", "--influx-url", "http://x/write"]] {
+        assert_eq!(parse(args).err().map(|e| e.kind()), Some(ClapError::ArgumentConflict), "{:?}", args);
+    }
+    assert!(parse(&["--get-tdp", "--get-tdc", "--set-ratio", "2.4GHz"]).is_ok());
+    assert!(parse(&["--set-tdp", "25", "--set-tdc", "30", "--get-tjmax"]).is_ok());
+    // --all-cores with nothing to apply
+    assert_eq!(parse(&["--all-cores", "--get-tdp"]).err().map(|e| e.kind()), Some(ClapError::MissingRequiredArgument));
+    assert!(parse(&["--all-cores", "--set-clock-modulation", "50%"]).is_ok());
+    assert!(parse(&["--all-cores", "set", "tdp", "25"]).is_ok());
+
+    // Through the binary it's a usage error like any other
+    let out = arrctl(&["--get-tdp", "--set-tdp", "25"]);
+    assert_eq!(out.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("cannot be used with"));
+
+    // ARRCTL_PROFILE counts as --apply
+    let out = Command::new(env!("CARGO_BIN_EXE_arrctl")).args(["daemon", "--apply-on-battery", "battery"]).env_remove("ARRCTL_PROFILE").output().unwrap();
+    assert_eq!(out.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--apply <PROFILE>"));
+    let out = Command::new(env!("CARGO_BIN_EXE_arrctl")).args(["daemon", "--apply-on-battery", "battery", "--schema"]).env("ARRCTL_PROFILE", "ac").output().unwrap();
+    // Gets past parsing to --schema, which the daemon has none of
+    assert!(String::from_utf8_lossy(&out.stderr).contains("no JSON output"), "{}", String::from_utf8_lossy(&out.stderr));
+}
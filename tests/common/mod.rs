@@ -0,0 +1,25 @@
+// What the test files share, each takes it in with `mod common;` and uses
+// what it needs
+#![allow(dead_code)]
+
+use arrctl::msr::MockMsr;
+use std::path::PathBuf;
+use std::{env, fs};
+
+pub fn fixture(name: &str) -> String {
+    fs::read_to_string(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+// The i5-520M, two cores with two threads each
+pub fn msr() -> MockMsr {
+    MockMsr::from_dump(&fixture("i5-520m.dump")).unwrap()
+}
+
+// In the temp dir and this run's own, with whatever an earlier one left
+// there gone
+pub fn temp(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("arrctl-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+    path
+}
@@ -0,0 +1,41 @@
+use arrctl::compare;
+use arrctl::msr::{self, MockMsr};
+use arrctl::output::Human;
+
+fn fixture(name: &str) -> MockMsr {
+    compare::load(format!("{}/tests/fixtures/{}.dump", env!("CARGO_MANIFEST_DIR"), name).as_ref()).unwrap()
+}
+
+#[test]
+fn lists_differing_fields() {
+    let (a, b) = (fixture("i5-520m"), fixture("i7-620m"));
+    let entries = compare::compare(&a, &b);
+    let differing: Vec<&str> = entries.iter().filter(|e| !e.same()).map(|e| e.name.as_str()).collect();
+    assert!(differing.contains(&"brand"));
+    assert!(differing.contains(&"MSR_TURBO_LIMITS.tdp"));
+    assert!(differing.contains(&"MSR_TURBO_LIMITS.tdp_override"));
+    assert!(!differing.contains(&"MSR_TURBO_LIMITS.tdc"));
+    // Temperatures always differ, they're left out
+    assert!(!entries.iter().any(|e| e.name.starts_with("IA32_THERM_STATUS")));
+
+    let mut text = Vec::new();
+    compare::run(&mut Human::new(&mut text), &a, &b, false, false).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("MSR_TURBO_LIMITS.tdp: 280, vs 200\n"), "{}", text);
+    assert!(!text.contains("MSR_TURBO_LIMITS.tdc:"), "{}", text);
+    assert!(text.ends_with(&format!("{} of {} fields differ\n", differing.len(), entries.len())), "{}", text);
+}
+
+#[test]
+fn microcode_survives_a_dump() {
+    let a = fixture("i5-520m");
+    let mut out = Vec::new();
+    msr::write_dump(&mut out, &a, a.brand.as_deref().unwrap(), Some(0x7)).unwrap();
+    let b = MockMsr::from_dump(&String::from_utf8(out).unwrap()).unwrap();
+    assert_eq!(b.microcode, Some(0x7));
+
+    let entries = compare::compare(&a, &b);
+    let microcode = entries.iter().find(|e| e.name == "microcode").unwrap();
+    assert_eq!((microcode.a.as_deref(), microcode.b.as_deref()), (None, Some("0x7")));
+    assert_eq!(entries.iter().filter(|e| !e.same()).count(), 1);
+}
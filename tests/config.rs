@@ -0,0 +1,166 @@
+use arrctl::config::{self, Diagnostic};
+
+fn messages(text: &str) -> Vec<String> {
+    config::validate(text).iter().map(|d| d.to_string()).collect()
+}
+
+#[test]
+fn valid_file_has_no_diagnostics() {
+    let text = "# quiet\n[profiles.quiet]\ntdp = 12\nturbo = true\nigp_cap = \"low\"\n\n[profiles.slow]\nturbo = false\n";
+    assert_eq!(config::validate(text), Vec::<Diagnostic>::new());
+}
+
+#[test]
+fn unknown_key_points_at_the_key() {
+    let text = "[profiles.gaming]\ntdp = 18\n  tpd = 20\n";
+    assert_eq!(messages(text), ["3:3: Unknown key tpd in profile gaming"]);
+
+    assert_eq!(messages("tdp = 18\n"), ["1:1: Unknown top level key tdp"]);
+}
+
+#[test]
+fn bad_values_point_at_the_value() {
+    let text = "[profiles.a]\ntdp = 9000\nclock_modulation = 8\nigp_cap = \"huge\"\ntdc = \"lots\"\n";
+    assert_eq!(messages(text), [
+        "4:11: igp_cap must be one of \"low\", \"medium\", \"high\", \"max\"",
+        "5:7: tdc must be a non-negative integer",
+        "2:7: Profile a: TDP must be between 1 and 4095",
+        "3:20: Profile a: Clock modulation duty cycle must be between 0 and 7 eighths",
+    ]);
+}
+
+#[test]
+fn conflicting_settings() {
+    let text = "[profiles.a]\ntdp = 18\nturbo = false\n";
+    assert_eq!(messages(text), ["3:9: Profile a: turbo = false conflicts with tdp/tdc, those only limit turbo"]);
+}
+
+#[test]
+fn turbo_ratios() {
+    assert_eq!(config::validate("[profiles.a]\nturbo_ratios = [21, 19]\n"), Vec::<Diagnostic>::new());
+    assert_eq!(messages("[profiles.a]\nturbo_ratios = 21\n"), ["2:16: turbo_ratios must be an array of ratios"]);
+    assert_eq!(messages("[profiles.a]\nturbo_ratios = [19, 21]\n"), ["2:16: Profile a: Turbo ratios can't go up with more cores active"]);
+    assert_eq!(messages("[profiles.a]\nturbo = false\nturbo_ratios = [20]\n"), [
+        "2:9: Profile a: turbo = false conflicts with turbo_ratios, they'd never be used",
+    ]);
+}
+
+#[test]
+fn syntax_errors_have_a_position() {
+    let diagnostics = config::validate("[profiles.a]\ntdp = \n");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 7));
+}
+
+#[test]
+fn emergency_needs_a_known_profile_and_a_trigger() {
+    let ok = "[profiles.quiet]\ntdp = 10\n[emergency]\nprofile = \"quiet\"\nmax_celsius = 95\n";
+    assert_eq!(config::validate(ok), Vec::<Diagnostic>::new());
+
+    let text = "[profiles.quiet]\ntdp = 10\n[emergency]\nprofile = \"qiet\"\nwindow_secs = 0\n";
+    assert_eq!(messages(text), [
+        "4:11: Emergency profile qiet isn't defined",
+        "5:15: window_secs must be at least 1",
+        "3:2: emergency needs max_celsius or max_throttle_events",
+    ]);
+}
+
+#[test]
+fn thresholds_can_be_under_tjmax() {
+    let ok = "[profiles.quiet]\ntdp = 10\n[emergency]\nprofile = \"quiet\"\nmax_celsius = \"tjmax-10\"\n[governor]\ntarget_celsius = \"tjmax - 20\"\nmin_tdp = 10\nmax_tdp = 25\n";
+    assert_eq!(config::validate(ok), Vec::<Diagnostic>::new());
+
+    let text = "[governor]\ntarget_celsius = \"tjmax+5\"\nmin_tdp = 10\nmax_tdp = 25\n";
+    assert_eq!(messages(text), ["2:18: target_celsius: Expected celsius or \"tjmax-N\", got \"tjmax+5\""]);
+}
+
+#[test]
+fn extends_has_to_lead_somewhere() {
+    let ok = "[profiles.quiet]\ntdp = 10\n[profiles.battery-quiet]\nextends = \"quiet\"\ntdp = 8\n";
+    assert_eq!(config::validate(ok), Vec::<Diagnostic>::new());
+
+    let text = "[profiles.a]\nextends = \"b\"\n[profiles.b]\nextends = \"a\"\n[profiles.c]\nextends = \"d\"\n[profiles.e]\nextends = 1\n";
+    assert_eq!(messages(text), [
+        "8:11: extends must be a profile name",
+        "2:11: Profile a extends itself through a > b > a",
+        "4:11: Profile b extends itself through b > a > b",
+        "6:11: Profile c extends d, which isn't defined",
+    ]);
+}
+
+#[test]
+fn fan_level_needs_opting_in() {
+    let profile = "[profiles.quiet]\ntdp = 12\nfan_level = 2\n";
+    assert_eq!(messages(profile), ["3:1: Profile quiet sets fan_level, which needs fan_control = true under [thinkpad]"]);
+
+    let opted_in = format!("[thinkpad]\nfan_control = true\n\n{}", profile);
+    let expected: &[&str] = if cfg!(feature = "thinkpad") { &[] } else { &["6:1: fan_level needs arrctl built with the thinkpad feature"] };
+    assert_eq!(messages(&opted_in), expected);
+
+    let bad = "[thinkpad]\nfan_control = \"yes\"\nfans = 2\n\n[profiles.loud]\nfan_level = 9\n";
+    assert_eq!(messages(bad), [
+        "2:15: fan_control must be true or false",
+        "3:1: Unknown key fans in thinkpad",
+        "6:13: fan_level must be 0 to 7, \"auto\" or \"full-speed\"",
+        "6:1: Profile loud sets fan_level, which needs fan_control = true under [thinkpad]",
+    ]);
+}
+
+#[test]
+fn governor_needs_a_sane_range() {
+    let ok = "[governor]\ntarget_celsius = 85\nmin_tdp = 10\nmax_tdp = 25\n";
+    assert_eq!(config::validate(ok), Vec::<Diagnostic>::new());
+
+    let text = "[governor]\ntarget_celsius = 85\nmin_tdp = 20\nmax_tdp = 15\nstep = 0\nsteps = 1\n";
+    assert_eq!(messages(text), [
+        "6:1: Unknown key steps in governor",
+        "4:11: max_tdp 15 is under min_tdp 20",
+        "5:8: step must be at least 1",
+    ]);
+    assert_eq!(messages("[governor]\nmax_tdp = -1\n"), [
+        "2:11: max_tdp must be a non-negative integer",
+        "1:2: governor needs target_celsius",
+        "1:2: governor needs min_tdp",
+    ]);
+}
+
+#[test]
+fn governor_controller_and_gains() {
+    let pid = "[governor]\ntarget_celsius = 85\nmin_tdp = 10\nmax_tdp = 25\ncontroller = \"pid\"\nkp = 1\nki = 0.05\n";
+    assert_eq!(config::validate(pid), Vec::<Diagnostic>::new());
+    let settings = toml::from_str::<arrctl::profile::Profiles>(pid).unwrap().governor.unwrap();
+    assert_eq!((settings.kp, settings.ki, settings.kd), (1.0, 0.05, 0.0));
+
+    let bad = "[governor]\ntarget_celsius = 85\nmin_tdp = 10\nmax_tdp = 25\ncontroller = \"pd\"\nkd = -1\n";
+    assert_eq!(messages(bad), ["5:14: controller must be \"step\" or \"pid\"", "6:6: kd must be a non-negative number"]);
+}
+
+#[test]
+fn core_clock_modulation() {
+    assert_eq!(config::validate("[profiles.a]\ncore_clock_modulation = [0, 3]\n"), Vec::<Diagnostic>::new());
+    assert_eq!(messages("[profiles.a]\ncore_clock_modulation = [0, 9]\nclock_modulation = 2\n"), [
+        "3:20: Profile a: clock_modulation conflicts with core_clock_modulation, set one or the other",
+    ]);
+    assert_eq!(messages("[profiles.a]\ncore_clock_modulation = [0, 9]\n"), [
+        "2:25: Profile a: Clock modulation duty cycles must be between 0 and 7 eighths",
+    ]);
+}
+
+#[test]
+fn policy_script_has_to_parse() {
+    let path = std::env::temp_dir().join(format!("arrctl-config-policy-{}.pol", std::process::id()));
+    std::fs::write(&path, "tdp = 25\n").unwrap();
+    let ok = format!("[policy]\nscript = {:?}\n", path);
+    assert_eq!(config::validate(&ok), Vec::<Diagnostic>::new());
+
+    std::fs::write(&path, "tdp = 25 +\n").unwrap();
+    let messages = messages(&ok);
+    assert_eq!(messages.len(), 1, "{:?}", messages);
+    assert!(messages[0].starts_with("2:10: In ") && messages[0].ends_with("Line 1: The line ends in the middle of an expression"), "{:?}", messages);
+    std::fs::remove_file(&path).unwrap();
+
+    let both = "[governor]\ntarget_celsius = 85\nmin_tdp = 10\nmax_tdp = 25\n[policy]\nscript = \"/nonexistent\"\n";
+    let messages = config::validate(both).iter().map(|d| d.message.clone()).collect::<Vec<_>>();
+    assert_eq!(messages[0], "governor and policy both pick the limits, keep one of them");
+    assert_eq!(messages.len(), 2, "{:?}", messages);
+}
@@ -0,0 +1,51 @@
+use arrctl::{converge, schema};
+use arrctl::journal::Journal;
+use arrctl::msr::MockMsr;
+use arrctl::output::{self, Format, Locale};
+use std::fs;
+use std::path::Path;
+
+mod common;
+
+use common::temp;
+
+fn converge(msr: &MockMsr, journal: &Journal, desired: &Path, check: bool) -> (bool, String) {
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    let changed = converge::run(&mut *out, msr, journal, desired, Path::new("/nonexistent"), check).unwrap();
+    drop(out);
+    (changed, String::from_utf8(text).unwrap())
+}
+
+#[test]
+fn second_run_is_unchanged() {
+    let msr = common::msr();
+    let journal = Journal::new(temp("converge.journal"));
+    let desired = temp("desired.toml");
+    fs::write(&desired, "tdp = 25\n").unwrap();
+
+    let (changed, text) = converge(&msr, &journal, &desired, true);
+    assert!(changed);
+    assert!(text.contains("\"status\":\"would change\""), "{}", text);
+    assert!(text.contains("\"kind\":\"change\""), "{}", text);
+    for line in text.lines() {
+        schema::check("converge", &serde_json::from_str(line).unwrap()).unwrap();
+    }
+    // Checking doesn't write, so it's still a change afterwards
+    assert!(converge(&msr, &journal, &desired, true).0);
+
+    let (changed, text) = converge(&msr, &journal, &desired, false);
+    assert!(changed);
+    assert!(text.contains("\"status\":\"changed\""), "{}", text);
+
+    let (changed, text) = converge(&msr, &journal, &desired, false);
+    assert!(!changed);
+    assert_eq!(text.lines().count(), 1, "{}", text);
+    assert!(text.contains("\"status\":\"unchanged\""), "{}", text);
+
+    fs::write(&desired, "tdp = 25\ntpd = 3\n").unwrap();
+    let err = converge::run(&mut output::Json(Vec::new()), &msr, &journal, &desired, Path::new("/nonexistent"), true).unwrap_err();
+    assert!(err.to_string().contains("tpd"), "{}", err);
+    fs::remove_file(&desired).unwrap();
+    fs::remove_file(journal.path()).unwrap();
+}
@@ -0,0 +1,66 @@
+use arrctl::cooling::{self, Point};
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::output::{self, Format, Locale};
+use arrctl::power::CoreSample;
+use std::{env, fs};
+
+fn sample(watts: f32, celsius: u64) -> Sample {
+    let cpu = CpuSample { cpu: 0, activity: CoreSample { effective_mhz: 2400.0, active: 1.0, volts: 1.1 }, celsius: Some(celsius), throttling: false };
+    Sample { cpus: vec![cpu], package_watts: watts, core_amps: 20.0, core_amps_at_tjmax: 25.0 }
+}
+
+// Half a minute each at 15 W and 25 W, settled at 70 and 85 celsius
+fn session() -> Vec<Sample> {
+    let mut samples = Vec::new();
+    samples.extend((0..30).map(|i| sample(15.0, 70 + i % 2)));
+    samples.extend((0..30).map(|i| sample(25.0, 85 - i % 2)));
+    samples
+}
+
+#[test]
+fn fits_settled_windows() {
+    let points = cooling::settled(&session(), 105);
+    assert_eq!(points, [Point { watts: 15.0, celsius: 70.5 }, Point { watts: 25.0, celsius: 84.5 }]);
+    // 1.4 celsius per watt from 49.5 at idle, to 100
+    let watts = cooling::sustainable_watts(&points, 105).unwrap();
+    assert!((watts - 36.07).abs() < 0.01, "{}", watts);
+
+    assert_eq!(cooling::warning(32, 21.4).as_deref(), Some("Chassis history suggests ~21 W sustainable; 32 W will thermally throttle"));
+    assert_eq!(cooling::warning(23, 21.4), None);
+}
+
+#[test]
+fn unsettled_history_says_nothing() {
+    // Still heating up, 1 celsius a second
+    let heating: Vec<Sample> = (0..60).map(|i| sample(25.0, 50 + i)).collect();
+    assert_eq!(cooling::settled(&heating, 105), []);
+    let throttled: Vec<Sample> = session().into_iter().map(|mut s| { s.cpus[0].throttling = true; s }).collect();
+    assert_eq!(cooling::settled(&throttled, 105), []);
+
+    // One power level doesn't make a line
+    let flat = cooling::settled(&(0..60).map(|_| sample(20.0, 80)).collect::<Vec<_>>(), 105);
+    assert_eq!(flat.len(), 2);
+    assert_eq!(cooling::sustainable_watts(&flat, 105), None);
+}
+
+#[test]
+fn loads_recordings() {
+    let dir = env::temp_dir().join(format!("arrctl-test-recordings-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut csv = Vec::new();
+    let mut out = output::sink(Format::Csv, Locale::C, false, &mut csv);
+    for sample in session() {
+        for record in sample.records() {
+            out.record(&record).unwrap();
+        }
+    }
+    out.finish().unwrap();
+    drop(out);
+    fs::write(dir.join("gaming.csv"), csv).unwrap();
+    fs::write(dir.join("notes.txt"), "not a recording").unwrap();
+
+    let points = cooling::load(&dir, 105).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(points.len(), 2);
+    assert!(cooling::load(&dir, 105).unwrap().is_empty());
+}
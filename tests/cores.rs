@@ -0,0 +1,48 @@
+use arrctl::cores::{self, Target};
+use arrctl::cpu::CpuInfo;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+// i5-520M numbering: CPU0/CPU2 share core 0, CPU1/CPU3 share core 2
+fn fake_sysfs(name: &str) -> PathBuf {
+    let root = env::temp_dir().join(format!("arrctl-test-cores-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("present"), "0-3\n").unwrap();
+    for (cpu, core) in [(0, 0), (1, 2), (2, 0), (3, 2)] {
+        let dir = root.join(format!("cpu{}/topology", cpu));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("core_id"), format!("{}\n", core)).unwrap();
+        fs::write(dir.join("physical_package_id"), "0\n").unwrap();
+        if cpu != 0 {
+            fs::write(root.join(format!("cpu{}/online", cpu)), "1\n").unwrap();
+        }
+    }
+    root
+}
+
+fn online(root: &Path, cpu: u16) -> String {
+    fs::read_to_string(root.join(format!("cpu{}/online", cpu))).unwrap()
+}
+
+#[test]
+fn siblings_go_first() {
+    let layout = [(0, 0), (1, 2), (2, 0), (3, 2)].map(|(cpu, core)| CpuInfo { cpu, core, package: 0 });
+    assert_eq!(cores::plan(&layout, 2), [0, 1]);
+    assert_eq!(cores::plan(&layout, 3), [0, 1, 2]);
+}
+
+#[test]
+fn set_and_restore() {
+    let root = fake_sysfs("set");
+    let result = cores::set(&root, Target::Count(2)).unwrap();
+    assert_eq!(result, [(0, true), (1, true), (2, false), (3, false)]);
+    assert_eq!(online(&root, 2), "0");
+    assert_eq!(online(&root, 3), "0");
+
+    cores::set(&root, Target::All).unwrap();
+    assert_eq!(online(&root, 3), "1");
+    assert!(cores::set(&root, Target::Count(5)).is_err());
+    assert!("0".parse::<Target>().is_err());
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,433 @@
+use arrctl::daemon::{self, Options};
+use arrctl::emergency::Guard;
+use arrctl::events;
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::msr::{MockMsr, MsrAccess};
+use arrctl::power::CoreSample;
+use arrctl::profile::Emergency;
+use arrctl::regs::*;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+mod common;
+
+fn start(name: &str, profiles: Option<&str>) -> (UnixStream, PathBuf, PathBuf, Arc<MockMsr>) {
+    start_applying(name, profiles, None)
+}
+
+fn start_applying(name: &str, profiles: Option<&str>, apply: Option<&str>) -> (UnixStream, PathBuf, PathBuf, Arc<MockMsr>) {
+    let msr = Arc::new(common::msr());
+    let socket = env::temp_dir().join(format!("arrctl-test-{}-{}.sock", name, std::process::id()));
+    let config = socket.with_extension("toml");
+    if let Some(profiles) = profiles {
+        fs::write(&config, profiles).unwrap();
+    }
+
+    let opts = Options { interval: Duration::from_millis(10), socket: socket.clone(), hook: None, config: config.clone(), influx: None, journal: socket.with_extension("journal"), apply: apply.map(String::from), apply_on_battery: None, read_only: false, user: None, audit: None, listen: None, history: Duration::from_secs(3600), history_file: None, enforce: None };
+    let shared = msr.clone();
+    thread::spawn(move || daemon::run(shared, opts));
+
+    let stream = (0..100)
+        .find_map(|_| {
+            thread::sleep(Duration::from_millis(20));
+            UnixStream::connect(&socket).ok()
+        })
+        .expect("daemon never came up");
+    (stream, socket, config, msr)
+}
+
+fn ask(stream: &mut UnixStream, command: &str) -> String {
+    stream.write_all(format!("{}\n", command).as_bytes()).unwrap();
+    let mut line = String::new();
+    BufReader::new(&*stream).read_line(&mut line).unwrap();
+    line
+}
+
+#[test]
+fn serves_status_over_socket() {
+    let (mut stream, socket, ..) = start("status", None);
+
+    // The first sample lands one interval after startup
+    thread::sleep(Duration::from_millis(50));
+    let line = ask(&mut stream, "status");
+    assert!(line.starts_with("CPU0: "), "unexpected reply {:?}", line);
+
+    let _ = fs::remove_file(&socket);
+}
+
+#[test]
+fn reloads_config_on_change() {
+    let (mut stream, socket, config, _) = start("reload", Some("[profiles.quiet]\ntdp = 12\n"));
+    assert_eq!(ask(&mut stream, "profiles"), "Profiles: quiet\n");
+
+    // Saved the way editors do, through a rename
+    let new = config.with_extension("new");
+    fs::write(&new, "[profiles.quiet]\ntdp = 12\n[profiles.gaming]\ntdp = 18\n").unwrap();
+    fs::rename(&new, &config).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(ask(&mut stream, "profiles"), "Profiles: gaming, quiet\n");
+
+    // A broken edit doesn't replace the running config
+    fs::write(&config, "[profiles.quiet]\ntpd = 12\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(ask(&mut stream, "profiles"), "Profiles: gaming, quiet\n");
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+}
+
+#[test]
+fn emergency_profile_on_overheat() {
+    // The fixture runs at 47 and 50 celsius
+    let profiles = "[profiles.quiet]\ntdp = 10\n\n[emergency]\nprofile = \"quiet\"\nmax_celsius = 45\n";
+    let (_stream, socket, config, msr) = start("emergency", Some(profiles));
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp(), 10 * 8);
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+    let _ = fs::remove_file(socket.with_extension("journal"));
+}
+
+#[test]
+fn keeps_a_profile_applied() {
+    let (_stream, socket, config, msr) = start_applying("apply", Some("[profiles.quiet]\ntdp = 12\n"), Some("quiet"));
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp(), 12 * 8);
+
+    // Edited profiles go in right away
+    let new = config.with_extension("new");
+    fs::write(&new, "[profiles.quiet]\ntdp = 14\n").unwrap();
+    fs::rename(&new, &config).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp(), 14 * 8);
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+    let _ = fs::remove_file(socket.with_extension("journal"));
+
+    // Config is gone by now, a profile that isn't in it stops the start
+    let opts = Options { interval: Duration::from_millis(10), socket, hook: None, config, influx: None, journal: PathBuf::new(), apply: Some("gaming".into()), apply_on_battery: None, read_only: false, user: None, audit: None, listen: None, history: Duration::from_secs(3600), history_file: None, enforce: None };
+    let err = daemon::run(msr, opts).unwrap_err();
+    assert!(err.to_string().contains("No profile gaming"), "{}", err);
+}
+
+#[test]
+fn applies_profiles_for_clients() {
+    let (mut stream, socket, config, msr) = start("apply-request", Some("[profiles.quiet]\ntdp = 12\n"));
+    assert_eq!(ask(&mut stream, "apply gaming"), "No profile gaming\n");
+    // Root doesn't get asked, anyone else would have to get past polkit
+    if unsafe { libc::geteuid() } == 0 {
+        assert_eq!(ask(&mut stream, "apply quiet"), "Applied profile quiet\n");
+        assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp(), 12 * 8);
+    }
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+    let _ = fs::remove_file(socket.with_extension("journal"));
+}
+
+#[test]
+fn governor_steps_the_tdp() {
+    // 50 celsius against a target of 45, from the stock 35 W
+    let profiles = "[governor]\ntarget_celsius = 45\nmin_tdp = 10\nmax_tdp = 25\n";
+    let (_stream, socket, config, msr) = start("governor", Some(profiles));
+    thread::sleep(Duration::from_millis(100));
+    // One write only, the next has to wait min_interval_secs
+    assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp(), 25 * 8);
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+    let _ = fs::remove_file(socket.with_extension("journal"));
+}
+
+#[test]
+fn throttle_events_count_edges_within_window() {
+    let settings = Emergency { profile: "quiet".into(), max_celsius: None, max_throttle_events: Some(2), window_secs: 10 };
+    let mut guard = Guard::new(settings, 105);
+    let sample = |throttling| Sample {
+        cpus: vec![CpuSample { cpu: 0, activity: CoreSample { effective_mhz: 0.0, active: 0.0, volts: 0.0 }, celsius: None, throttling }],
+        package_watts: 0.0,
+        core_amps: 0.0,
+        core_amps_at_tjmax: 0.0,
+    };
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+
+    // Staying throttled is one event, not one per sample
+    for secs in 0..5 {
+        assert_eq!(guard.check(&sample(true), at(secs)), None);
+    }
+    assert_eq!(guard.check(&sample(false), at(5)), None);
+    assert_eq!(guard.check(&sample(true), at(6)), None);
+    assert_eq!(guard.check(&sample(false), at(7)), None);
+    // The first event has aged out by now
+    assert_eq!(guard.check(&sample(true), at(11)), None);
+    assert_eq!(guard.check(&sample(false), at(12)), None);
+    assert!(guard.check(&sample(true), at(13)).is_some());
+    // Only trips once
+    assert_eq!(guard.check(&sample(false), at(14)), None);
+    assert_eq!(guard.check(&sample(true), at(15)), None);
+}
+
+#[test]
+fn streams_events_as_json_lines() {
+    let (mut stream, socket, config, msr) = start("events", Some("[profiles.quiet]\ntdp = 12\n"));
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream.write_all(b"events\n").unwrap();
+    let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+    let mut next = || serde_json::from_str::<serde_json::Value>(&lines.next().unwrap().unwrap()).unwrap();
+
+    let first = next();
+    assert_eq!(first["event"], "sample", "{}", first);
+    assert_eq!(first["schema_version"], arrctl::schema::VERSION);
+    assert!(first["records"].as_array().unwrap().iter().any(|r| r["kind"] == "package"), "{}", first);
+
+    let therm = msr.read(IA32_THERM_STATUS, 1).unwrap();
+    msr.write(IA32_THERM_STATUS, 1, therm | 1).unwrap();
+    fs::write(&config, "[profiles.quiet]\ntdp = 12\n[profiles.gaming]\ntdp = 18\n").unwrap();
+
+    let (mut throttle, mut change) = (None, None);
+    while throttle.is_none() || change.is_none() {
+        let event = next();
+        match event["event"].as_str().unwrap() {
+            "throttle" => throttle = Some(event),
+            "profile-change" => change = Some(event),
+            _ => (),
+        }
+    }
+    let throttle = throttle.unwrap();
+    assert_eq!((&throttle["cpu"], &throttle["active"]), (&1.into(), &true.into()), "{}", throttle);
+    assert_eq!(events::describe(&throttle), "CPU1 started throttling");
+    assert_eq!(change.unwrap()["profiles"], serde_json::json!(["gaming", "quiet"]));
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+}
+
+#[test]
+fn takes_sets_from_the_command_line() {
+    let missing = env::temp_dir().join(format!("arrctl-test-nodaemon-{}.sock", std::process::id()));
+    assert!(daemon::route(&missing, "tdp=20", None).unwrap().is_none());
+    assert!(daemon::parse_settings("fan_level=3").is_err());
+    assert!(daemon::parse_settings("clock_modulation=9").is_err());
+    assert_eq!(daemon::parse_settings("tdp=20 tdc=30").unwrap().tdc, Some(30));
+    let profile = arrctl::trial::profile(arrctl::trial::Setting::Turbo, "off").unwrap();
+    assert_eq!(daemon::settings(&profile), "turbo=false");
+    assert_eq!(daemon::parse_settings(&daemon::settings(&profile)).unwrap(), profile);
+
+    let (mut stream, socket, config, msr) = start("set-request", None);
+    assert!(ask(&mut stream, "set turbo_ratios=[1]").starts_with("Can't set turbo_ratios=[1]"));
+    if unsafe { libc::geteuid() } == 0 {
+        assert_eq!(daemon::route(&socket, "tdp=20 tdc=30", None).unwrap().as_deref(), Some("Set tdp=20 tdc=30"));
+        let limits = MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap());
+        assert_eq!((limits.tdp(), limits.tdc()), (20 * 8, 30 * 8));
+
+        // Comes back on its own, to what it was before
+        assert_eq!(ask(&mut stream, "set tdp=15 for=1"), "Set tdp=15 for 1 s\n");
+        assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp(), 15 * 8);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp() != 20 * 8 {
+            assert!(Instant::now() < deadline, "the temporary TDP never went back");
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(ask(&mut stream, "set tdp=15 for=soon"), "for= takes seconds\n");
+
+        // Only what no later set changed goes back
+        assert_eq!(ask(&mut stream, "set tdp=15 tdc=35 for=1"), "Set tdp=15 tdc=35 for 1 s\n");
+        assert_eq!(ask(&mut stream, "set tdc=33"), "Set tdc=33\n");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp() != 20 * 8 {
+            assert!(Instant::now() < deadline, "the temporary TDP never went back");
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdc(), 33 * 8);
+    }
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+    let _ = fs::remove_file(socket.with_extension("journal"));
+    let _ = fs::remove_file(socket.with_extension("expiries.json"));
+}
+
+#[test]
+fn rearms_temporary_sets_after_a_restart() {
+    let socket = env::temp_dir().join(format!("arrctl-test-rearm-{}.sock", std::process::id()));
+    let boot_id = arrctl::state::boot_id().unwrap();
+    let expiries = serde_json::json!({
+        "boot_id": boot_id,
+        "pending": [{ "id": 0, "deadline": 0, "set": { "tdp": 15 }, "manual": {}, "registers": { "tdp": 20 } }],
+    });
+    fs::write(socket.with_extension("expiries.json"), arrctl::atomic::seal(expiries).to_string()).unwrap();
+
+    let (_stream, socket, config, msr) = start("rearm", None);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp() != 20 * 8 {
+        assert!(Instant::now() < deadline, "the expiry from before the restart never ran");
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+    let _ = fs::remove_file(socket.with_extension("journal"));
+    let _ = fs::remove_file(socket.with_extension("expiries.json"));
+}
+
+#[test]
+fn streams_events_over_websockets() {
+    let msr = Arc::new(common::msr());
+    let socket = env::temp_dir().join(format!("arrctl-test-ws-{}.sock", std::process::id()));
+    // Whatever is free, there's a small window for someone else to take it
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let opts = Options {
+        interval: Duration::from_millis(10),
+        socket: socket.clone(),
+        hook: None,
+        config: socket.with_extension("toml"),
+        influx: None,
+        journal: socket.with_extension("journal"),
+        apply: None,
+        apply_on_battery: None,
+        read_only: true,
+        user: None,
+        audit: None,
+        listen: Some(addr),
+        history: Duration::from_secs(3600),
+        history_file: None,
+        enforce: None,
+    };
+    thread::spawn(move || daemon::run(msr, opts));
+    let mut stream = (0..100)
+        .find_map(|_| {
+            thread::sleep(Duration::from_millis(20));
+            std::net::TcpStream::connect(addr).ok()
+        })
+        .expect("daemon never came up");
+
+    // Another page the browser has open
+    let mut foreign = std::net::TcpStream::connect(addr).unwrap();
+    foreign.write_all(format!("GET /ws HTTP/1.1\r\nHost: {}\r\nOrigin: http://evil.example\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n", addr).as_bytes()).unwrap();
+    let mut line = String::new();
+    BufReader::new(foreign).read_line(&mut line).unwrap();
+    assert_eq!(line, "HTTP/1.1 403 Forbidden\r\n");
+
+    stream.write_all(b"GET /ws HTTP/1.1\r\nHost: x\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "HTTP/1.1 101 Switching Protocols\r\n");
+    while line != "\r\n" {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+    }
+    let mut start = [0; 2];
+    std::io::Read::read_exact(&mut reader, &mut start).unwrap();
+    assert_eq!(start[0], 0x81);
+    let len = match start[1] {
+        126 => {
+            let mut len = [0; 2];
+            std::io::Read::read_exact(&mut reader, &mut len).unwrap();
+            u16::from_be_bytes(len) as usize
+        }
+        len => len as usize,
+    };
+    let mut payload = vec![0; len];
+    std::io::Read::read_exact(&mut reader, &mut payload).unwrap();
+    let event: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+    assert_eq!(event["event"], "sample");
+
+    let mut other = std::net::TcpStream::connect(addr).unwrap();
+    other.write_all(b"GET /nothing HTTP/1.1\r\n\r\n").unwrap();
+    let mut reply = String::new();
+    BufReader::new(other).read_line(&mut reply).unwrap();
+    assert_eq!(reply, "HTTP/1.1 404 Not Found\r\n");
+    let _ = fs::remove_file(&socket);
+}
+
+fn http(addr: std::net::SocketAddr, request: &str) -> String {
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut reply = String::new();
+    std::io::Read::read_to_string(&mut stream, &mut reply).unwrap();
+    reply
+}
+
+#[test]
+fn serves_the_status_page() {
+    let msr = Arc::new(common::msr());
+    let socket = env::temp_dir().join(format!("arrctl-test-ui-{}.sock", std::process::id()));
+    let config = socket.with_extension("toml");
+    fs::write(&config, "[profiles.quiet]\ntdp = 12\n").unwrap();
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let opts = Options {
+        interval: Duration::from_millis(10),
+        socket: socket.clone(),
+        hook: None,
+        config: config.clone(),
+        influx: None,
+        journal: socket.with_extension("journal"),
+        apply: None,
+        apply_on_battery: None,
+        read_only: false,
+        user: None,
+        audit: None,
+        listen: Some(addr),
+        history: Duration::from_secs(3600),
+        history_file: None,
+        enforce: None,
+    };
+    let shared = msr.clone();
+    thread::spawn(move || daemon::run(shared, opts));
+    (0..100)
+        .find_map(|_| {
+            thread::sleep(Duration::from_millis(20));
+            std::net::TcpStream::connect(addr).ok()
+        })
+        .expect("daemon never came up");
+
+    let page = http(addr, "GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(page.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/html"));
+    assert!(page.contains("<title>arrctl</title>"));
+    let profiles = http(addr, "GET /profiles HTTP/1.1\r\n\r\n");
+    assert!(profiles.ends_with("{\"profiles\":[\"quiet\"]}"));
+    let limits = http(addr, "GET /limits HTTP/1.1\r\n\r\n");
+    let body: serde_json::Value = serde_json::from_str(limits.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    assert_eq!(body["tdp_watts"], MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp() as f64 / 8.0);
+    let history = http(addr, "GET /history?since=60 HTTP/1.1\r\n\r\n");
+    assert!(history.split("\r\n\r\n").nth(1).unwrap().starts_with('['));
+
+    // Another site's page posting to it
+    let forged = format!("POST /apply/quiet HTTP/1.1\r\nHost: {}\r\nOrigin: http://evil.example\r\n\r\n", addr);
+    assert!(http(addr, &forged).starts_with("HTTP/1.1 403 Forbidden"));
+    let missing = format!("POST /apply/gaming HTTP/1.1\r\nHost: {}\r\nOrigin: http://{}\r\n\r\n", addr, addr);
+    assert!(http(addr, &missing).starts_with("HTTP/1.1 404 Not Found"));
+    // Traced back to this process, which as root doesn't get asked
+    if unsafe { libc::geteuid() } == 0 {
+        let apply = format!("POST /apply/quiet HTTP/1.1\r\nHost: {}\r\nOrigin: http://{}\r\n\r\n", addr, addr);
+        assert!(http(addr, &apply).ends_with("Applied profile quiet\n"));
+        assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp(), 12 * 8);
+    }
+
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(&config);
+    let _ = fs::remove_file(socket.with_extension("journal"));
+}
+
+#[test]
+fn keeps_a_history() {
+    let (mut stream, socket, ..) = start("history", None);
+    thread::sleep(Duration::from_millis(100));
+    let points: Vec<arrctl::history::Point> = serde_json::from_str(&ask(&mut stream, "history 60")).unwrap();
+    assert!(points.iter().map(|p| p.samples).sum::<u64>() > 0);
+    assert_eq!(ask(&mut stream, "history soon"), "history takes seconds\n");
+
+    let points = arrctl::history::fetch(&socket, Duration::from_secs(60)).unwrap();
+    assert!(!points.is_empty());
+    let _ = fs::remove_file(&socket);
+    let _ = fs::remove_file(socket.with_extension("journal"));
+}
@@ -0,0 +1,126 @@
+use arrctl::advise::Observation;
+use arrctl::doctor::{self, Facts};
+use arrctl::monitor::{self, CpuSample, ExternalThrottle, Sample};
+use arrctl::msr::{MockMsr, MsrAccess};
+use arrctl::output::{self, Format, Level, Locale};
+use arrctl::power::CoreSample;
+use arrctl::regs::*;
+use arrctl::{schema, sku};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+mod common;
+
+fn fixture(name: &str) -> MockMsr {
+    MockMsr::from_dump(&common::fixture(name)).unwrap()
+}
+
+fn causes(facts: &Facts) -> Vec<&'static str> {
+    doctor::diagnose(facts).iter().map(|f| f.cause).collect()
+}
+
+#[test]
+fn healthy_fixture_has_no_findings() {
+    let msr = fixture("i5-520m.dump");
+    let facts = doctor::gather(&msr, sku::lookup("i5 CPU M 520"), Path::new("/nonexistent"), Duration::ZERO).unwrap();
+    assert_eq!(facts.base_mhz.round(), 2400.0);
+    assert_eq!(facts.cstate_limit, None);
+    assert_eq!(causes(&facts), Vec::<&str>::new());
+}
+
+#[test]
+fn package_sensor_counts_too() {
+    let msr = fixture("i5-520m.dump");
+    // Throttling on the package reading while every core is at 50 or less
+    msr.write(IA32_PACKAGE_THERM_STATUS, 0, 0x0014_0003).unwrap();
+    let facts = doctor::gather(&msr, sku::lookup("i5 CPU M 520"), Path::new("/nonexistent"), Duration::ZERO).unwrap();
+    let findings = doctor::diagnose(&facts);
+    assert_eq!(findings.iter().map(|f| f.cause).collect::<Vec<_>>(), ["thermal"]);
+    assert!(findings[0].text.starts_with("The package is at its thermal limit"), "{}", findings[0].text);
+
+    assert_eq!(causes(&Facts { package_thermal_log: true, ..Default::default() }), ["thermal"]);
+}
+
+#[test]
+fn finds_what_was_set() {
+    let msr = fixture("i5-520m.dump");
+    let mut misc = ia32_misc_enable(&msr).unwrap();
+    misc.set_turbo_disable(true);
+    msr.write(IA32_MISC_ENABLE, 0, misc.0).unwrap();
+    let mut modulation = ia32_clock_modulation(&msr, 1).unwrap();
+    modulation.set_enable(true);
+    modulation.set_duty_cycle(4);
+    msr.write(IA32_CLOCK_MODULATION, 1, modulation.0).unwrap();
+    let therm = ia32_therm_status(&msr, 2).unwrap().0;
+    msr.write(IA32_THERM_STATUS, 2, therm | 1 << 2).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("arrctl-doctor-{}", std::process::id()));
+    let params = dir.join("module/intel_idle/parameters");
+    fs::create_dir_all(&params).unwrap();
+    fs::write(params.join("max_cstate"), "1\n").unwrap();
+
+    let facts = doctor::gather(&msr, sku::lookup("i5 CPU M 520"), &dir, Duration::ZERO).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(facts.clock_modulation, vec![(1, 4)]);
+    let findings = doctor::diagnose(&facts);
+    assert_eq!(findings.iter().map(|f| f.cause).collect::<Vec<_>>(), ["prochot", "clock_modulation", "turbo_disabled", "cstate_limit"]);
+    assert_eq!(findings[0].level, Level::Bad);
+    assert!(findings[0].text.contains("CPU2"), "{}", findings[0].text);
+}
+
+#[test]
+fn low_tdp_is_relative_to_stock() {
+    let facts = Facts { tdp_watts: 20.0, tdp_override: true, stock_tdp: Some(35), base_mhz: 2400.0, ..Default::default() };
+    assert_eq!(causes(&facts), ["low_tdp"]);
+    // Fine for a 25 W part
+    assert_eq!(causes(&Facts { stock_tdp: Some(25), ..facts.clone() }), Vec::<&str>::new());
+    // Not overridden means the fused value, whatever it is
+    assert_eq!(causes(&Facts { tdp_override: false, ..facts.clone() }), Vec::<&str>::new());
+    // Without a known SKU only really low values count
+    assert_eq!(causes(&Facts { stock_tdp: None, tdp_watts: 12.0, ..facts }), ["low_tdp"]);
+
+    let sticky = Facts { thermal_log: vec![0], cstate_limit: Some(0), scaling_max_mhz: Some(1200), base_mhz: 2400.0, ..Default::default() };
+    let findings = doctor::diagnose(&sticky);
+    assert_eq!(findings.iter().map(|f| f.cause).collect::<Vec<_>>(), ["thermal", "cstate_limit", "cpufreq_cap"]);
+    assert_eq!(findings[0].level, Level::Warn);
+}
+
+fn busy_at(mhz: f32, celsius: u64) -> Sample {
+    let cpu = |cpu| CpuSample { cpu, activity: CoreSample { effective_mhz: mhz, active: 1.0, volts: 0.8 }, celsius: Some(celsius), throttling: false };
+    Sample { cpus: vec![cpu(0), cpu(1)], package_watts: 9.0, core_amps: 10.0, core_amps_at_tjmax: 12.0 }
+}
+
+#[test]
+fn external_throttle_pattern() {
+    let msr = fixture("i5-520m.dump");
+    let mut external = ExternalThrottle::new(&msr).unwrap();
+    assert_eq!(external.min_mhz.round(), 1200.0);
+
+    assert!(external.pinned(&busy_at(1200.0, 55)));
+    // Hot means the CPU's own thermal control, fast means not pinned
+    assert!(!external.pinned(&busy_at(1200.0, 95)));
+    assert!(!external.pinned(&busy_at(2400.0, 55)));
+    // At TDP the power limit is a reason of its own
+    assert!(!external.pinned(&Sample { package_watts: 34.0, ..busy_at(1200.0, 55) }));
+
+    // Has to last, and a single fast sample starts over
+    let at = Duration::from_secs;
+    assert!(!external.update(&busy_at(1200.0, 55), at(1)));
+    assert!(!external.update(&busy_at(1200.0, 55), at(2)));
+    assert!(external.update(&busy_at(1200.0, 55), at(3)));
+    assert!(!external.update(&busy_at(2400.0, 55), at(4)));
+    assert!(!external.update(&busy_at(1200.0, 55), at(5)));
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    out.record(&monitor::external_throttle_record(true)).unwrap();
+    drop(out);
+    let line: serde_json::Value = serde_json::from_slice(&text).unwrap();
+    schema::check("status", &line).unwrap();
+    assert_eq!(line["active"], true);
+
+    let observed = Observation { externally_throttled: true, ..Default::default() };
+    let facts = Facts { observed: Some(observed), base_mhz: 2400.0, ..Default::default() };
+    assert_eq!(causes(&facts), ["external_throttle"]);
+}
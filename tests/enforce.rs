@@ -0,0 +1,58 @@
+use arrctl::enforce::{self, Stats, Strategy};
+use arrctl::journal::Journal;
+use arrctl::msr::{MockMsr, MsrAccess, RegSpec};
+use arrctl::output::{self, Format, Locale};
+use arrctl::regs::MSR_TURBO_LIMITS;
+use arrctl::schema;
+use arrctl::state::Saved;
+use std::time::Duration;
+use std::{env, fs};
+
+#[test]
+fn checks_land_within_the_jitter() {
+    let strategy = Strategy { every: Duration::from_secs(30), jitter: Duration::from_secs(5), firmware: Vec::new() };
+    for seed in 0..100 {
+        let wait = strategy.wait(seed);
+        assert!(wait >= Duration::from_secs(25) && wait <= Duration::from_secs(35), "{:?}", wait);
+    }
+    assert_ne!(strategy.wait(1), strategy.wait(2));
+
+    // Never a busy loop, however it's set
+    let tight = Strategy { every: Duration::from_millis(100), jitter: Duration::from_millis(500), firmware: Vec::new() };
+    assert!((0..100).all(|seed| tight.wait(seed) >= Duration::from_secs(1)));
+}
+
+#[test]
+fn counts_what_the_firmware_put_back() {
+    let path = env::temp_dir().join(format!("arrctl-test-enforce-{}.journal", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let journal = Journal::new(&path);
+    let msr = MockMsr::from_dump("0 0x1ac 0x2").unwrap();
+    let strategy = Strategy { firmware: vec![Saved { cpu: 0, register: MSR_TURBO_LIMITS, value: 0x1 }], ..Default::default() };
+    let desired = [(RegSpec { reg: MSR_TURBO_LIMITS, cpu: 0 }, 0x2)];
+    let mut stats = Stats::default();
+
+    // Still as set
+    enforce::check(&msr, &journal, &desired, &strategy, &mut stats, 1000).unwrap();
+    assert_eq!(stats, Stats { checks: 1, ..Default::default() });
+
+    // Back at the firmware's value
+    msr.write(MSR_TURBO_LIMITS, 0, 0x1).unwrap();
+    enforce::check(&msr, &journal, &desired, &strategy, &mut stats, 2000).unwrap();
+    assert_eq!(msr.read(MSR_TURBO_LIMITS, 0).unwrap(), 0x2);
+    assert_eq!(stats, Stats { checks: 2, rewrites: 1, reverted: 1, last_reverted_ms: Some(2000) });
+
+    // Something else wrote it, written back but not counted against the firmware
+    msr.write(MSR_TURBO_LIMITS, 0, 0x5).unwrap();
+    enforce::check(&msr, &journal, &desired, &strategy, &mut stats, 3000).unwrap();
+    assert_eq!(stats, Stats { checks: 3, rewrites: 2, reverted: 1, last_reverted_ms: Some(2000) });
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    out.record(&stats.record(12_000)).unwrap();
+    drop(out);
+    let value: serde_json::Value = serde_json::from_slice(&text).unwrap();
+    assert_eq!(value["last_reverted_seconds"], 10);
+    schema::check("status", &value).unwrap();
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,160 @@
+use arrctl::features;
+use arrctl::msr::MsrAccess;
+use arrctl::regs::*;
+use serde_json::Value;
+use std::path::Path;
+
+mod common;
+
+use common::msr;
+
+fn setting(doc: &Value, name: &str) -> Value {
+    doc["settings"].as_array().unwrap().iter().find(|s| s["name"] == name).unwrap().clone()
+}
+
+#[test]
+fn lock_state_follows_platform_info() {
+    let msr = msr();
+    let brand = msr.brand.clone().unwrap();
+    let doc = serde_json::to_value(features::describe(&msr, &brand, Path::new("/nonexistent"))).unwrap();
+    assert_eq!(doc["sku"], "i5-520M");
+    assert_eq!(setting(&doc, "tdp")["locked"], false);
+    assert_eq!(setting(&doc, "tdp")["unit"], "W");
+    assert!(doc["settings"].as_array().unwrap().iter().all(|s| s["name"] != "igp_cap"));
+
+    let plat_info = msr.read(MSR_PLATFORM_INFO, 0).unwrap();
+    msr.write(MSR_PLATFORM_INFO, 0, plat_info & !(1 << 29)).unwrap();
+    let doc = serde_json::to_value(features::describe(&msr, &brand, Path::new("/nonexistent"))).unwrap();
+    assert_eq!(setting(&doc, "tdc")["locked"], true);
+    let limits = doc["registers"].as_array().unwrap().iter().find(|r| r["name"] == "MSR_TURBO_LIMITS").unwrap();
+    assert_eq!(limits["scope"], "package");
+    assert!(limits["fields"].as_array().unwrap().iter().all(|f| f["writable"] == false));
+}
+
+// Keeps the schema honest: every object has exactly the keys it lists
+fn check(value: &Value, schema: &Value) {
+    match value {
+        Value::Object(obj) => {
+            let required: Vec<&str> = schema["required"].as_array().unwrap().iter().map(|k| k.as_str().unwrap()).collect();
+            let mut keys: Vec<&str> = obj.keys().map(String::as_str).collect();
+            let mut sorted = required.clone();
+            keys.sort();
+            sorted.sort();
+            assert_eq!(keys, sorted);
+            for (key, value) in obj {
+                check(value, &schema["properties"][key]);
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| check(item, &schema["items"])),
+        _ => (),
+    }
+}
+
+#[test]
+fn document_matches_schema() {
+    let msr = msr();
+    let doc = serde_json::to_value(features::describe(&msr, msr.brand.as_deref().unwrap(), Path::new("/nonexistent"))).unwrap();
+    check(&doc, &features::schema());
+    assert_eq!(doc["schema_version"], features::SCHEMA_VERSION);
+}
+
+#[test]
+fn fields_match_bitfields() {
+    let bits = |reg: u32, name: &str| {
+        let field = lookup(reg).unwrap().fields.iter().find(|f| f.name == name).unwrap();
+        assert!(field.lsb <= field.msb);
+        ((1u64 << (field.msb - field.lsb + 1)) - 1) << field.lsb
+    };
+    assert_eq!(MsrTurboLimits(bits(MSR_TURBO_LIMITS, "tdc")).tdc(), 0x7fff);
+    assert_eq!(MsrTurboLimits(bits(MSR_TURBO_LIMITS, "tdc")).tdp(), 0);
+    assert!(Ia32MiscEnable(bits(IA32_MISC_ENABLE, "turbo_disable")).turbo_disable());
+    assert_eq!(Ia32ClockModulation(bits(IA32_CLOCK_MODULATION, "duty_cycle")).duty_cycle(), 7);
+    assert_eq!(MsrTemperatureTarget(bits(MSR_TEMPERATURE_TARGET, "tjmax")).get(), 0xff);
+    assert_eq!(Ia32PerfStatus(bits(IA32_PERF_STATUS, "vid")).vid(), 0xffff);
+}
+
+#[test]
+fn registry_is_consistent() {
+    assert!(REGISTERS.windows(2).all(|pair| pair[0].address < pair[1].address));
+    for reg in REGISTERS {
+        assert!(reg.supported(MODEL_ARRANDALE), "{}", reg.name);
+        // No two fields share a bit
+        for fields in std::iter::once(reg.fields).chain(reg.overrides.iter().map(|o| o.fields)) {
+            let mut seen = 0u64;
+            for field in fields {
+                assert!(field.lsb <= field.msb && field.msb < 64, "{}.{}", reg.name, field.name);
+                let mask = field.get(u64::MAX) << field.lsb;
+                assert_eq!(seen & mask, 0, "{}.{}", reg.name, field.name);
+                seen |= mask;
+            }
+        }
+        // Nothing writes a field of a register arrctl never writes
+        assert!(reg.writable || reg.fields.iter().all(|f| !f.writable), "{}", reg.name);
+    }
+    assert_eq!(name(MSR_PKG_CST_CONFIG_CONTROL), Some("MSR_PKG_CST_CONFIG_CONTROL"));
+    assert_eq!(scope(IA32_PACKAGE_THERM_STATUS), Scope::Package);
+    assert_eq!(lookup(MSR_TURBO_LIMITS).unwrap().decode(MODEL_ARRANDALE, 0x8080_80c8), [("tdp", 200), ("tdp_override", 1), ("tdc", 128), ("tdc_override", 1)]);
+}
+
+#[test]
+fn models_pick_their_layout() {
+    let ratios = lookup(MSR_TURBO_RATIOS).unwrap();
+    assert_eq!(ratios.decode(MODEL_ARRANDALE, 0x1616_1719_1a1a).len(), 4);
+    let six = ratios.decode(MODEL_WESTMERE_EP, 0x1616_1719_1a1a);
+    assert_eq!(six[4], ("five_cores", 0x16));
+    assert_eq!(six[5], ("six_cores", 0x16));
+
+    // 105 is fine on Arrandale, far too hot for a Xeon
+    let target = lookup(MSR_TEMPERATURE_TARGET).unwrap();
+    assert!(target.field(MODEL_ARRANDALE, "tjmax").unwrap().is_valid(0x690000));
+    assert!(!target.field(MODEL_WESTMERE_EP, "tjmax").unwrap().is_valid(0x690000));
+    // Models without a layout of their own get the default
+    assert_eq!(target.fields_for(0x1e).len(), 1);
+}
+
+#[test]
+fn one_definition_per_register() {
+    // Setters make fields writable, and the registry entry comes along
+    assert!(MsrTurboLimits::FIELDS.iter().all(|f| f.writable));
+    assert!(MsrTurboRatios::FIELDS.iter().all(|f| !f.writable));
+    let flags = |reg: Register| (reg.writable, reg.dumped);
+    assert_eq!(flags(MsrTurboRatios::REGISTER), (true, true));
+    assert_eq!(flags(Ia32PackageThermStatus::REGISTER), (false, false));
+    assert_eq!(Ia32ClockModulation::FIELDS[1].lsb, 4);
+
+    // Named for what it is rather than the getter, with its sane range
+    let tjmax = &MsrTemperatureTarget::FIELDS[0];
+    assert_eq!((tjmax.name, tjmax.lsb, tjmax.msb, tjmax.valid), ("tjmax", 16, 23, Some((60, 110))));
+
+    let reg = lookup(MSR_TURBO_LIMITS).unwrap();
+    assert_eq!(reg.to_json(MODEL_ARRANDALE, 0x8080_80c8), serde_json::json!({ "tdp": 200, "tdp_override": 1, "tdc": 128, "tdc_override": 1 }));
+    assert_eq!(reg.describe(MODEL_ARRANDALE, 0x80c8), "tdp 200, tdp_override 1, tdc 0, tdc_override 0");
+    assert_eq!(lookup(IA32_MPERF).unwrap().describe(MODEL_ARRANDALE, 0x10), "0x10");
+}
+
+#[test]
+fn codes_have_names() {
+    assert_eq!(MsrPkgCstConfigControl(0x8003).limit_state(), Some(CStateLimit::C6));
+    assert_eq!(MsrPkgCstConfigControl(0x0).limit_state(), Some(CStateLimit::C1));
+    assert_eq!(MsrPkgCstConfigControl(0x5).limit_state(), None);
+    assert_eq!("no-limit".parse::<CStateLimit>().unwrap().code(), 7);
+    assert_eq!("c3".parse(), Ok(CStateLimit::C3));
+    assert!("C5".parse::<CStateLimit>().unwrap_err().contains("C1, C3, C6, C7, no-limit"));
+
+    let mut modulation = Ia32ClockModulation(0);
+    modulation.set_duty_cycle(4);
+    assert_eq!(modulation.duty(), DutyCycle::Off);
+    modulation.set_enable(true);
+    assert_eq!(modulation.duty(), DutyCycle::Percent50);
+    assert_eq!(serde_json::to_value(modulation.duty()).unwrap(), "50%");
+
+    assert_eq!(Ia32EnergyPerfBias(6).bias(), Some(EnergyPerfBias::Normal));
+    assert_eq!(Ia32EnergyPerfBias(7).bias(), None);
+    assert_eq!("balance-power".parse(), Ok(EnergyPerfBias::BalancePower));
+
+    // And the registry shows them by name
+    let reg = lookup(MSR_PKG_CST_CONFIG_CONTROL).unwrap();
+    assert_eq!(reg.describe(MODEL_ARRANDALE, 0x8003), "limit C6, lock 1");
+    assert_eq!(reg.to_json(MODEL_ARRANDALE, 0x5)["limit"], 5);
+    assert_eq!(lookup(IA32_CLOCK_MODULATION).unwrap().to_json(MODEL_ARRANDALE, 0x18)["duty_cycle"], "50%");
+}
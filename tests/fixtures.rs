@@ -0,0 +1,261 @@
+use arrctl::msr::{self, MockMsr, MsrAccess, RegSpec};
+use arrctl::output::{self, Format, Human, Locale, Record, Value};
+use arrctl::regs::*;
+use arrctl::{sku, status};
+
+mod common;
+
+use common::fixture;
+
+fn decode(msr: &MockMsr) -> String {
+    let stock = msr.brand.as_deref().and_then(sku::lookup);
+    let mut text = Vec::new();
+    let mut out = Human::new(&mut text);
+    status::tdp(&mut out, msr, stock).unwrap();
+    status::tdc(&mut out, msr, stock).unwrap();
+    status::tjmax(&mut out, msr).unwrap();
+    status::turbo_ratios(&mut out, msr, stock).unwrap();
+    status::voltage(&mut out, msr).unwrap();
+    String::from_utf8(text).unwrap()
+}
+
+fn check_golden(name: &str) {
+    let msr = MockMsr::from_dump(&fixture(&format!("{}.dump", name))).unwrap();
+    assert_eq!(decode(&msr), fixture(&format!("{}.golden", name)));
+}
+
+#[test]
+fn i5_520m_golden() {
+    check_golden("i5-520m");
+}
+
+#[test]
+fn i7_620m_golden() {
+    check_golden("i7-620m");
+}
+
+#[test]
+fn sku_matches_brand() {
+    for (name, sku) in [("i5-520m", "i5-520M"), ("i7-620m", "i7-620M")] {
+        let msr = MockMsr::from_dump(&fixture(&format!("{}.dump", name))).unwrap();
+        assert_eq!(msr.brand.as_deref().and_then(sku::lookup).unwrap().name, sku);
+    }
+}
+
+#[test]
+fn dump_round_trips() {
+    let dump = fixture("i5-520m.dump");
+    let msr = MockMsr::from_dump(&dump).unwrap();
+    let mut out = Vec::new();
+    msr::write_dump(&mut out, &msr, msr.brand.as_deref().unwrap(), msr.microcode).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), dump);
+}
+
+#[test]
+fn dump_register_past_32_bits_is_refused() {
+    let Err(err) = MockMsr::from_dump("0 0x1ac 0x1\n0 0x1000001ac 0x2") else {
+        panic!("0x1000001ac was taken as a register");
+    };
+    assert_eq!(format!("{:#}", err).split(':').next(), Some("Line 2"), "{:#}", err);
+    assert!(err.to_string().contains("bad register"), "{}", err);
+}
+
+#[test]
+fn decoded_fields() {
+    let msr = MockMsr::from_dump(&fixture("i7-620m.dump")).unwrap();
+    assert_eq!(msr.cpus(), [0, 1, 2, 3]);
+
+    let plat_info = msr_platform_info(&msr).unwrap();
+    assert_eq!(plat_info.max_non_turbo_ratio(), 20);
+    assert_eq!(plat_info.minimum_ratio(), 9);
+    assert!(plat_info.programmable_tdc_tdp());
+    assert!(!plat_info.programmable_turbo_ratio());
+
+    assert!(ia32_misc_enable(&msr).unwrap().turbo_disable());
+
+    let therm = ia32_therm_status(&msr, 2).unwrap();
+    assert!(therm.reading_valid());
+    assert_eq!(therm.digital_readout(), 46);
+
+    let limits = msr_turbo_limits(&msr).unwrap();
+    assert_eq!(limits.tdp(), 200);
+    assert!(limits.tdp_override());
+    assert_eq!(limits.tdc(), 384);
+    assert!(!limits.tdc_override());
+}
+
+#[test]
+fn mock_write_then_read() {
+    let msr = MockMsr::from_dump(&fixture("i5-520m.dump")).unwrap();
+    let mut limits = msr_turbo_limits(&msr).unwrap();
+    limits.set_tdp(25 * 8);
+    limits.set_tdp_override(true);
+    msr.write(MSR_TURBO_LIMITS, 0, limits.0).unwrap();
+
+    let limits = msr_turbo_limits(&msr).unwrap();
+    assert_eq!(limits.tdp(), 200);
+    assert!(limits.tdp_override());
+    assert_eq!(limits.tdc(), 384);
+}
+
+#[test]
+fn batch_read_keeps_order() {
+    let msr = MockMsr::from_dump(&fixture("i5-520m.dump")).unwrap();
+    let specs = [
+        RegSpec { reg: IA32_PERF_STATUS, cpu: 3 },
+        RegSpec { reg: MSR_TEMPERATURE_TARGET, cpu: 0 },
+        RegSpec { reg: IA32_PERF_STATUS, cpu: 0 },
+    ];
+    let vals = msr.batch_read(&specs).unwrap();
+    let single: Vec<u64> = specs.iter().map(|s| msr.read(s.reg, s.cpu).unwrap()).collect();
+    assert_eq!(vals, single);
+    assert_eq!(vals[1], 0x690000);
+}
+
+#[test]
+fn machine_readable_formats() {
+    let msr = MockMsr::from_dump(&fixture("i5-520m.dump")).unwrap();
+    let render = |format| {
+        let mut text = Vec::new();
+        let mut out = output::sink(format, Locale::C, false, &mut text);
+        status::tjmax(&mut *out, &msr).unwrap();
+        out.finish().unwrap();
+        drop(out);
+        String::from_utf8(text).unwrap()
+    };
+
+    assert_eq!(render(Format::Json), "{\"celsius\":105,\"kind\":\"tjmax\",\"schema_version\":1}\n");
+    assert_eq!(render(Format::Csv), "kind,cpu,field,value\ntjmax,,celsius,105\n");
+    assert_eq!(render(Format::Prometheus), "# TYPE arrctl_tjmax_celsius gauge\narrctl_tjmax_celsius 105\n");
+    assert!(render(Format::Influx).starts_with("arrctl_tjmax celsius=105i "));
+}
+
+#[test]
+fn influx_line_protocol() {
+    let record = Record::new("voltage").cpu(1).field("volts", "", Value::Fixed(1.1, 4), "V").hidden("ok", true);
+    assert_eq!(output::influx_line(&record, 42), "arrctl_voltage,cpu=1 volts=1.1,ok=true 42");
+
+    let record = Record::new("sku").field("name", "SKU", "i5-520M, ES", "").field("tdp", "", 35u64, "W");
+    assert_eq!(output::influx_line(&record, 42), "arrctl_sku,name=i5-520M\\,\\ ES tdp=35i 42");
+
+    // Nothing numeric, so the text has to be the field
+    let record = Record::new("sku").field("name", "SKU", "say \"hi\"", "");
+    assert_eq!(output::influx_line(&record, 42), "arrctl_sku name=\"say \\\"hi\\\"\" 42");
+}
+
+#[test]
+fn locale_decimal_point() {
+    assert_eq!(Locale::parse("de_DE.UTF-8").decimal_point, ',');
+    assert_eq!(Locale::parse("de_CH.UTF-8").decimal_point, '.');
+    assert_eq!(Locale::parse("en_US.UTF-8").decimal_point, '.');
+    assert_eq!(Locale::parse("C").decimal_point, '.');
+
+    let msr = MockMsr::from_dump(&fixture("i5-520m.dump")).unwrap();
+    let mut text = Vec::new();
+    status::voltage(&mut Human::with_locale(&mut text, Locale::parse("fr_FR")), &msr).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.starts_with("CPU0 voltage: 0,"), "{}", text);
+}
+
+#[test]
+fn platform_id() {
+    let msr = MockMsr::from_dump(&fixture("i7-620m.dump")).unwrap();
+    let mut text = Vec::new();
+    status::platform(&mut Human::new(&mut text), &msr, msr.brand.as_deref().unwrap()).unwrap();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "Platform: ID 4, fused max ratio 20, ratio unlocked: false, engineering sample: false\n\
+         Ratios are fused on this part, flex ratio changes won't do anything\n"
+    );
+    assert!(sku::engineering_sample("Genuine Intel(R) CPU           @ 2.40GHz"));
+}
+
+#[test]
+fn turbo_mhz_marks_overrides() {
+    let msr = MockMsr::from_dump(&fixture("i5-520m.dump")).unwrap();
+    msr.write(MSR_TURBO_RATIOS, 0, 0x1416).unwrap();
+    let mut text = Vec::new();
+    status::turbo_ratios(&mut Human::new(&mut text), &msr, msr.brand.as_deref().and_then(sku::lookup)).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("With 1 active core: 2933 MHz, fused 2933 MHz\n"), "{}", text);
+    assert!(text.contains("With 2 active cores: 2667 MHz, fused 2667 MHz\n"), "{}", text);
+
+    msr.write(MSR_TURBO_RATIOS, 0, 0x1415).unwrap();
+    let mut text = Vec::new();
+    status::turbo_ratios(&mut Human::new(&mut text), &msr, msr.brand.as_deref().and_then(sku::lookup)).unwrap();
+    assert!(String::from_utf8(text).unwrap().contains("With 1 active core: 2800 MHz, fused 2933 MHz, overridden\n"));
+}
+
+#[test]
+fn color_marks_spec_and_locks() {
+    let msr = MockMsr::from_dump(&fixture("i7-620m.dump")).unwrap();
+    let stock = msr.brand.as_deref().and_then(sku::lookup);
+    let mut text = Vec::new();
+    let mut out = Human::new(&mut text).color(true);
+    status::tdp(&mut out, &msr, stock).unwrap();
+    status::platform(&mut out, &msr, msr.brand.as_deref().unwrap()).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("Maximum turbo TDP: \x1b[32m25 W\x1b[0m\n"), "{:?}", text);
+    assert!(text.contains("Turbo TDP override status: \x1b[33mtrue\x1b[0m\n"), "{:?}", text);
+    assert!(text.contains("ratio unlocked: \x1b[31mfalse\x1b[0m"), "{:?}", text);
+    // Nothing to judge, nothing colored
+    assert!(text.contains("Stock TDP of i7-620M: 35 W\n"), "{:?}", text);
+
+    // The goldens above are uncolored, as is anything not sent to a terminal
+    let mut limits = MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap());
+    limits.set_tdp(100 * 8);
+    msr.write(MSR_TURBO_LIMITS, 0, limits.0).unwrap();
+    let mut text = Vec::new();
+    status::tdp(&mut Human::new(&mut text).color(true), &msr, stock).unwrap();
+    assert!(String::from_utf8(text).unwrap().contains("\x1b[33m100 W\x1b[0m"));
+}
+
+#[test]
+fn thermal_scopes() {
+    let msr = MockMsr::from_dump(&fixture("i5-520m.dump")).unwrap();
+    let mut text = Vec::new();
+    status::thermal(&mut Human::new(&mut text), &msr, false).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.starts_with("CPU0 core temperature: 47 celsius, throttling false, "), "{}", text);
+    assert!(text.contains("CPU3 core temperature: 50 celsius"), "{}", text);
+    assert!(text.ends_with("This CPU has no package sensor, the hottest core is the closest reading there is\n"), "{}", text);
+
+    // 57 celsius, throttling now and logged
+    msr.write(IA32_PACKAGE_THERM_STATUS, 0, 0x0030_0003).unwrap();
+    let mut json = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut json);
+    status::thermal(&mut *out, &msr, true).unwrap();
+    out.finish().unwrap();
+    drop(out);
+    let lines: Vec<serde_json::Value> = String::from_utf8(json).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[..4].iter().all(|l| l["scope"] == "core"));
+    assert_eq!(lines[4]["scope"], "package");
+    assert_eq!(lines[4]["celsius"], 57);
+    assert_eq!(lines[4]["throttling"], true);
+    assert_eq!(lines[4]["prochot"], false);
+    assert!(lines[4].get("cpu").is_none());
+}
+
+#[test]
+fn missing_registers_only_take_out_their_part() {
+    // A stepping without the turbo ratios register, and CPU1's VID unreadable
+    let dump: String = fixture("i5-520m.dump").lines().filter(|line| !line.contains(" 0x1ad ") && !line.starts_with("1 0x198 ")).map(|line| format!("{}\n", line)).collect();
+    let msr = MockMsr::from_dump(&dump).unwrap();
+    let stock = msr.brand.as_deref().and_then(sku::lookup);
+    let mut text = Vec::new();
+    let mut out = Human::new(&mut text);
+    status::section(&mut out, "turbo_ratios", |out| status::turbo_ratios(out, &msr, stock)).unwrap();
+    status::section(&mut out, "tjmax", |out| status::tjmax(out, &msr)).unwrap();
+    status::section(&mut out, "voltage", |out| status::voltage(out, &msr)).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.starts_with("Turbo ratios: unavailable (Failed to read MSR 0x1ad on CPU0)\nTJmax: 105 celsius\nCPU0 voltage: "), "{}", text);
+    assert!(text.contains("Voltage: unavailable (Failed to read MSR 0x198 on CPU1)\nCPU2 voltage: "), "{}", text);
+
+    let mut out = Vec::new();
+    msr::write_dump(&mut out, &msr, msr.brand.as_deref().unwrap(), msr.microcode).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("# MSR_TURBO_RATIOS on CPU3: unavailable (Failed to read MSR 0x1ad on CPU3)\n"), "{}", out);
+    // And still loads back
+    assert_eq!(MockMsr::from_dump(&out).unwrap().read(IA32_PERF_STATUS, 1).ok(), None);
+}
@@ -0,0 +1,79 @@
+use arrctl::apply;
+use arrctl::cpu::CpuInfo;
+use arrctl::error::{self, ErrorKind};
+use arrctl::freq::{self, Mechanism};
+use arrctl::msr::RegSpec;
+use arrctl::regs::*;
+use std::{env, fs};
+
+mod common;
+
+// The i5-520M: base ratio 18, lowest 9, bins of 22 and 20 that can't be
+// programmed
+const PLATFORM_INFO: u64 = 0x0000_0900_2000_1210;
+const FUSED: [u64; 2] = [22, 20];
+const BCLK: f64 = 133.33;
+
+#[test]
+fn picks_a_mechanism_per_ratio() {
+    let plat_info = MsrPlatformInfo(PLATFORM_INFO | 1 << 28);
+
+    let plan = freq::plan(&plat_info, &FUSED, 2660.0, BCLK).unwrap();
+    assert_eq!((plan.ratio, plan.mechanism), (20, Mechanism::TurboRatios));
+    assert_eq!(plan.profile.turbo_ratios, Some(vec![20, 20]));
+    assert_eq!(plan.profile.turbo, Some(true));
+
+    let plan = freq::plan(&plat_info, &FUSED, 1600.0, BCLK).unwrap();
+    assert_eq!((plan.ratio, plan.mechanism), (12, Mechanism::PerfCtl));
+    assert_eq!((plan.profile.pinned_ratio, plan.profile.turbo), (Some(12), Some(false)));
+    assert!(plan.profile.problems().is_empty());
+
+    // Past the top bin puts the fused bins back
+    let plan = freq::plan(&plat_info, &FUSED, 3000.0, BCLK).unwrap();
+    assert_eq!(plan.mechanism, Mechanism::Uncapped);
+    assert_eq!(plan.profile.turbo_ratios, Some(FUSED.to_vec()));
+
+    let err = freq::plan(&plat_info, &FUSED, 1000.0, BCLK).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+    assert!(err.to_string().contains("1200 MHz"), "{}", err);
+}
+
+#[test]
+fn locked_bins_fall_back_to_turbo_off() {
+    let plat_info = MsrPlatformInfo(PLATFORM_INFO);
+
+    let plan = freq::plan(&plat_info, &FUSED, 2400.0, BCLK).unwrap();
+    assert_eq!(plan.mechanism, Mechanism::TurboOff);
+    assert_eq!((plan.profile.turbo, plan.profile.turbo_ratios), (Some(false), None));
+
+    let err = freq::plan(&plat_info, &FUSED, 2660.0, BCLK).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::RegisterLocked);
+    assert!(err.to_string().contains("turbo off at 2400 MHz"), "{}", err);
+}
+
+#[test]
+fn pins_every_thread() {
+    let msr = common::msr();
+    let layout = [(0, 0), (1, 0), (2, 2), (3, 2)].map(|(cpu, core)| CpuInfo { cpu, core, package: 0 });
+    let plan = freq::for_cpu(&msr, None, "1.6GHz").unwrap();
+    assert_eq!(plan.bclk_mhz, arrctl::cpu::BCLK_MHZ as f64);
+
+    let writes = apply::register_writes(&msr, &plan.profile, &layout, false).unwrap();
+    let pinned: Vec<_> = writes.iter().filter(|(spec, _)| spec.reg == IA32_PERF_CTL).collect();
+    assert_eq!(pinned.len(), 4);
+    assert!(pinned.iter().all(|(_, val)| Ia32PerfCtl(*val).ratio() == 12));
+    assert!(writes.contains(&(RegSpec { reg: IA32_MISC_ENABLE, cpu: 0 }, 0x0000_0040_0085_0089)));
+}
+
+#[test]
+fn finds_governors_that_undo_a_pin() {
+    let sysfs = env::temp_dir().join(format!("arrctl-test-governors-{}", std::process::id()));
+    for (cpu, governor) in [(0, "userspace"), (1, "ondemand")] {
+        let dir = sysfs.join(format!("cpu{}/cpufreq", cpu));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("scaling_governor"), format!("{}\n", governor)).unwrap();
+    }
+    // CPU2 has no cpufreq at all
+    assert_eq!(freq::stepping_governors(&sysfs, &[0, 1, 2]), vec![(1, "ondemand".to_string())]);
+    fs::remove_dir_all(&sysfs).unwrap();
+}
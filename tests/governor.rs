@@ -0,0 +1,108 @@
+use arrctl::governor::{self, Controller};
+use arrctl::journal::Journal;
+use arrctl::monitor::Sampler;
+use arrctl::msr::MsrAccess;
+use arrctl::profile::{ControllerKind, Governor};
+use arrctl::regs::*;
+use std::fs;
+use std::time::{Duration, Instant};
+
+mod common;
+
+fn settings() -> Governor {
+    Governor {
+        target_celsius: 85.into(),
+        min_tdp: 10,
+        max_tdp: 25,
+        controller: ControllerKind::Step,
+        step: 1,
+        hysteresis_celsius: 3,
+        min_interval_secs: 5,
+        kp: 0.5,
+        ki: 0.025,
+        kd: 0.0,
+    }
+}
+
+fn pid() -> Governor {
+    Governor { controller: ControllerKind::Pid, min_interval_secs: 0, kp: 1.0, ki: 0.1, ..settings() }
+}
+
+#[test]
+fn holds_still_inside_the_band() {
+    let start = Instant::now();
+    let mut controller = Controller::new(settings(), 20, 105);
+    assert_eq!(controller.update(86, start), Some(19));
+    // Too soon after the last write, however hot
+    assert_eq!(controller.update(95, start + Duration::from_secs(2)), None);
+    assert_eq!(controller.update(95, start + Duration::from_secs(5)), Some(18));
+    // Between 82 and 85 nothing moves, that's what keeps it from oscillating
+    for celsius in 82..=85 {
+        assert_eq!(controller.update(celsius, start + Duration::from_secs(60)), None);
+    }
+    assert_eq!(controller.update(81, start + Duration::from_secs(60)), Some(19));
+    assert_eq!(controller.watts(), 19);
+    assert_eq!(controller.adjustments, 3);
+    assert_eq!(controller.per_hour(start + Duration::from_secs(60)), 3);
+    assert_eq!(controller.per_hour(start + Duration::from_secs(3650)), 1);
+}
+
+#[test]
+fn stays_within_limits() {
+    let start = Instant::now();
+    // Stock 35 W is above max_tdp and comes down even in the band
+    let mut controller = Controller::new(settings(), 35, 105);
+    assert_eq!(controller.update(84, start), Some(25));
+    assert_eq!(controller.update(70, start + Duration::from_secs(10)), None);
+
+    let mut controller = Controller::new(settings(), 10, 105);
+    assert_eq!(controller.update(99, start), None);
+    assert_eq!(controller.adjustments, 0);
+
+    let record = controller.record(99, start);
+    assert_eq!(record.kind, "governor");
+    assert_eq!(record.fields.last().unwrap().key, "adjustments_per_hour");
+}
+
+#[test]
+fn pid_doesnt_wind_up() {
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut controller = Controller::new(pid(), 25, 105);
+    // A long cool spell pins it at max_tdp without banking any integral
+    for secs in 0..600 {
+        assert_eq!(controller.update(60, at(secs)), None);
+    }
+    assert_eq!(controller.terms.i, 0.0);
+
+    // So the first degree over is felt right away
+    assert_eq!(controller.update(90, at(600)), Some(20));
+    assert_eq!(controller.update(90, at(610)), Some(15));
+    assert_eq!(controller.terms.i, 5.5);
+    // Pinned at min_tdp, the integral stops there too
+    for secs in 611..700 {
+        controller.update(95, at(secs));
+    }
+    assert_eq!(controller.watts(), 10);
+    let pinned = controller.terms.i;
+    controller.update(95, at(800));
+    assert_eq!(controller.terms.i, pinned);
+
+    let record = controller.record(95, at(800));
+    assert_eq!(record.fields.iter().map(|f| f.key).collect::<Vec<_>>()[5..], ["p", "i", "d"]);
+}
+
+#[test]
+fn ticks_write_the_tdp() {
+    let msr = common::msr();
+    let journal = Journal::new(std::env::temp_dir().join(format!("arrctl-govern-{}.journal", std::process::id())));
+    let mut sampler = Sampler::new(&msr).unwrap();
+    let sample = sampler.sample(&msr).unwrap();
+
+    // 50 celsius against 45, from the stock 35 W
+    let mut controller = Controller::new(Governor { target_celsius: 45.into(), ..pid() }, 35, 105);
+    let record = governor::tick(&msr, &journal, &mut controller, &sample, Instant::now()).unwrap().unwrap();
+    assert_eq!(MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp(), 20 * 8);
+    assert_eq!(record.kind, "governor");
+    let _ = fs::remove_file(journal.path());
+}
@@ -0,0 +1,59 @@
+use arrctl::histogram::{Histogram, Histograms};
+use arrctl::history::Point;
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::output::{self, Format, Locale};
+use arrctl::power::CoreSample;
+use arrctl::schema;
+
+fn sample(mhz: f32, celsius: u64) -> Sample {
+    let cpu = |cpu, celsius| CpuSample { cpu, activity: CoreSample { effective_mhz: mhz, active: 1.0, volts: 1.1 }, celsius: Some(celsius), throttling: false };
+    Sample { cpus: vec![cpu(0, celsius), cpu(1, celsius - 3)], package_watts: 20.0, core_amps: 15.0, core_amps_at_tjmax: 18.0 }
+}
+
+#[test]
+fn buckets_by_ratio_and_band() {
+    let mut histograms = Histograms::default();
+    for _ in 0..3 {
+        histograms.add_sample(&sample(2930.0, 74), 1.0);
+    }
+    // A little off the ratio still counts for it
+    histograms.add_sample(&sample(2940.0, 75), 1.0);
+    histograms.add_sample(&sample(2400.0, 81), 2.0);
+    assert_eq!(histograms.frequency.seconds.get(&22), Some(&4.0));
+    assert_eq!(histograms.frequency.seconds.get(&18), Some(&2.0));
+    // The hottest core decides the band
+    assert_eq!(histograms.temperature.seconds.get(&14), Some(&3.0));
+    assert_eq!(histograms.temperature.seconds.get(&15), Some(&1.0));
+    assert_eq!(histograms.temperature.seconds.get(&16), Some(&2.0));
+
+    let records = histograms.records();
+    assert_eq!(records.len(), 5);
+    assert!(records[3].title.as_deref().unwrap().starts_with("75-79 celsius"));
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    for record in &records {
+        out.record(record).unwrap();
+    }
+    drop(out);
+    let values: Vec<serde_json::Value> = text.split(|&b| b == b'\n').filter(|line| !line.is_empty()).map(|line| serde_json::from_slice(line).unwrap()).collect();
+    assert_eq!(values[0]["metric"], "frequency");
+    assert_eq!(values[1]["seconds"], 4.0);
+    for value in &values {
+        schema::check("replay", value).unwrap();
+        schema::check("status", value).unwrap();
+    }
+}
+
+#[test]
+fn weighs_history_by_duration() {
+    let point = |seconds, mean_mhz, max_celsius| Point { time_ms: 0, seconds, samples: seconds, mean_watts: 15.0, max_watts: 20.0, mean_mhz, max_celsius, throttled: false };
+    let mut histograms = Histograms::default();
+    histograms.add_point(&point(60, 1200.0, Some(52)));
+    histograms.add_point(&point(180, 1200.0, None));
+    assert_eq!(histograms.frequency.seconds.get(&9), Some(&240.0));
+    assert_eq!(histograms.temperature.seconds.get(&10), Some(&60.0));
+
+    // Nothing seen, nothing shown
+    assert!(Histogram::temperature().records().is_empty());
+}
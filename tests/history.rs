@@ -0,0 +1,96 @@
+use arrctl::history::{self, History};
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::output::{self, Format, Locale};
+use arrctl::power::CoreSample;
+use arrctl::schema;
+use std::time::Duration;
+
+fn sample(watts: f32, celsius: u64, throttling: bool) -> Sample {
+    let cpu = |cpu| CpuSample { cpu, activity: CoreSample { effective_mhz: 2400.0, active: 1.0, volts: 1.1 }, celsius: Some(celsius), throttling };
+    Sample { cpus: vec![cpu(0), cpu(1)], package_watts: watts, core_amps: 30.0, core_amps_at_tjmax: 35.0 }
+}
+
+const HOUR_MS: u64 = 3_600_000;
+
+#[test]
+fn merges_samples_into_points() {
+    let mut history = History::new(Duration::from_secs(24 * 3600));
+    // One second samples, a hot throttling one in the middle
+    for secs in 0..10 {
+        history.push(secs * 1000, &sample(if secs == 5 { 40.0 } else { 20.0 }, if secs == 5 { 95 } else { 60 }, secs == 5));
+    }
+    history.push(10_000, &sample(10.0, 50, false));
+    let points = history.since(0);
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].samples, 10);
+    assert_eq!(points[0].mean_watts, 22.0);
+    assert_eq!(points[0].max_watts, 40.0);
+    assert_eq!(points[0].max_celsius, Some(95));
+    assert!(points[0].throttled);
+    assert!(!points[1].throttled);
+    assert_eq!(history.since(10_000).len(), 1);
+}
+
+#[test]
+fn downsamples_and_forgets() {
+    let mut history = History::new(Duration::from_secs(24 * 3600));
+    // A sample a second for a day and a half, only every 10th to keep it quick
+    for secs in (0..36 * 3600).step_by(10) {
+        history.push(secs * 1000, &sample(20.0, 60, secs == 3 * 3600));
+    }
+    let now = 36 * HOUR_MS;
+    let points = history.since(0);
+    // Nothing from before the last 24 hours
+    assert!(points[0].time_ms + points[0].seconds * 1000 > now - 24 * HOUR_MS - 60_000);
+    // The last hour at 10 s, the rest of the day at a minute
+    let fine = points.iter().filter(|p| p.seconds == history::FINE.as_secs()).count();
+    assert!((360..=361).contains(&fine));
+    assert!(points.iter().filter(|p| p.seconds == 60).count() >= 23 * 60);
+    assert!(points.len() < 1800 + 360);
+    assert!(points.iter().all(|p| !p.throttled));
+    // Oldest first
+    assert!(points.windows(2).all(|w| w[0].time_ms < w[1].time_ms));
+    assert_eq!(history.since(now - HOUR_MS / 2).iter().map(|p| p.samples).sum::<u64>(), 180);
+}
+
+#[test]
+fn records_what_the_schema_says() {
+    let mut history = History::new(Duration::from_secs(3600));
+    history.push(0, &sample(20.0, 60, true));
+    let point = &history.since(0)[0];
+    let record = point.record(90 * 60 * 1000);
+    assert!(record.title.as_deref().unwrap().contains("1h30m"));
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    out.record(&record).unwrap();
+    drop(out);
+    let value: serde_json::Value = serde_json::from_slice(&text).unwrap();
+    assert_eq!(value["kind"], "history");
+    assert_eq!(value["max_celsius"], 60);
+    schema::check("history", &value).unwrap();
+
+    // What the socket sends back
+    let json = serde_json::to_string(&history.since(0)).unwrap();
+    let back: Vec<history::Point> = serde_json::from_str(&json).unwrap();
+    assert_eq!(&back[0], point);
+}
+
+#[test]
+fn survives_a_restart() {
+    let path = std::env::temp_dir().join(format!("arrctl-test-history-{}.json", std::process::id()));
+    let mut history = History::new(Duration::from_secs(24 * 3600));
+    for secs in (0..2 * 3600).step_by(10) {
+        history.push(secs * 1000, &sample(20.0, 60, false));
+    }
+    history.save(&path).unwrap();
+    let back = History::load(&path, Duration::from_secs(24 * 3600), 2 * HOUR_MS).unwrap();
+    assert_eq!(back.since(0), history.since(0));
+    // Only what's still within retention comes back
+    let later = History::load(&path, Duration::from_secs(24 * 3600), 25 * HOUR_MS + HOUR_MS / 2).unwrap();
+    assert!(later.since(0).len() < history.since(0).len());
+
+    std::fs::write(&path, std::fs::read_to_string(&path).unwrap().replace("\"samples\":6", "\"samples\":7")).unwrap();
+    assert!(History::load(&path, Duration::from_secs(24 * 3600), 2 * HOUR_MS).is_err());
+    assert!(History::load(&path.with_extension("missing"), Duration::from_secs(3600), 0).unwrap().since(0).is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
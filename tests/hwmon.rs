@@ -0,0 +1,102 @@
+use arrctl::cpu::CpuInfo;
+use arrctl::hwmon::{self, Coretemp, Divergence, TempSource};
+use arrctl::monitor::{self, Sampler};
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+
+use common::msr;
+
+// Two cores with two threads each, like the fixture
+fn layout() -> Vec<CpuInfo> {
+    [(0, 0), (1, 0), (2, 2), (3, 2)].map(|(cpu, core)| CpuInfo { cpu, core, package: 0 }).to_vec()
+}
+
+// An ACPI zone that isn't coretemp and coretemp without a package sensor
+fn class(name: &str, core2_millidegrees: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("arrctl-hwmon-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("hwmon0")).unwrap();
+    fs::write(dir.join("hwmon0/name"), "acpitz\n").unwrap();
+    fs::write(dir.join("hwmon0/temp1_input"), "99000\n").unwrap();
+    let coretemp = dir.join("hwmon1");
+    fs::create_dir_all(&coretemp).unwrap();
+    fs::write(coretemp.join("name"), "coretemp\n").unwrap();
+    fs::write(coretemp.join("temp2_label"), "Core 0\n").unwrap();
+    fs::write(coretemp.join("temp2_input"), "46600\n").unwrap();
+    fs::write(coretemp.join("temp3_label"), "Core 2\n").unwrap();
+    fs::write(coretemp.join("temp3_input"), core2_millidegrees).unwrap();
+    dir
+}
+
+#[test]
+fn maps_cores_to_cpus() {
+    let dir = class("map", "50000\n");
+    let coretemp = Coretemp::open(&dir, &layout()).unwrap();
+    assert_eq!([0, 1, 2, 3].map(|cpu| coretemp.read(cpu)), [Some(47), Some(47), Some(50), Some(50)]);
+    assert_eq!(coretemp.read(4), None);
+
+    // A second package only gets its own cores
+    let package = dir.join("hwmon1/temp1_label");
+    fs::write(&package, "Package id 1\n").unwrap();
+    assert!(Coretemp::open(&dir, &layout()).is_err());
+    let other: Vec<CpuInfo> = layout().into_iter().map(|info| CpuInfo { package: 1, ..info }).collect();
+    assert_eq!(Coretemp::open(&dir, &other).unwrap().read(3), Some(50));
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(Coretemp::open(&dir, &layout()).is_err());
+}
+
+#[test]
+fn picks_source_and_cross_checks() {
+    let msr = msr();
+    let dir = class("sample", "63000\n");
+    let coretemp = Coretemp::open(&dir, &layout()).unwrap();
+
+    let mut sampler = Sampler::new(&msr).unwrap().with_coretemp(coretemp.clone(), TempSource::Msr);
+    let sample = sampler.sample(&msr).unwrap();
+    assert_eq!(sample.cpus.iter().map(|c| c.celsius).collect::<Vec<_>>(), [Some(47), Some(47), Some(50), Some(50)]);
+    let expected = [2, 3].map(|cpu| Divergence { cpu, msr_celsius: 50, hwmon_celsius: 63 });
+    assert_eq!(sampler.divergent(), expected);
+
+    let mut sampler = Sampler::new(&msr).unwrap().with_coretemp(coretemp, TempSource::Hwmon);
+    let sample = sampler.sample(&msr).unwrap();
+    assert_eq!(sample.cpus.iter().map(|c| c.celsius).collect::<Vec<_>>(), [Some(47), Some(47), Some(63), Some(63)]);
+    assert_eq!(sampler.divergent().len(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let record = monitor::divergence_record(&expected[0]);
+    assert_eq!(record.kind, "temp_divergence");
+
+    // Within the margin is the same reading
+    assert_eq!(hwmon::diverges(0, Some(50), Some(60)), None);
+    assert_eq!(hwmon::diverges(0, None, Some(90)), None);
+}
+
+#[test]
+fn finds_fans() {
+    let dir = class("fans", "50000\n");
+    fs::write(dir.join("hwmon1/fan1_input"), "bogus\n").unwrap();
+    for (device, name, files) in [
+        ("hwmon10", "applesmc", &[("fan1_label", "Exhaust  \n"), ("fan1_input", "2000\n"), ("fan2_input", "1800\n")][..]),
+        ("hwmon2", "thinkpad", &[("fan1_input", "3412\n"), ("pwm1", "128\n")][..]),
+    ] {
+        let dev = dir.join(device);
+        fs::create_dir_all(&dev).unwrap();
+        fs::write(dev.join("name"), format!("{}\n", name)).unwrap();
+        for (file, text) in files {
+            fs::write(dev.join(file), text).unwrap();
+        }
+    }
+
+    let fans = hwmon::fans(&dir);
+    let names: Vec<(&str, &str)> = fans.iter().map(|f| (f.device.as_str(), f.label.as_str())).collect();
+    assert_eq!(names, [("coretemp", "fan1"), ("thinkpad", "fan1"), ("applesmc", "Exhaust"), ("applesmc", "fan2")]);
+    assert_eq!(fans.iter().map(|f| f.rpm()).collect::<Vec<_>>(), [None, Some(3412), Some(2000), Some(1800)]);
+
+    let record = hwmon::fan_record(&fans[1], 3412);
+    assert_eq!(record.title.as_deref(), Some("thinkpad fan1"));
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(hwmon::fans(&dir).is_empty());
+}
@@ -0,0 +1,61 @@
+use arrctl::influx::{self, Url};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+#[test]
+fn url_forms() {
+    let url = Url::parse("http://grafana:8086/api/v2/write?org=home&bucket=arrctl").unwrap();
+    assert_eq!(url, Url { host: "grafana".into(), port: 8086, path: "/api/v2/write?org=home&bucket=arrctl".into() });
+    assert_eq!(Url::parse("http://10.0.0.2").unwrap().port, 8086);
+    assert!(Url::parse("https://grafana/write").is_err());
+    assert!(Url::parse("http://:8086/write").is_err());
+}
+
+fn serve_once(status: &'static str) -> (u16, thread::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(&stream);
+        let mut head = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(n) = line.to_ascii_lowercase().strip_prefix("content-length: ") {
+                length = n.trim().parse().unwrap();
+            }
+            head += &line;
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        (&stream).write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).as_bytes()).unwrap();
+        head + &String::from_utf8(body).unwrap()
+    });
+    (port, server)
+}
+
+#[test]
+fn push_posts_line_protocol() {
+    let (port, server) = serve_once("204 No Content");
+    let url = Url { host: "127.0.0.1".into(), port, path: "/write?db=arrctl".into() };
+    influx::push(&url, Some("secret"), "arrctl_tjmax celsius=105i 42\n").unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /write?db=arrctl HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains("Authorization: Token secret\r\n"), "{}", request);
+    assert!(request.ends_with("\r\n\r\narrctl_tjmax celsius=105i 42\n"), "{}", request);
+}
+
+#[test]
+fn refused_write_is_an_error() {
+    let (port, server) = serve_once("401 Unauthorized");
+    let url = Url { host: "127.0.0.1".into(), port, path: "/write".into() };
+    let err = influx::push(&url, None, "x v=1i 1\n").unwrap_err();
+    assert!(err.to_string().contains("401"), "{}", err);
+    server.join().unwrap();
+}
@@ -0,0 +1,83 @@
+use arrctl::init::{self, Answers, Given, Goal, Prompt, Setup};
+use arrctl::output::{self, Format, Locale};
+use arrctl::{profile, schema, sku};
+use std::path::PathBuf;
+use std::{env, fs};
+
+mod common;
+
+use common::msr;
+
+fn dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("arrctl-test-init-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn setup(dir: &std::path::Path) -> Setup {
+    fs::write(dir.join("passwd"), "root:x:0:0::/root:/bin/sh\n").unwrap();
+    Setup { config: dir.join("profiles.toml"), overwrite: false, exe: "/usr/bin/arrctl".into(), passwd: dir.join("passwd") }
+}
+
+#[test]
+fn targets_follow_the_goal() {
+    assert_eq!(Goal::Performance.targets(35), (35, 26));
+    assert_eq!(Goal::Quiet.targets(35), (23, 17));
+    // Never under the floor, even on an 18 W part
+    assert_eq!(Goal::Battery.targets(18), (13, init::MIN_TDP));
+}
+
+#[test]
+fn writes_profiles_and_a_unit() {
+    let dir = dir("flags");
+    let setup = setup(&dir);
+    let stock = sku::lookup("Intel(R) Core(TM) i5 CPU M 520 @ 2.40GHz");
+    let given = Given { goal: Some(Goal::Battery), battery_tdp: Some(12), systemd: Some(dir.clone()), ..Default::default() };
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    init::run(&mut *out, &msr(), stock, &setup, given.clone(), None).unwrap();
+    drop(out);
+
+    let profiles = profile::load(&setup.config).unwrap();
+    assert_eq!(profiles.profiles["ac"].tdp, Some(26));
+    assert_eq!(profiles.profiles["battery"].tdp, Some(12));
+    assert!(profiles.profiles.values().all(|p| p.problems().is_empty()));
+    assert_eq!(profiles.emergency.unwrap().profile, "battery");
+
+    // No arrctl user in that passwd, so the daemon stays root
+    let unit = fs::read_to_string(dir.join(init::UNIT)).unwrap();
+    assert!(unit.contains("daemon --config") && unit.contains("--apply ac --apply-on-battery battery --user root"), "{}", unit);
+    assert!(unit.contains("Type=notify") && unit.contains("Requires=arrctl.socket"), "{}", unit);
+    let socket = fs::read_to_string(dir.join(init::SOCKET_UNIT)).unwrap();
+    assert!(socket.contains("ListenStream=/run/arrctl.sock"), "{}", socket);
+
+    let last = text.split(|&b| b == b'\n').rfind(|line| !line.is_empty()).unwrap();
+    let value: serde_json::Value = serde_json::from_slice(last).unwrap();
+    assert_eq!(value["goal"], "battery");
+    schema::check("init", &value).unwrap();
+
+    // A second run leaves the first one's file alone
+    let err = init::run(&mut *output::sink(Format::Json, Locale::C, false, Vec::new()), &msr(), stock, &setup, given, None).unwrap_err();
+    assert!(err.to_string().contains("--overwrite"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn asks_for_what_isnt_given() {
+    let mut input: &[u8] = b"loud\nperformance\n\n20W\n";
+    let mut asked = Vec::new();
+    let mut prompt = Prompt { input: &mut input, output: &mut asked };
+    let given = Given { systemd: Some("/tmp".into()), ..Default::default() };
+    let answers = init::answers(given, 35, Some(&mut prompt)).unwrap();
+    assert_eq!(answers, Answers { goal: Goal::Performance, ac_tdp: 35, battery_tdp: 20, systemd: Some("/tmp".into()) });
+    let asked = String::from_utf8(asked).unwrap();
+    assert!(asked.contains("battery, quiet or performance [quiet]: "), "{}", asked);
+    assert!(asked.contains("[35W]: "), "{}", asked);
+
+    // Running out of input stops rather than guessing
+    let mut input: &[u8] = b"";
+    let mut prompt = Prompt { input: &mut input, output: &mut Vec::new() };
+    assert!(init::answers(Given::default(), 35, Some(&mut prompt)).is_err());
+    assert!(init::answers(Given::default(), 35, None).is_err());
+}
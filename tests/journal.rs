@@ -0,0 +1,114 @@
+use anyhow::{bail, Result};
+use arrctl::audit;
+use arrctl::journal::Journal;
+use arrctl::msr::{MockMsr, MsrAccess, RegSpec};
+use arrctl::regs::MSR_TURBO_LIMITS;
+use std::fs;
+
+mod common;
+
+fn journal(name: &str) -> Journal {
+    Journal::new(common::temp(&format!("{}.journal", name)))
+}
+
+#[test]
+fn completed_set_leaves_nothing_pending() {
+    let journal = journal("complete");
+    let msr = MockMsr::from_dump("0 0x1ac 0x1").unwrap();
+    journal.apply(&msr, &[(RegSpec { reg: MSR_TURBO_LIMITS, cpu: 0 }, 0x2)]).unwrap();
+
+    assert_eq!(msr.read(MSR_TURBO_LIMITS, 0).unwrap(), 0x2);
+    assert_eq!(journal.pending().unwrap(), None);
+    assert!(journal.recover(&msr).is_err());
+    fs::remove_file(journal.path()).unwrap();
+}
+
+#[test]
+fn interrupted_set_is_recovered() {
+    let journal = journal("interrupted");
+    fs::write(journal.path(), "begin 100\nold 0 0x1ac 0x1\nold 1 0x1ac 0x3\nnew 0 0x1ac 0x2\nnew 1 0x1ac 0x2\n").unwrap();
+    let msr = MockMsr::from_dump("0 0x1ac 0x2\n1 0x1ac 0x3").unwrap();
+
+    let pending = journal.pending().unwrap().unwrap();
+    assert_eq!(pending.started, 100);
+    assert_eq!(pending.old.len(), 2);
+
+    // Nothing gets written over it until it's recovered or forced
+    let write = [(RegSpec { reg: MSR_TURBO_LIMITS, cpu: 0 }, 0x4)];
+    assert!(journal.apply(&msr, &write).is_err());
+    assert_eq!(msr.read(MSR_TURBO_LIMITS, 0).unwrap(), 0x2);
+    assert!(journal.pending().unwrap().is_some());
+
+    journal.recover(&msr).unwrap();
+    assert_eq!(msr.read(MSR_TURBO_LIMITS, 0).unwrap(), 0x1);
+    assert_eq!(msr.read(MSR_TURBO_LIMITS, 1).unwrap(), 0x3);
+    assert_eq!(journal.pending().unwrap(), None);
+    journal.apply(&msr, &write).unwrap();
+
+    fs::write(journal.path(), "begin 100\nold 0 0x1ac 0x1\n").unwrap();
+    journal.clone().forced().apply(&msr, &write).unwrap();
+    fs::remove_file(journal.path()).unwrap();
+}
+
+// CPU1's register is locked
+struct Locked(MockMsr);
+
+impl MsrAccess for Locked {
+    fn cpus(&self) -> Vec<u16> {
+        self.0.cpus()
+    }
+
+    fn read(&self, reg: u32, cpu: u16) -> Result<u64> {
+        self.0.read(reg, cpu)
+    }
+
+    fn write(&self, reg: u32, cpu: u16, val: u64) -> Result<()> {
+        match cpu {
+            1 => bail!("Failed to write MSR {:#x} on CPU1, it is probably locked", reg),
+            _ => self.0.write(reg, cpu, val),
+        }
+    }
+}
+
+#[test]
+fn failed_set_is_rolled_back() {
+    let journal = journal("failed");
+    let log = journal.path().with_extension("audit");
+    let _ = fs::remove_file(&log);
+    let journal = journal.audited(&log);
+    let msr = Locked(MockMsr::from_dump("0 0x1ac 0x1\n1 0x1ac 0x1\n2 0x1ac 0x1").unwrap());
+    let writes = [0, 1, 2].map(|cpu| (RegSpec { reg: MSR_TURBO_LIMITS, cpu }, 0x2));
+
+    assert!(journal.apply(&msr, &writes).is_err());
+    assert_eq!(msr.read(MSR_TURBO_LIMITS, 0).unwrap(), 0x1);
+    assert_eq!(msr.read(MSR_TURBO_LIMITS, 2).unwrap(), 0x1);
+    assert_eq!(journal.pending().unwrap(), None);
+    // CPU0 there and back, CPU1 and CPU2 never
+    let text = fs::read_to_string(&log).unwrap();
+    assert_eq!(audit::verify(&log).unwrap().entries, 2);
+    assert!(text.lines().all(|line| line.contains(" cpu=0 ")), "{}", text);
+
+    // Only the last set stays in the journal
+    journal.apply(&msr, &writes[..1]).unwrap();
+    journal.apply(&msr, &writes[2..]).unwrap();
+    assert_eq!(fs::read_to_string(journal.path()).unwrap().matches("begin").count(), 1);
+    fs::remove_file(journal.path()).unwrap();
+    fs::remove_file(&log).unwrap();
+    let _ = fs::remove_file(audit::head_path(&log));
+}
+
+#[test]
+fn set_stands_without_the_audit_log() {
+    let journal = journal("unaudited");
+    // A directory can't be appended to
+    let log = journal.path().with_extension("audit.d");
+    fs::create_dir_all(&log).unwrap();
+    let journal = journal.audited(&log);
+    let msr = MockMsr::from_dump("0 0x1ac 0x1").unwrap();
+
+    journal.apply(&msr, &[(RegSpec { reg: MSR_TURBO_LIMITS, cpu: 0 }, 0x2)]).unwrap();
+    assert_eq!(msr.read(MSR_TURBO_LIMITS, 0).unwrap(), 0x2);
+    assert_eq!(journal.pending().unwrap(), None);
+    fs::remove_file(journal.path()).unwrap();
+    fs::remove_dir(&log).unwrap();
+}
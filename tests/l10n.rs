@@ -0,0 +1,131 @@
+use arrctl::l10n::{self, Bundle};
+use arrctl::output::Human;
+use arrctl::{cli, sku, status};
+use std::collections::HashSet;
+
+mod common;
+
+fn leaked(lang: &str) -> &'static Bundle {
+    Box::leak(Box::new(Bundle::new(lang)))
+}
+
+#[test]
+fn parses_messages() {
+    let messages = l10n::parse("# comment\nhello = Hello { $name }!\nlong =\n    First\n      indented\n\nnext = x\n").unwrap();
+    assert_eq!(messages["hello"], "Hello { $name }!");
+    assert_eq!(messages["long"], "First\n  indented");
+    assert_eq!(messages["next"], "x");
+
+    assert!(l10n::parse("    orphan").is_err());
+    assert!(l10n::parse("no equals sign").is_err());
+    assert!(l10n::parse("a = 1\na = 2").unwrap_err().contains("twice"));
+    // Fluent that the subset doesn't do is refused, not shown as written
+    assert!(l10n::parse("n = { $count ->\n    [one] one\n   *[other] many\n}").unwrap_err().contains("selectors"));
+    assert!(l10n::parse("-brand = arrctl").unwrap_err().contains("terms"));
+    assert!(l10n::parse("a = b\n    .title = c").unwrap_err().contains("attributes"));
+    assert!(l10n::parse("a = { -brand } or { other }").unwrap_err().starts_with("Line 1"));
+}
+
+#[test]
+fn fills_placeables() {
+    let bundle = Bundle::new("en");
+    assert_eq!(bundle.format("tdp-stock", &[("sku", "i5-520M")]), "Stock TDP of i5-520M");
+    // Missing arguments stay visible rather than vanishing
+    assert_eq!(bundle.format("tdp-stock", &[]), "Stock TDP of { $sku }");
+    assert_eq!(bundle.format("no-such-message", &[]), "no-such-message");
+}
+
+#[test]
+fn picks_language_from_locale() {
+    assert_eq!(l10n::language("es_AR.UTF-8"), "es");
+    assert_eq!(l10n::language("es"), "es");
+    assert_eq!(l10n::language("en_US.UTF-8"), "en");
+    assert_eq!(l10n::language("de_DE.UTF-8"), "en");
+    assert_eq!(l10n::language("C"), "en");
+    assert_eq!(Bundle::new("fr").lang, "en");
+}
+
+#[test]
+fn catalogs_agree() {
+    let english = Bundle::new("en");
+    let en: HashSet<&str> = english.ids().collect();
+    for (lang, text) in l10n::LANGUAGES {
+        // Bundle::new would quietly fall back to English
+        if let Err(e) = l10n::parse(text) {
+            panic!("{}.ftl: {}", lang, e);
+        }
+        let bundle = Bundle::new(lang);
+        let ids: HashSet<&str> = bundle.ids().collect();
+        let extra: Vec<&&str> = ids.iter().filter(|id| !en.contains(**id) && !id.starts_with("heading-") && **id != "exit-codes").collect();
+        assert!(extra.is_empty(), "{} has ids English doesn't: {:?}", lang, extra);
+        let missing: Vec<&&str> = en.iter().filter(|id| !ids.contains(**id)).collect();
+        assert!(missing.is_empty(), "{} is missing {:?}", lang, missing);
+    }
+}
+
+#[test]
+fn falls_back_to_english() {
+    let bundle = Bundle::new("es");
+    assert_eq!(bundle.get("tjmax"), Some("TJmax"));
+    assert_eq!(bundle.get("unit-busy"), Some("% ocupada"));
+    assert_eq!(Bundle::new("en").get("heading-usage"), None);
+}
+
+// Every about id and, for arguments, the path and id they're looked up by
+fn help_ids(cmd: &clap::Command, path: &str, abouts: &mut Vec<String>, args: &mut Vec<(String, String)>) {
+    abouts.push(if path.is_empty() { "about".to_string() } else { format!("about-{}", path) });
+    for arg in cmd.get_arguments() {
+        args.push((path.to_string(), arg.get_id().to_string()));
+    }
+    for sub in cmd.get_subcommands().filter(|sub| sub.get_name() != "help") {
+        let path = if path.is_empty() { sub.get_name().to_string() } else { format!("{}-{}", path, sub.get_name()) };
+        help_ids(sub, &path, abouts, args);
+    }
+}
+
+#[test]
+fn every_command_has_help() {
+    let bundle = leaked("en");
+    let (mut abouts, mut args) = (Vec::new(), Vec::new());
+    help_ids(&cli::command(bundle), "", &mut abouts, &mut args);
+    for id in abouts {
+        assert!(bundle.get(&id).is_some(), "no help for {}", id);
+    }
+    for (path, arg) in args {
+        let found = bundle.get(&format!("arg-{}-{}", path, arg)).or_else(|| bundle.get(&format!("arg-{}", arg)));
+        assert!(found.is_some(), "no help for {} of {:?}", arg, path);
+    }
+}
+
+#[test]
+fn spanish_help() {
+    let help = cli::command(leaked("es")).render_help().to_string();
+    assert!(help.contains("Lee y ajusta los límites de potencia turbo"), "{}", help);
+    assert!(help.contains("Uso:"), "{}", help);
+    assert!(help.contains("Opciones"), "{}", help);
+    assert!(help.contains("Códigos de salida:"), "{}", help);
+
+    let mut cmd = cli::command(leaked("es"));
+    let soak = cmd.find_subcommand_mut("soak").unwrap().render_help().to_string();
+    assert!(soak.contains("Cuánto tiempo ejecutar"), "{}", soak);
+
+    // English keeps what the derive said, same text as the catalog
+    let english = cli::command(leaked("en")).render_help().to_string();
+    assert!(english.contains("Usage:"), "{}", english);
+    assert!(english.contains("Exit codes:"), "{}", english);
+}
+
+#[test]
+fn spanish_status() {
+    l10n::init("es");
+    let msr = common::msr();
+    let stock = msr.brand.as_deref().and_then(sku::lookup);
+    let mut text = Vec::new();
+    let mut out = Human::new(&mut text);
+    status::tdp(&mut out, &msr, stock).unwrap();
+    status::turbo_ratios(&mut out, &msr, stock).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("TDP turbo máximo: "), "{}", text);
+    assert!(text.contains("Con 2 núcleos activos: "), "{}", text);
+    assert!(text.contains("Multiplicadores de fábrica del i5-520M:"), "{}", text);
+}
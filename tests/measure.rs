@@ -0,0 +1,60 @@
+use arrctl::battery::Discharge;
+use arrctl::error::{self, ErrorKind};
+use arrctl::measure::{self, Meter};
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::output::{self, Format, Locale};
+use arrctl::power::CoreSample;
+use arrctl::schema;
+use std::path::Path;
+use std::time::Duration;
+
+mod common;
+
+fn sample(watts: f32, mhz: f32) -> Sample {
+    let cpu = |cpu| CpuSample { cpu, activity: CoreSample { effective_mhz: mhz, active: 1.0, volts: 1.1 }, celsius: Some(60), throttling: false };
+    Sample { cpus: vec![cpu(0), cpu(1)], package_watts: watts, core_amps: 30.0, core_amps_at_tjmax: 35.0 }
+}
+
+#[test]
+fn adds_up_energy_over_time() {
+    let draw = |watts, watt_hours| Some(Discharge { watts, watt_hours: Some(watt_hours) });
+    let mut meter = Meter::new(draw(20.0, 40.0));
+    meter.add(&sample(10.0, 2400.0), 1.0, draw(20.0, 40.0));
+    meter.add(&sample(20.0, 1200.0), 3.0, draw(30.0, 39.5));
+    assert_eq!((meter.seconds, meter.cpu_joules, meter.mean_mhz()), (4.0, 70.0, 1500.0));
+    assert_eq!(meter.system_joules, Some(110.0));
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    out.record(&meter.record("make -j4")).unwrap();
+    drop(out);
+    let value: serde_json::Value = serde_json::from_slice(&text).unwrap();
+    assert_eq!(value["mean_cpu_watts"], 17.5);
+    assert_eq!(value["mean_system_watts"], 27.5);
+    assert_eq!(value["battery_wh"], 0.5);
+    schema::check("measure", &value).unwrap();
+
+    // Plugged in halfway, the system part stops meaning anything
+    meter.add(&sample(10.0, 2400.0), 1.0, None);
+    assert_eq!(meter.system_joules, None);
+}
+
+#[test]
+fn fails_with_the_command() {
+    let msr = common::msr();
+    let none = Path::new("/nonexistent");
+    let interval = Duration::from_millis(10);
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    measure::run(&mut *out, &msr, &["true".to_string()], interval, none).unwrap();
+
+    let err = measure::run(&mut *out, &msr, &["sh".to_string(), "-c".to_string(), "exit 3".to_string()], interval, none).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::Failure);
+    assert!(err.to_string().contains("exited with 3"), "{}", err);
+    drop(out);
+    let lines: Vec<serde_json::Value> = text.split(|&b| b == b'\n').filter(|l| !l.is_empty()).map(|l| serde_json::from_slice(l).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["command"], "sh -c exit 3");
+    assert!(lines[1]["samples"].as_u64().unwrap() >= 1);
+    assert!(lines[1].get("system_joules").is_none());
+}
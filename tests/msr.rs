@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+use arrctl::error::{self, Error, ErrorKind};
+use arrctl::msr::{self, Fault, MsrAccess, Retry, RetryPolicy};
+use std::cell::Cell;
+use std::io;
+use std::time::Duration;
+
+// Fails with errno the first failures times, like a CPU coming back online
+struct Flaky {
+    errno: i32,
+    failures: Cell<u32>,
+    online: bool,
+}
+
+impl MsrAccess for Flaky {
+    fn cpus(&self) -> Vec<u16> {
+        if self.online { vec![0, 1] } else { vec![0] }
+    }
+
+    fn read(&self, reg: u32, cpu: u16) -> Result<u64> {
+        if self.failures.get() > 0 {
+            self.failures.set(self.failures.get() - 1);
+            return Err(io::Error::from_raw_os_error(self.errno)).with_context(|| format!("Failed to read MSR {:#x} on CPU{}", reg, cpu));
+        }
+        Ok(0x1600)
+    }
+
+    fn write(&self, reg: u32, cpu: u16, _val: u64) -> Result<()> {
+        Err(Error::new(ErrorKind::RegisterLocked, "locked").register(reg, cpu).into())
+    }
+}
+
+fn retry(errno: i32, failures: u32, online: bool) -> Retry<Flaky> {
+    Retry::new(Flaky { errno, failures: Cell::new(failures), online }, RetryPolicy { retries: 2, delay: Duration::ZERO })
+}
+
+#[test]
+fn rides_out_a_hiccup() {
+    let msr = retry(libc::EIO, 2, true);
+    assert_eq!(msr.read(0xce, 1).unwrap(), 0x1600);
+    let msr = retry(libc::EAGAIN, 1, true);
+    assert_eq!(msr.read(0xce, 1).unwrap(), 0x1600);
+}
+
+#[test]
+fn explains_what_kept_failing() {
+    // Still online, so the register is what's missing
+    let err = retry(libc::EIO, 3, true).read(0x1ad, 1).unwrap_err();
+    assert!(format!("{:#}", err).contains("this stepping probably doesn't have it"), "{:#}", err);
+    assert_eq!(msr::classify(&err), Fault::Io);
+
+    let err = retry(libc::EIO, 3, false).read(0x1ad, 1).unwrap_err();
+    assert!(err.to_string().starts_with("CPU1 went offline"), "{}", err);
+
+    // Tried once, locked registers don't unlock
+    let msr = retry(libc::EIO, 0, true);
+    let err = msr.write(0x610, 0, 1).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::RegisterLocked);
+    assert_eq!(msr::classify(&err), Fault::Locked);
+
+    // Nothing worth a retry goes through as it was
+    let msr = retry(libc::EINVAL, 1, true);
+    assert!(msr.read(0xce, 0).is_err());
+    assert_eq!(msr.read(0xce, 0).unwrap(), 0x1600);
+}
@@ -0,0 +1,76 @@
+use arrctl::error::{self, ErrorKind};
+use arrctl::msr::{self, MockMsr};
+use arrctl::plugins;
+use arrctl::regs::{self, Scope, MODEL_ARRANDALE};
+use std::fs;
+use std::path::PathBuf;
+
+fn dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("arrctl-plugins-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, text) in files {
+        fs::write(dir.join(file), text).unwrap();
+    }
+    dir
+}
+
+const POWER_CTL: &str = r#"
+[[register]]
+name = "MSR_POWER_CTL"
+address = 0x1fc
+scope = "package"
+
+[[register.field]]
+name = "c1e_promotion"
+lsb = 1
+
+[[register.field]]
+name = "mode"
+lsb = 4
+msb = 5
+names = { fast = 0, slow = 1 }
+"#;
+
+#[test]
+fn custom_registers_are_dumped_and_decoded() {
+    let dir = dir("ok", &[("power.toml", POWER_CTL), ("notes.txt", "not a register")]);
+    let registers = plugins::load(&dir).unwrap();
+    assert_eq!(registers.len(), 1);
+    let reg = &registers[0];
+    assert_eq!((reg.name, reg.address, reg.scope), ("MSR_POWER_CTL", 0x1fc, Scope::Package));
+    assert!(!reg.writable && reg.supported(MODEL_ARRANDALE));
+    assert_eq!(reg.describe(MODEL_ARRANDALE, 0x12), "c1e_promotion 1, mode slow");
+
+    assert!(regs::add_custom(registers));
+    assert_eq!(regs::name(0x1fc), Some("MSR_POWER_CTL"));
+    assert_eq!(regs::scope(0x1fc), Scope::Package);
+    let dump = "# brand: Intel(R) Core(TM) i5 CPU M 520 @ 2.40GHz\n0 0x1fc 0x12\n";
+    let mut text = Vec::new();
+    msr::write_dump(&mut text, &MockMsr::from_dump(dump).unwrap(), "test", None).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("0 0x1fc 0x0000000000000012"), "{}", text);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bad_definitions_are_refused() {
+    assert!(plugins::load(&std::env::temp_dir().join("arrctl-no-such-dir")).unwrap().is_empty());
+
+    let clash = "[[register]]\nname = \"MINE\"\naddress = 0x1ac\n";
+    let backwards = "[[register]]\nname = \"MINE\"\naddress = 0x1fd\n[[register.field]]\nname = \"x\"\nlsb = 5\nmsb = 2\n";
+    let typo = "[[register]]\nname = \"MINE\"\naddress = 0x1fd\nscop = \"core\"\n";
+    for (name, text, expected) in [("clash", clash, "already MSR_TURBO_LIMITS"), ("backwards", backwards, "bits 5..2"), ("typo", typo, "unknown field")] {
+        let dir = dir(name, &[("a.toml", text)]);
+        let err = plugins::load(&dir).err().unwrap();
+        assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+        assert!(format!("{:#}", err).contains(expected), "{:#}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // The same address in two files
+    let dir = dir("twice", &[("a.toml", POWER_CTL), ("b.toml", POWER_CTL)]);
+    let err = plugins::load(&dir).err().unwrap();
+    assert!(err.to_string().contains("b.toml"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
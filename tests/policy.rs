@@ -0,0 +1,146 @@
+use arrctl::backtest::{self, Options};
+use arrctl::error::{self, ErrorKind};
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::output::{self, Format, Locale};
+use arrctl::policy::{self, Inputs, Script};
+use arrctl::power::CoreSample;
+use arrctl::profile::Profile;
+use arrctl::schema;
+use std::time::Duration;
+
+const POLICY: &str = "
+# Ease off as it heats up, harder on battery
+hot = max(temperature - 80, 0)
+tdp = if ac then clamp(35 - hot, 15, 35) else 18
+turbo = temperature < 95 and busy > 50
+clock_modulation = if temperature >= 100 then 50 else 100
+";
+
+fn inputs(temperature: f64, ac: bool) -> Inputs {
+    Inputs { temperature, mhz: 2400.0, busy: 80.0, watts: 25.0, amps: 20.0, ac }
+}
+
+#[test]
+fn picks_limits_from_inputs() {
+    let script = Script::parse(POLICY).unwrap();
+    assert_eq!(script.outputs().collect::<Vec<_>>(), ["tdp", "turbo", "clock_modulation"]);
+    let pick = |temperature, ac| script.evaluate(&inputs(temperature, ac)).unwrap();
+    assert_eq!(pick(70.0, true), Profile { tdp: Some(35), turbo: Some(true), clock_modulation: Some(0), ..Default::default() });
+    assert_eq!(pick(90.0, true).tdp, Some(25));
+    assert_eq!(pick(99.0, true).turbo, Some(false));
+    assert_eq!(pick(110.0, true).tdp, Some(15));
+    assert_eq!(pick(110.0, true).clock_modulation, Some(4));
+    assert_eq!(pick(70.0, false).tdp, Some(18));
+
+    // Later lines see earlier ones, and only what's assigned is picked
+    let script = Script::parse("a = 10\na = a * 2 + 1\ntdc = a\n").unwrap();
+    assert_eq!(script.evaluate(&inputs(50.0, true)).unwrap(), Profile { tdc: Some(21), ..Default::default() });
+}
+
+#[test]
+fn refuses_what_it_cant_run() {
+    for (text, expected) in [
+        ("tdp = 25 +", "Line 1: The line ends in the middle"),
+        ("temperature = 3", "is an input"),
+        ("tdp = speed * 2", "speed isn't an input or set on an earlier line"),
+        ("tdp = if ac then 20", "Expected else"),
+        ("tdp = exec(1)", "No function exec"),
+        ("tdp = clamp(1, 2)", "clamp takes 3 argument(s)"),
+        ("tdp 25", "Expected name = expression"),
+        ("\n\ntdp = 25 $", "Line 3: Unexpected '$'"),
+    ] {
+        let err = Script::parse(text).unwrap_err();
+        assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+        assert!(err.to_string().contains(expected), "{:?}: {}", text, err);
+    }
+
+    // Bounded, however it's written
+    let deep = format!("tdp = {}1{}", "(".repeat(40), ")".repeat(40));
+    assert!(Script::parse(&deep).unwrap_err().to_string().contains("Nested deeper"));
+    let long = format!("tdp = 1{}", " + 1".repeat(200));
+    assert!(Script::parse(&long).unwrap_err().to_string().contains("tokens"));
+    assert!(Script::parse(&"a = 1\n".repeat(20_000)).is_err());
+
+    // What it works out still has to be a limit
+    let run = |text: &str| Script::parse(text).unwrap().evaluate(&inputs(80.0, true)).unwrap_err().to_string();
+    assert!(run("tdp = 9000").contains("expected 1 to"));
+    assert!(run("tdp = 25 / (temperature - 80)").contains("Line 1"));
+    assert!(run("clock_modulation = 3").contains("expected 12.5 to 100"));
+    // A guard keeps the division from running
+    let guarded = Script::parse("tdp = if temperature != 80 and 100 / (temperature - 80) > 1 then 20 else 25").unwrap();
+    assert_eq!(guarded.evaluate(&inputs(80.0, true)).unwrap().tdp, Some(25));
+}
+
+#[test]
+fn dry_run_over_a_recording() {
+    let sample = |celsius: Option<u64>| Sample {
+        cpus: vec![CpuSample { cpu: 0, activity: CoreSample { effective_mhz: 2400.0, active: 0.9, volts: 1.1 }, celsius, throttling: false }],
+        package_watts: 25.0,
+        core_amps: 20.0,
+        core_amps_at_tjmax: 22.0,
+    };
+    let samples = [sample(Some(70)), sample(Some(75)), sample(Some(90)), sample(None), sample(Some(90))];
+    let script = Script::parse(POLICY).unwrap();
+    let records = policy::test(&script, &samples, true).unwrap();
+    // Only when the pick changes, then the summary
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[1].title.as_deref(), Some("Sample 3"));
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    for record in &records {
+        out.record(record).unwrap();
+    }
+    drop(out);
+    let values: Vec<serde_json::Value> = text.split(|&b| b == b'\n').filter(|line| !line.is_empty()).map(|line| serde_json::from_slice(line).unwrap()).collect();
+    assert_eq!(values[1]["tdp"], 25);
+    assert_eq!(values[2]["changes"], 2);
+    assert_eq!(values[2]["skipped"], 1);
+    for value in &values {
+        schema::check("policy", value).unwrap();
+    }
+}
+
+#[test]
+fn backtest_over_a_session() {
+    let sample = |celsius: u64, watts: f32| Sample {
+        cpus: vec![CpuSample { cpu: 0, activity: CoreSample { effective_mhz: 2400.0, active: 0.9, volts: 1.1 }, celsius: Some(celsius), throttling: false }],
+        package_watts: watts,
+        core_amps: 20.0,
+        core_amps_at_tjmax: 22.0,
+    };
+    // Heats up, then the policy's 25 W would have been broken for two samples
+    let samples = [sample(70, 30.0), sample(90, 30.0), sample(92, 30.0), sample(93, 24.0), sample(96, 24.0), sample(80, 24.0)];
+    let script = Script::parse("tdp = if temperature > 85 then 25 else 35").unwrap();
+    let opts = Options { interval: Duration::from_secs(2), min_interval: Duration::from_secs(1), ac: true, max_celsius: Some(95) };
+    let records = backtest::run(&script, &samples, &opts).unwrap();
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    for record in &records {
+        out.record(record).unwrap();
+    }
+    drop(out);
+    let values: Vec<serde_json::Value> = text.split(|&b| b == b'\n').filter(|line| !line.is_empty()).map(|line| serde_json::from_slice(line).unwrap()).collect();
+    for value in &values {
+        schema::check("policy", value).unwrap();
+    }
+    let kinds: Vec<&str> = values.iter().map(|v| v["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["backtest_step", "backtest_step", "backtest_step", "violation", "violation", "backtest"]);
+    assert_eq!(values[1]["at_seconds"], 2);
+    assert_eq!(values[1]["tdp"], 25);
+    assert_eq!(values[2]["tdp"], 35);
+    assert_eq!(values[3]["at_seconds"], 2);
+    assert!(values[3]["text"].as_str().unwrap().contains("over the 25 W TDP, for 4 s"), "{}", values[3]);
+    assert_eq!(values[4]["at_seconds"], 8);
+    assert!(values[4]["text"].as_str().unwrap().contains("96.0 celsius"));
+    assert_eq!(values[5]["writes"], 3);
+    assert_eq!(values[5]["violations"], 2);
+    assert_eq!(values[5]["violation_seconds"], 6);
+    assert_eq!(values[5]["seconds"], 12);
+
+    // A write holds the next one off for min_interval
+    let opts = Options { min_interval: Duration::from_secs(60), ..opts };
+    let records = backtest::run(&script, &samples, &opts).unwrap();
+    assert_eq!(records.iter().filter(|r| r.kind == "backtest_step").count(), 1);
+}
@@ -0,0 +1,72 @@
+use arrctl::error::{self, ErrorKind};
+use arrctl::polkit;
+use std::fs;
+
+#[test]
+fn subject_has_start_time() {
+    let root = std::env::temp_dir().join(format!("arrctl-proc-{}", std::process::id()));
+    fs::create_dir_all(root.join("4242")).unwrap();
+    // An applet named to trip up naive parsing
+    let stat = "4242 (my (odd) applet) S 1 4242 4242 0 -1 4194560 1234 0 0 0 5 3 0 0 20 0 4 0 987654 123456789 4321";
+    fs::write(root.join("4242/stat"), stat).unwrap();
+    assert_eq!(polkit::subject(&root, 4242, 1000).unwrap(), "4242,987654,1000");
+    assert!(polkit::subject(&root, 1, 1000).is_err());
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn reads_pkcheck_exit_codes() {
+    assert!(polkit::verdict(Some(0)).unwrap());
+    assert!(!polkit::verdict(Some(1)).unwrap());
+    assert!(!polkit::verdict(Some(2)).unwrap());
+    assert_eq!(error::kind_of(&polkit::verdict(Some(3)).unwrap_err()), ErrorKind::PermissionDenied);
+    assert!(polkit::verdict(Some(127)).is_err());
+    assert!(polkit::verdict(None).is_err());
+}
+
+#[test]
+fn ships_a_policy_for_the_action() {
+    let policy = fs::read_to_string(format!("{}/dist/org.arrctl.policy", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    assert!(policy.contains(&format!("<action id=\"{}\">", polkit::APPLY_PROFILE)));
+}
+
+#[test]
+fn finds_the_process_behind_a_tcp_connection() {
+    let root = std::env::temp_dir().join(format!("arrctl-proc-tcp-{}", std::process::id()));
+    fs::create_dir_all(root.join("net")).unwrap();
+    fs::create_dir_all(root.join("4242/fd")).unwrap();
+    let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+               \x20  0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 111 1 0 100 0 0 10 0\n\
+               \x20  1: 0100007F:D431 0100007F:1F90 01 00000000:00000000 00:00000000 00000000  1000        0 555 1 0 20 4 30 10 -1\n";
+    fs::write(root.join("net/tcp"), tcp).unwrap();
+    std::os::unix::fs::symlink("socket:[555]", root.join("4242/fd/7")).unwrap();
+
+    let peer = "127.0.0.1:54321".parse().unwrap();
+    let local = "127.0.0.1:8080".parse().unwrap();
+    assert_eq!(polkit::tcp_peer(&root, peer, local), Some((4242, 1000)));
+    // The listener on [::] sees IPv4 clients as mapped addresses
+    assert_eq!(polkit::tcp_peer(&root, "[::ffff:127.0.0.1]:54321".parse().unwrap(), "[::ffff:127.0.0.1]:8080".parse().unwrap()), Some((4242, 1000)));
+    assert_eq!(polkit::tcp_peer(&root, "127.0.0.1:54322".parse().unwrap(), local), None);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn finds_its_own_connection() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (_server, peer) = listener.accept().unwrap();
+    assert_eq!(peer, client.local_addr().unwrap());
+    let uid = unsafe { libc::getuid() };
+    assert_eq!(polkit::tcp_peer(std::path::Path::new("/proc"), peer, listener.local_addr().unwrap()), Some((std::process::id() as i32, uid)));
+}
+
+#[test]
+fn looks_peers_up_through_the_helper() {
+    let lookup = polkit::PeerLookup::spawn().unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (_server, peer) = listener.accept().unwrap();
+    let uid = unsafe { libc::getuid() };
+    assert_eq!(lookup.find(peer, listener.local_addr().unwrap()), Some((std::process::id() as i32, uid)));
+    assert_eq!(lookup.find(client.local_addr().unwrap(), "127.0.0.1:1".parse().unwrap()), None);
+}
@@ -0,0 +1,35 @@
+// On its own, dropping root takes the whole test binary along
+use arrctl::error::{self, ErrorKind};
+use arrctl::privs::{self, Account};
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::{chown, FileExt, OpenOptionsExt};
+
+#[test]
+fn keeps_open_files_after_dropping_root() {
+    let dir = std::env::temp_dir().join(format!("arrctl-privs-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let passwd = dir.join("passwd");
+    fs::write(&passwd, "root:x:0:0:root:/root:/bin/bash\nbroken:x\narrctl:x:981:979:arrctl daemon:/var/lib/arrctl:/usr/sbin/nologin\n").unwrap();
+    assert_eq!(privs::lookup(&passwd, "arrctl").unwrap(), Account { uid: 981, gid: 979 });
+    let err = privs::lookup(&passwd, "nobody").unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+
+    if unsafe { libc::geteuid() } != 0 {
+        fs::remove_dir_all(&dir).unwrap();
+        return;
+    }
+    let nobody = Account { uid: 65534, gid: 65534 };
+    let journal = dir.join("state/journal");
+    privs::hand_over(&[&journal], nobody).unwrap();
+    // A stand-in for /dev/cpu/0/msr, only root can open it
+    let msr_path = dir.join("msr");
+    let msr = OpenOptions::new().create(true).truncate(true).write(true).mode(0o600).open(&msr_path).unwrap();
+    chown(&dir, Some(nobody.uid), Some(nobody.gid)).unwrap();
+
+    privs::drop_to(nobody).unwrap();
+    assert_eq!(unsafe { libc::geteuid() }, 65534);
+    msr.write_at(&[0; 8], 0x1ac).unwrap();
+    assert!(OpenOptions::new().write(true).open(&msr_path).is_err());
+    fs::write(&journal, "").unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
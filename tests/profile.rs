@@ -0,0 +1,214 @@
+use arrctl::apply;
+use arrctl::cpu::CpuInfo;
+use arrctl::error::{self, ErrorKind};
+use arrctl::igp::{Gt, IgpCap};
+use arrctl::msr::{MockMsr, RegSpec};
+use arrctl::output::Value;
+use arrctl::profile::{self, Override, Profile, Profiles, Threshold};
+use arrctl::regs::*;
+use arrctl::sku;
+use std::fs;
+
+mod common;
+
+use common::temp;
+
+#[test]
+fn save_keeps_other_profiles_and_comments() {
+    let path = temp("profiles.toml");
+    fs::write(&path, "# hand written\n[profiles.quiet]\ntdp = 12\n").unwrap();
+
+    let gaming = Profile { tdp: Some(18), igp_cap: Some(IgpCap::High), ..Default::default() };
+    profile::save(&path, "gaming", &gaming).unwrap();
+
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.starts_with("# hand written\n"), "{}", text);
+    let profiles = profile::load(&path).unwrap().profiles;
+    assert_eq!(profiles["gaming"], gaming);
+    assert_eq!(profiles["quiet"].tdp, Some(12));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn igp_cap_within_hardware_range() {
+    let drm = temp("drm");
+    let card = drm.join("card0");
+    fs::create_dir_all(&card).unwrap();
+    fs::write(card.join("gt_RPn_freq_mhz"), "300\n").unwrap();
+    fs::write(card.join("gt_RP0_freq_mhz"), "700\n").unwrap();
+    fs::write(card.join("gt_max_freq_mhz"), "700\n").unwrap();
+
+    let gt = Gt::find(&drm).unwrap();
+    assert_eq!(gt.set_cap(IgpCap::Low).unwrap(), 400);
+    assert_eq!(gt.max_mhz().unwrap(), 400);
+    assert_eq!(gt.cap_mhz(IgpCap::Max).unwrap(), 700);
+    assert!(Gt::find(&card).is_err());
+    fs::remove_dir_all(&drm).unwrap();
+}
+
+#[test]
+fn package_limits_written_once_per_package() {
+    let msr = MockMsr::from_dump("0 0x1ac 0x0\n1 0x1ac 0x0\n2 0x1ac 0x0\n3 0x1ac 0x0").unwrap();
+    let layout = [(0, 0), (1, 2), (2, 0), (3, 2)].map(|(cpu, core)| CpuInfo { cpu, core, package: 0 });
+    let profile = Profile { tdp: Some(25), ..Default::default() };
+
+    let writes = apply::register_writes(&msr, &profile, &layout, true).unwrap();
+    assert_eq!(writes.len(), 1);
+    let (spec, val) = writes[0];
+    assert_eq!(spec, RegSpec { reg: MSR_TURBO_LIMITS, cpu: 0 });
+    assert_eq!(MsrTurboLimits(val).tdp(), 200);
+    assert!(MsrTurboLimits(val).tdp_override());
+}
+
+#[test]
+fn turbo_ratios_checked_against_fused_bins() {
+    let msr = MockMsr::from_dump("0 0x1ad 0x1416\n1 0x1ad 0x1416").unwrap();
+    let layout = [(0, 0), (1, 0)].map(|(cpu, core)| CpuInfo { cpu, core, package: 0 });
+    let i5_520m = sku::lookup("i5 CPU M 520");
+
+    // Leaving off the two core bin keeps it
+    let profile = Profile { turbo_ratios: Some(vec![21]), ..Default::default() };
+    apply::check_fused(&profile, i5_520m).unwrap();
+    let writes = apply::register_writes(&msr, &profile, &layout, true).unwrap();
+    assert_eq!(writes, [(RegSpec { reg: MSR_TURBO_RATIOS, cpu: 0 }, 0x1415)]);
+
+    let over = Profile { turbo_ratios: Some(vec![22, 21]), ..Default::default() };
+    let err = apply::check_fused(&over, i5_520m).unwrap_err();
+    assert!(err.to_string().contains("above the fused 20"), "{}", err);
+    // Nothing to check against on an unknown part, the read back catches it
+    apply::check_fused(&over, None).unwrap();
+
+    apply::verify_ratios(&msr, &Profile { turbo_ratios: Some(vec![22, 20]), ..Default::default() }, &[0]).unwrap();
+    let err = apply::verify_ratios(&msr, &over, &[0]).unwrap_err();
+    assert!(err.to_string().contains("the CPU kept 20"), "{}", err);
+
+    // Only with the programmable ratio bit
+    let err = apply::validate(&profile, &MsrPlatformInfo(0)).unwrap_err();
+    assert!(err.to_string().contains("turbo ratios"), "{}", err);
+    apply::validate(&profile, &MsrPlatformInfo(1 << 28)).unwrap();
+}
+
+#[test]
+fn clock_modulation_per_core() {
+    let msr = MockMsr::from_dump("0 0x19a 0x0\n1 0x19a 0x0\n2 0x19a 0x0\n3 0x19a 0x0").unwrap();
+    let layout = [(0, 0), (1, 2), (2, 0), (3, 2)].map(|(cpu, core)| CpuInfo { cpu, core, package: 0 });
+    // Core 0 left alone, core 1 at three eighths on both its threads
+    let profile = Profile { core_clock_modulation: Some(vec![0, 3]), ..Default::default() };
+    let writes = apply::register_writes(&msr, &profile, &layout, false).unwrap();
+    let duties: Vec<(u16, bool, u64)> = writes
+        .iter()
+        .map(|(spec, val)| (spec.cpu, Ia32ClockModulation(*val).enable(), Ia32ClockModulation(*val).duty_cycle()))
+        .collect();
+    assert_eq!(duties, [(0, false, 0), (2, false, 0), (1, true, 3), (3, true, 3)]);
+
+    let three = Profile { core_clock_modulation: Some(vec![1, 2, 3]), ..Default::default() };
+    let err = apply::register_writes(&msr, &three, &layout, true).unwrap_err();
+    assert!(err.to_string().contains("this CPU has 2 core(s)"), "{}", err);
+
+    let both = Profile { clock_modulation: Some(2), ..profile.clone() };
+    assert_eq!(both.problems()[0].key, "clock_modulation");
+    assert_eq!(Profile { core_clock_modulation: Some(vec![8]), ..Default::default() }.problems()[0].key, "core_clock_modulation");
+}
+
+#[test]
+fn refuses_a_tdc_under_idle_draw() {
+    let msr = common::msr();
+    let low = Profile { tdc: Some(5), ..Default::default() };
+    let err = apply::check_tdc_floor(&msr, &low, None).unwrap_err();
+    assert_eq!(arrctl::error::kind_of(&err), arrctl::error::ErrorKind::Validation);
+    assert!(err.to_string().contains("1200 MHz and 0.90 V"), "{}", err);
+    assert!(err.to_string().contains("--force"), "{}", err);
+
+    apply::check_tdc_floor(&msr, &Profile { tdc: Some(30), ..Default::default() }, None).unwrap();
+    apply::check_tdc_floor(&msr, &Profile { tdp: Some(5), ..Default::default() }, None).unwrap();
+}
+
+#[test]
+fn thresholds_resolve_per_cpu() {
+    let text = "[emergency]\nprofile = \"quiet\"\nmax_celsius = \"tjmax-15\"\n[governor]\ntarget_celsius = 85\nmin_tdp = 10\nmax_tdp = 25\n";
+    let profiles: profile::Profiles = toml::from_str(text).unwrap();
+    let max = profiles.emergency.unwrap().max_celsius.unwrap();
+    assert_eq!(max, Threshold::BelowTjmax(15));
+    // The same file on an i5-520M and an i7-640LM
+    assert_eq!((max.resolve(105), max.resolve(90)), (90, 75));
+    assert_eq!(profiles.governor.unwrap().target_celsius.resolve(90), 85);
+
+    assert_eq!("tjmax".parse(), Ok(Threshold::BelowTjmax(0)));
+    assert_eq!(" tjmax - 5 ".parse(), Ok(Threshold::BelowTjmax(5)));
+    assert_eq!("80".parse(), Ok(Threshold::Celsius(80)));
+    assert!("tjmax-".parse::<Threshold>().is_err());
+    assert!("hot".parse::<Threshold>().is_err());
+    assert_eq!(Threshold::BelowTjmax(15).to_string(), "tjmax-15");
+    assert_eq!(Threshold::BelowTjmax(200).resolve(90), 0);
+}
+
+#[test]
+fn profiles_extend_each_other() {
+    let text = "[profiles.quiet]\ntdp = 15\nturbo = true\nturbo_ratios = [20, 19]\n\n[profiles.battery-quiet]\nextends = \"quiet\"\ntdp = 12\n\n[profiles.travel]\nextends = \"battery-quiet\"\nturbo_ratios = [18]\n";
+    let path = temp("extends.toml");
+    fs::write(&path, text).unwrap();
+
+    let written = profile::read(&path).unwrap();
+    assert_eq!(written.profiles["travel"].extends.as_deref(), Some("battery-quiet"));
+    assert_eq!(written.chain("travel").unwrap(), ["travel", "battery-quiet", "quiet"]);
+    // Lists replace the whole list, they don't merge
+    let travel = Profile { tdp: Some(12), turbo: Some(true), turbo_ratios: Some(vec![18]), ..Default::default() };
+    assert_eq!(written.resolved("travel").unwrap(), travel);
+
+    let loaded = profile::load(&path).unwrap();
+    assert_eq!(loaded.profiles["travel"], travel);
+    assert_eq!(loaded.profiles["battery-quiet"].turbo_ratios, Some(vec![20, 19]));
+    assert!(loaded.profiles.values().all(|p| p.extends.is_none()));
+
+    let record = profile::record("travel", &travel, Some(&["travel", "battery-quiet", "quiet"]));
+    assert!(matches!(record.get("layers"), Some(Value::Text(text)) if text == "travel > battery-quiet > quiet"));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn extends_refuses_loops_and_unknown_profiles() {
+    let path = temp("extends-loop.toml");
+    for (text, expected) in [
+        ("[profiles.a]\nextends = \"b\"\n[profiles.b]\nextends = \"a\"\n", "Profile a extends itself through a > b > a"),
+        ("[profiles.a]\nextends = \"a\"\n", "Profile a extends itself through a > a"),
+        ("[profiles.a]\nextends = \"quiet\"\n", "Profile a extends quiet, which isn't defined"),
+    ] {
+        fs::write(&path, text).unwrap();
+        let err = profile::load(&path).unwrap_err();
+        assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+        assert!(format!("{:#}", err).contains(expected), "{:#}", err);
+    }
+    // Only inside the file, not on a profile sent on its own
+    let alone = Profile { extends: Some("quiet".into()), ..Default::default() };
+    assert_eq!(alone.problems()[0].key, "extends");
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn overrides_go_over_the_file() {
+    let text = "# Shipped with the image\n[profiles.quiet]\ntdp = 15\n\n[governor]\ntarget_celsius = 85\nmin_tdp = 10\nmax_tdp = 25\n";
+    let flags: Vec<Override> = ["profile.quiet.tdp=18", "profiles.quiet.igp_cap=low", "profiles.travel.turbo=false"].iter().map(|s| s.parse().unwrap()).collect();
+    let env = "governor.target_celsius=\"tjmax-20\"; profiles.quiet.tdp = 12\nemergency.profile=quiet";
+    let overrides = profile::overrides(Some(env), &flags).unwrap();
+    assert_eq!(overrides.len(), 6);
+
+    let merged = profile::with_overrides(text, &overrides).unwrap();
+    assert!(merged.starts_with("# Shipped with the image\n"));
+    let profiles: Profiles = toml::from_str(&merged).unwrap();
+    // The flag came after ARRCTL_SET, so its 18 wins over the 12
+    assert_eq!(profiles.profiles["quiet"].tdp, Some(18));
+    assert_eq!(profiles.profiles["quiet"].igp_cap, Some(IgpCap::Low));
+    assert_eq!(profiles.profiles["travel"].turbo, Some(false));
+    assert_eq!(profiles.governor.unwrap().target_celsius, Threshold::BelowTjmax(20));
+    assert_eq!(profiles.emergency.unwrap().profile, "quiet");
+
+    for bad in ["tdp=18", "profiles.quiet=1", "fans.speed=3", "governor..x=1", "profiles.quiet.tdp"] {
+        assert!(bad.parse::<Override>().is_err(), "{}", bad);
+    }
+    let err = profile::overrides(Some("governor=1"), &[]).unwrap_err();
+    assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+    assert!(err.to_string().starts_with("ARRCTL_SET: "));
+    // Into something that isn't a table
+    let err = profile::with_overrides("profiles = 3\n", &["profiles.quiet.tdp=1".parse().unwrap()]).unwrap_err();
+    assert!(err.to_string().contains("profiles isn't a table"), "{}", err);
+}
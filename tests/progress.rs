@@ -0,0 +1,12 @@
+use arrctl::progress;
+use std::time::Duration;
+
+#[test]
+fn bar_shows_share_and_time_left() {
+    assert_eq!(progress::bar("Watching", 0, 10, Duration::ZERO), format!("Watching [{}]   0%", " ".repeat(30)));
+    assert_eq!(
+        progress::bar("Watching", 4, 10, Duration::from_secs(2)),
+        format!("Watching [{}{}]  40%, 3 s left", "#".repeat(12), " ".repeat(18))
+    );
+    assert_eq!(progress::bar("Watching", 12, 10, Duration::from_secs(5)), format!("Watching [{}] 100%", "#".repeat(30)));
+}
@@ -0,0 +1,48 @@
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::output::{self, Format, Locale};
+use arrctl::power::CoreSample;
+use arrctl::replay;
+
+fn sample(mhz: f32, celsius: Option<u64>) -> Sample {
+    let cpus = (0..2)
+        .map(|cpu| CpuSample {
+            cpu,
+            activity: CoreSample { effective_mhz: mhz, active: 0.5, volts: 1.1 },
+            celsius,
+            throttling: cpu == 1,
+        })
+        .collect();
+    Sample { cpus, package_watts: 21.5, core_amps: 30.0, core_amps_at_tjmax: 33.0 }
+}
+
+fn render(format: Format, samples: &[Sample]) -> String {
+    let mut text = Vec::new();
+    let mut out = output::sink(format, Locale::C, false, &mut text);
+    for sample in samples {
+        for record in sample.records() {
+            out.record(&record).unwrap();
+        }
+    }
+    out.finish().unwrap();
+    drop(out);
+    String::from_utf8(text).unwrap()
+}
+
+#[test]
+fn csv_recording_round_trips() {
+    let recorded = [sample(2400.0, Some(60)), sample(1200.0, None)];
+    let mut csv = render(Format::Csv, &recorded);
+    // Summaries and other records in the file are skipped
+    csv += "summary,,samples,2\n";
+
+    let replayed = replay::parse(&csv).unwrap();
+    assert_eq!(replayed.len(), 2);
+    assert!(replayed[0].cpus[1].throttling);
+    assert_eq!(render(Format::Human, &replayed), render(Format::Human, &recorded));
+}
+
+#[test]
+fn rejects_other_files() {
+    assert!(replay::parse("cpu,mhz\n0,2400\n").is_err());
+    assert!(replay::parse("kind,cpu,field,value\ncpu,0,effective_mhz,fast\n").is_err());
+}
@@ -0,0 +1,38 @@
+// On its own, the filter covers every thread of the test binary
+use arrctl::sandbox;
+use std::fs::{self, File};
+use std::io::{IoSlice, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+use std::process::Command;
+
+#[test]
+fn refuses_msr_writes_and_exec() {
+    assert_eq!(sandbox::ranges(&[9, 5, 6, 7, 6]), [(5, 7), (9, 9)]);
+    assert!(sandbox::filter(&(0..1000).step_by(2).collect::<Vec<_>>(), false).is_err());
+
+    let dir = std::env::temp_dir().join(format!("arrctl-sandbox-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    // Stand-ins for /dev/cpu/N/msr
+    let mut msr = File::create(dir.join("msr")).unwrap();
+    let mut other = File::create(dir.join("other")).unwrap();
+    sandbox::drop_rawio().unwrap();
+    sandbox::apply(&sandbox::filter(&[msr.as_raw_fd()], false).unwrap()).unwrap();
+
+    let err = msr.write_at(&[0; 8], 0x1ac).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+    // The position is the register just as well
+    msr.seek(SeekFrom::Start(0x1ac)).unwrap();
+    assert_eq!(msr.write(&[0; 8]).unwrap_err().raw_os_error(), Some(libc::EPERM));
+    assert_eq!(msr.write_vectored(&[IoSlice::new(&[0; 8])]).unwrap_err().raw_os_error(), Some(libc::EPERM));
+    other.write_at(&[0; 8], 0x1ac).unwrap();
+    other.seek(SeekFrom::Start(0x1ac)).unwrap();
+    other.write_all(&[0; 8]).unwrap();
+
+    // No opening /dev/cpu/N/msr again
+    let status = fs::read_to_string("/proc/thread-self/status").unwrap();
+    let effective = status.lines().find_map(|l| l.strip_prefix("CapEff:")).unwrap().trim();
+    assert_eq!(u64::from_str_radix(effective, 16).unwrap() & (1 << 17), 0);
+    assert_eq!(Command::new("/bin/true").status().unwrap_err().raw_os_error(), Some(libc::EPERM));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,84 @@
+use arrctl::monitor::{Sampler, Summary};
+use arrctl::msr::MockMsr;
+use arrctl::output::{self, Format, Locale, OutputSink};
+use arrctl::{schema, selftest, sku, status};
+use serde_json::Value;
+use std::process::Command;
+use std::time::Duration;
+
+mod common;
+
+fn fixture() -> MockMsr {
+    common::msr()
+}
+
+fn json_lines(run: impl FnOnce(&mut dyn OutputSink)) -> Vec<Value> {
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    run(&mut *out);
+    drop(out);
+    String::from_utf8(text).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+fn check_all(command: &str, records: &[Value]) {
+    assert!(!records.is_empty());
+    for record in records {
+        if let Err(e) = schema::check(command, record) {
+            panic!("{}: {}", e, record);
+        }
+    }
+}
+
+// The table has to follow the records, or dashboards validate against fiction
+#[test]
+fn records_match_the_schema() {
+    let msr = fixture();
+    let stock = msr.brand.as_deref().and_then(sku::lookup);
+    check_all("status", &json_lines(|out| {
+        status::tdp(out, &msr, stock).unwrap();
+        status::tdc(out, &msr, stock).unwrap();
+        status::tjmax(out, &msr).unwrap();
+        status::turbo_ratios(out, &msr, stock).unwrap();
+        status::voltage(out, &msr).unwrap();
+
+        let mut sampler = Sampler::new(&msr).unwrap();
+        let sample = sampler.sample(&msr).unwrap();
+        let mut summary = Summary::default();
+        summary.update(&sample);
+        for record in sample.records() {
+            out.record(&record).unwrap();
+        }
+        out.record(&summary.record(Duration::from_secs(1))).unwrap();
+    }));
+    check_all("id", &json_lines(|out| status::platform(out, &msr, msr.brand.as_deref().unwrap()).unwrap()));
+    check_all("selftest", &json_lines(|out| {
+        let _ = selftest::run(out, &msr);
+    }));
+}
+
+#[test]
+fn every_listed_kind_is_described() {
+    for (command, kinds) in schema::COMMANDS {
+        let doc = schema::command(command).unwrap();
+        assert_eq!(doc["oneOf"].as_array().unwrap().len(), kinds.len(), "{}", command);
+        assert_eq!(doc["$defs"]["error"]["properties"]["schema_version"]["const"], schema::VERSION);
+    }
+    let events = schema::command("events").unwrap();
+    assert_eq!(events["oneOf"].as_array().unwrap().len(), schema::EVENTS.len());
+    assert_eq!(schema::check("status", &serde_json::json!({ "kind": "tjmax", "celsius": 1.5, "schema_version": 1 })).unwrap_err(), "tjmax.celsius should be integer, got 1.5");
+}
+
+#[test]
+fn schema_flag_needs_no_root() {
+    let run = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_arrctl")).args(args).output().unwrap();
+    let out = run(&["events", "--schema"]);
+    assert_eq!(out.status.code(), Some(0));
+    let doc: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(doc["title"], "arrctl events");
+
+    let out = run(&["features", "--schema"]);
+    let doc: Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert!(doc["properties"]["registers"].is_object(), "{}", doc);
+
+    assert_eq!(run(&["dump", "--schema"]).status.code(), Some(5));
+}
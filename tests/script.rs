@@ -0,0 +1,57 @@
+use arrctl::error::{self, ErrorKind};
+use arrctl::journal::Journal;
+use arrctl::msr::MsrAccess;
+use arrctl::output::{self, Format, Locale};
+use arrctl::regs::*;
+use arrctl::script::{self, Action, Reading, Run};
+use arrctl::{schema, trial::Setting};
+use std::{env, fs};
+
+mod common;
+
+#[test]
+fn parses_the_whole_file_first() {
+    let steps = script::parse("# lower it\nset tdp 25W\n\nsleep 0ms  # no wait\nif temperature >= 80 set clock-modulation 50%\nget turbo-ratios\n").unwrap();
+    assert_eq!(steps.iter().map(|s| s.line).collect::<Vec<_>>(), [2, 4, 5, 6]);
+    assert_eq!(steps[0].action, Action::Set(Setting::Tdp, "25W".into()));
+    assert!(matches!(&steps[2].action, Action::If(Reading::Temperature, _, n, then) if *n == 80.0 && then.writes()));
+    assert!(!steps[3].action.writes());
+
+    for (text, expected) in [
+        ("get tdp\nset tdp lots\n", "Line 2"),
+        ("sleep\n", "Wrong number of arguments to sleep"),
+        ("if temperature ~ 80 get tdp\n", "Expected <, <="),
+        ("if fan > 1 get tdp\n", "Can't compare \"fan\""),
+        ("reboot\n", "Unknown command"),
+    ] {
+        let err = script::parse(text).unwrap_err();
+        assert_eq!(error::kind_of(&err), ErrorKind::Validation);
+        assert!(err.to_string().contains(expected), "{}", err);
+    }
+}
+
+#[test]
+fn runs_in_order_with_conditions() {
+    let msr = common::msr();
+    let journal = Journal::new(env::temp_dir().join(format!("arrctl-test-script-{}", std::process::id())));
+    let hottest = script::read(&msr, Reading::Temperature).unwrap();
+    let steps = script::parse(&format!(
+        "set tdp 20\nif temperature > {hot} set tdc 10\nif temperature <= {hot} set tdc 40\nif tdp == 20 get tdp\nget tdc\n",
+        hot = hottest
+    ))
+    .unwrap();
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    Run { msr: &msr, journal: &journal, stock: None, all_cores: false, force: true }.run(&mut *out, &steps).unwrap();
+    drop(out);
+
+    let limits = MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap());
+    assert_eq!((limits.tdp_watts().whole(), limits.tdc_amps().whole()), (20, 40));
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(text.lines().count(), 2, "{}", text);
+    for line in text.lines() {
+        schema::check("run", &serde_json::from_str(line).unwrap()).unwrap();
+    }
+    let _ = fs::remove_file(journal.path());
+}
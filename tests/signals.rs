@@ -0,0 +1,15 @@
+use arrctl::signals;
+use std::time::{Duration, Instant};
+
+#[test]
+fn term_stops_sleeps() {
+    signals::install().unwrap();
+    assert!(signals::sleep(Duration::from_millis(10)));
+    assert!(!signals::stopping());
+
+    unsafe { libc::raise(libc::SIGTERM) };
+    assert!(signals::stopping());
+    let start = Instant::now();
+    assert!(!signals::sleep(Duration::from_secs(10)));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
@@ -0,0 +1,65 @@
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::output::{self, Format, Locale};
+use arrctl::power::CoreSample;
+use arrctl::schema;
+use arrctl::soak::{self, Criteria, Judge};
+use std::time::Duration;
+
+fn sample(mhz: f32, celsius: u64) -> Sample {
+    let cpu = |cpu| CpuSample { cpu, activity: CoreSample { effective_mhz: mhz, active: 1.0, volts: 1.1 }, celsius: Some(celsius), throttling: false };
+    Sample { cpus: vec![cpu(0), cpu(1)], package_watts: 30.0, core_amps: 30.0, core_amps_at_tjmax: 35.0 }
+}
+
+#[test]
+fn sustained_is_a_window_average() {
+    let mut judge = Judge::new(Criteria { max_celsius: Some(92), min_sustained_mhz: Some(2200.0) });
+    // One slow second in a good minute doesn't count
+    for secs in 1..=60 {
+        let mhz = if secs == 30 { 1200.0 } else { 2400.0 };
+        judge.update(&sample(mhz, 80), Duration::from_secs(secs));
+    }
+    assert_eq!(judge.violation, None);
+    assert!(judge.min_window_mhz.unwrap() > 2350.0);
+
+    // A whole slow minute does
+    for secs in 61..=120 {
+        judge.update(&sample(1800.0, 85), Duration::from_secs(secs));
+    }
+    let violation = judge.violation.clone().unwrap();
+    assert_eq!(violation.at, Duration::from_secs(120));
+    assert!(violation.text.starts_with("Sustained 1800 MHz"), "{}", violation.text);
+
+    // Later ones don't replace the first
+    judge.update(&sample(2400.0, 95), Duration::from_secs(121));
+    assert_eq!(judge.violation, Some(violation));
+    assert_eq!(judge.max_celsius, Some(95));
+}
+
+#[test]
+fn report_and_verdict() {
+    let mut judge = Judge::new(Criteria { max_celsius: Some(92), ..Default::default() });
+    judge.update(&sample(2400.0, 90), Duration::from_secs(1));
+    let render = |judge: &Judge, elapsed| {
+        let mut text = Vec::new();
+        let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+        for record in judge.records(Duration::from_secs(elapsed), Duration::from_secs(3600)) {
+            out.record(&record).unwrap();
+        }
+        drop(out);
+        let lines: Vec<serde_json::Value> = String::from_utf8(text).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        lines.iter().for_each(|l| schema::check("soak", l).unwrap());
+        lines
+    };
+
+    assert_eq!(render(&judge, 3600)[0]["passed"], true);
+    // Cut short isn't a pass
+    assert_eq!(render(&judge, 1800)[0]["passed"], false);
+
+    judge.update(&sample(2400.0, 93), Duration::from_secs(2));
+    let lines = render(&judge, 3600);
+    assert_eq!(lines[0]["kind"], "violation");
+    assert_eq!(lines[1]["passed"], false);
+
+    assert_eq!(soak::parse_hours("0.5"), Ok(Duration::from_secs(1800)));
+    assert!(soak::parse_hours("-1").is_err());
+}
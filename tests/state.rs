@@ -0,0 +1,109 @@
+use arrctl::msr::{MockMsr, MsrAccess};
+use arrctl::regs::*;
+use arrctl::state::{self, Migration, Saved, State};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod common;
+
+use common::temp;
+
+#[test]
+fn baseline_taken_once_per_boot() {
+    let path = temp("baseline");
+    let msr = MockMsr::from_dump("0 0x1ac 0x1\n0 0x1ad 0x1416\n0 0x1a0 0x2\n0 0x19a 0x0\n0 0x199 0x1200\n1 0x1ac 0x1\n1 0x1ad 0x1416\n1 0x1a0 0x2\n1 0x19a 0x0\n1 0x199 0x1200").unwrap();
+    assert_eq!(state::load(&path).unwrap(), State::default());
+
+    assert!(state::ensure_baseline(&path, &msr, "boot-a".to_string()).unwrap());
+    let baseline = state::load(&path).unwrap().baseline.unwrap();
+    assert_eq!(baseline.registers.len(), 10);
+    assert!(baseline.registers.contains(&Saved { cpu: 1, register: IA32_MISC_ENABLE, value: 0x2 }));
+
+    // Later runs in the same boot keep the firmware values
+    msr.write(MSR_TURBO_LIMITS, 0, 0x5).unwrap();
+    assert!(!state::ensure_baseline(&path, &msr, "boot-a".to_string()).unwrap());
+    assert!(state::ensure_baseline(&path, &msr, "boot-b".to_string()).unwrap());
+    let baseline = state::load(&path).unwrap().baseline.unwrap();
+    assert!(baseline.registers.contains(&Saved { cpu: 0, register: MSR_TURBO_LIMITS, value: 0x5 }));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn migrations_run_in_order() {
+    fn rename(mut value: Value) -> anyhow::Result<Value> {
+        value["limits"] = value["old_limits"].take();
+        Ok(value)
+    }
+    fn add(mut value: Value) -> anyhow::Result<Value> {
+        value["added"] = true.into();
+        Ok(value)
+    }
+    let migrations: &[Migration] = &[rename, add];
+
+    let value = state::upgrade(json!({"version": 1, "old_limits": 18}), migrations).unwrap();
+    assert_eq!(value, json!({"version": 3, "old_limits": null, "limits": 18, "added": true}));
+    let value = state::upgrade(json!({"version": 2, "limits": 18}), migrations).unwrap();
+    assert_eq!(value, json!({"version": 3, "limits": 18, "added": true}));
+}
+
+#[test]
+fn newer_state_is_refused() {
+    let path = temp("newer");
+    fs::write(&path, format!("{{\"version\": {}, \"baseline\": null}}", state::VERSION + 1)).unwrap();
+    let err = state::load(&path).unwrap_err();
+    assert!(format!("{:#}", err).contains("newer"), "{:#}", err);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupted_state_is_caught() {
+    let path = temp("checksum");
+    let msr = MockMsr::from_dump("0 0x1ac 0x1\n0 0x1ad 0x1416\n0 0x1a0 0x2\n0 0x19a 0x0\n0 0x199 0x1200").unwrap();
+    state::ensure_baseline(&path, &msr, "boot-a".to_string()).unwrap();
+    assert!(leftovers(&path).is_empty());
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.contains("\"checksum\""));
+
+    // Still JSON, but a flipped value the baseline would have restored
+    fs::write(&path, text.replace("5142", "5143")).unwrap();
+    let err = state::load(&path).unwrap_err();
+    assert_eq!(arrctl::error::kind_of(&err), arrctl::error::ErrorKind::Validation);
+    assert!(format!("{:#}", err).contains("checksum"), "{:#}", err);
+
+    // From before there were checksums
+    fs::write(&path, "{\"version\": 1, \"baseline\": null}").unwrap();
+    assert_eq!(state::load(&path).unwrap(), State::default());
+    fs::remove_file(&path).unwrap();
+}
+
+// Temporary files next to path that didn't get renamed
+fn leftovers(path: &Path) -> Vec<PathBuf> {
+    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+    fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.file_name().unwrap().to_str().is_some_and(|n| n.starts_with(&name) && n.ends_with(".tmp")))
+        .collect()
+}
+
+#[test]
+fn concurrent_writers_dont_mix() {
+    let path = temp("concurrent");
+    let writers = [b'a', b'b'].map(|byte| {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            for _ in 0..50 {
+                arrctl::atomic::write(&path, &[byte; 64 * 1024]).unwrap();
+            }
+        })
+    });
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    let contents = fs::read(&path).unwrap();
+    assert_eq!(contents.len(), 64 * 1024);
+    assert!(contents.iter().all(|&b| b == contents[0]));
+    assert!(leftovers(&path).is_empty());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,55 @@
+use arrctl::systemd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+use std::{env, fs};
+
+#[test]
+fn notifies_over_the_socket() {
+    let path = env::temp_dir().join(format!("arrctl-notify-{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let sock = UnixDatagram::bind(&path).unwrap();
+    systemd::notify_to(path.to_str().unwrap(), "READY=1\nSTATUS=Sampling").unwrap();
+    let mut buf = [0; 64];
+    let n = sock.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"READY=1\nSTATUS=Sampling");
+    let _ = fs::remove_file(&path);
+
+    // Abstract ones too
+    let name = format!("arrctl-notify-{}", std::process::id());
+    let sock = UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+    systemd::notify_to(&format!("@{}", name), "WATCHDOG=1").unwrap();
+    let n = sock.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"WATCHDOG=1");
+
+    assert!(systemd::notify_to("/nonexistent/notify", "READY=1").is_err());
+}
+
+#[test]
+fn reads_what_systemd_passes() {
+    assert_eq!(systemd::watchdog(Some("30000000"), None, 7), Some(Duration::from_secs(15)));
+    assert_eq!(systemd::watchdog(Some("30000000"), Some("7"), 7), Some(Duration::from_secs(15)));
+    // Meant for another process, or off
+    assert_eq!(systemd::watchdog(Some("30000000"), Some("8"), 7), None);
+    assert_eq!(systemd::watchdog(Some("0"), None, 7), None);
+    assert_eq!(systemd::watchdog(None, None, 7), None);
+
+    assert_eq!(systemd::listen_fd_range(Some("7"), Some("2"), 7), 3..5);
+    assert!(systemd::listen_fd_range(Some("8"), Some("2"), 7).is_empty());
+    assert!(systemd::listen_fd_range(None, Some("2"), 7).is_empty());
+    assert!(systemd::listen_fd_range(Some("7"), Some("-1"), 7).is_empty());
+}
+
+#[test]
+fn journal_entries() {
+    assert_eq!(systemd::priority("<3>Policy off: no such file"), (3, "Policy off: no such file"));
+    assert_eq!(systemd::priority("Reloaded /etc/arrctl/profiles.toml"), (6, "Reloaded /etc/arrctl/profiles.toml"));
+    assert_eq!(systemd::priority("<9>odd"), (6, "<9>odd"));
+
+    assert_eq!(systemd::journal_entry(&[("MESSAGE", "hot"), ("PRIORITY", "2")]), b"MESSAGE=hot\nPRIORITY=2\n");
+    // A newline in the value is only safe length-prefixed
+    let mut expected = b"MESSAGE\n".to_vec();
+    expected.extend_from_slice(&7u64.to_le_bytes());
+    expected.extend_from_slice(b"one\ntwo\n");
+    assert_eq!(systemd::journal_entry(&[("MESSAGE", "one\ntwo")]), expected);
+}
@@ -0,0 +1,56 @@
+use arrctl::error::{Error, ErrorKind};
+use arrctl::profile::{self, Profile};
+use arrctl::thinkpad::FanLevel;
+use std::fs;
+
+mod common;
+
+use common::temp;
+
+#[test]
+fn fan_levels() {
+    assert_eq!("3".parse(), Ok(FanLevel::Level(3)));
+    assert_eq!("auto".parse(), Ok(FanLevel::Auto));
+    assert_eq!("full-speed".parse(), Ok(FanLevel::FullSpeed));
+    assert!("8".parse::<FanLevel>().is_err());
+    assert!("disengaged".parse::<FanLevel>().is_err());
+
+    // Numbers and names both, the way thinkpad_acpi takes them
+    let profile: Profile = toml::from_str("fan_level = 0").unwrap();
+    assert_eq!(profile.fan_level, Some(FanLevel::Level(0)));
+    let profile: Profile = toml::from_str("fan_level = \"full-speed\"").unwrap();
+    assert_eq!(profile.fan_level, Some(FanLevel::FullSpeed));
+    assert!(toml::from_str::<Profile>("fan_level = 12").is_err());
+    assert_eq!(FanLevel::FullSpeed.to_string(), "full-speed");
+}
+
+#[test]
+fn profiles_need_fan_control() {
+    let path = temp("profiles");
+    fs::write(&path, "[profiles.quiet]\ntdp = 12\nfan_level = \"auto\"\n").unwrap();
+    let err = profile::load(&path).unwrap_err();
+    assert_eq!(err.downcast_ref::<Error>().map(|e| e.kind), Some(ErrorKind::Validation));
+
+    fs::write(&path, "[thinkpad]\nfan_control = true\n\n[profiles.quiet]\ntdp = 12\nfan_level = \"auto\"\n").unwrap();
+    let profiles = profile::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(profiles.profiles["quiet"].fan_level, Some(FanLevel::Auto));
+}
+
+#[cfg(feature = "thinkpad")]
+#[test]
+fn sets_level_through_procfs() {
+    use arrctl::thinkpad::Fan;
+
+    let path = temp("fan");
+    fs::write(&path, "status:\t\tenabled\nspeed:\t\t2700\nlevel:\t\tauto\n").unwrap();
+    // Without fan_control=1 there are no commands and writes fail
+    assert!(Fan::find(&path).is_err());
+
+    fs::write(&path, "status:\t\tenabled\nspeed:\t\t2700\nlevel:\t\tauto\ncommands:\tlevel <level> (<level> is 0-7, auto, disengaged, full-speed)\n").unwrap();
+    let fan = Fan::find(&path).unwrap();
+    assert_eq!(fan.level().unwrap(), "auto");
+    fan.set_level(FanLevel::Level(2)).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "level 2");
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,134 @@
+use arrctl::cpu::{self, CpuInfo};
+use arrctl::regs::{self, Scope, IA32_CLOCK_MODULATION, IA32_THERM_STATUS, MSR_TURBO_LIMITS};
+use arrctl::monitor::Sampler;
+use arrctl::msr::{MockMsr, MsrAccess};
+use anyhow::Result;
+use arrctl::output::{Record, Value};
+use std::{env, fs};
+
+mod common;
+
+// 2 cores with HT, siblings numbered 0/2 and 1/3 like Arrandale enumerates them
+fn arrandale() -> Vec<CpuInfo> {
+    [(0, 0), (1, 2), (2, 0), (3, 2)]
+        .map(|(cpu, core)| CpuInfo { cpu, core, package: 0 })
+        .to_vec()
+}
+
+#[test]
+fn core_scope_skips_siblings() {
+    assert_eq!(cpu::representatives(&arrandale(), regs::scope(IA32_THERM_STATUS)), [0, 1]);
+    assert_eq!(cpu::representatives(&arrandale(), regs::scope(MSR_TURBO_LIMITS)), [0]);
+    assert_eq!(cpu::representatives(&arrandale(), regs::scope(IA32_CLOCK_MODULATION)), [0, 1, 2, 3]);
+}
+
+#[test]
+fn packages_are_kept_apart() {
+    let layout: Vec<CpuInfo> = (0..4).map(|cpu| CpuInfo { cpu, core: 0, package: cpu as u32 / 2 }).collect();
+    assert_eq!(cpu::representatives(&layout, Scope::Package), [0, 2]);
+    assert_eq!(cpu::representatives(&layout, Scope::Core), [0, 2]);
+}
+
+#[test]
+fn layout_from_sysfs() {
+    let root = env::temp_dir().join(format!("arrctl-test-sysfs-{}", std::process::id()));
+    for (cpu, core) in [(0, 0), (1, 2)] {
+        let dir = root.join(format!("cpu{}/topology", cpu));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("core_id"), format!("{}\n", core)).unwrap();
+        fs::write(dir.join("physical_package_id"), "0\n").unwrap();
+    }
+
+    let layout = cpu::layout_from(&root, 0..3);
+    assert_eq!(layout[1], CpuInfo { cpu: 1, core: 2, package: 0 });
+    // No topology for CPU2, so it stands alone
+    assert_eq!(cpu::representatives(&layout, Scope::Core), [0, 1, 2]);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn cpu_list_with_holes() {
+    assert_eq!(cpu::parse_cpu_list("0-1,3\n"), Some(vec![0, 1, 3]));
+    assert_eq!(cpu::parse_cpu_list("0"), Some(vec![0]));
+    assert_eq!(cpu::parse_cpu_list("0-x"), None);
+}
+
+#[test]
+fn sampler_follows_offline_siblings() {
+    // CPU1 and CPU3 offlined, like echo 0 > /sys/devices/system/cpu/cpu1/online
+    let dump = common::fixture("i5-520m.dump");
+    let dump: String = dump
+        .lines()
+        .filter(|line| !line.starts_with("1 ") && !line.starts_with("3 "))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let msr = MockMsr::from_dump(&dump).unwrap();
+    assert_eq!(msr.cpus(), [0, 2]);
+
+    let mut sampler = Sampler::new(&msr).unwrap();
+    let sample = sampler.sample(&msr).unwrap();
+    assert_eq!(sample.cpus.iter().map(|c| c.cpu).collect::<Vec<_>>(), [0, 2]);
+}
+
+#[test]
+fn rows_are_labelled_by_place() {
+    let cpuinfo = "processor\t: 0\nvendor_id\t: GenuineIntel\napicid\t\t: 0\n\nprocessor\t: 1\napicid\t\t: 4\n\nprocessor\t: 2\napicid\t\t: 1\n";
+    assert_eq!(cpu::apic_ids(cpuinfo), [(0, 0), (1, 4), (2, 1)]);
+
+    let places = cpu::Places::new(arrandale(), cpu::apic_ids(cpuinfo));
+    let record = places.label(Record::new("cpu").title("CPU1").cpu(1));
+    assert_eq!(record.title.as_deref(), Some("CPU1 (core 2, APIC 4)"));
+    assert!(matches!(record.get("apic"), Some(Value::Int(4))));
+    assert_eq!(places.label(Record::new("cpu").cpu(3)).title.as_deref(), Some("CPU3 (core 2, APIC ?)"));
+
+    // Two Westmere-EP sockets number their CPUs alternating
+    let layout: Vec<CpuInfo> = (0..4).map(|cpu| CpuInfo { cpu, core: cpu as u32 / 2, package: cpu as u32 % 2 }).collect();
+    let places = cpu::Places::new(layout, vec![(1, 32)]);
+    let mut records: Vec<Record> = (0..4).map(|cpu| places.label(Record::new("cpu").cpu(cpu))).collect();
+    records.push(Record::new("package"));
+    places.group(&mut records);
+    assert_eq!(records.iter().map(|r| r.cpu).collect::<Vec<_>>(), [Some(0), Some(2), Some(1), Some(3), None]);
+    assert_eq!(records[2].title.as_deref(), Some("CPU1 (package 1 core 0, APIC 32)"));
+}
+
+#[test]
+fn an_offline_cpu0_has_a_stand_in() {
+    // The second socket's threads stay online, and one of the first's
+    let layout = [(1, 0, 0), (4, 0, 1), (5, 1, 1)].map(|(cpu, core, package)| CpuInfo { cpu, core, package }).to_vec();
+    assert_eq!(cpu::stand_in(&layout, 0), Some(1));
+    assert_eq!(cpu::stand_in(&layout, 1), Some(4));
+    // The whole first socket offline
+    assert_eq!(cpu::stand_in(&layout[1..], 0), Some(4));
+    assert_eq!(cpu::stand_in(&[], 0), None);
+
+    let dump = common::fixture("i5-520m.dump");
+    let limits = regs::msr_turbo_limits(&MockMsr::from_dump(&dump).unwrap()).unwrap();
+    let without_cpu0: String = dump.lines().filter(|line| !line.starts_with("0 ")).map(|line| format!("{}\n", line)).collect();
+    let msr = MockMsr::from_dump(&without_cpu0).unwrap();
+    assert!(!msr.cpus().contains(&0));
+    assert_eq!(regs::msr_turbo_limits(&msr).unwrap().0, limits.0);
+
+    // Gone from /dev/cpu before it's gone from the online list
+    let msr = Unplugged(MockMsr::from_dump(&dump).unwrap());
+    assert!(msr.cpus().contains(&0));
+    assert_eq!(regs::msr_turbo_limits(&msr).unwrap().0, limits.0);
+}
+
+struct Unplugged(MockMsr);
+
+impl MsrAccess for Unplugged {
+    fn cpus(&self) -> Vec<u16> {
+        self.0.cpus()
+    }
+
+    fn read(&self, reg: u32, cpu: u16) -> Result<u64> {
+        match cpu {
+            0 => Err(std::io::Error::from_raw_os_error(libc::ENOENT).into()),
+            _ => self.0.read(reg, cpu),
+        }
+    }
+
+    fn write(&self, reg: u32, cpu: u16, val: u64) -> Result<()> {
+        self.0.write(reg, cpu, val)
+    }
+}
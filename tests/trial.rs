@@ -0,0 +1,65 @@
+use arrctl::cli;
+use arrctl::journal::Journal;
+use arrctl::msr::{MockMsr, MsrAccess};
+use arrctl::regs::*;
+use arrctl::trial::{self, Setting};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+mod common;
+
+use common::msr;
+
+fn temp(name: &str) -> (PathBuf, Journal) {
+    let path = common::temp(&format!("{}.json", name));
+    let journal = Journal::new(path.with_extension("journal"));
+    let _ = fs::remove_file(journal.path());
+    (path, journal)
+}
+
+fn tdp(msr: &MockMsr) -> u64 {
+    MsrTurboLimits(msr.read(MSR_TURBO_LIMITS, 0).unwrap()).tdp() / 8
+}
+
+#[test]
+fn unconfirmed_try_reverts() {
+    let (path, journal) = temp("try-revert");
+    let msr = msr();
+    let profile = trial::profile(Setting::Tdp, "30").unwrap();
+    let started = trial::start(&msr, &journal, &path, &profile, Duration::from_secs(60), "boot".into()).unwrap();
+    assert_eq!(tdp(&msr), 30);
+    assert!(trial::start(&msr, &journal, &path, &profile, Duration::from_secs(60), "boot".into()).is_err());
+
+    // Not due yet, then due
+    assert_eq!(trial::expire(&msr, &journal, &path, "boot", started.deadline - 1).unwrap(), None);
+    assert!(trial::expire(&msr, &journal, &path, "boot", started.deadline).unwrap().is_some());
+    assert_eq!(tdp(&msr), 35);
+    assert!(!path.exists());
+    fs::remove_file(journal.path()).unwrap();
+}
+
+#[test]
+fn confirmed_try_stays() {
+    let (path, journal) = temp("try-confirm");
+    let msr = msr();
+    let profile = trial::profile(Setting::Tdp, "30").unwrap();
+    let started = trial::start(&msr, &journal, &path, &profile, Duration::from_secs(60), "boot".into()).unwrap();
+
+    // Trials from an earlier boot don't count
+    assert!(trial::confirm(&path, "other boot").is_err());
+    trial::confirm(&path, "boot").unwrap();
+    assert_eq!(trial::expire(&msr, &journal, &path, "boot", started.deadline).unwrap(), None);
+    assert_eq!(tdp(&msr), 30);
+    fs::remove_file(journal.path()).unwrap();
+}
+
+#[test]
+fn settings_and_durations() {
+    assert_eq!(trial::profile(Setting::Turbo, "off").unwrap().turbo, Some(false));
+    assert!(trial::profile(Setting::Tdc, "lots").is_err());
+    assert_eq!(cli::parse_duration("90"), Ok(Duration::from_secs(90)));
+    assert_eq!(cli::parse_duration("2m"), Ok(Duration::from_secs(120)));
+    assert!(cli::parse_duration("soon").is_err());
+    assert!(cli::parse_duration("99999999999999999h").unwrap_err().contains("too long"));
+}
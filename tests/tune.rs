@@ -0,0 +1,41 @@
+use arrctl::output::{self, Format, Locale};
+use arrctl::schema;
+use arrctl::tune::{self, Search, Step};
+
+fn step(tdp: u64, mhz: f32) -> Step {
+    Step { tdp, mhz, package_watts: tdp as f32 - 0.5, max_celsius: Some(80) }
+}
+
+#[test]
+fn stops_at_the_knee() {
+    let mut search = Search::new(2400.0, 2, 8);
+    assert_eq!(search.next(step(25, 2800.0)), Some(23));
+    assert_eq!(search.next(step(23, 2600.0)), Some(21));
+    // The clock gave at 21 W, 23 was the last that held
+    assert_eq!(search.next(step(21, 2300.0)), None);
+    assert_eq!(search.knee(), Some(23));
+    assert!(!search.reached_floor());
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    out.record(&tune::step_record(&search.steps[2], search.min_mhz)).unwrap();
+    out.record(&tune::record(&search, 23, &"spin".parse().unwrap())).unwrap();
+    drop(out);
+    for line in text.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+        schema::check("tune", &serde_json::from_slice(line).unwrap()).unwrap();
+    }
+}
+
+#[test]
+fn stops_at_the_floor() {
+    let mut search = Search::new(1000.0, 3, 8);
+    assert_eq!(search.next(step(12, 2000.0)), Some(9));
+    assert_eq!(search.next(step(9, 1900.0)), None);
+    assert_eq!(search.knee(), Some(9));
+    assert!(search.reached_floor());
+
+    // Already too slow where it started leaves nothing to recommend
+    let mut search = Search::new(3000.0, 1, 8);
+    assert_eq!(search.next(step(35, 2900.0)), None);
+    assert_eq!(search.knee(), None);
+}
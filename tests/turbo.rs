@@ -0,0 +1,72 @@
+use arrctl::monitor::{CpuSample, Sample};
+use arrctl::output::{self, Format, Locale};
+use arrctl::power::CoreSample;
+use arrctl::schema;
+use arrctl::turbo::Residency;
+
+// One CPU at a ratio, busy or not
+fn sample(ratio: f32, active: f32) -> Sample {
+    Sample {
+        cpus: vec![CpuSample { cpu: 0, activity: CoreSample { effective_mhz: ratio * 133.33, active, volts: 1.1 }, celsius: Some(70), throttling: false }],
+        package_watts: 20.0,
+        core_amps: 15.0,
+        core_amps_at_tjmax: 18.0,
+    }
+}
+
+#[test]
+fn times_turbo_until_a_limit_binds() {
+    let mut residency = Residency::new(18, 133.33);
+    // 8 s of turbo, then back to base under the same load
+    for _ in 0..8 {
+        residency.update(&sample(22.0, 1.0), 1.0);
+    }
+    for _ in 0..4 {
+        residency.update(&sample(18.0, 1.0), 1.0);
+    }
+    // A shorter spell that ends with the load, no limit to blame for that
+    for _ in 0..3 {
+        residency.update(&sample(21.0, 1.0), 1.0);
+    }
+    residency.update(&sample(9.0, 0.1), 1.0);
+
+    assert_eq!(residency.bound, vec![8.0]);
+    assert_eq!(residency.ongoing(), None);
+    assert_eq!(residency.seconds.get(&22), Some(&8.0));
+    assert_eq!(residency.seconds.get(&18), Some(&4.0));
+    // Idle time isn't counted at any ratio
+    assert_eq!(residency.seconds.get(&9), None);
+
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    for record in residency.records() {
+        out.record(&record).unwrap();
+    }
+    drop(out);
+    let values: Vec<serde_json::Value> = text.split(|&b| b == b'\n').filter(|line| !line.is_empty()).map(|line| serde_json::from_slice(line).unwrap()).collect();
+    assert_eq!(values.len(), 4);
+    assert_eq!(values[1]["ratio"], 21);
+    assert_eq!(values[1]["turbo"], true);
+    assert_eq!(values[3]["longest_seconds"], 8.0);
+    for value in &values {
+        schema::check("bench", value).unwrap();
+    }
+}
+
+#[test]
+fn turbo_that_holds_is_ongoing() {
+    let mut residency = Residency::new(18, 133.33);
+    for _ in 0..5 {
+        residency.update(&sample(22.0, 1.0), 0.5);
+    }
+    assert!(residency.bound.is_empty());
+    assert_eq!(residency.ongoing(), Some(2.5));
+    let duration = residency.records().pop().unwrap();
+    assert_eq!(duration.kind, "turbo_duration");
+    assert!(duration.get("longest_seconds").is_none());
+
+    // No turbo at all leaves out the spells
+    let mut residency = Residency::new(18, 133.33);
+    residency.update(&sample(18.0, 1.0), 1.0);
+    assert!(residency.records().iter().all(|r| r.kind != "turbo_duration"));
+}
@@ -0,0 +1,54 @@
+use arrctl::cli::Cli;
+use arrctl::msr::MockMsr;
+use arrctl::units::*;
+use clap::Parser;
+use std::time::Duration;
+
+#[test]
+fn limits_take_units() {
+    assert_eq!(parse_watts("25"), Ok(25));
+    assert_eq!(parse_watts("25W"), Ok(25));
+    assert_eq!(parse_watts("25000mW"), Ok(25));
+    assert!(parse_watts("25500mW").unwrap_err().contains("whole W"));
+    assert!(parse_watts("25A").is_err());
+    assert_eq!(parse_amps("30A"), Ok(30));
+    assert_eq!(parse_amps("30000mA"), Ok(30));
+
+    assert_eq!(parse_millis("1000"), Ok(1000));
+    assert_eq!(parse_millis("500ms"), Ok(500));
+    assert_eq!(parse_millis("2s"), Ok(2000));
+    assert!(parse_millis("2h").is_err());
+
+    let cli = Cli::try_parse_from(["arrctl", "--set-tdp", "25W", "--set-tdc", "30000mA", "--interval", "2s"]).unwrap();
+    assert_eq!((cli.set_tdp, cli.set_tdc, cli.interval), (Some(25), Some(30), 2000));
+    assert!(Cli::try_parse_from(["arrctl", "--set-tdp", "25.5W"]).is_err());
+}
+
+#[test]
+fn frequencies_become_ratios() {
+    assert!((parse_mhz("2.66GHz").unwrap() - 2660.0).abs() < 1e-9);
+    assert_eq!(parse_mhz("2660MHz"), Ok(2660.0));
+    assert!(parse_mhz("0").is_err());
+    assert_eq!(parse_whole_mhz("1.2GHz"), Ok(1200));
+    assert_eq!(nearest_ratio(2660.0, 133.33), 20);
+    assert_eq!(nearest_ratio(10.0, 133.33), 1);
+
+    // A dump has no running TSC, so the measurement falls back to the nominal BCLK
+    let msr = MockMsr::from_dump("0 0x10 0x1000\n").unwrap();
+    assert_eq!(arrctl::cpu::measure_bclk(&msr, 9, Duration::from_millis(5)), arrctl::cpu::BCLK_MHZ as f64);
+}
+
+#[test]
+fn duty_cycles_by_name() {
+    assert_eq!(parse_duty_cycle("off"), Ok(0));
+    assert_eq!(parse_duty_cycle("50%"), Ok(4));
+    assert_eq!(parse_duty_cycle("37%"), Ok(3));
+    assert_eq!(parse_duty_cycle("87.5 %"), Ok(7));
+    // Eighths, as it always took
+    assert_eq!(parse_duty_cycle("2"), Ok(2));
+    assert!(parse_duty_cycle("50").unwrap_err().contains("12.5%"));
+    assert!(parse_duty_cycle("100%").is_err());
+
+    let cli = Cli::try_parse_from(["arrctl", "--set-clock-modulation", "25%"]).unwrap();
+    assert_eq!(cli.set_clock_modulation, Some(2));
+}
@@ -0,0 +1,67 @@
+use arrctl::web::{self, Request};
+
+#[test]
+fn parses_requests() {
+    let head = "GET /ws HTTP/1.1\r\nHost: server:9110\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+    let request = web::parse_request(head).unwrap();
+    assert_eq!((request.method.as_str(), request.path.as_str()), ("GET", "/ws"));
+    assert_eq!(request.header("sec-websocket-key"), Some("dGhlIHNhbXBsZSBub25jZQ=="));
+    assert!(request.wants_websocket());
+    assert!(!Request { headers: Vec::new(), ..request }.wants_websocket());
+    assert_eq!(web::parse_request("SSH-2.0-OpenSSH_9.6\r\n\r\n"), None);
+    assert_eq!(web::parse_request("GET / HTTP/1.1\r\nno colon\r\n\r\n"), None);
+
+    // The example from RFC 6455
+    assert_eq!(web::accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    assert_eq!(web::base64(b"ab"), "YWI=");
+    assert_eq!(web::base64(b"a"), "YQ==");
+}
+
+#[test]
+fn frames_both_ways() {
+    assert_eq!(web::frame(web::TEXT, b"hi"), [0x81, 2, b'h', b'i']);
+    let long = web::frame(web::TEXT, &[0; 300]);
+    assert_eq!(long[..4], [0x81, 126, 1, 44]);
+    assert_eq!(web::frame(web::TEXT, &[0; 70000])[1..10], [127, 0, 0, 0, 0, 0, 1, 0x11, 0x70]);
+
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    // A masked close with code 1000, the way browsers send it
+    let mask = [1, 2, 3, 4];
+    let mut bytes = vec![0x88, 0x82];
+    bytes.extend(mask);
+    bytes.extend([0x03 ^ 1, 0xe8 ^ 2]);
+    let mut reader = &bytes[..];
+    assert_eq!(rt.block_on(web::read_frame(&mut reader)).unwrap(), Some((web::CLOSE, vec![0x03, 0xe8])));
+    assert_eq!(rt.block_on(web::read_frame(&mut reader)).unwrap(), None);
+    // Clients have to mask
+    assert!(rt.block_on(web::read_frame(&mut &[0x81, 0x01, b'x'][..])).is_err());
+}
+
+#[test]
+fn only_trusts_its_own_pages() {
+    let request = |host: &str, origin: Option<&str>| {
+        let mut headers = vec![("Host".to_string(), host.to_string())];
+        headers.extend(origin.map(|origin| ("Origin".to_string(), origin.to_string())));
+        Request { method: "POST".into(), path: "/apply/quiet".into(), headers }
+    };
+    assert!(web::same_origin(&request("127.0.0.1:9110", Some("http://127.0.0.1:9110"))));
+    assert!(web::same_origin(&request("localhost:9110", Some("http://localhost:9110"))));
+    assert!(web::same_origin(&request("[::1]:9110", Some("http://[::1]:9110"))));
+    assert!(web::same_origin(&request("[::1]", Some("http://[::1]"))));
+    assert!(!web::same_origin(&request("127.0.0.1:9110", Some("http://evil.example"))));
+    assert!(!web::same_origin(&request("127.0.0.1:9110", None)));
+    // DNS rebinding, the name resolves to 127.0.0.1 and the origin matches
+    assert!(!web::same_origin(&request("evil.example:9110", Some("http://evil.example:9110"))));
+
+    // Reads only turn away other pages, tools send no Origin
+    assert!(web::not_cross_site(&request("127.0.0.1:9110", None)));
+    assert!(web::not_cross_site(&request("127.0.0.1:9110", Some("http://127.0.0.1:9110"))));
+    assert!(!web::not_cross_site(&request("127.0.0.1:9110", Some("http://evil.example"))));
+    assert!(!web::not_cross_site(&request("evil.example:9110", Some("http://evil.example:9110"))));
+
+    assert_eq!(web::percent_decode("on%20battery").as_deref(), Some("on battery"));
+    assert_eq!(web::percent_decode("gaming").as_deref(), Some("gaming"));
+    assert_eq!(web::percent_decode("bad%2"), None);
+    assert_eq!(web::percent_decode("%ff"), None);
+    assert!(web::UI.contains("/ws"));
+}
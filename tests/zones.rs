@@ -0,0 +1,66 @@
+use arrctl::output::{self, Format, Level, Locale};
+use arrctl::zones::{self, Trip};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn zone(class: &Path, name: &str, kind: &str, temp: &str, trips: &[(&str, &str)]) {
+    let dir = class.join(name);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("type"), format!("{}\n", kind)).unwrap();
+    fs::write(dir.join("temp"), format!("{}\n", temp)).unwrap();
+    for (i, (kind, temp)) in trips.iter().enumerate() {
+        fs::write(dir.join(format!("trip_point_{}_type", i)), format!("{}\n", kind)).unwrap();
+        fs::write(dir.join(format!("trip_point_{}_temp", i)), format!("{}\n", temp)).unwrap();
+    }
+}
+
+// What a ThinkPad of the era has, give or take
+fn class() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("arrctl-zones-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    zone(&dir, "thermal_zone0", "acpitz", "55000", &[("critical", "108000"), ("passive", "95000"), ("active", "70000")]);
+    zone(&dir, "thermal_zone10", "pch_thermal", "48000", &[]);
+    zone(&dir, "thermal_zone2", "INT3400", "-273000", &[]);
+    fs::create_dir_all(dir.join("cooling_device0")).unwrap();
+    dir
+}
+
+fn json(record: &output::Record) -> serde_json::Value {
+    let mut text = Vec::new();
+    let mut out = output::sink(Format::Json, Locale::C, false, &mut text);
+    out.record(record).unwrap();
+    out.finish().unwrap();
+    drop(out);
+    serde_json::from_slice(&text).unwrap()
+}
+
+#[test]
+fn reads_zones_and_trips() {
+    let dir = class();
+    let zones = zones::zones(&dir);
+    assert_eq!(zones.iter().map(|z| z.name.as_str()).collect::<Vec<_>>(), ["thermal_zone0", "thermal_zone2", "thermal_zone10"]);
+    assert_eq!(zones[0].kind, "acpitz");
+    assert_eq!(zones[0].trips[1], Trip { kind: "passive".to_string(), celsius: 95 });
+    assert_eq!(zones[0].read(), Some(55));
+    // Not wired up
+    assert_eq!(zones[1].read(), None);
+
+    // Fans don't count, the passive trip is what throttles first
+    assert_eq!(zones[0].next_trip(55).unwrap().kind, "passive");
+    assert_eq!(zones[0].next_trip(100).unwrap().kind, "critical");
+    assert_eq!(zones[0].next_trip(110), None);
+
+    let passive = json(&zones::trip_record(&zones[0], &zones[0].trips[1], 105));
+    assert_eq!(passive["before_tjmax"], true);
+    assert_eq!(passive["zone_type"], "acpitz");
+    assert_eq!(zones::trip_record(&zones[0], &zones[0].trips[1], 105).fields[4].level, Level::Warn);
+    assert_eq!(zones::trip_record(&zones[0], &zones[0].trips[0], 105).fields[4].level, Level::Plain);
+    assert_eq!(zones::trip_record(&zones[0], &zones[0].trips[2], 105).fields[4].level, Level::Plain);
+
+    let reading = json(&zones::reading_record(&zones[0], 55));
+    assert_eq!((reading["next_trip"].as_str(), reading["to_trip"].as_u64()), (Some("passive"), Some(40)));
+    assert_eq!(zones::reading_record(&zones[0], 91).fields.last().unwrap().level, Level::Warn);
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(zones::zones(&dir).is_empty());
+}